@@ -1,7 +1,7 @@
 use std::{net::SocketAddr, path::PathBuf};
 
 use chrono::NaiveDate;
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Registry};
 
 #[derive(Debug, Parser)]
@@ -16,28 +16,7 @@ struct Cli {
 
 #[derive(Debug, Subcommand)]
 enum Commands {
-  Serve {
-    #[clap(long, env)]
-    database_path: PathBuf,
-    // TODO mouais c'est pas génial
-    #[clap(long, env = "PG_DATABASE_URL")]
-    pg_database_url: String,
-    #[clap(long, env)]
-    r_script: PathBuf,
-    #[clap(long, env)]
-    python_script: PathBuf,
-    // jusqu'ici
-    #[clap(long, env)]
-    topk_database_path: PathBuf,
-    #[clap(long, env)]
-    kratos_browser_url: String,
-    #[clap(long, env = "DIRECTORY_PATH")]
-    directory_path: PathBuf,
-    #[clap(long, env, default_value = "0.0.0.0:3000")]
-    listen_to: SocketAddr,
-    #[clap(long, env, default_value = "http://127.0.0.1:4433")]
-    kratos_base_path: String,
-  },
+  Serve(Box<Serve>),
   #[clap(subcommand)]
   Index(Index),
 
@@ -48,16 +27,167 @@ enum Commands {
   Study(Study),
 }
 
+#[derive(Debug, Args)]
+struct Serve {
+  #[clap(long, env)]
+  database_path: PathBuf,
+  // TODO mouais c'est pas génial
+  #[clap(long, env = "PG_DATABASE_URL")]
+  pg_database_url: String,
+  /// facultatif : sans script, les graphes sont calculés en Rust
+  #[clap(long, env)]
+  r_script: Option<PathBuf>,
+  /// facultatif : sans script, la visualisation des graphes est calculée en Rust
+  #[clap(long, env)]
+  python_script: Option<PathBuf>,
+  // jusqu'ici
+  #[clap(long, env)]
+  topk_database_path: PathBuf,
+  #[clap(long, env)]
+  kratos_browser_url: String,
+  #[clap(long, env = "DIRECTORY_PATH")]
+  directory_path: PathBuf,
+  #[clap(long, env, default_value = "0.0.0.0:3000")]
+  listen_to: SocketAddr,
+  #[clap(long, env, default_value = "http://127.0.0.1:4433")]
+  kratos_base_path: String,
+  /// nombre de workers exécutant les jobs du pipeline d'automatisation
+  #[clap(long, env, default_value_t = 1)]
+  job_workers: usize,
+  /// facultatif : relais SMTP (`localhost:25`) des courriels d'alerte
+  #[clap(long, env)]
+  smtp_address: Option<String>,
+  /// expéditeur des courriels d'alerte
+  #[clap(long, env, default_value = "cocktail@localhost")]
+  mail_from: String,
+  /// hôtes que les webhooks des alertes peuvent appeler, séparés par des virgules
+  #[clap(long, env, use_value_delimiter = true)]
+  webhook_hosts: Vec<String>,
+  /// fuseau horaire IANA des intervalles des graphiques
+  #[clap(long, env, default_value = "Europe/Paris")]
+  timezone: String,
+  /// options des index créés par le pipeline d'automatisation
+  #[clap(flatten)]
+  index_settings: IndexSettings,
+}
+
+/// Options d'un index créé par `index create` ou par le pipeline d'automatisation
+#[derive(Debug, Args)]
+struct IndexSettings {
+  /// retire les mots vides français du champ `text`
+  #[clap(long)]
+  stopwords: bool,
+  /// poids d'un retweet dans le nombre d'engagements
+  #[clap(long, default_value_t = 1)]
+  retweet_weight: u64,
+  /// poids d'une réponse dans le nombre d'engagements
+  #[clap(long, default_value_t = 1)]
+  reply_weight: u64,
+  /// poids d'une citation dans le nombre d'engagements
+  #[clap(long, default_value_t = 1)]
+  quote_weight: u64,
+}
+
+impl From<IndexSettings> for fts::IndexOptions {
+  fn from(settings: IndexSettings) -> Self {
+    fts::IndexOptions {
+      text_analysis: fts::TextAnalysisOptions {
+        stopwords: settings.stopwords,
+      },
+      engagement: fts::EngagementWeights {
+        retweets: settings.retweet_weight,
+        replies: settings.reply_weight,
+        quotes: settings.quote_weight,
+      },
+    }
+  }
+}
+
 #[derive(Debug, Subcommand)]
 enum Index {
   Create {
     #[clap(long, env = "DIRECTORY_PATH")]
     directory_path: PathBuf,
+    #[clap(flatten)]
+    options: IndexSettings,
   },
   Ingest {
     #[clap(long, env = "DIRECTORY_PATH")]
     directory_path: PathBuf,
+    /// fichier NDJSON à ingérer, éventuellement compressé (`.gz`) ; l'entrée standard sinon
+    #[clap(long)]
+    input: Option<PathBuf>,
+  },
+  /// supprime des tweets, désignés par leur identifiant ou par une requête
+  Delete {
+    #[clap(long, env = "DIRECTORY_PATH")]
+    directory_path: PathBuf,
+    /// identifiant d'un tweet à supprimer, répétable
+    #[clap(
+      long = "id",
+      conflicts_with = "query",
+      required_unless_present = "query"
+    )]
+    ids: Vec<String>,
+    /// requête tantivy (`user_screen_name:compte`, `published_time_ms:[0 TO 1000]`…)
+    #[clap(long)]
+    query: Option<String>,
+  },
+  /// reconstruit un index créé par une version antérieure dans le schéma courant
+  Migrate {
+    #[clap(long, env = "DIRECTORY_PATH")]
+    directory_path: PathBuf,
+    /// conserve l'ancien index à côté du nouveau, suffixé par sa version
+    #[clap(long)]
+    keep_backup: bool,
+  },
+  /// nombre de documents, segments, taille, période couverte, auteurs et hashtags distincts
+  Stats {
+    #[clap(long, env = "DIRECTORY_PATH")]
+    directory_path: PathBuf,
+  },
+  /// fusionne les segments, purge les documents supprimés et les fichiers inutiles
+  Merge {
+    #[clap(long, env = "DIRECTORY_PATH")]
+    directory_path: PathBuf,
+  },
+  /// vérifie la version, le schéma et chacun des documents de l'index
+  Verify {
+    #[clap(long, env = "DIRECTORY_PATH")]
+    directory_path: PathBuf,
+  },
+  /// tweets correspondant à une requête, au format JSON
+  Search {
+    #[clap(long, env = "DIRECTORY_PATH")]
+    directory_path: PathBuf,
+    /// requête des études (`loup AND #pastoralisme`, `@compte`, `date:[2022-01-01 TO
+    /// 2022-06-30]`, `lang:fr`…)
+    #[clap(long, env = "QUERY")]
+    query: String,
+    #[clap(long, default_value_t = 10)]
+    limit: usize,
+    /// tri : `retweets`, `reponses`, `citations` ou `engageants`, date de publication sinon
+    #[clap(long, default_value = "date")]
+    order_by: String,
+    /// `croissant` ou `decroissant`
+    #[clap(long, default_value = "decroissant")]
+    order: String,
+  },
+  /// copie l'index dans un répertoire vide
+  Snapshot {
+    #[clap(long, env = "DIRECTORY_PATH")]
+    directory_path: PathBuf,
+    #[clap(long)]
+    output: PathBuf,
   },
+  /// remplace l'index par une copie faite avec `snapshot`, serveur arrêté
+  Restore {
+    #[clap(long, env = "DIRECTORY_PATH")]
+    directory_path: PathBuf,
+    #[clap(long)]
+    snapshot: PathBuf,
+  },
+  /// hashtags à une faute de frappe près de la requête, au format JSON
   HashtagSearch {
     #[clap(long, env = "DIRECTORY_PATH")]
     directory_path: PathBuf,
@@ -99,17 +229,24 @@ async fn main() -> anyhow::Result<()> {
     .init();
   let args = Cli::parse();
   match args.commmand {
-    Commands::Serve {
-      database_path,
-      topk_database_path,
-      directory_path,
-      listen_to,
-      kratos_base_path,
-      r_script,
-      python_script,
-      pg_database_url,
-      kratos_browser_url,
-    } => {
+    Commands::Serve(serve) => {
+      let Serve {
+        database_path,
+        topk_database_path,
+        directory_path,
+        listen_to,
+        kratos_base_path,
+        r_script,
+        python_script,
+        pg_database_url,
+        kratos_browser_url,
+        job_workers,
+        smtp_address,
+        mail_from,
+        webhook_hosts,
+        timezone,
+        index_settings,
+      } = *serve;
       tracing::info!("serve");
       let databases = cocktail_server::Databases {
         web_database_path: database_path,
@@ -124,19 +261,114 @@ async fn main() -> anyhow::Result<()> {
         r_script,
         python_script,
       };
-      cocktail_server::run(directory_path, listen_to, databases, kratos, scripts).await?
+      let jobs = cocktail_server::Jobs {
+        workers: job_workers,
+        smtp_address,
+        mail_from,
+        webhook_hosts,
+        index_options: index_settings.into(),
+      };
+      let charts = cocktail_server::Charts {
+        timezone: fts::parse_timezone(&timezone)?,
+      };
+      cocktail_server::run(
+        directory_path,
+        listen_to,
+        databases,
+        kratos,
+        scripts,
+        jobs,
+        charts,
+      )
+      .await?
     }
     Commands::Index(command) => match command {
-      Index::Create { directory_path } => fts::create_index_config(&directory_path)?,
-      Index::Ingest { directory_path } => fts::ingest(directory_path)?,
+      Index::Create {
+        directory_path,
+        options,
+      } => fts::create_index_config_with_options(&directory_path, &options.into())?,
+      Index::Ingest {
+        directory_path,
+        input,
+      } => {
+        let stats = match input {
+          Some(input) => fts::ingest_file(directory_path, input)?,
+          None => fts::ingest(directory_path, std::io::stdin().lock())?,
+        };
+        eprintln!("{}", serde_json::to_string_pretty(&stats)?);
+      }
+      Index::Delete {
+        directory_path,
+        ids,
+        query,
+      } => {
+        let stats = match query {
+          Some(query) => fts::delete_tweets_by_query(directory_path, &query)?,
+          None => fts::delete_tweets(directory_path, &ids)?,
+        };
+        eprintln!("{}", serde_json::to_string_pretty(&stats)?);
+      }
+      Index::Migrate {
+        directory_path,
+        keep_backup,
+      } => {
+        let stats = fts::migrate_index(directory_path, keep_backup)?;
+        eprintln!("{}", serde_json::to_string_pretty(&stats)?);
+      }
+      Index::Stats { directory_path } => {
+        let stats = fts::index_stats(directory_path)?;
+        println!("{}", serde_json::to_string_pretty(&stats)?);
+      }
+      Index::Merge { directory_path } => {
+        let stats = fts::merge_index(directory_path)?;
+        eprintln!("{}", serde_json::to_string_pretty(&stats)?);
+      }
+      Index::Verify { directory_path } => {
+        let report = fts::verify_index(directory_path)?;
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        if !report.is_ok() {
+          anyhow::bail!("{} anomalies dans l'index", report.problem_count);
+        }
+      }
+      Index::Search {
+        directory_path,
+        query,
+        limit,
+        order_by,
+        order,
+      } => {
+        let index = fts::retrieve_index(directory_path)?;
+        let tweets = fts::search_tweets_with_limit(
+          &index,
+          &fts::QueryExpr::parse(&query)?,
+          &fts::OrderBy::from(order_by.as_str()),
+          fts::SortOrder::from(order.as_str()),
+          limit,
+        )?;
+        println!("{}", serde_json::to_string_pretty(&tweets)?);
+      }
+      Index::Snapshot {
+        directory_path,
+        output,
+      } => {
+        let stats = fts::snapshot_index(directory_path, output)?;
+        eprintln!("{}", serde_json::to_string_pretty(&stats)?);
+      }
+      Index::Restore {
+        directory_path,
+        snapshot,
+      } => {
+        let stats = fts::restore_index(snapshot, directory_path)?;
+        eprintln!("{}", serde_json::to_string_pretty(&stats)?);
+      }
       Index::HashtagSearch {
         directory_path,
         query,
         limit,
       } => {
-        let index = fts::Index::open_in_dir(directory_path)?;
-        let results = fts::hashtag_search(&index, query, limit)?;
-        dbg!(results);
+        let index = fts::retrieve_index(directory_path)?;
+        let hashtags = fts::hashtag_search(&index, query, limit)?;
+        println!("{}", serde_json::to_string_pretty(&hashtags)?);
       }
     },
     Commands::TopK(command) => match command {
@@ -9,20 +9,31 @@ struct Args {
   directory_path: PathBuf,
   #[clap(long, short)]
   query: String,
+  /// Base topk dont la table `hashtag` est remplacée, sortie JSON sinon
+  #[clap(long)]
+  topk_database_path: Option<PathBuf>,
 }
 
-fn main() -> anyhow::Result<()> {
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
   let args = Args::parse();
 
-  let index = fts::retrieve_index(args.directory_path.clone()).map_err(|e| {
-    anyhow::anyhow!("Erreur lors de la récupération de l'index: {:?}", e)
-  })?;
+  let index = fts::retrieve_index(args.directory_path.clone())
+    .map_err(|e| anyhow::anyhow!("Erreur lors de la récupération de l'index: {:?}", e))?;
 
-  let topk = fts::topk(&index, &args.query, MAX).map_err(|e| {
-    anyhow::anyhow!("Erreur lors de la génération des top hashtags: {:?}", e)
-  })?;
-  
-  println!("{}", topk);
+  let topk = fts::topk_hashtags(&index, &args.query, MAX)
+    .map_err(|e| anyhow::anyhow!("Erreur lors de la génération des top hashtags: {:?}", e))?;
+
+  match args.topk_database_path {
+    Some(path) => {
+      let rows: Vec<(String, i64)> = topk
+        .into_iter()
+        .map(|hashtag| (hashtag.key, hashtag.doc_count as i64))
+        .collect();
+      cocktail_db_twitter::replace_topk_hashtags(path, &rows).await?;
+    }
+    None => println!("{}", serde_json::to_string(&topk)?),
+  }
 
   Ok(())
 }
@@ -1,43 +1,54 @@
+use std::path::PathBuf;
+
 use clap::Parser;
-use serde::{Deserialize, Serialize};
-use sqlx::{postgres::PgPoolOptions, FromRow};
 
 #[derive(Debug, Parser)]
 struct Args {
-  #[clap(long, env = "PG_DATABASE_URL")]
-  pg_database_url: String,
-  #[clap(long, short)]
-  schema: String,
-}
-
-#[derive(Debug, Deserialize, Serialize, FromRow)]
-struct Cooccurence {
-  hashtag1: String,
-  hashtag2: String,
-  count: i64,
+  #[clap(long, env = "DIRECTORY_PATH")]
+  directory_path: PathBuf,
+  /// requête tantivy délimitant les tweets (`loup AND hashtags:pastoralisme`…)
+  #[clap(long, default_value = "*")]
+  query: String,
+  /// nombre de paires retournées, toutes si 0
+  #[clap(long, default_value_t = 10)]
+  limit: usize,
+  /// nombre minimal de tweets contenant la paire
+  #[clap(long, default_value_t = 1)]
+  min_support: u64,
+  /// mesure d'association classant les paires : count, pmi, jaccard ou lift
+  #[clap(long, default_value = "count")]
+  measure: fts::AssociationMeasure,
+  /// Base topk dont la table `hashtag_cooccurence` est remplacée, sortie JSON sinon
+  #[clap(long)]
+  topk_database_path: Option<PathBuf>,
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
   let args = Args::parse();
-  let pool = PgPoolOptions::new()
-    .connect(args.pg_database_url.as_ref())
-    .await?;
-
-  let topk = sqlx::query_as::<_, Cooccurence>(&format!(
-    r#"
-    SELECT ht1.hashtag AS hashtag1, ht2.hashtag AS hashtag2, COUNT(ht1.tweet_id) as count
-    FROM {schema}.tweet_hashtag ht1
-        INNER JOIN {schema}.tweet_hashtag ht2 ON ht1.tweet_id = ht2.tweet_id AND ht1.hashtag < ht2.hashtag 
-    GROUP BY ht1.hashtag, ht2.hashtag 
-    ORDER BY COUNT(ht1.tweet_id) DESC 
-    LIMIT 10  
-            "#,
-    schema = args.schema
-  ))
-  .fetch_all(&pool).await?;
+  let index = fts::retrieve_index(&args.directory_path)?;
+  let options = fts::CooccurenceOptions {
+    top: args.limit,
+    min_support: args.min_support,
+    measure: args.measure,
+  };
+  let cooccurences = fts::hashtags_cooccurence_for_query(&index, &args.query, &options)?;
 
-  println!("{}", serde_json::to_string_pretty(&topk)?);
+  match args.topk_database_path {
+    Some(path) => {
+      let topk: Vec<_> = cooccurences
+        .pairs
+        .into_iter()
+        .map(|pair| cocktail_db_twitter::HashtagCooccurence {
+          hashtag1: pair.hashtag1,
+          hashtag2: pair.hashtag2,
+          count: pair.count as i64,
+        })
+        .collect();
+      cocktail_db_twitter::replace_topk_hashtags_cooccurence(path, &topk).await?
+    }
+    None => println!("{}", serde_json::to_string_pretty(&cooccurences)?),
+  }
 
   Ok(())
 }
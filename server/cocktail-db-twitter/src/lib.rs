@@ -1,10 +1,19 @@
 use chrono::{Duration, NaiveDate};
-use sqlx::{SqliteConnection, SqlitePool};
+use sqlx::{
+  sqlite::{SqliteConnectOptions, SqlitePoolOptions},
+  SqliteConnection, SqlitePool,
+};
+
+pub use writer::*;
+
+mod writer;
 
 #[derive(Debug, thiserror::Error)]
 pub enum DbTwitterError {
   #[error("Erreur SQL")]
   SQL { source: sqlx::Error },
+  #[error("Erreur d'accès au fichier de la base topk")]
+  IO { source: std::io::Error },
 }
 
 impl From<sqlx::Error> for DbTwitterError {
@@ -13,13 +22,40 @@ impl From<sqlx::Error> for DbTwitterError {
   }
 }
 
+impl From<std::io::Error> for DbTwitterError {
+  fn from(source: std::io::Error) -> Self {
+    DbTwitterError::IO { source }
+  }
+}
+
 #[derive(Debug, Clone)]
 pub struct TopKDatabase(SqlitePool);
 
+/// Durée de vie des connexions à la base topk : une table remplacée par renommage (voir
+/// [`replace_topk_hashtags`]) est lue par toutes les connexions au plus tard après ce délai
+pub const TOPK_CONNECTION_LIFETIME: std::time::Duration = std::time::Duration::from_secs(30);
+
 impl TopKDatabase {
   pub fn new(pool: SqlitePool) -> Self {
     Self(pool)
   }
+
+  /// Pool de connexions à la base topk, renouvelées après [`TOPK_CONNECTION_LIFETIME`]
+  pub async fn connect(options: SqliteConnectOptions) -> sqlx::Result<Self> {
+    Self::connect_with_lifetime(options, TOPK_CONNECTION_LIFETIME).await
+  }
+
+  pub(crate) async fn connect_with_lifetime(
+    options: SqliteConnectOptions,
+    lifetime: std::time::Duration,
+  ) -> sqlx::Result<Self> {
+    let pool = SqlitePoolOptions::new()
+      .max_lifetime(lifetime)
+      .idle_timeout(lifetime)
+      .connect_with(options)
+      .await?;
+    Ok(Self(pool))
+  }
 }
 
 impl AsRef<SqlitePool> for TopKDatabase {
@@ -70,13 +106,73 @@ pub struct Hashtag {
   pub available: bool,
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, sqlx::FromRow)]
 pub struct HashtagCooccurence {
   pub hashtag1: String,
   pub hashtag2: String,
   pub count: i64,
 }
 
+/// Nombre de tweets d'un jour contenant un hashtag
+#[derive(Debug, Clone)]
+pub struct HashtagDay {
+  pub key: String,
+  pub day: NaiveDate,
+  pub doc_count: i64,
+}
+
+/// Fenêtre des tendances : les `recent_days` jours se terminant à `end` (par défaut le
+/// dernier jour de la base) comparés aux `baseline_days` jours qui les précèdent
+#[derive(Debug, Clone, Copy)]
+pub struct TrendWindow {
+  pub end: Option<NaiveDate>,
+  pub recent_days: i64,
+  pub baseline_days: i64,
+}
+
+impl Default for TrendWindow {
+  fn default() -> Self {
+    Self {
+      end: None,
+      recent_days: 7,
+      baseline_days: 28,
+    }
+  }
+}
+
+/// Hashtag plus utilisé sur la fenêtre récente qu'au rythme de la période de référence
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct TrendingHashtag {
+  pub hashtag: String,
+  pub recent_count: i64,
+  pub baseline_count: i64,
+  #[sqlx(default)]
+  pub score: f64,
+}
+
+impl TrendingHashtag {
+  /// Écart du compte récent au compte attendu d'après les `baseline_days` jours connus de
+  /// la période de référence, rapporté à sa dispersion (loi de Poisson lissée) : un hashtag
+  /// rare qui apparaît ne l'emporte pas sur un hashtag courant dont l'usage double
+  fn score(&mut self, recent_days: i64, baseline_days: i64) {
+    let expected = match baseline_days {
+      0 => 0.0,
+      _ => self.baseline_count as f64 * recent_days as f64 / baseline_days as f64,
+    };
+    self.score = (self.recent_count as f64 - expected) / (expected + 1.0).sqrt();
+  }
+}
+
+impl From<TrendingHashtag> for Hashtag {
+  fn from(trending: TrendingHashtag) -> Self {
+    Self {
+      hashtag: trending.hashtag,
+      count: trending.recent_count,
+      ..Default::default()
+    }
+  }
+}
+
 impl From<_Hashtag> for Hashtag {
   fn from(_Hashtag { hashtag, count }: _Hashtag) -> Self {
     Self {
@@ -139,3 +235,69 @@ LIMIT 10 "#
 
   Ok(rows.into_iter().map(Into::into).collect())
 }
+
+/// `limit` hashtags en tendance sur `window`, par score décroissant, d'après les comptes
+/// quotidiens de la table `hashtag_day` ; vide pour une base topk qui ne les a pas encore
+pub async fn search_trending_hashtags<S: AsRef<SqlitePool>>(
+  conn: S,
+  window: &TrendWindow,
+  limit: usize,
+) -> Result<Vec<TrendingHashtag>, DbTwitterError> {
+  let has_days: bool = sqlx::query_scalar(
+    "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'hashtag_day')",
+  )
+  .fetch_one(conn.as_ref())
+  .await?;
+  if !has_days {
+    return Ok(vec![]);
+  }
+
+  let end = window.end.map(|end| end.format("%F").to_string());
+  let recent_start = format!("-{} days", window.recent_days);
+  let baseline_start = format!("-{} days", window.recent_days + window.baseline_days);
+
+  // jours de la période de référence présents dans la base, qui ne conserve qu'un historique
+  // limité
+  let baseline_days: i64 = sqlx::query_scalar(
+    r#"
+WITH window_end AS (SELECT COALESCE($1, MAX(day)) AS day FROM hashtag_day)
+SELECT COUNT(DISTINCT hashtag_day.day)
+FROM hashtag_day, window_end
+WHERE hashtag_day.day <= date(window_end.day, $2) AND hashtag_day.day > date(window_end.day, $3) "#,
+  )
+  .bind(&end)
+  .bind(&recent_start)
+  .bind(&baseline_start)
+  .fetch_one(conn.as_ref())
+  .await?;
+
+  let mut rows = sqlx::query_as::<_, TrendingHashtag>(
+    r#"
+WITH window_end AS (SELECT COALESCE($1, MAX(day)) AS day FROM hashtag_day)
+SELECT key AS hashtag,
+  SUM(CASE WHEN hashtag_day.day > date(window_end.day, $2) THEN doc_count ELSE 0 END) AS recent_count,
+  SUM(CASE WHEN hashtag_day.day <= date(window_end.day, $2) THEN doc_count ELSE 0 END) AS baseline_count
+FROM hashtag_day, window_end
+WHERE hashtag_day.day <= window_end.day AND hashtag_day.day > date(window_end.day, $3)
+GROUP BY key
+HAVING recent_count > 0 "#,
+  )
+  .bind(&end)
+  .bind(&recent_start)
+  .bind(&baseline_start)
+  .fetch_all(conn.as_ref())
+  .await?;
+
+  rows
+    .iter_mut()
+    .for_each(|row| row.score(window.recent_days, baseline_days));
+  rows.retain(|row| row.score > 0.0);
+  rows.sort_by(|a, b| {
+    b.score
+      .total_cmp(&a.score)
+      .then_with(|| a.hashtag.cmp(&b.hashtag))
+  });
+  rows.truncate(limit);
+
+  Ok(rows)
+}
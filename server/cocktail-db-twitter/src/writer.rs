@@ -0,0 +1,305 @@
+use std::{
+  ffi::OsString,
+  fs,
+  path::{Path, PathBuf},
+};
+
+use sqlx::{
+  sqlite::SqliteConnectOptions, ConnectOptions, Connection, Sqlite, SqliteConnection,
+  Transaction,
+};
+
+use crate::{DbTwitterError, HashtagCooccurence, HashtagDay};
+
+/// Table de la base topk à remplacer
+enum TopKTable<'a> {
+  Hashtag(&'a [(String, i64)]),
+  HashtagCooccurence(&'a [HashtagCooccurence]),
+  HashtagDay(&'a [HashtagDay]),
+}
+
+impl TopKTable<'_> {
+  fn name(&self) -> &'static str {
+    match self {
+      TopKTable::Hashtag(_) => "hashtag",
+      TopKTable::HashtagCooccurence(_) => "hashtag_cooccurence",
+      TopKTable::HashtagDay(_) => "hashtag_day",
+    }
+  }
+}
+
+/// Remplace le contenu de la table `hashtag` de la base topk `path` par `hashtags`
+/// (hashtag, nombre de tweets).
+///
+/// La base est écrite dans une copie puis substituée à l'originale par un renommage
+/// atomique : un lecteur voit l'ancienne ou la nouvelle version, jamais une écriture
+/// partielle. Les connexions déjà ouvertes sur l'ancien fichier continuent de le lire
+/// jusqu'à leur renouvellement, au plus tard après [`crate::TOPK_CONNECTION_LIFETIME`]
+/// pour un pool ouvert par [`crate::TopKDatabase::connect`].
+pub async fn replace_topk_hashtags<P: AsRef<Path>>(
+  path: P,
+  hashtags: &[(String, i64)],
+) -> Result<(), DbTwitterError> {
+  replace_table(path.as_ref(), TopKTable::Hashtag(hashtags)).await
+}
+
+/// Remplace le contenu de la table `hashtag_cooccurence` de la base topk `path`,
+/// de la même manière que [`replace_topk_hashtags`]
+pub async fn replace_topk_hashtags_cooccurence<P: AsRef<Path>>(
+  path: P,
+  cooccurences: &[HashtagCooccurence],
+) -> Result<(), DbTwitterError> {
+  replace_table(path.as_ref(), TopKTable::HashtagCooccurence(cooccurences)).await
+}
+
+/// Remplace le contenu de la table `hashtag_day` de la base topk `path` par les comptes
+/// quotidiens `days`, de la même manière que [`replace_topk_hashtags`]
+pub async fn replace_topk_hashtags_daily<P: AsRef<Path>>(
+  path: P,
+  days: &[HashtagDay],
+) -> Result<(), DbTwitterError> {
+  replace_table(path.as_ref(), TopKTable::HashtagDay(days)).await
+}
+
+async fn replace_table(path: &Path, table: TopKTable<'_>) -> Result<(), DbTwitterError> {
+  let temp_path = temp_path(path, table.name());
+  let _ = fs::remove_file(&temp_path);
+
+  let written = write_copy(path, &temp_path, &table).await;
+  let swapped = written.and_then(|_| fs::rename(&temp_path, path).map_err(Into::into));
+  if swapped.is_err() {
+    let _ = fs::remove_file(&temp_path);
+  }
+
+  swapped
+}
+
+/// Copie la base `path` (si elle existe) vers `temp_path` et y remplace la table
+async fn write_copy(
+  path: &Path,
+  temp_path: &Path,
+  table: &TopKTable<'_>,
+) -> Result<(), DbTwitterError> {
+  if path.exists() {
+    fs::copy(path, temp_path)?;
+  }
+
+  let mut conn = SqliteConnectOptions::new()
+    .filename(temp_path)
+    .create_if_missing(true)
+    .connect()
+    .await?;
+
+  let mut tx = conn.begin().await?;
+  write_table(&mut tx, table).await?;
+  tx.commit().await?;
+
+  SqliteConnection::close(conn).await?;
+  Ok(())
+}
+
+async fn write_table(
+  tx: &mut Transaction<'_, Sqlite>,
+  table: &TopKTable<'_>,
+) -> Result<(), DbTwitterError> {
+  sqlx::query(&format!("DROP TABLE IF EXISTS {}", table.name()))
+    .execute(&mut *tx)
+    .await?;
+
+  match table {
+    TopKTable::Hashtag(hashtags) => {
+      sqlx::query("CREATE TABLE hashtag (key TEXT PRIMARY KEY, doc_count INTEGER NOT NULL)")
+        .execute(&mut *tx)
+        .await?;
+
+      for (key, doc_count) in hashtags.iter() {
+        sqlx::query("INSERT INTO hashtag (key, doc_count) VALUES ($1, $2)")
+          .bind(key)
+          .bind(doc_count)
+          .execute(&mut *tx)
+          .await?;
+      }
+    }
+    TopKTable::HashtagCooccurence(cooccurences) => {
+      sqlx::query(
+        r#"
+CREATE TABLE hashtag_cooccurence (
+  hashtag1 TEXT NOT NULL,
+  hashtag2 TEXT NOT NULL,
+  count INTEGER NOT NULL,
+  PRIMARY KEY (hashtag1, hashtag2)
+) "#,
+      )
+      .execute(&mut *tx)
+      .await?;
+
+      for cooccurence in cooccurences.iter() {
+        sqlx::query(
+          "INSERT INTO hashtag_cooccurence (hashtag1, hashtag2, count) VALUES ($1, $2, $3)",
+        )
+        .bind(&cooccurence.hashtag1)
+        .bind(&cooccurence.hashtag2)
+        .bind(cooccurence.count)
+        .execute(&mut *tx)
+        .await?;
+      }
+    }
+    TopKTable::HashtagDay(days) => {
+      sqlx::query(
+        r#"
+CREATE TABLE hashtag_day (
+  key TEXT NOT NULL,
+  day TEXT NOT NULL,
+  doc_count INTEGER NOT NULL,
+  PRIMARY KEY (key, day)
+) "#,
+      )
+      .execute(&mut *tx)
+      .await?;
+
+      for day in days.iter() {
+        sqlx::query("INSERT INTO hashtag_day (key, day, doc_count) VALUES ($1, $2, $3)")
+          .bind(&day.key)
+          .bind(day.day.format("%F").to_string())
+          .bind(day.doc_count)
+          .execute(&mut *tx)
+          .await?;
+      }
+    }
+  }
+
+  Ok(())
+}
+
+/// `topk.db` → `topk.db.hashtag.tmp`, dans le même répertoire pour que le renommage
+/// reste atomique
+fn temp_path(path: &Path, table: &str) -> PathBuf {
+  let mut name = OsString::from(path.as_os_str());
+  name.push(format!(".{table}.tmp"));
+  PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+  use std::time::Duration;
+
+  use sqlx::{Connection, SqliteConnection};
+
+  use super::*;
+  use crate::TopKDatabase;
+
+  #[tokio::test]
+  async fn replace_keeps_other_table() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("topk.db");
+
+    replace_topk_hashtags(&path, &[("vegan".to_string(), 3), ("pac".to_string(), 2)])
+      .await
+      .unwrap();
+    replace_topk_hashtags_cooccurence(
+      &path,
+      &[HashtagCooccurence {
+        hashtag1: "pac".to_string(),
+        hashtag2: "vegan".to_string(),
+        count: 2,
+      }],
+    )
+    .await
+    .unwrap();
+    replace_topk_hashtags(&path, &[("climat".to_string(), 5)])
+      .await
+      .unwrap();
+
+    let mut conn = SqliteConnection::connect(&format!("sqlite:{}", path.display()))
+      .await
+      .unwrap();
+    let hashtags: Vec<(String, i64)> = sqlx::query_as("SELECT key, doc_count FROM hashtag")
+      .fetch_all(&mut conn)
+      .await
+      .unwrap();
+    let cooccurences: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM hashtag_cooccurence")
+      .fetch_one(&mut conn)
+      .await
+      .unwrap();
+
+    assert_eq!(hashtags, vec![("climat".to_string(), 5)]);
+    assert_eq!(cooccurences, 1);
+    assert!(!temp_path(&path, "hashtag").exists());
+  }
+
+  #[tokio::test]
+  async fn pool_reads_replaced_table() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("topk.db");
+    replace_topk_hashtags(&path, &[("vegan".to_string(), 3)])
+      .await
+      .unwrap();
+
+    let lifetime = Duration::from_millis(200);
+    let options = SqliteConnectOptions::new().filename(&path);
+    let db = TopKDatabase::connect_with_lifetime(options, lifetime)
+      .await
+      .unwrap();
+    let hashtags = || async {
+      sqlx::query_scalar::<_, String>("SELECT key FROM hashtag")
+        .fetch_all(db.as_ref())
+        .await
+        .unwrap()
+    };
+    assert_eq!(hashtags().await, vec!["vegan".to_string()]);
+
+    replace_topk_hashtags(&path, &[("climat".to_string(), 5)])
+      .await
+      .unwrap();
+    tokio::time::sleep(lifetime * 2).await;
+    assert_eq!(hashtags().await, vec!["climat".to_string()]);
+  }
+
+  #[tokio::test]
+  async fn trending_compares_recent_days_with_baseline() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("topk.db");
+    let day = |key: &str, day: u32, doc_count: i64| HashtagDay {
+      key: key.to_string(),
+      day: chrono::NaiveDate::from_ymd(2022, 6, day),
+      doc_count,
+    };
+
+    // #pac est stable, #loup double ses derniers jours et #brebis apparaît
+    let mut days = vec![];
+    for d in 1..=10 {
+      days.push(day("pac", d, 20));
+      days.push(day("loup", d, if d > 8 { 20 } else { 10 }));
+    }
+    days.push(day("brebis", 10, 2));
+    replace_topk_hashtags_daily(&path, &days).await.unwrap();
+
+    let pool = crate::TopKDatabase::new(
+      sqlx::SqlitePool::connect(&format!("sqlite:{}", path.display()))
+        .await
+        .unwrap(),
+    );
+    let window = crate::TrendWindow {
+      end: None,
+      recent_days: 2,
+      baseline_days: 8,
+    };
+    let trending = crate::search_trending_hashtags(pool.clone(), &window, 10)
+      .await
+      .unwrap();
+    let hashtags: Vec<_> = trending.iter().map(|t| t.hashtag.as_str()).collect();
+    assert_eq!(hashtags, vec!["loup", "brebis"]);
+    assert_eq!((trending[0].recent_count, trending[0].baseline_count), (40, 80));
+
+    // une fenêtre qui se termine avant la hausse ne voit rien, même si sa période de
+    // référence déborde de l'historique
+    let window = crate::TrendWindow {
+      end: Some(chrono::NaiveDate::from_ymd(2022, 6, 8)),
+      ..window
+    };
+    let trending = crate::search_trending_hashtags(pool.clone(), &window, 10)
+      .await
+      .unwrap();
+    assert!(trending.is_empty());
+  }
+}
@@ -0,0 +1,26 @@
+CREATE TABLE "job" (
+	"job_id" TEXT NOT NULL PRIMARY KEY,
+	"schema_name" TEXT NOT NULL,
+	"project_id" TEXT,
+	"status" TEXT NOT NULL DEFAULT 'pending',
+	"completed_steps" INTEGER NOT NULL DEFAULT 0,
+	"total_steps" INTEGER NOT NULL,
+	"error" TEXT,
+	"attempts" INTEGER NOT NULL DEFAULT 0,
+	"run_date" TEXT NOT NULL,
+	"created_at" TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	"updated_at" TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX "job_status" ON "job" ("status", "created_at");
+
+CREATE TABLE "job_step" (
+	"job_id" TEXT NOT NULL REFERENCES "job"("job_id") ON DELETE CASCADE,
+	"step" INTEGER NOT NULL,
+	"name" TEXT NOT NULL,
+	"status" TEXT NOT NULL DEFAULT 'pending',
+	"error" TEXT,
+	"started_at" TEXT,
+	"finished_at" TEXT,
+	PRIMARY KEY("job_id", "step")
+) WITHOUT ROWID;
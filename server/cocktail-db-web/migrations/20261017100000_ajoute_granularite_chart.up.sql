@@ -0,0 +1,12 @@
+-- les graphiques en cache étaient comptés par jour UTC : ils sont recalculés à la demande
+DROP TABLE "chart";
+CREATE TABLE "chart" (
+	"project_id" TEXT NOT NULL,
+	"title" TEXT NOT NULL,
+	"tab" TEXT NOT NULL,
+	"granularity" TEXT NOT NULL,
+	"timezone" TEXT NOT NULL,
+	"json" TEXT NOT NULL,
+	"date" TEXT NOT NULL,
+	PRIMARY KEY("project_id", "title", "tab", "granularity", "timezone")
+) WITHOUT ROWID;
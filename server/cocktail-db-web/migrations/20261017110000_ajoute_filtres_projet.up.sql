@@ -0,0 +1,2 @@
+-- filtres de langue, de zone et de contenu sensible des études
+ALTER TABLE project ADD COLUMN filters TEXT DEFAULT '{}' NOT NULL;
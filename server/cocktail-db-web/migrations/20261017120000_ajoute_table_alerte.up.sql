@@ -0,0 +1,26 @@
+CREATE TABLE "alert" (
+	"alert_id" TEXT NOT NULL PRIMARY KEY,
+	"project_id" TEXT NOT NULL REFERENCES "project"("project_id") ON DELETE CASCADE,
+	"user_id" TEXT NOT NULL,
+	"name" TEXT NOT NULL,
+	"condition" TEXT NOT NULL,
+	"schedule" TEXT NOT NULL DEFAULT 'daily',
+	"email" TEXT,
+	"webhook_url" TEXT,
+	"active" BOOLEAN NOT NULL DEFAULT TRUE,
+	"last_evaluated_at" TEXT,
+	"created_at" TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX "alert_project" ON "alert" ("project_id");
+
+CREATE TABLE "alert_event" (
+	"alert_event_id" TEXT NOT NULL PRIMARY KEY,
+	"alert_id" TEXT NOT NULL REFERENCES "alert"("alert_id") ON DELETE CASCADE,
+	"summary" TEXT NOT NULL,
+	"evaluation" TEXT NOT NULL,
+	"delivery" TEXT,
+	"triggered_at" TEXT NOT NULL
+);
+
+CREATE INDEX "alert_event_alert" ON "alert_event" ("alert_id", "triggered_at");
@@ -0,0 +1,421 @@
+use std::fmt::Debug;
+
+use chrono::{Duration, NaiveDateTime};
+use fts::{AlertCondition, AlertEvaluation};
+use serde::{Deserialize, Serialize};
+use sqlx::{types::Json, FromRow, SqlitePool};
+use uuid::Uuid;
+
+/// Fréquence d'évaluation d'une alerte, au plus une fois par pipeline d'automatisation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum AlertSchedule {
+  EachRun,
+  Daily,
+  Weekly,
+}
+
+impl AlertSchedule {
+  /// Intervalle minimal entre deux évaluations, aucun pour `EachRun`
+  pub fn period(&self) -> Option<Duration> {
+    match self {
+      AlertSchedule::EachRun => None,
+      AlertSchedule::Daily => Some(Duration::days(1)),
+      AlertSchedule::Weekly => Some(Duration::weeks(1)),
+    }
+  }
+
+  /// L'alerte évaluée pour la dernière fois à `last_evaluated_at` est à réévaluer à `now` ;
+  /// une heure de marge évite qu'un pipeline quotidien un peu en avance saute un jour
+  pub fn is_due(&self, last_evaluated_at: Option<NaiveDateTime>, now: NaiveDateTime) -> bool {
+    match (self.period(), last_evaluated_at) {
+      (Some(period), Some(last_evaluated_at)) => {
+        now - last_evaluated_at >= period - Duration::hours(1)
+      }
+      _ => true,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Alert {
+  pub alert_id: String,
+  pub project_id: String,
+  pub user_id: String,
+  pub name: String,
+  pub condition: Json<AlertCondition>,
+  pub schedule: AlertSchedule,
+  pub email: Option<String>,
+  pub webhook_url: Option<String>,
+  pub active: bool,
+  /// les nouveaux tweets d'une alerte sont ceux publiés depuis sa dernière évaluation
+  pub last_evaluated_at: Option<NaiveDateTime>,
+  pub created_at: NaiveDateTime,
+}
+
+/// Alerte à créer dans un projet
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewAlert {
+  pub name: String,
+  pub condition: AlertCondition,
+  pub schedule: AlertSchedule,
+  pub email: Option<String>,
+  pub webhook_url: Option<String>,
+}
+
+/// Déclenchement d'une alerte
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct AlertEvent {
+  pub alert_event_id: String,
+  pub alert_id: String,
+  pub alert_name: String,
+  pub summary: String,
+  pub evaluation: Json<AlertEvaluation>,
+  /// résultat de l'envoi de la notification, absent si aucune n'est demandée
+  pub delivery: Option<String>,
+  pub triggered_at: NaiveDateTime,
+}
+
+#[tracing::instrument]
+pub async fn create_alert<S>(
+  pool: S,
+  project_id: &str,
+  user_id: &str,
+  alert: &NewAlert,
+) -> sqlx::Result<Alert>
+where
+  S: AsRef<SqlitePool> + Debug,
+{
+  let alert_id = Uuid::new_v4().to_hyphenated().to_string();
+
+  let condition = Json(&alert.condition);
+  sqlx::query!(
+    r#"
+INSERT INTO alert (alert_id, project_id, user_id, name, condition, schedule, email, webhook_url)
+VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+    "#,
+    alert_id,
+    project_id,
+    user_id,
+    alert.name,
+    condition,
+    alert.schedule,
+    alert.email,
+    alert.webhook_url
+  )
+  .execute(pool.as_ref())
+  .await?;
+
+  sqlx::query_as!(
+    Alert,
+    r#"
+SELECT alert_id, project_id, user_id, name,
+    condition AS "condition: Json<AlertCondition>",
+    schedule AS "schedule: AlertSchedule",
+    email, webhook_url, active,
+    last_evaluated_at AS "last_evaluated_at: NaiveDateTime",
+    created_at AS "created_at: NaiveDateTime"
+FROM alert
+WHERE alert_id = ?1
+    "#,
+    alert_id
+  )
+  .fetch_one(pool.as_ref())
+  .await
+}
+
+#[tracing::instrument]
+pub async fn project_alerts<S>(pool: S, project_id: &str, user_id: &str) -> sqlx::Result<Vec<Alert>>
+where
+  S: AsRef<SqlitePool> + Debug,
+{
+  sqlx::query_as!(
+    Alert,
+    r#"
+SELECT alert_id, project_id, user_id, name,
+    condition AS "condition: Json<AlertCondition>",
+    schedule AS "schedule: AlertSchedule",
+    email, webhook_url, active,
+    last_evaluated_at AS "last_evaluated_at: NaiveDateTime",
+    created_at AS "created_at: NaiveDateTime"
+FROM alert
+WHERE project_id = ?1 AND user_id = ?2
+ORDER BY created_at, rowid
+    "#,
+    project_id,
+    user_id
+  )
+  .fetch_all(pool.as_ref())
+  .await
+}
+
+/// Alertes actives de tous les projets, à évaluer après un pipeline d'automatisation
+#[tracing::instrument]
+pub async fn active_alerts<S>(pool: S) -> sqlx::Result<Vec<Alert>>
+where
+  S: AsRef<SqlitePool> + Debug,
+{
+  sqlx::query_as!(
+    Alert,
+    r#"
+SELECT alert_id, project_id, user_id, name,
+    condition AS "condition: Json<AlertCondition>",
+    schedule AS "schedule: AlertSchedule",
+    email, webhook_url, active,
+    last_evaluated_at AS "last_evaluated_at: NaiveDateTime",
+    created_at AS "created_at: NaiveDateTime"
+FROM alert
+WHERE active
+ORDER BY created_at, rowid
+    "#
+  )
+  .fetch_all(pool.as_ref())
+  .await
+}
+
+/// Supprime l'alerte et son historique ; renvoie `false` si elle n'existe pas
+#[tracing::instrument]
+pub async fn delete_alert<S>(pool: S, alert_id: &str, user_id: &str) -> sqlx::Result<bool>
+where
+  S: AsRef<SqlitePool> + Debug,
+{
+  let mut transaction = pool.as_ref().begin().await?;
+
+  sqlx::query!(
+    r#"
+DELETE FROM alert_event
+WHERE alert_id IN (SELECT alert_id FROM alert WHERE alert_id = ?1 AND user_id = ?2)
+    "#,
+    alert_id,
+    user_id
+  )
+  .execute(&mut transaction)
+  .await?;
+
+  let deleted = sqlx::query!(
+    r#"DELETE FROM alert WHERE alert_id = ?1 AND user_id = ?2"#,
+    alert_id,
+    user_id
+  )
+  .execute(&mut transaction)
+  .await?
+  .rows_affected();
+
+  transaction.commit().await?;
+
+  Ok(deleted > 0)
+}
+
+#[tracing::instrument]
+pub async fn set_alert_active<S>(
+  pool: S,
+  alert_id: &str,
+  user_id: &str,
+  active: bool,
+) -> sqlx::Result<bool>
+where
+  S: AsRef<SqlitePool> + Debug,
+{
+  let updated = sqlx::query!(
+    r#"UPDATE alert SET active = ?3 WHERE alert_id = ?1 AND user_id = ?2"#,
+    alert_id,
+    user_id,
+    active
+  )
+  .execute(pool.as_ref())
+  .await?
+  .rows_affected();
+
+  Ok(updated > 0)
+}
+
+#[tracing::instrument]
+pub async fn set_alert_evaluated<S>(
+  pool: S,
+  alert_id: &str,
+  evaluated_at: NaiveDateTime,
+) -> sqlx::Result<()>
+where
+  S: AsRef<SqlitePool> + Debug,
+{
+  sqlx::query!(
+    r#"UPDATE alert SET last_evaluated_at = ?2 WHERE alert_id = ?1"#,
+    alert_id,
+    evaluated_at
+  )
+  .execute(pool.as_ref())
+  .await?;
+
+  Ok(())
+}
+
+#[tracing::instrument(skip(evaluation))]
+pub async fn create_alert_event<S>(
+  pool: S,
+  alert: &Alert,
+  evaluation: &AlertEvaluation,
+  triggered_at: NaiveDateTime,
+) -> sqlx::Result<AlertEvent>
+where
+  S: AsRef<SqlitePool> + Debug,
+{
+  let alert_event_id = Uuid::new_v4().to_hyphenated().to_string();
+
+  let stored_evaluation = Json(evaluation);
+  sqlx::query!(
+    r#"
+INSERT INTO alert_event (alert_event_id, alert_id, summary, evaluation, triggered_at)
+VALUES (?1, ?2, ?3, ?4, ?5)
+    "#,
+    alert_event_id,
+    alert.alert_id,
+    evaluation.summary,
+    stored_evaluation,
+    triggered_at
+  )
+  .execute(pool.as_ref())
+  .await?;
+
+  Ok(AlertEvent {
+    alert_event_id,
+    alert_id: alert.alert_id.clone(),
+    alert_name: alert.name.clone(),
+    summary: evaluation.summary.clone(),
+    evaluation: Json(evaluation.clone()),
+    delivery: None,
+    triggered_at,
+  })
+}
+
+#[tracing::instrument]
+pub async fn set_alert_event_delivery<S>(
+  pool: S,
+  alert_event_id: &str,
+  delivery: &str,
+) -> sqlx::Result<()>
+where
+  S: AsRef<SqlitePool> + Debug,
+{
+  sqlx::query!(
+    r#"UPDATE alert_event SET delivery = ?2 WHERE alert_event_id = ?1"#,
+    alert_event_id,
+    delivery
+  )
+  .execute(pool.as_ref())
+  .await?;
+
+  Ok(())
+}
+
+/// Déclenchements des alertes du projet, les plus récents d'abord
+#[tracing::instrument]
+pub async fn project_alert_events<S>(
+  pool: S,
+  project_id: &str,
+  user_id: &str,
+  limit: i64,
+) -> sqlx::Result<Vec<AlertEvent>>
+where
+  S: AsRef<SqlitePool> + Debug,
+{
+  sqlx::query_as!(
+    AlertEvent,
+    r#"
+SELECT alert_event.alert_event_id, alert_event.alert_id, alert.name AS alert_name,
+    alert_event.summary,
+    alert_event.evaluation AS "evaluation: Json<AlertEvaluation>",
+    alert_event.delivery,
+    alert_event.triggered_at AS "triggered_at: NaiveDateTime"
+FROM alert_event JOIN alert ON alert.alert_id = alert_event.alert_id
+WHERE alert.project_id = ?1 AND alert.user_id = ?2
+ORDER BY alert_event.triggered_at DESC, alert_event.rowid DESC
+LIMIT ?3
+    "#,
+    project_id,
+    user_id,
+    limit
+  )
+  .fetch_all(pool.as_ref())
+  .await
+}
+
+#[cfg(test)]
+mod tests {
+  use chrono::NaiveDate;
+
+  use super::*;
+  use crate::test_database;
+
+  #[tokio::test]
+  async fn test_alert_lifecycle() {
+    let db = test_database().await;
+    sqlx::query(
+      r#"
+INSERT INTO project (project_id, user_id, title, event_count, tweet_count, updated_at, start_date, end_date)
+VALUES ('projet', 'chercheuse', 'Loup', 0, 0, '2022-06-01', '2022-06-01', '2022-06-30')
+      "#,
+    )
+    .execute(db.as_ref())
+    .await
+    .unwrap();
+
+    let alert = create_alert(
+      &db,
+      "projet",
+      "chercheuse",
+      &NewAlert {
+        name: "Pic #loup".to_string(),
+        condition: AlertCondition::HashtagSurge {
+          hashtag: "loup".to_string(),
+          factor: 2.0,
+        },
+        schedule: AlertSchedule::Weekly,
+        email: Some("chercheuse@example.org".to_string()),
+        webhook_url: None,
+      },
+    )
+    .await
+    .unwrap();
+    assert!(alert.active);
+    assert_eq!(alert.schedule, AlertSchedule::Weekly);
+    assert_eq!(alert.last_evaluated_at, None);
+
+    let now = NaiveDate::from_ymd(2022, 6, 10).and_hms(8, 0, 0);
+    set_alert_evaluated(&db, &alert.alert_id, now).await.unwrap();
+    let evaluation = AlertEvaluation {
+      triggered: true,
+      summary: "12 tweets #loup".to_string(),
+      observed: 12,
+      threshold: 4.0,
+      tweets: vec![],
+    };
+    let event = create_alert_event(&db, &alert, &evaluation, now).await.unwrap();
+    set_alert_event_delivery(&db, &event.alert_event_id, "courriel envoyé")
+      .await
+      .unwrap();
+
+    let alerts = active_alerts(&db).await.unwrap();
+    assert_eq!(alerts.len(), 1);
+    assert_eq!(alerts[0].last_evaluated_at, Some(now));
+    assert_eq!(alerts[0].condition.0, alert.condition.0);
+    let events = project_alert_events(&db, "projet", "chercheuse", 10)
+      .await
+      .unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].alert_name, "Pic #loup");
+    assert_eq!(events[0].delivery.as_deref(), Some("courriel envoyé"));
+    assert_eq!(events[0].evaluation.0.observed, 12);
+
+    assert!(set_alert_active(&db, &alert.alert_id, "chercheuse", false)
+      .await
+      .unwrap());
+    assert!(active_alerts(&db).await.unwrap().is_empty());
+    assert!(!delete_alert(&db, &alert.alert_id, "autre").await.unwrap());
+    assert!(delete_alert(&db, &alert.alert_id, "chercheuse").await.unwrap());
+    assert!(project_alerts(&db, "projet", "chercheuse")
+      .await
+      .unwrap()
+      .is_empty());
+  }
+}
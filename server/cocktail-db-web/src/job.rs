@@ -0,0 +1,651 @@
+use std::fmt::Debug;
+
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, SqlitePool};
+use uuid::Uuid;
+
+/// État d'un job du pipeline d'automatisation ou de l'une de ses étapes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+  Pending,
+  Running,
+  Completed,
+  Failed,
+  /// annulation demandée pendant une étape : le worker arrête le job à la fin de l'étape
+  Cancelling,
+  Cancelled,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Job {
+  pub job_id: String,
+  pub schema_name: String,
+  pub project_id: Option<String>,
+  pub status: JobStatus,
+  pub completed_steps: i64,
+  pub total_steps: i64,
+  pub error: Option<String>,
+  pub attempts: i64,
+  /// date figée à la création du job, pour qu'une reprise retrouve les mêmes fichiers
+  pub run_date: String,
+  pub created_at: NaiveDateTime,
+  pub updated_at: NaiveDateTime,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct JobStep {
+  pub job_id: String,
+  pub step: i64,
+  pub name: String,
+  pub status: JobStatus,
+  pub error: Option<String>,
+  pub started_at: Option<NaiveDateTime>,
+  pub finished_at: Option<NaiveDateTime>,
+}
+
+#[tracing::instrument]
+pub async fn create_job<S>(
+  pool: S,
+  schema_name: &str,
+  project_id: Option<String>,
+  run_date: &str,
+  steps: &[&str],
+) -> sqlx::Result<Job>
+where
+  S: AsRef<SqlitePool> + Debug,
+{
+  let job_id = Uuid::new_v4().to_hyphenated().to_string();
+  let mut transaction = pool.as_ref().begin().await?;
+
+  let total_steps = steps.len() as i64;
+  sqlx::query!(
+    r#"
+INSERT INTO job (job_id, schema_name, project_id, total_steps, run_date)
+VALUES (?1, ?2, ?3, ?4, ?5)
+    "#,
+    job_id,
+    schema_name,
+    project_id,
+    total_steps,
+    run_date
+  )
+  .execute(&mut transaction)
+  .await?;
+
+  for (index, name) in steps.iter().enumerate() {
+    let step = index as i64 + 1;
+    sqlx::query!(
+      r#"INSERT INTO job_step (job_id, step, name) VALUES (?1, ?2, ?3)"#,
+      job_id,
+      step,
+      name
+    )
+    .execute(&mut transaction)
+    .await?;
+  }
+
+  transaction.commit().await?;
+
+  job(pool, &job_id).await?.ok_or(sqlx::Error::RowNotFound)
+}
+
+#[tracing::instrument]
+pub async fn jobs<S>(pool: S) -> sqlx::Result<Vec<Job>>
+where
+  S: AsRef<SqlitePool> + Debug,
+{
+  sqlx::query_as!(
+    Job,
+    r#"
+SELECT job_id, schema_name, project_id,
+    status AS "status: JobStatus",
+    completed_steps, total_steps, error, attempts, run_date,
+    created_at AS "created_at: NaiveDateTime",
+    updated_at AS "updated_at: NaiveDateTime"
+FROM job
+ORDER BY created_at DESC, rowid DESC
+    "#
+  )
+  .fetch_all(pool.as_ref())
+  .await
+}
+
+#[tracing::instrument]
+pub async fn job<S>(pool: S, job_id: &str) -> sqlx::Result<Option<Job>>
+where
+  S: AsRef<SqlitePool> + Debug,
+{
+  sqlx::query_as!(
+    Job,
+    r#"
+SELECT job_id, schema_name, project_id,
+    status AS "status: JobStatus",
+    completed_steps, total_steps, error, attempts, run_date,
+    created_at AS "created_at: NaiveDateTime",
+    updated_at AS "updated_at: NaiveDateTime"
+FROM job
+WHERE job_id = ?1
+    "#,
+    job_id
+  )
+  .fetch_optional(pool.as_ref())
+  .await
+}
+
+#[tracing::instrument]
+pub async fn job_steps<S>(pool: S, job_id: &str) -> sqlx::Result<Vec<JobStep>>
+where
+  S: AsRef<SqlitePool> + Debug,
+{
+  sqlx::query_as!(
+    JobStep,
+    r#"
+SELECT job_id, step, name,
+    status AS "status: JobStatus",
+    error,
+    started_at AS "started_at: NaiveDateTime",
+    finished_at AS "finished_at: NaiveDateTime"
+FROM job_step
+WHERE job_id = ?1
+ORDER BY step
+    "#,
+    job_id
+  )
+  .fetch_all(pool.as_ref())
+  .await
+}
+
+/// Passe le plus ancien job en attente à l'état `running` et le renvoie ; un job dont le
+/// schéma est déjà traité par un autre job attend la fin de celui-ci, les deux écriraient
+/// dans le même index
+#[tracing::instrument]
+pub async fn claim_next_job<S>(pool: S) -> sqlx::Result<Option<Job>>
+where
+  S: AsRef<SqlitePool> + Debug,
+{
+  let mut transaction = pool.as_ref().begin().await?;
+
+  let next = sqlx::query_scalar!(
+    r#"
+SELECT job_id FROM job
+WHERE status = 'pending'
+  AND schema_name NOT IN (SELECT schema_name FROM job WHERE status IN ('running', 'cancelling'))
+ORDER BY created_at, rowid
+LIMIT 1
+    "#
+  )
+  .fetch_optional(&mut transaction)
+  .await?;
+
+  let job_id = match next {
+    Some(job_id) => job_id,
+    None => return Ok(None),
+  };
+
+  let claimed = sqlx::query!(
+    r#"
+UPDATE job SET status = 'running', attempts = attempts + 1, updated_at = CURRENT_TIMESTAMP
+WHERE job_id = ?1 AND status = 'pending'
+  AND schema_name NOT IN (SELECT schema_name FROM job WHERE status IN ('running', 'cancelling'))
+    "#,
+    job_id
+  )
+  .execute(&mut transaction)
+  .await?
+  .rows_affected();
+
+  transaction.commit().await?;
+
+  if claimed == 0 {
+    return Ok(None);
+  }
+
+  job(pool, &job_id).await
+}
+
+#[tracing::instrument]
+pub async fn start_job_step<S>(pool: S, job_id: &str, step: i64) -> sqlx::Result<()>
+where
+  S: AsRef<SqlitePool> + Debug,
+{
+  sqlx::query!(
+    r#"
+UPDATE job_step SET status = 'running', error = NULL, started_at = CURRENT_TIMESTAMP, finished_at = NULL
+WHERE job_id = ?1 AND step = ?2
+    "#,
+    job_id,
+    step
+  )
+  .execute(pool.as_ref())
+  .await?;
+
+  Ok(())
+}
+
+/// Enregistre la fin de l'étape `step` d'un job en cours ; sans effet sur un job annulé
+/// entre-temps, dont l'étape sera refaite à la relance
+#[tracing::instrument]
+pub async fn complete_job_step<S>(pool: S, job_id: &str, step: i64) -> sqlx::Result<()>
+where
+  S: AsRef<SqlitePool> + Debug,
+{
+  let mut transaction = pool.as_ref().begin().await?;
+
+  sqlx::query!(
+    r#"
+UPDATE job_step SET status = 'completed', finished_at = CURRENT_TIMESTAMP
+WHERE job_id = ?1 AND step = ?2
+  AND job_id IN (SELECT job_id FROM job WHERE status = 'running')
+    "#,
+    job_id,
+    step
+  )
+  .execute(&mut transaction)
+  .await?;
+
+  sqlx::query!(
+    r#"
+UPDATE job SET completed_steps = ?2, updated_at = CURRENT_TIMESTAMP
+WHERE job_id = ?1 AND status = 'running'
+    "#,
+    job_id,
+    step
+  )
+  .execute(&mut transaction)
+  .await?;
+
+  transaction.commit().await
+}
+
+/// Marque le job en cours en échec, ainsi que l'étape `step` si elle est connue ; sans effet
+/// sur un job annulé entre-temps
+#[tracing::instrument]
+pub async fn fail_job<S>(pool: S, job_id: &str, step: Option<i64>, error: &str) -> sqlx::Result<()>
+where
+  S: AsRef<SqlitePool> + Debug,
+{
+  let mut transaction = pool.as_ref().begin().await?;
+
+  if let Some(step) = step {
+    sqlx::query!(
+      r#"
+UPDATE job_step SET status = 'failed', error = ?3, finished_at = CURRENT_TIMESTAMP
+WHERE job_id = ?1 AND step = ?2
+  AND job_id IN (SELECT job_id FROM job WHERE status = 'running')
+      "#,
+      job_id,
+      step,
+      error
+    )
+    .execute(&mut transaction)
+    .await?;
+  }
+
+  sqlx::query!(
+    r#"
+UPDATE job SET status = 'failed', error = ?2, updated_at = CURRENT_TIMESTAMP
+WHERE job_id = ?1 AND status = 'running'
+    "#,
+    job_id,
+    error
+  )
+  .execute(&mut transaction)
+  .await?;
+
+  transaction.commit().await
+}
+
+#[tracing::instrument]
+pub async fn complete_job<S>(pool: S, job_id: &str) -> sqlx::Result<()>
+where
+  S: AsRef<SqlitePool> + Debug,
+{
+  sqlx::query!(
+    r#"
+UPDATE job SET status = 'completed', error = NULL, updated_at = CURRENT_TIMESTAMP
+WHERE job_id = ?1 AND status = 'running'
+    "#,
+    job_id
+  )
+  .execute(pool.as_ref())
+  .await?;
+
+  Ok(())
+}
+
+/// Annule un job en attente ou en cours ; renvoie `false` si le job n'est pas annulable.
+/// Un job en cours passe à `cancelling` : son worker l'arrête à la fin de l'étape en train
+/// de s'exécuter (voir [`acknowledge_cancel`]), il n'est relançable qu'ensuite.
+#[tracing::instrument]
+pub async fn cancel_job<S>(pool: S, job_id: &str) -> sqlx::Result<bool>
+where
+  S: AsRef<SqlitePool> + Debug,
+{
+  let mut transaction = pool.as_ref().begin().await?;
+
+  let cancelled = sqlx::query!(
+    r#"
+UPDATE job
+SET status = CASE status WHEN 'running' THEN 'cancelling' ELSE 'cancelled' END,
+    updated_at = CURRENT_TIMESTAMP
+WHERE job_id = ?1 AND status IN ('pending', 'running')
+    "#,
+    job_id
+  )
+  .execute(&mut transaction)
+  .await?
+  .rows_affected();
+
+  sqlx::query!(
+    r#"UPDATE job_step SET status = 'cancelled' WHERE job_id = ?1 AND status = 'pending'"#,
+    job_id
+  )
+  .execute(&mut transaction)
+  .await?;
+
+  transaction.commit().await?;
+
+  Ok(cancelled > 0)
+}
+
+/// Arrête un job en cours d'annulation, à la fin d'une étape : le job et ses étapes
+/// inachevées passent à `cancelled`. Renvoie `false` si le job n'était pas en annulation.
+#[tracing::instrument]
+pub async fn acknowledge_cancel<S>(pool: S, job_id: &str) -> sqlx::Result<bool>
+where
+  S: AsRef<SqlitePool> + Debug,
+{
+  let mut transaction = pool.as_ref().begin().await?;
+
+  let cancelled = sqlx::query!(
+    r#"
+UPDATE job SET status = 'cancelled', updated_at = CURRENT_TIMESTAMP
+WHERE job_id = ?1 AND status = 'cancelling'
+    "#,
+    job_id
+  )
+  .execute(&mut transaction)
+  .await?
+  .rows_affected();
+
+  if cancelled > 0 {
+    sqlx::query!(
+      r#"
+UPDATE job_step SET status = 'cancelled', finished_at = CURRENT_TIMESTAMP
+WHERE job_id = ?1 AND status IN ('pending', 'running')
+      "#,
+      job_id
+    )
+    .execute(&mut transaction)
+    .await?;
+  }
+
+  transaction.commit().await?;
+
+  Ok(cancelled > 0)
+}
+
+/// Remet en attente un job en échec ou annulé ; il reprendra après sa dernière étape terminée.
+/// Renvoie `false` si le job n'est pas relançable.
+#[tracing::instrument]
+pub async fn retry_job<S>(pool: S, job_id: &str) -> sqlx::Result<bool>
+where
+  S: AsRef<SqlitePool> + Debug,
+{
+  let mut transaction = pool.as_ref().begin().await?;
+
+  let retried = sqlx::query!(
+    r#"
+UPDATE job SET status = 'pending', error = NULL, updated_at = CURRENT_TIMESTAMP
+WHERE job_id = ?1 AND status IN ('failed', 'cancelled')
+    "#,
+    job_id
+  )
+  .execute(&mut transaction)
+  .await?
+  .rows_affected();
+
+  if retried > 0 {
+    sqlx::query!(
+      r#"
+UPDATE job_step SET status = 'pending', error = NULL, started_at = NULL, finished_at = NULL
+WHERE job_id = ?1 AND status <> 'completed'
+      "#,
+      job_id
+    )
+    .execute(&mut transaction)
+    .await?;
+  }
+
+  transaction.commit().await?;
+
+  Ok(retried > 0)
+}
+
+/// Au démarrage du serveur, les jobs restés `running` ont été interrompus : ils repassent en
+/// attente pour reprendre après leur dernière étape terminée. Ceux qui étaient en cours
+/// d'annulation sont annulés.
+#[tracing::instrument]
+pub async fn requeue_interrupted_jobs<S>(pool: S) -> sqlx::Result<u64>
+where
+  S: AsRef<SqlitePool> + Debug,
+{
+  let mut transaction = pool.as_ref().begin().await?;
+
+  sqlx::query!(
+    r#"
+UPDATE job_step SET status = 'cancelled', finished_at = CURRENT_TIMESTAMP
+WHERE status IN ('pending', 'running')
+  AND job_id IN (SELECT job_id FROM job WHERE status = 'cancelling')
+    "#
+  )
+  .execute(&mut transaction)
+  .await?;
+
+  sqlx::query!(
+    r#"UPDATE job SET status = 'cancelled', updated_at = CURRENT_TIMESTAMP WHERE status = 'cancelling'"#
+  )
+  .execute(&mut transaction)
+  .await?;
+
+  sqlx::query!(
+    r#"
+UPDATE job_step SET status = 'pending', started_at = NULL
+WHERE status = 'running' AND job_id IN (SELECT job_id FROM job WHERE status = 'running')
+    "#
+  )
+  .execute(&mut transaction)
+  .await?;
+
+  let requeued = sqlx::query!(
+    r#"UPDATE job SET status = 'pending', updated_at = CURRENT_TIMESTAMP WHERE status = 'running'"#
+  )
+  .execute(&mut transaction)
+  .await?
+  .rows_affected();
+
+  transaction.commit().await?;
+
+  Ok(requeued)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_database;
+
+  const STEPS: [&str; 2] = ["Export", "Ingestion"];
+
+  #[tokio::test]
+  async fn test_claim_runs_jobs_in_order() {
+    let db = test_database().await;
+    let first = create_job(&db, "public", None, "2022_06_01", &STEPS)
+      .await
+      .unwrap();
+    let second = create_job(&db, "projet", None, "2022_06_01", &STEPS)
+      .await
+      .unwrap();
+    assert_eq!(first.status, JobStatus::Pending);
+    assert_eq!(first.total_steps, 2);
+
+    let claimed = claim_next_job(&db).await.unwrap().unwrap();
+    assert_eq!(claimed.job_id, first.job_id);
+    assert_eq!(claimed.status, JobStatus::Running);
+    assert_eq!(claimed.attempts, 1);
+
+    start_job_step(&db, &claimed.job_id, 1).await.unwrap();
+    complete_job_step(&db, &claimed.job_id, 1).await.unwrap();
+    start_job_step(&db, &claimed.job_id, 2).await.unwrap();
+    complete_job_step(&db, &claimed.job_id, 2).await.unwrap();
+    complete_job(&db, &claimed.job_id).await.unwrap();
+
+    let done = job(&db, &first.job_id).await.unwrap().unwrap();
+    assert_eq!(done.status, JobStatus::Completed);
+    assert_eq!(done.completed_steps, 2);
+    let steps = job_steps(&db, &first.job_id).await.unwrap();
+    assert!(steps.iter().all(|step| step.status == JobStatus::Completed));
+    assert!(steps.iter().all(|step| step.finished_at.is_some()));
+
+    let claimed = claim_next_job(&db).await.unwrap().unwrap();
+    assert_eq!(claimed.job_id, second.job_id);
+    assert!(claim_next_job(&db).await.unwrap().is_none());
+  }
+
+  #[tokio::test]
+  async fn test_claim_one_job_per_schema() {
+    let db = test_database().await;
+    let first = create_job(&db, "public", None, "2022_06_01", &STEPS)
+      .await
+      .unwrap();
+    let second = create_job(&db, "public", None, "2022_06_02", &STEPS)
+      .await
+      .unwrap();
+    let other = create_job(&db, "projet", None, "2022_06_02", &STEPS)
+      .await
+      .unwrap();
+
+    assert_eq!(claim_next_job(&db).await.unwrap().unwrap().job_id, first.job_id);
+    // le second job de `public` attend la fin du premier
+    assert_eq!(claim_next_job(&db).await.unwrap().unwrap().job_id, other.job_id);
+    assert!(claim_next_job(&db).await.unwrap().is_none());
+
+    complete_job(&db, &first.job_id).await.unwrap();
+    assert_eq!(claim_next_job(&db).await.unwrap().unwrap().job_id, second.job_id);
+  }
+
+  #[tokio::test]
+  async fn test_cancel_and_retry() {
+    let db = test_database().await;
+    let pending = create_job(&db, "public", None, "2022_06_01", &STEPS)
+      .await
+      .unwrap();
+
+    assert!(cancel_job(&db, &pending.job_id).await.unwrap());
+    // un job annulé n'est plus ni pris ni annulable
+    assert!(claim_next_job(&db).await.unwrap().is_none());
+    assert!(!cancel_job(&db, &pending.job_id).await.unwrap());
+    let steps = job_steps(&db, &pending.job_id).await.unwrap();
+    assert!(steps.iter().all(|step| step.status == JobStatus::Cancelled));
+
+    assert!(retry_job(&db, &pending.job_id).await.unwrap());
+    assert!(!retry_job(&db, &pending.job_id).await.unwrap());
+    let steps = job_steps(&db, &pending.job_id).await.unwrap();
+    assert!(steps.iter().all(|step| step.status == JobStatus::Pending));
+    let claimed = claim_next_job(&db).await.unwrap().unwrap();
+    assert_eq!(claimed.job_id, pending.job_id);
+    assert_eq!(claimed.attempts, 1);
+  }
+
+  #[tokio::test]
+  async fn test_cancel_running_job() {
+    let db = test_database().await;
+    let created = create_job(&db, "public", None, "2022_06_01", &STEPS)
+      .await
+      .unwrap();
+    let claimed = claim_next_job(&db).await.unwrap().unwrap();
+    start_job_step(&db, &claimed.job_id, 1).await.unwrap();
+
+    // le worker est encore dans l'étape 1 : ni relance, ni nouveau job sur le schéma
+    assert!(cancel_job(&db, &created.job_id).await.unwrap());
+    let cancelling = job(&db, &created.job_id).await.unwrap().unwrap();
+    assert_eq!(cancelling.status, JobStatus::Cancelling);
+    assert!(!retry_job(&db, &created.job_id).await.unwrap());
+    create_job(&db, "public", None, "2022_06_02", &STEPS)
+      .await
+      .unwrap();
+    assert!(claim_next_job(&db).await.unwrap().is_none());
+
+    // la fin ou l'échec de l'étape ne change plus l'état du job
+    complete_job_step(&db, &created.job_id, 1).await.unwrap();
+    fail_job(&db, &created.job_id, Some(1), "trop tard")
+      .await
+      .unwrap();
+    let cancelling = job(&db, &created.job_id).await.unwrap().unwrap();
+    assert_eq!(cancelling.status, JobStatus::Cancelling);
+    assert_eq!(cancelling.completed_steps, 0);
+
+    assert!(acknowledge_cancel(&db, &created.job_id).await.unwrap());
+    let cancelled = job(&db, &created.job_id).await.unwrap().unwrap();
+    assert_eq!(cancelled.status, JobStatus::Cancelled);
+    let steps = job_steps(&db, &created.job_id).await.unwrap();
+    assert!(steps.iter().all(|step| step.status == JobStatus::Cancelled));
+
+    assert!(retry_job(&db, &created.job_id).await.unwrap());
+    assert_eq!(claim_next_job(&db).await.unwrap().unwrap().job_id, created.job_id);
+  }
+
+  #[tokio::test]
+  async fn test_retry_resumes_after_last_completed_step() {
+    let db = test_database().await;
+    let created = create_job(&db, "public", None, "2022_06_01", &STEPS)
+      .await
+      .unwrap();
+    let claimed = claim_next_job(&db).await.unwrap().unwrap();
+    start_job_step(&db, &claimed.job_id, 1).await.unwrap();
+    complete_job_step(&db, &claimed.job_id, 1).await.unwrap();
+    start_job_step(&db, &claimed.job_id, 2).await.unwrap();
+    fail_job(&db, &claimed.job_id, Some(2), "index introuvable")
+      .await
+      .unwrap();
+
+    let failed = job(&db, &created.job_id).await.unwrap().unwrap();
+    assert_eq!(failed.status, JobStatus::Failed);
+    assert_eq!(failed.error.as_deref(), Some("index introuvable"));
+
+    assert!(retry_job(&db, &created.job_id).await.unwrap());
+    let retried = claim_next_job(&db).await.unwrap().unwrap();
+    assert_eq!(retried.completed_steps, 1);
+    assert_eq!(retried.attempts, 2);
+    assert_eq!(retried.error, None);
+    let steps = job_steps(&db, &created.job_id).await.unwrap();
+    assert_eq!(steps[0].status, JobStatus::Completed);
+    assert_eq!(steps[1].status, JobStatus::Pending);
+    assert_eq!(steps[1].error, None);
+  }
+
+  #[tokio::test]
+  async fn test_requeue_interrupted_jobs() {
+    let db = test_database().await;
+    let created = create_job(&db, "public", None, "2022_06_01", &STEPS)
+      .await
+      .unwrap();
+    let claimed = claim_next_job(&db).await.unwrap().unwrap();
+    start_job_step(&db, &claimed.job_id, 1).await.unwrap();
+    let cancelling = create_job(&db, "projet", None, "2022_06_01", &STEPS)
+      .await
+      .unwrap();
+    claim_next_job(&db).await.unwrap().unwrap();
+    cancel_job(&db, &cancelling.job_id).await.unwrap();
+
+    assert_eq!(requeue_interrupted_jobs(&db).await.unwrap(), 1);
+    let cancelled = job(&db, &cancelling.job_id).await.unwrap().unwrap();
+    assert_eq!(cancelled.status, JobStatus::Cancelled);
+    let requeued = job(&db, &created.job_id).await.unwrap().unwrap();
+    assert_eq!(requeued.status, JobStatus::Pending);
+    let steps = job_steps(&db, &created.job_id).await.unwrap();
+    assert_eq!(steps[0].status, JobStatus::Pending);
+  }
+}
@@ -7,10 +7,16 @@ use serde::{Deserialize, Serialize};
 use sqlx::{types::Json, Decode, SqlitePool};
 use uuid::adapter::Hyphenated;
 
-pub use fts::Bloc;
+pub use fts::{Bloc, StudyFilters};
 
+pub use alert::*;
+pub use job::*;
 pub use migration::*;
+#[cfg(test)]
+use migration::test_database;
 
+mod alert;
+mod job;
 mod migration;
 
 #[derive(Debug, Clone)]
@@ -45,6 +51,7 @@ where
             hashtag_list AS "hashtag_list: Json<HashSet<HashtagWithCount>>",
             exclude_hashtag_list AS "exclude_hashtag_list: Json<HashSet<HashtagWithCount>>",
             request_params AS "request_params: Json<Vec<Vec<Bloc>>>",
+            filters AS "filters: Json<StudyFilters>",
             is_analyzed
         FROM project
         WHERE user_id = ?1
@@ -72,6 +79,7 @@ where
             hashtag_list AS "hashtag_list: Json<HashSet<HashtagWithCount>>",
             exclude_hashtag_list AS "exclude_hashtag_list: Json<HashSet<HashtagWithCount>>",
             request_params AS "request_params: Json<Vec<Vec<Bloc>>>",
+            filters AS "filters: Json<StudyFilters>",
             is_analyzed
         FROM project
         WHERE project_id = ?1 AND user_id = ?2"#,
@@ -166,6 +174,36 @@ where
   Ok(())
 }
 
+#[tracing::instrument]
+pub async fn update_project_filters<S>(
+  pool: S,
+  project_id: Hyphenated,
+  user_id: &String,
+  filters: StudyFilters,
+) -> sqlx::Result<()>
+where
+  S: AsRef<SqlitePool> + Debug,
+{
+  let today = chrono::offset::Local::now().format("%Y-%m-%d").to_string();
+
+  let json_filters = Json(filters);
+
+  sqlx::query!(
+    r#"
+        UPDATE project SET filters = ?1,
+            updated_at = ?2
+        WHERE project_id = ?3 AND user_id = ?4"#,
+    json_filters,
+    today,
+    project_id,
+    user_id
+  )
+  .execute(pool.as_ref())
+  .await?;
+
+  Ok(())
+}
+
 #[tracing::instrument]
 pub async fn create_project<S>(pool: S, project: Project) -> sqlx::Result<()>
 where
@@ -291,9 +329,9 @@ where
   sqlx::query!(
     r#"
       INSERT INTO project(project_id, title, user_id, event_count, tweet_count, start_date, 
-        end_date, is_custom_date, hashtag_list, exclude_hashtag_list) 
+        end_date, is_custom_date, hashtag_list, exclude_hashtag_list, filters) 
       SELECT ?1, ?2, ?4, event_count, tweet_count, start_date, end_date, 
-        is_custom_date, hashtag_list, exclude_hashtag_list
+        is_custom_date, hashtag_list, exclude_hashtag_list, filters
       FROM project
       WHERE project_id = ?3 AND user_id = ?4"#,
     new_project_id,
@@ -918,6 +956,8 @@ pub async fn save_chart<S>(
   project_id: String,
   title: String,
   tab: String,
+  granularity: String,
+  timezone: String,
   chart: impl Serialize + Debug,
 ) -> sqlx::Result<()>
 where
@@ -931,12 +971,14 @@ where
 
   sqlx::query!(
     r#"
-INSERT OR REPLACE INTO chart (project_id, title, tab, json, date)
-VALUES ($1, $2, $3, $4, $5)
+INSERT OR REPLACE INTO chart (project_id, title, tab, granularity, timezone, json, date)
+VALUES ($1, $2, $3, $4, $5, $6, $7)
     "#,
     project_id,
     title,
     tab,
+    granularity,
+    timezone,
     json,
     now,
   )
@@ -952,17 +994,22 @@ pub async fn get_chart<S>(
   project_id: &String,
   title: &String,
   tab: &String,
+  granularity: &String,
+  timezone: &String,
 ) -> sqlx::Result<String>
 where
   S: AsRef<SqlitePool> + Debug,
 {
   let data = sqlx::query!(
     r#"
-SELECT json FROM chart WHERE project_id = $1 AND title = $2 AND tab = $3
+SELECT json FROM chart
+WHERE project_id = $1 AND title = $2 AND tab = $3 AND granularity = $4 AND timezone = $5
 "#,
     project_id,
     title,
     tab,
+    granularity,
+    timezone,
   )
   .fetch_one(pool.as_ref())
   .await?;
@@ -1022,6 +1069,7 @@ pub struct Project {
   pub hashtag_list: Json<HashSet<HashtagWithCount>>,
   pub exclude_hashtag_list: Json<HashSet<HashtagWithCount>>,
   pub request_params: Json<Vec<Vec<Bloc>>>,
+  pub filters: Json<StudyFilters>,
   pub is_analyzed: i64,
 }
 
@@ -1041,6 +1089,7 @@ impl Default for Project {
       hashtag_list: Json::default(),
       exclude_hashtag_list: Json::default(),
       request_params: Json::default(),
+      filters: Json::default(),
       is_analyzed: Default::default(),
     }
   }
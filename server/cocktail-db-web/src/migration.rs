@@ -24,3 +24,16 @@ pub async fn create_database(database_path: &PathBuf) {
 pub async fn migrate(pool: SqlitePool) -> Result<(), MigrateError> {
   sqlx::migrate!("./migrations").run(&pool).await
 }
+
+/// Base en mémoire migrée, pour les tests ; une seule connexion pour que toutes les
+/// requêtes voient la même base
+#[cfg(test)]
+pub(crate) async fn test_database() -> crate::WebDatabase {
+  let pool = sqlx::sqlite::SqlitePoolOptions::new()
+    .max_connections(1)
+    .connect("sqlite::memory:")
+    .await
+    .unwrap();
+  migrate(pool.clone()).await.unwrap();
+  crate::WebDatabase::new(pool)
+}
@@ -1,11 +1,20 @@
-use std::{fs::File, path::PathBuf, process::Command, str::FromStr};
+use std::{
+  fs::File,
+  path::{Path, PathBuf},
+  process::Command,
+  str::FromStr,
+};
 
 use error::GraphError;
-use fts::{DocAddress, Index};
 use serde::{Deserialize, Serialize};
 use sqlx::{postgres::PgPoolOptions, types::chrono::NaiveDateTime, FromRow, PgPool};
 
 pub mod error;
+mod native;
+pub mod network;
+
+/// Taille des paquets d'identifiants de tweets envoyés à `COPY`, en octets
+const COPY_CHUNK_SIZE: usize = 1 << 20;
 
 #[derive(Debug, Deserialize, Default, FromRow)]
 pub struct JsonDataGraph {
@@ -24,17 +33,58 @@ pub struct Status {
   pub status: String,
 }
 
+/// Ligne de la table `status`, avec les paramètres du graphe qu'elle concerne
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, FromRow)]
+pub struct GraphStatus {
+  pub datetime: NaiveDateTime,
+  pub status: String,
+  pub graph_name: String,
+  pub community: String,
+  pub centrality: String,
+  pub max_rank: i32,
+  pub show_interaction: bool,
+}
+
 #[derive(Debug, Deserialize, Serialize, FromRow)]
 pub struct Graph {
   pub modularity: f64,
 }
 
+/// Historique de la table `status` du schéma `schema`, tous graphes confondus, du plus ancien
+/// au plus récent
+#[tracing::instrument(skip(pool))]
+pub async fn graph_status_history(pool: &PgPool, schema: &str) -> Result<Vec<GraphStatus>, GraphError> {
+  let exists = sqlx::query_as::<_, TableStatus>(&format!(
+    r#"SELECT EXISTS (
+      SELECT FROM information_schema.tables
+      WHERE  table_schema = '{schema}'
+      AND    table_name   = 'status'
+      );"#,
+  ))
+  .fetch_one(pool)
+  .await?
+  .exists;
+
+  if !exists {
+    return Ok(vec![]);
+  }
+
+  let res = sqlx::query_as::<_, GraphStatus>(&format!(
+    r#"SELECT datetime, status, graph_name, community, centrality, max_rank, show_interaction
+    FROM "{schema}".status ORDER BY datetime"#,
+  ))
+  .fetch_all(pool)
+  .await?;
+
+  Ok(res)
+}
+
 #[derive(Debug)]
 pub struct GraphGenerator {
   database_url: String,
   schema: String,
-  r_script: PathBuf,
-  python_script: PathBuf,
+  r_script: Option<PathBuf>,
+  python_script: Option<PathBuf>,
   graph_name: String,
   community: String,
   centrality: String,
@@ -46,8 +96,8 @@ impl GraphGenerator {
   pub fn new<P: AsRef<str>>(
     database_url: String,
     schema: P,
-    r_script: PathBuf,
-    python_script: PathBuf,
+    r_script: Option<PathBuf>,
+    python_script: Option<PathBuf>,
     graph_name: String,
     community: String,
     centrality: String,
@@ -78,7 +128,7 @@ impl GraphGenerator {
     }
     self.set_status_started(&pool).await?;
 
-    self.create_viz().await?;
+    self.create_viz(&pool).await?;
 
     pool.close().await;
 
@@ -95,9 +145,9 @@ impl GraphGenerator {
 
     let index = fts::retrieve_index(&directory_path)?;
 
-    let docs = fts::search_for_communities(&index)?;
+    let mut tweets = fts::all_tweets(&index)?.peekable();
 
-    if docs.0.is_empty() {
+    if tweets.peek().is_none() {
       return Err(GraphError::Searcher("aucun document".to_string()));
     }
 
@@ -105,13 +155,13 @@ impl GraphGenerator {
     self.create_schema(&pool).await?;
     self.set_status_started(&pool).await?;
     self.create_tmp_table(&pool).await?;
-    self.copy_tweet_ids_to_table(&index, &pool, docs.0).await?;
+    self.copy_tweet_ids_to_table(&pool, tweets).await?;
     self
       .create_schema_and_stuff_for_the_current_search_query(&pool)
       .await?;
     self.create_graph_db(&pool).await?;
-    self.create_data_graph().await?;
-    let json_data = self.create_viz().await?;
+    self.create_data_graph(&pool).await?;
+    let json_data = self.create_viz(&pool).await?;
 
     pool.close().await;
 
@@ -119,36 +169,10 @@ impl GraphGenerator {
   }
 
   #[tracing::instrument(skip_all)]
-  async fn copy_tweet_ids_to_table(
-    &self,
-    index: &Index,
-    pool: &PgPool,
-    docs: Vec<(f32, DocAddress)>,
-  ) -> Result<(), GraphError> {
-    let schema = index.schema();
-    let id = schema.get_field("id").unwrap(); // unwrap car **on sait** que id est un champ dans l'index
-    let searcher = index
-      .reader()
-      .map_err(|e| GraphError::Searcher(format!("Searcher: {e:?}")))?
-      .searcher();
-
-    let v: String = docs
-      .iter()
-      .flat_map(|(_score, doc_address)| {
-        if let Ok(retrieved_doc) = searcher.doc(*doc_address) {
-          retrieved_doc
-            .get_first(id)
-            .and_then(|i| i.as_text())
-            .map(|s| s.to_string())
-        } else {
-          None
-        }
-
-        // format!("{}\n", iii.unwrap().as_text().unwrap_or_default())
-      })
-      .collect::<Vec<_>>()
-      .join("\n");
-
+  async fn copy_tweet_ids_to_table<I>(&self, pool: &PgPool, tweets: I) -> Result<(), GraphError>
+  where
+    I: Iterator<Item = Result<fts::Tweet, fts::SearchError>>,
+  {
     let mut copy = pool
       .copy_in_raw(&format!(
         r#"COPY "{}".searched_tweet_id FROM STDIN WITH (FORMAT CSV)"#,
@@ -156,7 +180,18 @@ impl GraphGenerator {
       ))
       .await
       .unwrap(); // TODO
-    copy.send(v.as_bytes()).await.unwrap();
+
+    // identifiants envoyés par paquets, au fil de la lecture de l'index
+    let mut ids = String::new();
+    for tweet in tweets {
+      ids.push_str(&tweet?.id);
+      ids.push('\n');
+      if ids.len() >= COPY_CHUNK_SIZE {
+        copy.send(ids.as_bytes()).await.unwrap();
+        ids.clear();
+      }
+    }
+    copy.send(ids.as_bytes()).await.unwrap();
     let rows = copy.finish().await?;
 
     dbg!(rows);
@@ -596,8 +631,16 @@ CREATE TABLE IF NOT EXISTS "{schema}".link (
     Ok(())
   }
 
-  #[tracing::instrument]
-  async fn create_data_graph(&self) -> Result<(), GraphError> {
+  /// Sans script R configuré, les graphes sont construits en Rust (voir `native`).
+  #[tracing::instrument(skip(pool))]
+  async fn create_data_graph(&self, pool: &PgPool) -> Result<(), GraphError> {
+    match &self.r_script {
+      Some(r_script) => self.run_r_script(r_script),
+      None => self.build_graphs(pool).await,
+    }
+  }
+
+  fn run_r_script(&self, r_script: &Path) -> Result<(), GraphError> {
     let log = File::create(format!("./log/{}.log", &self.schema)).expect("failed to open log");
     let log_error =
       File::create(format!("./log/{}-error.log", &self.schema)).expect("failed to open log");
@@ -606,7 +649,7 @@ CREATE TABLE IF NOT EXISTS "{schema}".link (
     let spawned = Command::new("Rscript")
       .stderr(log_error)
       .stdout(log)
-      .args(&[self.r_script.to_str().unwrap_or_default(), &self.schema])
+      .args(&[r_script.to_str().unwrap_or_default(), &self.schema])
       .spawn()?;
 
     // en mode docker pour mac / windows qu'on pourrait surement faire par env je ne sais pas comment
@@ -629,8 +672,16 @@ CREATE TABLE IF NOT EXISTS "{schema}".link (
     }
   }
 
-  #[tracing::instrument]
-  async fn create_viz(&self) -> Result<JsonDataGraph, GraphError> {
+  /// Sans script Python configuré, la visualisation est calculée en Rust (voir `native`).
+  #[tracing::instrument(skip(pool))]
+  async fn create_viz(&self, pool: &PgPool) -> Result<JsonDataGraph, GraphError> {
+    match &self.python_script {
+      Some(python_script) => self.run_python_script(python_script),
+      None => self.build_viz(pool).await,
+    }
+  }
+
+  fn run_python_script(&self, python_script: &Path) -> Result<JsonDataGraph, GraphError> {
     let log = File::options()
       .append(true)
       .open(format!("./log/{}.log", &self.schema))
@@ -644,7 +695,7 @@ CREATE TABLE IF NOT EXISTS "{schema}".link (
       .stderr(log_error)
       .stdout(log)
       .args(&[
-        self.python_script.to_str().unwrap_or_default(),
+        python_script.to_str().unwrap_or_default(),
         &self.schema,
         &self.graph_name,
         &self.community,
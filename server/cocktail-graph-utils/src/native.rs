@@ -0,0 +1,816 @@
+//! Construction des graphes, calcul des communautés / centralités et préparation de la
+//! visualisation directement en Rust.
+//!
+//! Reprend à l'identique ce que faisaient `scripts-ub/create-data-graph.R` et
+//! `scripts-ub/viz.py` : mêmes tables (`graph`, `node`, `node_attribute`, `link`, `vis`),
+//! mêmes noms de graphes et d'attributs.
+use std::collections::HashMap;
+
+use serde_json::{json, Value};
+use sqlx::{FromRow, PgPool};
+
+use crate::{
+  error::GraphError,
+  network::{self, Mode, Network},
+  GraphGenerator, JsonDataGraph,
+};
+
+const MODULARITY_THRESHOLD: f64 = 0.5;
+const LAYOUT_ITERATIONS: usize = 50;
+
+struct GraphDefinition {
+  name: &'static str,
+  description: &'static str,
+  links_query: &'static str,
+  directed: bool,
+}
+
+const GRAPHS: [GraphDefinition; 5] = [
+  GraphDefinition {
+    name: "user_user_retweet",
+    description: "Graphe dont les noeuds sont les users,
+et le liens sont les retweets qu'un user fait des tweets d'un autre user.
+Le poids des liens représente le nombre de retweets.",
+    links_query: r#"SELECT t1.user_id AS node_out, t2.user_id AS node_in, COUNT(*) AS weight
+    FROM "{schema}".tweet t1
+        INNER JOIN "{schema}".retweet rt ON t1.id = rt.tweet_id
+        INNER JOIN "{schema}".tweet t2 ON t2.id = rt.retweeted_tweet_id
+    WHERE t1.user_id <> t2.user_id
+    GROUP BY node_out, node_in
+    HAVING COUNT(*) > 5"#,
+    directed: true,
+  },
+  GraphDefinition {
+    name: "user_user_mention",
+    description: "Graphe dont les noeuds sont les users,
+et le liens sont les mentions qu'un user fait d'un autre user.
+Le poids des liens représente le nombre de mentions.",
+    links_query: r#"SELECT t.user_id AS node_out, m.user_id AS node_in, COUNT(*) AS weight
+    FROM "{schema}".tweet t
+        INNER JOIN (SELECT DISTINCT tweet_id, user_id FROM "{schema}".tweet_user_mention) AS m ON t.id = m.tweet_id
+    WHERE t.user_id <> m.user_id
+    GROUP BY node_out, node_in
+    HAVING COUNT(*) > 5"#,
+    directed: true,
+  },
+  GraphDefinition {
+    name: "user_user_quote",
+    description: "Graphe dont les noeuds sont les users,
+et le liens sont les quotes qu'un user fait des tweets d'un autre user.
+Le poids des liens représente le nombre de quotes",
+    links_query: r#"SELECT t1.user_id AS node_out, t2.user_id AS node_in, COUNT(*) AS weight
+    FROM "{schema}".tweet t1
+        INNER JOIN "{schema}".quote q ON t1.id = q.tweet_id
+        INNER JOIN "{schema}".tweet t2 ON t2.id = q.quoted_tweet_id
+    WHERE t1.user_id <> t2.user_id
+    GROUP BY node_out, node_in
+    HAVING COUNT(*) > 5"#,
+    directed: true,
+  },
+  GraphDefinition {
+    name: "hashtag_hashtag",
+    description: "Graphe dont les noeuds sont les hashtags,
+et le liens sont les coocurrences entre les hashtags.
+Le poids des liens représente le nombre de coocurrences.",
+    links_query: r#"SELECT ht1.hashtag AS node_out, ht2.hashtag AS node_in, COUNT(*) AS weight
+    FROM (SELECT DISTINCT tweet_id, hashtag FROM "{schema}".tweet_hashtag) ht1
+        INNER JOIN (SELECT DISTINCT tweet_id, hashtag FROM "{schema}".tweet_hashtag) ht2 ON ht1.tweet_id = ht2.tweet_id AND ht1.hashtag > ht2.hashtag
+    GROUP BY node_out, node_in
+    HAVING COUNT(*) > 5"#,
+    directed: false,
+  },
+  GraphDefinition {
+    name: "user_hashtag",
+    description: "Graphe dont les noeuds sont les users et hashtags,
+et le liens sont les utilisations des hashtags par les users.
+Le poids des liens représente le nombre d'utilisations.",
+    links_query: r#"SELECT '@'||t.user_id AS node_out, '#'||ht.hashtag AS node_in, COUNT(*) AS weight
+    FROM "{schema}".tweet t
+        INNER JOIN (SELECT DISTINCT tweet_id, hashtag FROM "{schema}".tweet_hashtag) ht ON t.id = ht.tweet_id
+    GROUP BY node_out, node_in
+    HAVING COUNT(*) > 5"#,
+    directed: true,
+  },
+];
+
+#[derive(Debug, Clone, Copy)]
+enum CommunityAlgorithm {
+  Louvain,
+  Walktrap,
+}
+
+impl CommunityAlgorithm {
+  fn name(&self) -> &'static str {
+    match self {
+      CommunityAlgorithm::Louvain => "louvain",
+      CommunityAlgorithm::Walktrap => "walktrap",
+    }
+  }
+
+  fn label(&self) -> &'static str {
+    match self {
+      CommunityAlgorithm::Louvain => "Louvain",
+      CommunityAlgorithm::Walktrap => "Walktrap",
+    }
+  }
+
+  fn membership(&self, graph: &Network) -> Vec<usize> {
+    match self {
+      CommunityAlgorithm::Louvain => graph.louvain(),
+      CommunityAlgorithm::Walktrap => graph.walktrap(),
+    }
+  }
+}
+
+#[derive(Debug, FromRow)]
+struct Link {
+  node_out: String,
+  node_in: String,
+  weight: i64,
+}
+
+#[derive(Debug, FromRow)]
+struct VisLink {
+  source: String,
+  target: String,
+  source_community: f64,
+  target_community: f64,
+  source_rank: i64,
+  target_rank: i64,
+  weight: Option<i32>,
+}
+
+impl GraphGenerator {
+  /// Équivalent de `create-data-graph.R` : construit les 5 graphes de l'étude puis calcule leurs
+  /// centralités et communautés.
+  #[tracing::instrument(skip_all)]
+  pub(crate) async fn build_graphs(&self, pool: &PgPool) -> Result<(), GraphError> {
+    let mut graphs = vec![];
+
+    for definition in GRAPHS.iter() {
+      let links = self.insert_graph(pool, definition).await?;
+      if links.is_empty() {
+        tracing::info!("Graphe {} vide", definition.name);
+        continue;
+      }
+
+      let mut names: Vec<String> = links
+        .iter()
+        .flat_map(|(node_out, node_in, _)| [node_out.clone(), node_in.clone()])
+        .collect();
+      names.sort();
+      names.dedup();
+
+      graphs.push((
+        definition.name,
+        Network::new(names, &links, definition.directed),
+      ));
+    }
+
+    for (name, graph) in graphs.iter() {
+      self.compute_centralities(pool, name, graph).await?;
+    }
+
+    for algorithm in [CommunityAlgorithm::Louvain, CommunityAlgorithm::Walktrap] {
+      for (name, graph) in graphs.iter() {
+        self
+          .compute_communities(pool, name, graph, algorithm)
+          .await?;
+      }
+    }
+
+    Ok(())
+  }
+
+  async fn insert_graph(
+    &self,
+    pool: &PgPool,
+    definition: &GraphDefinition,
+  ) -> Result<Vec<(String, String, f64)>, GraphError> {
+    tracing::info!("Construction du graphe {}", definition.name);
+
+    sqlx::query(&format!(
+      r#"INSERT INTO "{schema}".graph (name, description, directed, notebook_url) VALUES ($1, $2, $3, $4)"#,
+      schema = self.schema
+    ))
+    .bind(definition.name)
+    .bind(definition.description)
+    .bind(definition.directed)
+    .bind(format!(
+      "pathnotebook/CommunautesCentralites.ipynb#{}",
+      definition.name
+    ))
+    .execute(pool)
+    .await?;
+
+    let links = sqlx::query_as::<_, Link>(&format!(
+      r#"SELECT node_out::TEXT AS node_out, node_in::TEXT AS node_in, weight::BIGINT AS weight FROM ({}) AS t"#,
+      definition.links_query.replace("{schema}", &self.schema)
+    ))
+    .fetch_all(pool)
+    .await?;
+
+    let mut nodes: Vec<String> = links
+      .iter()
+      .flat_map(|link| [link.node_out.clone(), link.node_in.clone()])
+      .collect();
+    nodes.sort();
+    nodes.dedup();
+
+    sqlx::query(&format!(
+      r#"INSERT INTO "{schema}".node (id, graph_name) SELECT id, $2 FROM UNNEST($1::TEXT[]) AS t(id)"#,
+      schema = self.schema
+    ))
+    .bind(&nodes)
+    .bind(definition.name)
+    .execute(pool)
+    .await?;
+
+    sqlx::query(&format!(
+      r#"INSERT INTO "{schema}".link (node_out, node_in, graph_name, weight)
+      SELECT node_out, node_in, $4, weight FROM UNNEST($1::TEXT[], $2::TEXT[], $3::INTEGER[]) AS t(node_out, node_in, weight)"#,
+      schema = self.schema
+    ))
+    .bind(links.iter().map(|l| l.node_out.clone()).collect::<Vec<_>>())
+    .bind(links.iter().map(|l| l.node_in.clone()).collect::<Vec<_>>())
+    .bind(links.iter().map(|l| l.weight as i32).collect::<Vec<_>>())
+    .bind(definition.name)
+    .execute(pool)
+    .await?;
+
+    Ok(
+      links
+        .into_iter()
+        .map(|l| (l.node_out, l.node_in, l.weight as f64))
+        .collect(),
+    )
+  }
+
+  /// Centralités de degré, HITS, PageRank, intermédiarité et k-core. HITS, PageRank et
+  /// intermédiarité ne sont calculés que sur la plus grande composante connexe.
+  async fn compute_centralities(
+    &self,
+    pool: &PgPool,
+    graph_name: &str,
+    graph: &Network,
+  ) -> Result<(), GraphError> {
+    tracing::info!("Calcul des centralités pour le graphe {}", graph_name);
+
+    for (name, mode) in [
+      ("degree_in_centrality", Mode::In),
+      ("degree_out_centrality", Mode::Out),
+      ("degree_in_out_centrality", Mode::All),
+    ] {
+      self
+        .insert_node_attribute(pool, graph_name, name, &graph.names, &graph.degree(mode))
+        .await?;
+    }
+
+    let largest = graph.largest_component();
+    let (hubs, authorities) = largest.hits();
+    self
+      .insert_node_attribute(
+        pool,
+        graph_name,
+        "hits_authority_centrality",
+        &largest.names,
+        &authorities,
+      )
+      .await?;
+    self
+      .insert_node_attribute(
+        pool,
+        graph_name,
+        "hits_hub_centrality",
+        &largest.names,
+        &hubs,
+      )
+      .await?;
+    self
+      .insert_node_attribute(
+        pool,
+        graph_name,
+        "page_rank_centrality",
+        &largest.names,
+        &largest.page_rank(),
+      )
+      .await?;
+    self
+      .insert_node_attribute(
+        pool,
+        graph_name,
+        "edge_betweenness_centrality",
+        &largest.names,
+        &largest.betweenness(),
+      )
+      .await?;
+
+    for (name, mode) in [
+      ("k_core_in_centrality", Mode::In),
+      ("k_core_out_centrality", Mode::Out),
+      ("k_core_in_out_centrality", Mode::All),
+    ] {
+      self
+        .insert_node_attribute(pool, graph_name, name, &graph.names, &graph.coreness(mode))
+        .await?;
+    }
+
+    Ok(())
+  }
+
+  /// La modularité est toujours enregistrée dans le graphe `{graph}_{algo}_community`. Si elle
+  /// dépasse 0.5, chaque communauté assez grande devient un sous-graphe
+  /// `{graph}_{algo}_community_{n}` avec ses propres centralités, et l'attribut
+  /// `{algo}_community` du graphe parent reçoit le numéro de communauté.
+  async fn compute_communities(
+    &self,
+    pool: &PgPool,
+    graph_name: &str,
+    graph: &Network,
+    algorithm: CommunityAlgorithm,
+  ) -> Result<(), GraphError> {
+    tracing::info!(
+      "Calcul de communautés {} pour le graphe {}",
+      algorithm.label(),
+      graph_name
+    );
+
+    let largest = graph.largest_component();
+
+    sqlx::query(&format!(
+      r#"DELETE FROM "{schema}".graph WHERE graph_parent = $1 AND name LIKE $2"#,
+      schema = self.schema
+    ))
+    .bind(graph_name)
+    .bind(format!("{}_{}_community_%", graph_name, algorithm.name()))
+    .execute(pool)
+    .await?;
+
+    let undirected = largest.to_undirected();
+    let membership = algorithm.membership(&undirected);
+    let modularity = undirected.modularity(&membership);
+    tracing::info!("Modularité de {} : {}", algorithm.label(), modularity);
+
+    sqlx::query(&format!(
+      r#"INSERT INTO "{schema}".graph (name, graph_parent, modularity) VALUES ($1, $2, $3)
+      ON CONFLICT (name) DO UPDATE SET modularity = EXCLUDED.modularity"#,
+      schema = self.schema
+    ))
+    .bind(format!("{}_{}_community", graph_name, algorithm.name()))
+    .bind(graph_name)
+    .bind(modularity)
+    .execute(pool)
+    .await?;
+
+    if modularity <= MODULARITY_THRESHOLD {
+      tracing::info!("La modularité est < 0.5, pas de structure communautaire dans le graphe.");
+      return Ok(());
+    }
+
+    let min_size = (largest.edge_count() as f64 / 2.).sqrt();
+    let mut community_nodes = vec![];
+    let mut community_values = vec![];
+    let mut community_number = 1;
+
+    for community in network::communities(&membership) {
+      if community.len() as f64 <= min_size {
+        continue;
+      }
+
+      let subgraph = largest.induced_subgraph(&community);
+      let subgraph_name = format!(
+        "{}_{}_community_{}",
+        graph_name,
+        algorithm.name(),
+        community_number
+      );
+      self
+        .insert_subgraph(
+          pool,
+          &subgraph_name,
+          &format!(
+            "Sous-graphe représentant la communauté {} obtenue avec {} à partir du graphe {}.",
+            community_number,
+            algorithm.label(),
+            graph_name
+          ),
+          &format!(
+            "/user/jupyter/notebooks/vegan/CommunautesCentralites.ipynb#{}",
+            algorithm.label()
+          ),
+          graph_name,
+          &subgraph.names,
+          modularity,
+        )
+        .await?;
+
+      community_values.extend(std::iter::repeat_n(
+        community_number as f64,
+        subgraph.node_count(),
+      ));
+      community_nodes.extend(subgraph.names.iter().cloned());
+
+      self
+        .compute_centralities(pool, &subgraph_name, &subgraph)
+        .await?;
+
+      community_number += 1;
+    }
+
+    if !community_nodes.is_empty() {
+      self
+        .insert_node_attribute(
+          pool,
+          graph_name,
+          &format!("{}_community", algorithm.name()),
+          &community_nodes,
+          &community_values,
+        )
+        .await?;
+    }
+
+    Ok(())
+  }
+
+  #[allow(clippy::too_many_arguments)]
+  async fn insert_subgraph(
+    &self,
+    pool: &PgPool,
+    graph_name: &str,
+    description: &str,
+    notebook_url: &str,
+    graph_parent: &str,
+    nodes: &[String],
+    modularity: f64,
+  ) -> Result<(), GraphError> {
+    sqlx::query(&format!(
+      r#"INSERT INTO "{schema}".graph (name, description, directed, notebook_url, graph_parent, modularity)
+      SELECT $1, $2, directed, $3, name, $4
+      FROM "{schema}".graph
+      WHERE name = $5"#,
+      schema = self.schema
+    ))
+    .bind(graph_name)
+    .bind(description)
+    .bind(notebook_url)
+    .bind(modularity)
+    .bind(graph_parent)
+    .execute(pool)
+    .await?;
+
+    sqlx::query(&format!(
+      r#"INSERT INTO "{schema}".node (id, graph_name) SELECT id, $2 FROM UNNEST($1::TEXT[]) AS t(id)"#,
+      schema = self.schema
+    ))
+    .bind(nodes)
+    .bind(graph_name)
+    .execute(pool)
+    .await?;
+
+    sqlx::query(&format!(
+      r#"INSERT INTO "{schema}".link (graph_name, node_out, node_in, weight)
+      SELECT $1, node_out, node_in, weight
+      FROM "{schema}".link
+      WHERE node_in IN (SELECT id FROM "{schema}".node WHERE graph_name = $1)
+        AND node_out IN (SELECT id FROM "{schema}".node WHERE graph_name = $1)
+        AND graph_name = $2"#,
+      schema = self.schema
+    ))
+    .bind(graph_name)
+    .bind(graph_parent)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+  }
+
+  async fn insert_node_attribute(
+    &self,
+    pool: &PgPool,
+    graph_name: &str,
+    name: &str,
+    nodes: &[String],
+    values: &[f64],
+  ) -> Result<(), GraphError> {
+    sqlx::query(&format!(
+      r#"DELETE FROM "{schema}".node_attribute WHERE graph_name = $1 AND name = $2"#,
+      schema = self.schema
+    ))
+    .bind(graph_name)
+    .bind(name)
+    .execute(pool)
+    .await?;
+
+    sqlx::query(&format!(
+      r#"INSERT INTO "{schema}".node_attribute (node_id, graph_name, name, value)
+      SELECT node_id, $3, $4, value FROM UNNEST($1::TEXT[], $2::FLOAT8[]) AS t(node_id, value)"#,
+      schema = self.schema
+    ))
+    .bind(nodes)
+    .bind(values)
+    .bind(graph_name)
+    .bind(name)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+  }
+
+  /// Équivalent de `viz.py` : sélectionne les noeuds les mieux classés de chaque communauté,
+  /// calcule leur disposition et enregistre le résultat dans la table `vis`.
+  #[tracing::instrument(skip_all)]
+  pub(crate) async fn build_viz(&self, pool: &PgPool) -> Result<JsonDataGraph, GraphError> {
+    let links = sqlx::query_as::<_, VisLink>(&self.vis_links_query())
+      .fetch_all(pool)
+      .await?;
+
+    let mut nodes: Vec<String> = vec![];
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    let mut attributes: HashMap<String, (i64, i64)> = HashMap::new();
+    let mut edges: Vec<(usize, usize, i32, i64)> = vec![];
+    let mut edge_positions: HashMap<(usize, usize), usize> = HashMap::new();
+
+    let mut position = |node: &String| -> usize {
+      *positions.entry(node.clone()).or_insert_with(|| {
+        nodes.push(node.clone());
+        nodes.len() - 1
+      })
+    };
+
+    for link in links.iter() {
+      let source = position(&link.source);
+      let target = position(&link.target);
+      attributes.insert(
+        link.source.clone(),
+        (link.source_rank, link.source_community as i64),
+      );
+      attributes.insert(
+        link.target.clone(),
+        (link.target_rank, link.target_community as i64),
+      );
+
+      // graphe non orienté : un seul lien par paire, le dernier rencontré l'emporte
+      let edge = (
+        source,
+        target,
+        link.weight.unwrap_or_default(),
+        link.source_community as i64,
+      );
+      match edge_positions
+        .get(&(source, target))
+        .or_else(|| edge_positions.get(&(target, source)))
+      {
+        Some(&i) => edges[i] = (edges[i].0, edges[i].1, edge.2, edge.3),
+        None => {
+          edge_positions.insert((source, target), edges.len());
+          edges.push(edge);
+        }
+      }
+    }
+
+    let mut neighbors: Vec<Vec<&str>> = vec![vec![]; nodes.len()];
+    for &(source, target, _, _) in edges.iter() {
+      neighbors[source].push(&nodes[target]);
+      if source != target {
+        neighbors[target].push(&nodes[source]);
+      }
+    }
+
+    let layout = spring_layout(
+      nodes.len(),
+      &edges
+        .iter()
+        .map(|&(source, target, weight, _)| (source, target, weight as f64))
+        .collect::<Vec<_>>(),
+    );
+
+    let json_nodes: Vec<Value> = nodes
+      .iter()
+      .enumerate()
+      .map(|(i, node)| {
+        let (rank, community) = attributes[node];
+        let size = if rank > 10 { 5 } else { 65 - rank * 5 };
+        json!({
+          "color": group_color(community),
+          "font": { "size": size },
+          "group": community,
+          "id": node,
+          "label": node,
+          "shape": "dot",
+          "size": size,
+          "title": format!(
+            "Comunity: {}<br>Rank: {}<br>{} Neighbors:<br>{}",
+            community,
+            rank,
+            node,
+            neighbors[i].join("<br>")
+          ),
+          "value": size,
+          "x": layout[i].0 * 1000.,
+          "y": layout[i].1 * 1000.,
+        })
+      })
+      .collect();
+
+    let json_edges: Vec<Value> = edges
+      .iter()
+      .map(|&(source, target, weight, community)| {
+        json!({
+          "color": group_color(community),
+          "from": nodes[source],
+          "to": nodes[target],
+          "weight": weight,
+          "width": weight,
+        })
+      })
+      .collect();
+
+    let json_data = JsonDataGraph {
+      nodes: Value::Array(json_nodes),
+      edges: Value::Array(json_edges),
+    };
+
+    sqlx::query(&format!(
+      r#"CREATE TABLE IF NOT EXISTS "{schema}".vis (graph_name VARCHAR NOT NULL, community VARCHAR NOT NULL, centrality VARCHAR NOT NULL, max_rank INTEGER NOT NULL, show_interaction BOOLEAN NOT NULL, nodes JSON NOT NULL, edges JSON NOT NULL)"#,
+      schema = self.schema
+    ))
+    .execute(pool)
+    .await?;
+
+    sqlx::query(&format!(
+      r#"INSERT INTO "{schema}".vis (graph_name, community, centrality, max_rank, show_interaction, nodes, edges) VALUES ($1, $2, $3, $4, $5, $6, $7)"#,
+      schema = self.schema
+    ))
+    .bind(&self.graph_name)
+    .bind(&self.community)
+    .bind(&self.centrality)
+    .bind(self.max_rank as i32)
+    .bind(self.show_interaction)
+    .bind(&json_data.nodes)
+    .bind(&json_data.edges)
+    .execute(pool)
+    .await?;
+
+    sqlx::query(&format!(
+      r#"INSERT INTO "{schema}".status (status, graph_name, community, centrality, max_rank, show_interaction) VALUES ('done', $1, $2, $3, $4, $5)"#,
+      schema = self.schema
+    ))
+    .bind(&self.graph_name)
+    .bind(&self.community)
+    .bind(&self.centrality)
+    .bind(self.max_rank as i32)
+    .bind(self.show_interaction)
+    .execute(pool)
+    .await?;
+
+    Ok(json_data)
+  }
+
+  fn vis_links_query(&self) -> String {
+    let (partition, source, joins) = match self.graph_name.as_str() {
+      "user_hashtag" => (
+        "graph_name, SUBSTRING(node_id FOR 1)",
+        "'@' || user1.screen_name AS source, node2.node_id AS target",
+        r#"INNER JOIN "{schema}".user user1 ON user1.id = SUBSTRING(link.node_out FROM 2)"#,
+      ),
+      "hashtag_hashtag" => (
+        "graph_name",
+        "node1.node_id AS source, node2.node_id AS target",
+        "",
+      ),
+      _ => (
+        "graph_name",
+        "user1.screen_name AS source, user2.screen_name AS target",
+        r#"INNER JOIN "{schema}".user user1 ON user1.id = link.node_out
+            INNER JOIN "{schema}".user user2 ON user2.id = link.node_in"#,
+      ),
+    };
+
+    format!(
+      r#"
+        WITH node_rank AS (
+            SELECT node_id,
+                graph_name,
+                name,
+                RANK () OVER (PARTITION BY {partition} ORDER BY value DESC, node_id) AS rank
+            FROM "{schema}".node_attribute
+            WHERE name = '{centrality}'
+        ), node_complete AS (
+            SELECT nc.graph_name AS graph_name, nr.node_id AS node_id, nr.rank AS rank, nc.value AS community
+            FROM "{schema}".graph g
+                INNER JOIN node_rank nr ON g.name = nr.graph_name
+                INNER JOIN "{schema}".node_attribute nc ON g.graph_parent = nc.graph_name
+                    AND nr.graph_name SIMILAR TO '{graph_name}_{community}_[1-9]+'
+                    AND nr.node_id = nc.node_id
+            WHERE nc.name = '{community}'
+        )
+        SELECT {source},
+            node1.community AS source_community,
+            node2.community AS target_community,
+            node1.rank AS source_rank,
+            node2.rank AS target_rank,
+            weight
+        FROM node_complete node1
+            INNER JOIN "{schema}".link link ON node1.node_id = link.node_out
+                AND node1.graph_name = link.graph_name
+            INNER JOIN node_complete node2 ON node2.node_id = link.node_in
+                AND node2.graph_name = link.graph_name
+            {joins}
+        WHERE node1.graph_name = '{graph_name}'
+            AND node1.rank <= {max_rank}
+            AND node2.rank <= {max_rank}
+            AND (node1.community = node2.community OR {show_interaction})
+    "#,
+      joins = joins.replace("{schema}", &self.schema),
+      schema = self.schema,
+      graph_name = self.graph_name,
+      community = self.community,
+      centrality = self.centrality,
+      max_rank = self.max_rank,
+      show_interaction = self.show_interaction,
+    )
+  }
+}
+
+/// Générateur pseudo-aléatoire déterministe (xorshift), pour que la disposition et les couleurs
+/// d'une même étude soient stables d'un calcul à l'autre.
+struct XorShift(u64);
+
+impl XorShift {
+  fn next(&mut self) -> f64 {
+    self.0 ^= self.0 << 13;
+    self.0 ^= self.0 >> 7;
+    self.0 ^= self.0 << 17;
+    (self.0 >> 11) as f64 / (1u64 << 53) as f64
+  }
+}
+
+fn group_color(group: i64) -> String {
+  let mut random =
+    XorShift(0x9E37_79B9_7F4A_7C15 ^ (group as u64).wrapping_mul(0xBF58_476D_1CE4_E5B9));
+  let mut channel = || (random.next() * 255.) as u8;
+  format!("rgb({},{},{})", channel(), channel(), channel())
+}
+
+/// Disposition de Fruchterman-Reingold, calquée sur `networkx.spring_layout` : 50 itérations,
+/// positions recentrées et mises à l'échelle dans [-1, 1].
+fn spring_layout(n: usize, edges: &[(usize, usize, f64)]) -> Vec<(f64, f64)> {
+  let mut random = XorShift(0x2545_F491_4F6C_DD1D);
+  let mut positions: Vec<(f64, f64)> = (0..n).map(|_| (random.next(), random.next())).collect();
+  if n <= 1 {
+    return positions.iter().map(|_| (0., 0.)).collect();
+  }
+
+  let mut weights: HashMap<(usize, usize), f64> = HashMap::new();
+  for &(source, target, weight) in edges.iter() {
+    if source != target {
+      *weights.entry((source, target)).or_default() += weight;
+      *weights.entry((target, source)).or_default() += weight;
+    }
+  }
+
+  let k = (1. / n as f64).sqrt();
+  let mut temperature = 0.1;
+  let cooling = temperature / (LAYOUT_ITERATIONS + 1) as f64;
+
+  for _ in 0..LAYOUT_ITERATIONS {
+    let mut displacement = vec![(0., 0.); n];
+    for i in 0..n {
+      for j in 0..n {
+        if i == j {
+          continue;
+        }
+        let dx = positions[i].0 - positions[j].0;
+        let dy = positions[i].1 - positions[j].1;
+        let distance = (dx * dx + dy * dy).sqrt().max(0.01);
+        let weight = weights.get(&(i, j)).copied().unwrap_or(0.);
+        let force = k * k / (distance * distance) - weight * distance / k;
+        displacement[i].0 += dx * force;
+        displacement[i].1 += dy * force;
+      }
+    }
+
+    for i in 0..n {
+      let length = (displacement[i].0.powi(2) + displacement[i].1.powi(2)).sqrt();
+      let length = if length < 0.01 { 0.1 } else { length };
+      positions[i].0 += displacement[i].0 * temperature / length;
+      positions[i].1 += displacement[i].1 * temperature / length;
+    }
+    temperature -= cooling;
+  }
+
+  let mean = positions
+    .iter()
+    .fold((0., 0.), |acc, p| (acc.0 + p.0, acc.1 + p.1));
+  let mean = (mean.0 / n as f64, mean.1 / n as f64);
+  let scale = positions
+    .iter()
+    .map(|p| (p.0 - mean.0).abs().max((p.1 - mean.1).abs()))
+    .fold(0., f64::max);
+  let scale = if scale > 0. { scale } else { 1. };
+
+  positions
+    .iter()
+    .map(|p| ((p.0 - mean.0) / scale, (p.1 - mean.1) / scale))
+    .collect()
+}
@@ -0,0 +1,889 @@
+//! Algorithmes de graphe en Rust, portés depuis `scripts-ub/create-data-graph.R` (igraph).
+//!
+//! Les noeuds sont indexés de `0` à `n - 1`, leurs identifiants (`user_id`, hashtag…) sont
+//! conservés dans `Network::names`.
+use std::{
+  cmp::{Ordering, Reverse},
+  collections::{BinaryHeap, HashMap},
+};
+
+const DAMPING: f64 = 0.85;
+const MAX_ITERATIONS: usize = 1000;
+const EPSILON: f64 = 1e-10;
+const WALKTRAP_STEPS: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+  In,
+  Out,
+  All,
+}
+
+#[derive(Debug, Clone)]
+pub struct Network {
+  pub names: Vec<String>,
+  pub directed: bool,
+  /// (source, cible, poids)
+  pub edges: Vec<(usize, usize, f64)>,
+  out_adj: Vec<Vec<(usize, f64)>>,
+  in_adj: Vec<Vec<(usize, f64)>>,
+}
+
+impl Network {
+  /// Construit un graphe à partir de la liste des noeuds et des liens `(node_out, node_in, weight)`.
+  /// Les liens dont une extrémité n'est pas dans `names` sont ignorés.
+  pub fn new(names: Vec<String>, links: &[(String, String, f64)], directed: bool) -> Self {
+    let index: HashMap<&str, usize> = names
+      .iter()
+      .enumerate()
+      .map(|(i, name)| (name.as_str(), i))
+      .collect();
+
+    let edges = links
+      .iter()
+      .filter_map(|(from, to, weight)| {
+        Some((
+          *index.get(from.as_str())?,
+          *index.get(to.as_str())?,
+          *weight,
+        ))
+      })
+      .collect();
+
+    Self::from_edges(names, edges, directed)
+  }
+
+  fn from_edges(names: Vec<String>, edges: Vec<(usize, usize, f64)>, directed: bool) -> Self {
+    let n = names.len();
+    let mut out_adj = vec![vec![]; n];
+    let mut in_adj = vec![vec![]; n];
+
+    for &(from, to, weight) in edges.iter() {
+      out_adj[from].push((to, weight));
+      in_adj[to].push((from, weight));
+      if !directed {
+        out_adj[to].push((from, weight));
+        in_adj[from].push((to, weight));
+      }
+    }
+
+    Self {
+      names,
+      directed,
+      edges,
+      out_adj,
+      in_adj,
+    }
+  }
+
+  pub fn node_count(&self) -> usize {
+    self.names.len()
+  }
+
+  pub fn edge_count(&self) -> usize {
+    self.edges.len()
+  }
+
+  fn neighbors(&self, mode: Mode) -> Vec<Vec<usize>> {
+    (0..self.node_count())
+      .map(|v| match (mode, self.directed) {
+        (_, false) | (Mode::Out, true) => self.out_adj[v].iter().map(|(u, _)| *u).collect(),
+        (Mode::In, true) => self.in_adj[v].iter().map(|(u, _)| *u).collect(),
+        (Mode::All, true) => self.out_adj[v]
+          .iter()
+          .chain(self.in_adj[v].iter())
+          .map(|(u, _)| *u)
+          .collect(),
+      })
+      .collect()
+  }
+
+  /// Sous-graphe induit par `nodes`, dans l'ordre donné.
+  pub fn induced_subgraph(&self, nodes: &[usize]) -> Network {
+    let mut position = vec![None; self.node_count()];
+    for (i, &v) in nodes.iter().enumerate() {
+      position[v] = Some(i);
+    }
+
+    let edges = self
+      .edges
+      .iter()
+      .filter_map(|&(from, to, weight)| Some((position[from]?, position[to]?, weight)))
+      .collect();
+
+    Network::from_edges(
+      nodes.iter().map(|&v| self.names[v].clone()).collect(),
+      edges,
+      self.directed,
+    )
+  }
+
+  /// Composantes faiblement connexes, chacune triée par indice de noeud.
+  pub fn components(&self) -> Vec<Vec<usize>> {
+    let neighbors = self.neighbors(Mode::All);
+    let mut seen = vec![false; self.node_count()];
+    let mut components = vec![];
+
+    for start in 0..self.node_count() {
+      if seen[start] {
+        continue;
+      }
+      seen[start] = true;
+      let mut component = vec![start];
+      let mut stack = vec![start];
+      while let Some(v) = stack.pop() {
+        for &u in neighbors[v].iter() {
+          if !seen[u] {
+            seen[u] = true;
+            component.push(u);
+            stack.push(u);
+          }
+        }
+      }
+      component.sort_unstable();
+      components.push(component);
+    }
+
+    components
+  }
+
+  /// Équivalent de `getMaxComposanteConnexe` : plus grande composante connexe.
+  pub fn largest_component(&self) -> Network {
+    let largest = self
+      .components()
+      .into_iter()
+      .max_by_key(|c| c.len())
+      .unwrap_or_default();
+
+    self.induced_subgraph(&largest)
+  }
+
+  /// Version non orientée du graphe, les liens réciproques sont fusionnés en sommant leur poids
+  /// (`as.undirected(graph)` d'igraph).
+  pub fn to_undirected(&self) -> Network {
+    if !self.directed {
+      return self.clone();
+    }
+
+    let mut merged: HashMap<(usize, usize), f64> = HashMap::new();
+    for &(from, to, weight) in self.edges.iter() {
+      *merged.entry((from.min(to), from.max(to))).or_default() += weight;
+    }
+    let mut edges: Vec<_> = merged.into_iter().map(|((a, b), w)| (a, b, w)).collect();
+    edges.sort_by_key(|e| (e.0, e.1));
+
+    Network::from_edges(self.names.clone(), edges, false)
+  }
+
+  /// Degré (nombre de liens) de chaque noeud.
+  pub fn degree(&self, mode: Mode) -> Vec<f64> {
+    self
+      .neighbors(mode)
+      .iter()
+      .map(|n| n.len() as f64)
+      .collect()
+  }
+
+  /// k-core de chaque noeud (algorithme de Batagelj et Zaversnik).
+  pub fn coreness(&self, mode: Mode) -> Vec<f64> {
+    let neighbors = self.neighbors(mode);
+    // lorsqu'un noeud est retiré, ce sont les noeuds qui le comptent dans leur degré qui perdent un lien
+    let reverse = self.neighbors(match mode {
+      Mode::In => Mode::Out,
+      Mode::Out => Mode::In,
+      Mode::All => Mode::All,
+    });
+
+    let mut degree: Vec<usize> = neighbors.iter().map(|n| n.len()).collect();
+    let mut removed = vec![false; self.node_count()];
+    let mut core = vec![0.; self.node_count()];
+    let mut heap: BinaryHeap<Reverse<(usize, usize)>> = degree
+      .iter()
+      .enumerate()
+      .map(|(v, d)| Reverse((*d, v)))
+      .collect();
+    let mut k = 0;
+
+    while let Some(Reverse((d, v))) = heap.pop() {
+      if removed[v] || d != degree[v] {
+        continue;
+      }
+      k = k.max(d);
+      core[v] = k as f64;
+      removed[v] = true;
+      for &u in reverse[v].iter() {
+        if !removed[u] && degree[u] > 0 {
+          degree[u] -= 1;
+          heap.push(Reverse((degree[u], u)));
+        }
+      }
+    }
+
+    core
+  }
+
+  /// PageRank pondéré (facteur d'amortissement 0.85). La masse des noeuds sans lien sortant est
+  /// redistribuée uniformément.
+  pub fn page_rank(&self) -> Vec<f64> {
+    let n = self.node_count();
+    if n == 0 {
+      return vec![];
+    }
+
+    let out_strength: Vec<f64> = self
+      .out_adj
+      .iter()
+      .map(|adj| adj.iter().map(|(_, w)| w).sum())
+      .collect();
+    let mut rank = vec![1. / n as f64; n];
+
+    for _ in 0..MAX_ITERATIONS {
+      let dangling: f64 = (0..n)
+        .filter(|&v| out_strength[v] <= 0.)
+        .map(|v| rank[v])
+        .sum();
+      let base = (1. - DAMPING) / n as f64 + DAMPING * dangling / n as f64;
+      let mut next = vec![base; n];
+      for v in 0..n {
+        if out_strength[v] > 0. {
+          for &(u, w) in self.out_adj[v].iter() {
+            next[u] += DAMPING * rank[v] * w / out_strength[v];
+          }
+        }
+      }
+
+      let diff: f64 = next
+        .iter()
+        .zip(rank.iter())
+        .map(|(a, b)| (a - b).abs())
+        .sum();
+      rank = next;
+      if diff < EPSILON {
+        break;
+      }
+    }
+
+    let total: f64 = rank.iter().sum();
+    rank.iter().map(|r| r / total).collect()
+  }
+
+  /// Scores HITS `(hubs, authorities)` pondérés, normalisés pour que le maximum vaille 1.
+  pub fn hits(&self) -> (Vec<f64>, Vec<f64>) {
+    let n = self.node_count();
+    let mut hubs = vec![1.; n];
+    let mut authorities = vec![0.; n];
+
+    for _ in 0..MAX_ITERATIONS {
+      let mut next_authorities = vec![0.; n];
+      for (adj, hub) in self.out_adj.iter().zip(hubs.iter()) {
+        for &(u, w) in adj.iter() {
+          next_authorities[u] += w * hub;
+        }
+      }
+      scale_to_max(&mut next_authorities);
+
+      let mut next_hubs: Vec<f64> = self
+        .out_adj
+        .iter()
+        .map(|adj| adj.iter().map(|&(u, w)| w * next_authorities[u]).sum())
+        .collect();
+      scale_to_max(&mut next_hubs);
+
+      let diff: f64 = next_hubs
+        .iter()
+        .zip(hubs.iter())
+        .chain(next_authorities.iter().zip(authorities.iter()))
+        .map(|(a, b)| (a - b).abs())
+        .sum();
+      hubs = next_hubs;
+      authorities = next_authorities;
+      if diff < EPSILON {
+        break;
+      }
+    }
+
+    (hubs, authorities)
+  }
+
+  /// Centralité d'intermédiarité (algorithme de Brandes), les poids sont utilisés comme
+  /// distances comme le fait igraph.
+  pub fn betweenness(&self) -> Vec<f64> {
+    let n = self.node_count();
+    let mut centrality = vec![0.; n];
+
+    for source in 0..n {
+      let mut stack = Vec::with_capacity(n);
+      let mut predecessors: Vec<Vec<usize>> = vec![vec![]; n];
+      let mut paths = vec![0.; n];
+      let mut distance = vec![f64::INFINITY; n];
+      let mut settled = vec![false; n];
+      paths[source] = 1.;
+      distance[source] = 0.;
+
+      let mut heap = BinaryHeap::new();
+      heap.push(MinDistance(0., source));
+      while let Some(MinDistance(d, v)) = heap.pop() {
+        if settled[v] || d > distance[v] {
+          continue;
+        }
+        settled[v] = true;
+        stack.push(v);
+        for &(u, w) in self.out_adj[v].iter() {
+          let candidate = d + w;
+          if candidate < distance[u] - EPSILON {
+            distance[u] = candidate;
+            paths[u] = paths[v];
+            predecessors[u] = vec![v];
+            heap.push(MinDistance(candidate, u));
+          } else if (candidate - distance[u]).abs() <= EPSILON && !settled[u] {
+            paths[u] += paths[v];
+            predecessors[u].push(v);
+          }
+        }
+      }
+
+      let mut dependency = vec![0.; n];
+      while let Some(w) = stack.pop() {
+        for &v in predecessors[w].iter() {
+          dependency[v] += paths[v] / paths[w] * (1. + dependency[w]);
+        }
+        if w != source {
+          centrality[w] += dependency[w];
+        }
+      }
+    }
+
+    if !self.directed {
+      centrality.iter_mut().for_each(|c| *c /= 2.);
+    }
+
+    centrality
+  }
+
+  /// Modularité pondérée de la partition `membership` (le graphe est considéré non orienté).
+  pub fn modularity(&self, membership: &[usize]) -> f64 {
+    let total: f64 = self.edges.iter().map(|(_, _, w)| w).sum();
+    if total <= 0. {
+      return 0.;
+    }
+
+    let communities = membership.iter().max().map(|m| m + 1).unwrap_or(0);
+    let mut internal = vec![0.; communities];
+    let mut strength = vec![0.; communities];
+    for &(from, to, weight) in self.edges.iter() {
+      if membership[from] == membership[to] {
+        internal[membership[from]] += weight;
+      }
+      strength[membership[from]] += weight;
+      strength[membership[to]] += weight;
+    }
+
+    (0..communities)
+      .map(|c| internal[c] / total - (strength[c] / (2. * total)).powi(2))
+      .sum()
+  }
+
+  /// Communautés de Louvain (graphe non orienté pondéré), renvoie l'appartenance de chaque noeud.
+  pub fn louvain(&self) -> Vec<usize> {
+    let graph = self.to_undirected();
+    let n = graph.node_count();
+    let mut membership: Vec<usize> = (0..n).collect();
+
+    // graphe agrégé courant : adjacence sans boucle + poids des boucles
+    let mut adjacency: Vec<Vec<(usize, f64)>> = graph
+      .out_adj
+      .iter()
+      .enumerate()
+      .map(|(v, adj)| adj.iter().filter(|(u, _)| *u != v).copied().collect())
+      .collect();
+    let mut loops = vec![0.; n];
+    for &(from, to, weight) in graph.edges.iter() {
+      if from == to {
+        loops[from] += weight;
+      }
+    }
+
+    loop {
+      let size = adjacency.len();
+      let strength: Vec<f64> = (0..size)
+        .map(|v| adjacency[v].iter().map(|(_, w)| w).sum::<f64>() + 2. * loops[v])
+        .collect();
+      let total: f64 = strength.iter().sum();
+      if total <= 0. {
+        break;
+      }
+
+      let mut community: Vec<usize> = (0..size).collect();
+      let mut community_strength = strength.clone();
+      let mut improved = false;
+
+      loop {
+        let mut moved = false;
+        for v in 0..size {
+          let current = community[v];
+          let mut links: HashMap<usize, f64> = HashMap::new();
+          for &(u, w) in adjacency[v].iter() {
+            *links.entry(community[u]).or_default() += w;
+          }
+
+          community_strength[current] -= strength[v];
+          let gain = |c: usize, k_in: f64| k_in - community_strength[c] * strength[v] / total;
+          let mut best = current;
+          let mut best_gain = gain(current, links.get(&current).copied().unwrap_or(0.));
+          let mut candidates: Vec<_> = links.into_iter().collect();
+          candidates.sort_by_key(|(c, _)| *c);
+          for (c, k_in) in candidates {
+            let g = gain(c, k_in);
+            if g > best_gain + EPSILON {
+              best = c;
+              best_gain = g;
+            }
+          }
+          community_strength[best] += strength[v];
+
+          if best != current {
+            community[v] = best;
+            moved = true;
+            improved = true;
+          }
+        }
+        if !moved {
+          break;
+        }
+      }
+
+      if !improved {
+        break;
+      }
+
+      // renumérotation des communautés puis agrégation
+      let mut renumber = HashMap::new();
+      for c in community.iter_mut() {
+        let next = renumber.len();
+        *c = *renumber.entry(*c).or_insert(next);
+      }
+      for m in membership.iter_mut() {
+        *m = community[*m];
+      }
+
+      let count = renumber.len();
+      let mut aggregated: Vec<HashMap<usize, f64>> = vec![HashMap::new(); count];
+      let mut aggregated_loops = vec![0.; count];
+      for v in 0..size {
+        aggregated_loops[community[v]] += loops[v];
+        for &(u, w) in adjacency[v].iter() {
+          if community[u] == community[v] {
+            // chaque lien interne est vu depuis ses deux extrémités
+            aggregated_loops[community[v]] += w / 2.;
+          } else {
+            *aggregated[community[v]].entry(community[u]).or_default() += w;
+          }
+        }
+      }
+      adjacency = aggregated
+        .into_iter()
+        .map(|links| {
+          let mut links: Vec<_> = links.into_iter().collect();
+          links.sort_by_key(|(c, _)| *c);
+          links
+        })
+        .collect();
+      loops = aggregated_loops;
+    }
+
+    normalize_membership(&membership)
+  }
+
+  /// Communautés walktrap (Pons et Latapy, marches aléatoires de 4 pas) sur le graphe non
+  /// orienté ; le dendrogramme est coupé au niveau de modularité maximale.
+  pub fn walktrap(&self) -> Vec<usize> {
+    let graph = self.to_undirected();
+    let n = graph.node_count();
+    if n == 0 {
+      return vec![];
+    }
+
+    // comme dans l'implémentation d'origine, chaque noeud reçoit une boucle de poids moyen
+    let transitions: Vec<Vec<(usize, f64)>> = (0..n)
+      .map(|v| {
+        let adj: Vec<_> = graph.out_adj[v].iter().filter(|(u, _)| *u != v).collect();
+        let strength: f64 = adj.iter().map(|(_, w)| w).sum();
+        let self_loop = if adj.is_empty() {
+          1.
+        } else {
+          strength / adj.len() as f64
+        };
+        let mut t: Vec<(usize, f64)> = adj.into_iter().copied().collect();
+        t.push((v, self_loop));
+        t
+      })
+      .collect();
+    let degree: Vec<f64> = transitions
+      .iter()
+      .map(|t| t.iter().map(|(_, w)| w).sum())
+      .collect();
+
+    let walk = |v: usize| -> HashMap<usize, f64> {
+      let mut probabilities = HashMap::from([(v, 1.)]);
+      for _ in 0..WALKTRAP_STEPS {
+        let mut next: HashMap<usize, f64> = HashMap::new();
+        for (&i, &p) in probabilities.iter() {
+          for &(j, w) in transitions[i].iter() {
+            *next.entry(j).or_default() += p * w / degree[i];
+          }
+        }
+        probabilities = next;
+      }
+      probabilities
+    };
+
+    let distance = |a: &HashMap<usize, f64>, b: &HashMap<usize, f64>| -> f64 {
+      let mut sum = 0.;
+      for (k, pa) in a.iter() {
+        let pb = b.get(k).copied().unwrap_or(0.);
+        sum += (pa - pb).powi(2) / degree[*k];
+      }
+      for (k, pb) in b.iter() {
+        if !a.contains_key(k) {
+          sum += pb.powi(2) / degree[*k];
+        }
+      }
+      sum
+    };
+
+    let delta_sigma = |size_a: f64, size_b: f64, r2: f64| -> f64 {
+      size_a * size_b / (size_a + size_b) * r2 / n as f64
+    };
+
+    // état des communautés : vecteur de probabilités, taille, voisins (poids des liens d'origine)
+    let mut probabilities: Vec<Option<HashMap<usize, f64>>> =
+      (0..n).map(|v| Some(walk(v))).collect();
+    let mut sizes = vec![1.; n];
+    let mut neighbors: Vec<HashMap<usize, f64>> = vec![HashMap::new(); n];
+    let mut internal = vec![0.; n];
+    let mut strength = vec![0.; n];
+    let total: f64 = graph.edges.iter().map(|(_, _, w)| w).sum();
+    for &(from, to, weight) in graph.edges.iter() {
+      strength[from] += weight;
+      strength[to] += weight;
+      if from == to {
+        internal[from] += weight;
+      } else {
+        *neighbors[from].entry(to).or_default() += weight;
+        *neighbors[to].entry(from).or_default() += weight;
+      }
+    }
+
+    // une paire du tas est périmée dès que l'une de ses communautés a fusionné depuis
+    let mut versions = vec![0; n];
+    let mut heap = BinaryHeap::new();
+    for (a, links) in neighbors.iter().enumerate() {
+      for &b in links.keys() {
+        if a < b {
+          let r2 = distance(
+            probabilities[a].as_ref().unwrap(),
+            probabilities[b].as_ref().unwrap(),
+          );
+          heap.push(MergeCandidate {
+            delta: delta_sigma(1., 1., r2),
+            pair: (a, b),
+            versions: (0, 0),
+          });
+        }
+      }
+    }
+
+    let modularity = |internal: &[f64], strength: &[f64], alive: &[usize]| -> f64 {
+      if total <= 0. {
+        return 0.;
+      }
+      alive
+        .iter()
+        .map(|&c| internal[c] / total - (strength[c] / (2. * total)).powi(2))
+        .sum()
+    };
+
+    // les communautés fusionnées reçoivent de nouveaux indices à partir de n
+    let mut merges: Vec<(usize, usize)> = vec![];
+    let mut alive: Vec<usize> = (0..n).collect();
+    let mut best_modularity = modularity(&internal, &strength, &alive);
+    let mut best_step = 0;
+    let mut ids: Vec<usize> = (0..n).collect();
+
+    while let Some(MergeCandidate {
+      pair: (a, b),
+      versions: (version_a, version_b),
+      ..
+    }) = heap.pop()
+    {
+      if probabilities[a].is_none()
+        || probabilities[b].is_none()
+        || versions[a] != version_a
+        || versions[b] != version_b
+      {
+        continue;
+      }
+
+      let pa = probabilities[a].take().unwrap();
+      let pb = probabilities[b].take().unwrap();
+      let (sa, sb) = (sizes[a], sizes[b]);
+      let mut merged = pa;
+      merged.values_mut().for_each(|p| *p *= sa);
+      for (k, p) in pb {
+        *merged.entry(k).or_default() += p * sb;
+      }
+      merged.values_mut().for_each(|p| *p /= sa + sb);
+
+      // la communauté fusionnée réutilise l'emplacement de `a`
+      let links_b = std::mem::take(&mut neighbors[b]);
+      let between = neighbors[a].remove(&b).unwrap_or(0.);
+      for (c, w) in links_b {
+        if c != a {
+          *neighbors[a].entry(c).or_default() += w;
+          let links_c = &mut neighbors[c];
+          links_c.remove(&b);
+          *links_c.entry(a).or_default() += w;
+        }
+      }
+      internal[a] += internal[b] + between;
+      strength[a] += strength[b];
+      internal[b] = 0.;
+      strength[b] = 0.;
+      sizes[a] = sa + sb;
+      probabilities[a] = Some(merged);
+      versions[a] += 1;
+
+      merges.push((ids[a], ids[b]));
+      ids[a] = n + merges.len() - 1;
+      alive.retain(|&c| c != b);
+
+      let q = modularity(&internal, &strength, &alive);
+      if q > best_modularity + EPSILON {
+        best_modularity = q;
+        best_step = merges.len();
+      }
+
+      let mut links: Vec<usize> = neighbors[a].keys().copied().collect();
+      links.sort_unstable();
+      for c in links {
+        let r2 = distance(
+          probabilities[a].as_ref().unwrap(),
+          probabilities[c].as_ref().unwrap(),
+        );
+        let (low, high) = (a.min(c), a.max(c));
+        heap.push(MergeCandidate {
+          delta: delta_sigma(sizes[a], sizes[c], r2),
+          pair: (low, high),
+          versions: (versions[low], versions[high]),
+        });
+      }
+    }
+
+    // rejoue les fusions jusqu'à la coupe de modularité maximale
+    let mut parent: Vec<usize> = (0..n + merges.len()).collect();
+    for (step, &(x, y)) in merges.iter().take(best_step).enumerate() {
+      parent[x] = n + step;
+      parent[y] = n + step;
+    }
+    let membership: Vec<usize> = (0..n)
+      .map(|v| {
+        let mut root = v;
+        while parent[root] != root {
+          root = parent[root];
+        }
+        root
+      })
+      .collect();
+
+    normalize_membership(&membership)
+  }
+}
+
+/// Regroupe les noeuds par communauté, les plus grandes en premier.
+pub fn communities(membership: &[usize]) -> Vec<Vec<usize>> {
+  let count = membership.iter().max().map(|m| m + 1).unwrap_or(0);
+  let mut communities = vec![vec![]; count];
+  for (v, &c) in membership.iter().enumerate() {
+    communities[c].push(v);
+  }
+  communities.retain(|c| !c.is_empty());
+  communities.sort_by(|a, b| b.len().cmp(&a.len()).then(a[0].cmp(&b[0])));
+  communities
+}
+
+fn normalize_membership(membership: &[usize]) -> Vec<usize> {
+  let mut renumber = HashMap::new();
+  membership
+    .iter()
+    .map(|m| {
+      let next = renumber.len();
+      *renumber.entry(*m).or_insert(next)
+    })
+    .collect()
+}
+
+fn scale_to_max(values: &mut [f64]) {
+  let max = values.iter().cloned().fold(0., f64::max);
+  if max > 0. {
+    values.iter_mut().for_each(|v| *v /= max);
+  }
+}
+
+/// Élément de tas min ordonné par distance.
+#[derive(Debug, PartialEq)]
+struct MinDistance(f64, usize);
+
+impl Eq for MinDistance {}
+
+impl PartialOrd for MinDistance {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl Ord for MinDistance {
+  fn cmp(&self, other: &Self) -> Ordering {
+    other
+      .0
+      .total_cmp(&self.0)
+      .then_with(|| other.1.cmp(&self.1))
+  }
+}
+
+/// Fusion candidate de walktrap, du tas min ordonné par variation de distance ; les
+/// versions sont celles des deux communautés lors du calcul.
+#[derive(Debug, PartialEq)]
+struct MergeCandidate {
+  delta: f64,
+  pair: (usize, usize),
+  versions: (usize, usize),
+}
+
+impl Eq for MergeCandidate {}
+
+impl PartialOrd for MergeCandidate {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl Ord for MergeCandidate {
+  fn cmp(&self, other: &Self) -> Ordering {
+    other
+      .delta
+      .total_cmp(&self.delta)
+      .then_with(|| other.pair.cmp(&self.pair))
+      .then_with(|| other.versions.cmp(&self.versions))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn network(links: &[(&str, &str)], directed: bool) -> Network {
+    let mut names: Vec<String> = links
+      .iter()
+      .flat_map(|(a, b)| [a.to_string(), b.to_string()])
+      .collect();
+    names.sort();
+    names.dedup();
+    let links: Vec<_> = links
+      .iter()
+      .map(|(a, b)| (a.to_string(), b.to_string(), 1.))
+      .collect();
+    Network::new(names, &links, directed)
+  }
+
+  /// Deux cliques de 4 noeuds reliées par un seul lien.
+  fn two_cliques() -> Network {
+    network(
+      &[
+        ("a1", "a2"),
+        ("a1", "a3"),
+        ("a1", "a4"),
+        ("a2", "a3"),
+        ("a2", "a4"),
+        ("a3", "a4"),
+        ("b1", "b2"),
+        ("b1", "b3"),
+        ("b1", "b4"),
+        ("b2", "b3"),
+        ("b2", "b4"),
+        ("b3", "b4"),
+        ("a1", "b1"),
+      ],
+      false,
+    )
+  }
+
+  #[test]
+  fn test_degree_and_coreness() {
+    let graph = network(&[("a", "b"), ("b", "c"), ("c", "a"), ("c", "d")], true);
+    assert_eq!(graph.degree(Mode::In), vec![1., 1., 1., 1.]);
+    assert_eq!(graph.degree(Mode::Out), vec![1., 1., 2., 0.]);
+    assert_eq!(graph.degree(Mode::All), vec![2., 2., 3., 1.]);
+    assert_eq!(graph.coreness(Mode::All), vec![2., 2., 2., 1.]);
+  }
+
+  #[test]
+  fn test_largest_component() {
+    let graph = network(&[("a", "b"), ("b", "c"), ("x", "y")], true);
+    let largest = graph.largest_component();
+    assert_eq!(largest.names, vec!["a", "b", "c"]);
+    assert_eq!(largest.edge_count(), 2);
+  }
+
+  #[test]
+  fn test_page_rank_and_hits() {
+    let graph = network(&[("a", "c"), ("b", "c"), ("c", "a")], true);
+    let rank = graph.page_rank();
+    assert!((rank.iter().sum::<f64>() - 1.).abs() < 1e-9);
+    assert!(rank[2] > rank[0] && rank[0] > rank[1]);
+
+    let (hubs, authorities) = graph.hits();
+    assert!((authorities[2] - 1.).abs() < 1e-9);
+    assert!(hubs[0] > hubs[2]);
+  }
+
+  #[test]
+  fn test_betweenness() {
+    let graph = network(&[("a", "b"), ("b", "c")], false);
+    assert_eq!(graph.betweenness(), vec![0., 1., 0.]);
+  }
+
+  #[test]
+  fn test_communities() {
+    let graph = two_cliques();
+    let expected = vec![vec![0, 1, 2, 3], vec![4, 5, 6, 7]];
+
+    let louvain = graph.louvain();
+    assert_eq!(communities(&louvain), expected);
+    assert!(graph.modularity(&louvain) > 0.4);
+
+    let walktrap = graph.walktrap();
+    assert_eq!(communities(&walktrap), expected);
+    assert!((graph.modularity(&walktrap) - graph.modularity(&louvain)).abs() < 1e-9);
+  }
+
+  #[test]
+  fn test_walktrap_discards_stale_pairs() {
+    // le triangle c, d, f et l'étoile autour de e : les distances calculées avant les
+    // premières fusions, restées dans le tas, isoleraient a dans une troisième communauté
+    let graph = network(
+      &[
+        ("c", "d"),
+        ("b", "c"),
+        ("c", "f"),
+        ("b", "e"),
+        ("e", "f"),
+        ("a", "e"),
+        ("d", "f"),
+      ],
+      false,
+    );
+    let walktrap = graph.walktrap();
+    assert_eq!(communities(&walktrap), vec![vec![0, 1, 4], vec![2, 3, 5]]);
+    assert!((graph.modularity(&walktrap) - 10. / 49.).abs() < 1e-9);
+  }
+}
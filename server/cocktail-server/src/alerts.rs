@@ -0,0 +1,602 @@
+//! Évaluation des alertes des études après un pipeline d'automatisation et envoi de
+//! leurs déclenchements par courriel ou webhook.
+
+use std::{fmt::Debug, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use chrono::{NaiveDateTime, Utc};
+use cocktail_db_web::{Alert, AlertEvent, ParsedProjectCriteria, WebDatabase};
+use serde_json::json;
+use thiserror::Error;
+use tokio::{
+  io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+  net::TcpStream,
+  task,
+  time::timeout,
+};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::error::WebError;
+
+/// Nombre de déclenchements affichés dans l'historique d'un projet
+pub const ALERT_HISTORY_SIZE: i64 = 50;
+
+/// Durée maximale d'un envoi : l'évaluation des alertes bloque le worker de jobs
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Error, Debug)]
+pub enum DeliveryError {
+  #[error("aucun serveur SMTP n'est configuré")]
+  NoMailer,
+  #[error("connexion au serveur SMTP impossible : {0}")]
+  Io(#[from] std::io::Error),
+  #[error("réponse inattendue du serveur SMTP : {0}")]
+  Smtp(String),
+  #[error("adresse de courriel invalide : {0}")]
+  InvalidAddress(String),
+  #[error("pas de réponse du serveur SMTP après {0:?}")]
+  Timeout(Duration),
+  #[error("webhook : {0}")]
+  Webhook(#[from] reqwest::Error),
+  #[error("webhook : {0}")]
+  WebhookNotAllowed(String),
+  #[error("redirection du webhook non suivie : {0}")]
+  WebhookRedirected(String),
+}
+
+/// Vérifie que `address` est une seule adresse `local@domaine`, sans espace ni caractère
+/// de contrôle : elle est recopiée telle quelle dans la commande `RCPT TO` et l'en-tête `To`
+pub fn is_valid_email(address: &str) -> bool {
+  let (local, domain) = match address.split_once('@') {
+    Some(parts) => parts,
+    None => return false,
+  };
+  let local_ok = !local.is_empty()
+    && local.len() <= 64
+    && local
+      .chars()
+      .all(|c| c.is_ascii_graphic() && !"<>()[]\\,;:\"@".contains(c));
+  let domain_ok = !domain.is_empty()
+    && domain.len() <= 255
+    && domain
+      .split('.')
+      .all(|label| {
+        !label.is_empty()
+          && !label.starts_with('-')
+          && !label.ends_with('-')
+          && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+      });
+
+  local_ok && domain_ok
+}
+
+/// Vérifie que `url` est une adresse http(s) dont l'hôte fait partie de `allowed_hosts` :
+/// le serveur n'appelle que les webhooks autorisés par l'exploitant, jamais ses services
+/// internes
+pub fn check_webhook_url(url: &str, allowed_hosts: &[String]) -> Result<(), String> {
+  let parsed = reqwest::Url::parse(url).map_err(|_| format!("adresse de webhook invalide : {url}"))?;
+  if !matches!(parsed.scheme(), "http" | "https") {
+    return Err(format!("adresse de webhook invalide : {url}"));
+  }
+  let host = parsed.host_str().unwrap_or_default().to_lowercase();
+  match allowed_hosts.iter().any(|allowed| allowed.eq_ignore_ascii_case(&host)) {
+    true => Ok(()),
+    false => Err(format!("hôte de webhook non autorisé : {host}")),
+  }
+}
+
+/// Courriel en texte brut
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mail {
+  pub to: String,
+  pub subject: String,
+  pub body: String,
+}
+
+/// Envoi des courriels d'alerte, remplaçable par un faux serveur dans les tests
+#[async_trait]
+pub trait MailSender: Debug + Send + Sync {
+  async fn send(&self, mail: &Mail) -> Result<(), DeliveryError>;
+}
+
+/// Client SMTP minimal, sans authentification ni chiffrement : il s'adresse au relais
+/// local du serveur (`localhost:25` par exemple)
+#[derive(Debug, Clone)]
+pub struct SmtpSender {
+  pub address: String,
+  pub from: String,
+}
+
+impl SmtpSender {
+  pub fn new(address: impl Into<String>, from: impl Into<String>) -> Self {
+    Self {
+      address: address.into(),
+      from: from.into(),
+    }
+  }
+
+  fn message(&self, mail: &Mail) -> String {
+    let body = mail
+      .body
+      .lines()
+      // un point en début de ligne terminerait le message
+      .map(|line| match line.starts_with('.') {
+        true => format!(".{line}\r\n"),
+        false => format!("{line}\r\n"),
+      })
+      .collect::<String>();
+
+    format!(
+      "From: {}\r\nTo: {}\r\nSubject: {}\r\nDate: {}\r\nMIME-Version: 1.0\r\nContent-Type: text/plain; charset=UTF-8\r\nContent-Transfer-Encoding: 8bit\r\n\r\n{body}.\r\n",
+      strip_controls(&self.from),
+      strip_controls(&mail.to),
+      encode_header(&mail.subject),
+      Utc::now().to_rfc2822(),
+    )
+  }
+
+  /// Conversation SMTP complète, de la connexion au `QUIT`
+  async fn converse(&self, mail: &Mail) -> Result<(), DeliveryError> {
+    let (reader, mut writer) = TcpStream::connect(&self.address).await?.into_split();
+    let mut reader = BufReader::new(reader);
+
+    expect_reply(&mut reader, "220").await?;
+    for (command, code) in [
+      ("EHLO cocktail".to_string(), "250"),
+      (format!("MAIL FROM:<{}>", self.from), "250"),
+      (format!("RCPT TO:<{}>", mail.to), "25"),
+      ("DATA".to_string(), "354"),
+    ] {
+      writer
+        .write_all(format!("{command}\r\n").as_bytes())
+        .await?;
+      expect_reply(&mut reader, code).await?;
+    }
+    writer.write_all(self.message(mail).as_bytes()).await?;
+    expect_reply(&mut reader, "250").await?;
+    // le message est accepté, la réponse au QUIT importe peu
+    writer.write_all(b"QUIT\r\n").await?;
+    let _ = expect_reply(&mut reader, "221").await;
+
+    Ok(())
+  }
+}
+
+#[async_trait]
+impl MailSender for SmtpSender {
+  async fn send(&self, mail: &Mail) -> Result<(), DeliveryError> {
+    if !is_valid_email(&mail.to) {
+      return Err(DeliveryError::InvalidAddress(mail.to.clone()));
+    }
+    if !is_valid_email(&self.from) {
+      return Err(DeliveryError::InvalidAddress(self.from.clone()));
+    }
+
+    timeout(DELIVERY_TIMEOUT, self.converse(mail))
+      .await
+      .map_err(|_| DeliveryError::Timeout(DELIVERY_TIMEOUT))?
+  }
+}
+
+/// Lit une réponse SMTP, éventuellement sur plusieurs lignes (`250-…` puis `250 …`),
+/// et vérifie qu'elle commence par `code`
+async fn expect_reply<R>(reader: &mut R, code: &str) -> Result<(), DeliveryError>
+where
+  R: AsyncBufReadExt + Unpin,
+{
+  loop {
+    let mut line = String::new();
+    if reader.read_line(&mut line).await? == 0 {
+      return Err(DeliveryError::Smtp("connexion fermée".to_string()));
+    }
+    if !line.starts_with(code) {
+      return Err(DeliveryError::Smtp(line.trim_end().to_string()));
+    }
+    if line.as_bytes().get(3) != Some(&b'-') {
+      return Ok(());
+    }
+  }
+}
+
+/// Retire les caractères de contrôle, dont CR et LF, d'une valeur d'en-tête
+fn strip_controls(value: &str) -> String {
+  value.chars().filter(|c| !c.is_control()).collect()
+}
+
+/// Encode un en-tête non ASCII ou contenant des caractères de contrôle (RFC 2047) :
+/// un CR/LF ne peut pas y ajouter d'en-tête
+fn encode_header(value: &str) -> String {
+  if value.is_ascii() && !value.chars().any(|c| c.is_ascii_control()) {
+    return value.to_string();
+  }
+
+  let encoded = value
+    .bytes()
+    .map(|byte| match byte {
+      b' ' => "_".to_string(),
+      b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' => (byte as char).to_string(),
+      _ => format!("={byte:02X}"),
+    })
+    .collect::<String>();
+
+  format!("=?UTF-8?Q?{encoded}?=")
+}
+
+/// Envoi des déclenchements d'alerte
+#[derive(Debug, Clone)]
+pub struct AlertNotifier {
+  mailer: Option<Arc<dyn MailSender>>,
+  http: reqwest::Client,
+  webhook_hosts: Arc<Vec<String>>,
+}
+
+impl Default for AlertNotifier {
+  fn default() -> Self {
+    Self::new(None, vec![])
+  }
+}
+
+impl AlertNotifier {
+  /// `webhook_hosts` : hôtes que les webhooks des alertes peuvent appeler, aucun par défaut
+  pub fn new(mailer: Option<Arc<dyn MailSender>>, webhook_hosts: Vec<String>) -> Self {
+    Self {
+      mailer,
+      // une redirection mènerait hors des hôtes autorisés sans repasser par
+      // `check_webhook_url`
+      http: reqwest::Client::builder()
+        .timeout(DELIVERY_TIMEOUT)
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .expect("erreur : impossible de créer le client HTTP des webhooks"),
+      webhook_hosts: Arc::new(webhook_hosts),
+    }
+  }
+
+  /// Envoie `event` aux destinataires de l'alerte ; renvoie le compte rendu de l'envoi,
+  /// `None` si l'alerte n'a ni courriel ni webhook
+  pub async fn deliver(
+    &self,
+    project_title: &str,
+    alert: &Alert,
+    event: &AlertEvent,
+  ) -> Option<String> {
+    let mut reports = vec![];
+
+    if let Some(email) = &alert.email {
+      let mail = alert_mail(project_title, alert, event, email);
+      let sent = match &self.mailer {
+        Some(mailer) => mailer.send(&mail).await,
+        None => Err(DeliveryError::NoMailer),
+      };
+      reports.push(match sent {
+        Ok(()) => format!("courriel envoyé à {email}"),
+        Err(e) => format!("courriel non envoyé : {e}"),
+      });
+    }
+
+    if let Some(webhook_url) = &alert.webhook_url {
+      let payload = json!({
+        "alert_id": alert.alert_id,
+        "alert_name": alert.name,
+        "project_id": alert.project_id,
+        "project_title": project_title,
+        "condition": alert.condition.0,
+        "triggered_at": event.triggered_at,
+        "evaluation": event.evaluation.0,
+      });
+      let sent = match check_webhook_url(webhook_url, &self.webhook_hosts) {
+        Ok(()) => self
+          .http
+          .post(webhook_url)
+          .json(&payload)
+          .send()
+          .await
+          .and_then(|response| response.error_for_status())
+          .map_err(DeliveryError::from)
+          .and_then(|response| match response.status().is_redirection() {
+            true => Err(DeliveryError::WebhookRedirected(
+              response
+                .headers()
+                .get(reqwest::header::LOCATION)
+                .and_then(|location| location.to_str().ok())
+                .unwrap_or_default()
+                .to_string(),
+            )),
+            false => Ok(response),
+          }),
+        Err(e) => Err(DeliveryError::WebhookNotAllowed(e)),
+      };
+      reports.push(match sent {
+        Ok(response) => format!("webhook : {}", response.status()),
+        Err(e) => format!("webhook non appelé : {e}"),
+      });
+    }
+
+    match reports.is_empty() {
+      true => None,
+      false => Some(reports.join(" ; ")),
+    }
+  }
+}
+
+fn alert_mail(project_title: &str, alert: &Alert, event: &AlertEvent, to: &str) -> Mail {
+  let mut body = format!(
+    "L'alerte « {} » de l'étude « {project_title} » s'est déclenchée ({}).\n\n{}\n",
+    alert.name,
+    alert.condition.describe(),
+    event.summary,
+  );
+  if !event.evaluation.tweets.is_empty() {
+    body.push_str("\nTweets les plus récents :\n");
+    for tweet in &event.evaluation.tweets {
+      body.push_str(&format!(
+        "\n@{} ({}) :\n{}\n",
+        tweet.user_screen_name,
+        tweet.published_time.format("%d/%m/%Y %H:%M"),
+        tweet.text,
+      ));
+    }
+  }
+
+  Mail {
+    to: to.to_string(),
+    subject: format!("[Cocktail] {} : {}", alert.name, event.summary),
+    body,
+  }
+}
+
+/// Évalue sur `index` les alertes actives dont l'échéance est passée, enregistre leurs
+/// déclenchements et les envoie ; renvoie le nombre d'alertes déclenchées
+pub async fn evaluate_alerts(
+  db: &WebDatabase,
+  index: &fts::Index,
+  notifier: &AlertNotifier,
+  now: NaiveDateTime,
+) -> Result<usize, WebError> {
+  let mut triggered = 0;
+
+  for alert in cocktail_db_web::active_alerts(db).await? {
+    if !alert.schedule.is_due(alert.last_evaluated_at, now) {
+      continue;
+    }
+
+    let project_id =
+      Uuid::parse_str(&alert.project_id).map_err(|e| WebError::WTFError(e.to_string()))?;
+    let project =
+      match cocktail_db_web::project(db, project_id.to_hyphenated(), &alert.user_id).await {
+        Ok(project) => project,
+        Err(sqlx::Error::RowNotFound) => {
+          // projet supprimé
+          cocktail_db_web::delete_alert(db, &alert.alert_id, &alert.user_id).await?;
+          continue;
+        }
+        Err(e) => return Err(e.into()),
+      };
+
+    let since = alert.last_evaluated_at.unwrap_or(alert.created_at);
+    let background = index.clone();
+    let condition = alert.condition.0.clone();
+    let evaluation = task::spawn_blocking(move || {
+      let parsed_criteria = ParsedProjectCriteria::from(&project);
+      let criteria = fts::AlertCriteria {
+        hashtag_list: &parsed_criteria.hashtag_list,
+        exclude_hashtag_list: &parsed_criteria.exclude_hashtag_list,
+        request_params: &project.request_params,
+        filters: &project.filters,
+      };
+      fts::evaluate_alert(&background, criteria, &condition, since, now)
+        .map(|evaluation| (project.title, evaluation))
+    })
+    .await
+    .map_err(|e| WebError::WTFError(e.to_string()))?;
+    let (project_title, evaluation) = match evaluation {
+      Ok(evaluation) => evaluation,
+      Err(e) => {
+        // une requête devenue invalide ne bloque pas les autres alertes
+        warn!("Alerte {} : évaluation impossible : {}", alert.alert_id, e);
+        continue;
+      }
+    };
+    cocktail_db_web::set_alert_evaluated(db, &alert.alert_id, now).await?;
+
+    if !evaluation.triggered {
+      continue;
+    }
+    triggered += 1;
+    info!(
+      "Alerte {} déclenchée : {}",
+      alert.alert_id, evaluation.summary
+    );
+    let event = cocktail_db_web::create_alert_event(db, &alert, &evaluation, now).await?;
+    if let Some(delivery) = notifier.deliver(&project_title, &alert, &event).await {
+      cocktail_db_web::set_alert_event_delivery(db, &event.alert_event_id, &delivery).await?;
+    }
+  }
+
+  Ok(triggered)
+}
+
+#[cfg(test)]
+mod tests {
+  use cocktail_db_web::AlertSchedule;
+  use fts::{AlertCondition, AlertEvaluation};
+  use sqlx::types::Json;
+  use tokio::{io::AsyncReadExt, net::TcpListener};
+
+  use super::*;
+
+  #[tokio::test]
+  async fn test_smtp_sender() {
+    // faux serveur SMTP : répond à chaque commande et renvoie la conversation
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let server = tokio::spawn(async move {
+      let (mut socket, _) = listener.accept().await.unwrap();
+      socket.write_all(b"220 localhost ESMTP\r\n").await.unwrap();
+      let mut received = String::new();
+      let mut in_data = false;
+      loop {
+        let mut buffer = [0; 4096];
+        let read = socket.read(&mut buffer).await.unwrap();
+        if read == 0 {
+          break;
+        }
+        received.push_str(&String::from_utf8_lossy(&buffer[..read]));
+        let reply: &[u8] = if in_data {
+          if !received.ends_with("\r\n.\r\n") {
+            continue;
+          }
+          in_data = false;
+          b"250 OK\r\n"
+        } else if received.ends_with("EHLO cocktail\r\n") {
+          b"250-localhost\r\n250 8BITMIME\r\n"
+        } else if received.ends_with("DATA\r\n") {
+          in_data = true;
+          b"354 go ahead\r\n"
+        } else if received.ends_with("QUIT\r\n") {
+          socket.write_all(b"221 bye\r\n").await.unwrap();
+          break;
+        } else {
+          b"250 OK\r\n"
+        };
+        socket.write_all(reply).await.unwrap();
+      }
+      received
+    });
+
+    let sender = SmtpSender::new(address, "cocktail@localhost");
+    sender
+      .send(&Mail {
+        to: "chercheuse@example.org".to_string(),
+        subject: "Pic du hashtag #loup".to_string(),
+        body: "première ligne\n.ligne commençant par un point".to_string(),
+      })
+      .await
+      .unwrap();
+
+    let received = server.await.unwrap();
+    assert!(received.contains("MAIL FROM:<cocktail@localhost>\r\n"));
+    assert!(received.contains("RCPT TO:<chercheuse@example.org>\r\n"));
+    assert!(received.contains("Subject: Pic du hashtag #loup\r\n"));
+    assert!(received.contains("\r\n\r\npremière ligne\r\n..ligne commençant par un point\r\n.\r\n"));
+
+    assert_eq!(encode_header("élevage"), "=?UTF-8?Q?=C3=A9levage?=");
+    assert_eq!(
+      encode_header("loup\r\nBcc: x@example.org"),
+      "=?UTF-8?Q?loup=0D=0ABcc=3A_x=40example=2Eorg?="
+    );
+  }
+
+  #[tokio::test]
+  async fn test_smtp_sender_rejects_injection() {
+    // aucune connexion n'est tentée : l'adresse n'a pas de serveur
+    let sender = SmtpSender::new("127.0.0.1:9", "cocktail@localhost");
+    for to in [
+      "chercheuse@example.org>\r\nRCPT TO:<autre@example.org",
+      "chercheuse@example.org, autre@example.org",
+      "chercheuse",
+      "chercheuse@exa mple.org",
+    ] {
+      let error = sender
+        .send(&Mail {
+          to: to.to_string(),
+          subject: "test".to_string(),
+          body: "test".to_string(),
+        })
+        .await
+        .unwrap_err();
+      assert!(matches!(error, DeliveryError::InvalidAddress(_)), "{to}");
+    }
+  }
+
+  #[test]
+  fn test_check_webhook_url() {
+    let allowed = vec!["hooks.example.org".to_string()];
+    assert!(check_webhook_url("https://hooks.example.org/cocktail", &allowed).is_ok());
+    assert!(check_webhook_url("https://HOOKS.example.org/cocktail", &allowed).is_ok());
+    assert!(check_webhook_url("http://127.0.0.1:8080/admin", &allowed).is_err());
+    assert!(check_webhook_url("file:///etc/passwd", &allowed).is_err());
+    assert!(check_webhook_url("https://hooks.example.org/cocktail", &[]).is_err());
+  }
+
+  #[tokio::test]
+  async fn test_webhook_redirect_not_followed() {
+    // le webhook autorisé renvoie vers un hôte qui ne l'est pas
+    let internal = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let internal_port = internal.local_addr().unwrap().port();
+    let webhook = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let webhook_port = webhook.local_addr().unwrap().port();
+    tokio::spawn(async move {
+      let (mut socket, _) = webhook.accept().await.unwrap();
+      let mut buffer = [0; 4096];
+      assert!(socket.read(&mut buffer).await.unwrap() > 0);
+      let response = format!(
+        "HTTP/1.1 307 Temporary Redirect\r\nLocation: http://localhost:{internal_port}/admin\r\nContent-Length: 0\r\n\r\n"
+      );
+      socket.write_all(response.as_bytes()).await.unwrap();
+    });
+
+    let notifier = AlertNotifier::new(None, vec!["127.0.0.1".to_string()]);
+    let alert = Alert {
+      alert_id: "alerte".to_string(),
+      project_id: "etude".to_string(),
+      user_id: "chercheuse".to_string(),
+      name: "Pic du hashtag #loup".to_string(),
+      condition: Json(AlertCondition::NewTweets { threshold: 0 }),
+      schedule: AlertSchedule::EachRun,
+      email: None,
+      webhook_url: Some(format!("http://127.0.0.1:{webhook_port}/cocktail")),
+      active: true,
+      last_evaluated_at: None,
+      created_at: Utc::now().naive_utc(),
+    };
+    let evaluation = AlertEvaluation {
+      triggered: true,
+      summary: "1 nouveau tweet".to_string(),
+      observed: 1,
+      threshold: 0.0,
+      tweets: vec![],
+    };
+    let event = AlertEvent {
+      alert_event_id: "declenchement".to_string(),
+      alert_id: alert.alert_id.clone(),
+      alert_name: alert.name.clone(),
+      summary: evaluation.summary.clone(),
+      evaluation: Json(evaluation),
+      delivery: None,
+      triggered_at: Utc::now().naive_utc(),
+    };
+    let report = notifier.deliver("Loup", &alert, &event).await.unwrap();
+
+    assert!(
+      report.starts_with("webhook non appelé : redirection"),
+      "{report}"
+    );
+    let followed = timeout(Duration::from_millis(200), internal.accept()).await;
+    assert!(followed.is_err());
+  }
+
+  #[tokio::test]
+  async fn test_smtp_sender_rejected() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    tokio::spawn(async move {
+      let (mut socket, _) = listener.accept().await.unwrap();
+      socket
+        .write_all(b"554 no SMTP service here\r\n")
+        .await
+        .unwrap();
+    });
+
+    let error = SmtpSender::new(address, "cocktail@localhost")
+      .send(&Mail {
+        to: "chercheuse@example.org".to_string(),
+        subject: "test".to_string(),
+        body: "test".to_string(),
+      })
+      .await
+      .unwrap_err();
+    assert!(matches!(error, DeliveryError::Smtp(reply) if reply.starts_with("554")));
+  }
+}
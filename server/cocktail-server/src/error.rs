@@ -31,6 +31,8 @@ pub enum WebError {
   Community(String),
   #[error("Export impossible : {0}")]
   ExportImpossible(String),
+  #[error("{0}")]
+  OutdatedIndex(String),
 }
 
 impl IntoResponse for WebError {
@@ -48,6 +50,14 @@ impl IntoResponse for WebError {
         )
           .into_response()
       }
+      WebError::OutdatedIndex(e) => {
+        tracing::error!("{e}");
+        (
+          StatusCode::SERVICE_UNAVAILABLE,
+          Html(format!("<h1>INDEX À MIGRER</h1><p>{e}</p>")),
+        )
+          .into_response()
+      }
       _ => {
         tracing::error!("{}", self);
         (
@@ -68,7 +78,10 @@ impl From<hyper::Error> for WebError {
 
 impl From<fts::SearchError> for WebError {
   fn from(e: fts::SearchError) -> Self {
-    WebError::WTFError(e.to_string())
+    match e {
+      fts::SearchError::OutdatedIndex { .. } => WebError::OutdatedIndex(e.to_string()),
+      _ => WebError::WTFError(e.to_string()),
+    }
   }
 }
 
@@ -85,6 +98,7 @@ impl From<DbTwitterError> for WebError {
   fn from(e: DbTwitterError) -> Self {
     match e {
       DbTwitterError::SQL { source: _ } => WebError::NotFound,
+      DbTwitterError::IO { source } => WebError::WTFError(source.to_string()),
     }
   }
 }
@@ -0,0 +1,113 @@
+use chrono::{Local, NaiveDateTime};
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+/// Nombre d'évènements conservés pour un abonné trop lent avant qu'il n'en perde
+const CAPACITY: usize = 256;
+
+/// Traitement d'un projet dont la progression est diffusée
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProjectStep {
+  IndexCopy,
+  Ingestion,
+  Charts,
+  Graph,
+  Automation,
+}
+
+impl ProjectStep {
+  /// identifiant utilisé dans les templates (`project-progress-<id>`)
+  pub fn id(&self) -> &'static str {
+    match self {
+      ProjectStep::IndexCopy => "index_copy",
+      ProjectStep::Ingestion => "ingestion",
+      ProjectStep::Charts => "charts",
+      ProjectStep::Graph => "graph",
+      ProjectStep::Automation => "automation",
+    }
+  }
+
+  pub fn label(&self) -> &'static str {
+    match self {
+      ProjectStep::IndexCopy => "Copie de l'index",
+      ProjectStep::Ingestion => "Ingestion des tweets",
+      ProjectStep::Charts => "Calcul des graphiques",
+      ProjectStep::Graph => "Analyse des communautés",
+      ProjectStep::Automation => "Import",
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StepStatus {
+  Started,
+  Completed,
+  Failed,
+}
+
+impl StepStatus {
+  pub fn label(&self) -> &'static str {
+    match self {
+      StepStatus::Started => "en cours",
+      StepStatus::Completed => "terminé",
+      StepStatus::Failed => "erreur",
+    }
+  }
+}
+
+/// Transition d'une étape de traitement d'un projet
+#[derive(Debug, Clone, Serialize)]
+pub struct ProjectEvent {
+  pub project_id: String,
+  pub step: ProjectStep,
+  pub status: StepStatus,
+  /// précision sur l'étape : nom de l'étape d'import, graphe concerné, message d'erreur…
+  pub detail: Option<String>,
+  pub datetime: NaiveDateTime,
+}
+
+impl ProjectEvent {
+  pub fn new<P: ToString>(project_id: P, step: ProjectStep, status: StepStatus) -> Self {
+    Self {
+      project_id: project_id.to_string(),
+      step,
+      status,
+      detail: None,
+      datetime: Local::now().naive_local(),
+    }
+  }
+
+  pub fn detail<D: Into<String>>(mut self, detail: D) -> Self {
+    self.detail = Some(detail.into());
+    self
+  }
+}
+
+/// Canal de diffusion des évènements de tous les projets ; chaque flux SSE ne garde que ceux
+/// de son projet.
+#[derive(Debug, Clone)]
+pub struct ProjectEvents(broadcast::Sender<ProjectEvent>);
+
+impl ProjectEvents {
+  pub fn new() -> Self {
+    let (sender, _) = broadcast::channel(CAPACITY);
+    Self(sender)
+  }
+
+  pub fn publish(&self, event: ProjectEvent) {
+    // aucune page n'écoute : l'évènement est simplement perdu
+    let _ = self.0.send(event);
+  }
+
+  pub fn subscribe(&self) -> broadcast::Receiver<ProjectEvent> {
+    self.0.subscribe()
+  }
+}
+
+impl Default for ProjectEvents {
+  fn default() -> Self {
+    Self::new()
+  }
+}
@@ -1,4 +1,4 @@
-use std::{fmt, fs, net::SocketAddr, path::PathBuf, str::FromStr};
+use std::{fmt, fs, net::SocketAddr, path::PathBuf, str::FromStr, sync::Arc};
 
 use axum::{
   async_trait,
@@ -35,12 +35,13 @@ use tracing::log::LevelFilter;
 
 use crate::{
   error::WebError,
+  events::ProjectEvents,
   routes::{
-    charts::{hashtags as chart_hashtags, tweets},
+    charts::{hashtags as chart_hashtags, keywords, links, tweets},
     home, index,
     projects::{
-      basket::*, collect::*, daterange::*, delete::*, download::*, duplicate::*, hashtags::*, nouveau::*,
-      projects, rename::*, request::*, update::*, import::*,
+      alerts::*, basket::*, collect::*, daterange::*, delete::*, download::*, duplicate::*, events::*, explain::*, hashtags::*,
+      nouveau::*, projects, rename::*, request::*, update::*, import::*,
     },
     study::{analysis::*, authors::*, communities::*, results},
     csv_import,
@@ -49,7 +50,9 @@ use crate::{
 };
 use futures::future;
 
+pub mod alerts;
 pub mod error;
+pub mod events;
 mod helpers;
 mod models;
 mod routes;
@@ -66,8 +69,14 @@ pub struct AppState {
   pub kratos_browser_url: String,
   pub directory_path: PathBuf,
   pub database_url: String,
-  pub r_script: PathBuf,
-  pub python_script: PathBuf,
+  pub r_script: Option<PathBuf>,
+  pub python_script: Option<PathBuf>,
+  pub events: ProjectEvents,
+  pub timezone: fts::Tz,
+  /// hôtes que les webhooks des alertes peuvent appeler
+  pub webhook_hosts: Arc<Vec<String>>,
+  /// connexions des flux d'évènements des projets au statut des graphes
+  pub graph_status_db: sqlx::PgPool,
 }
 
 impl Default for AppState {
@@ -93,9 +102,14 @@ impl Default for AppState {
       kratos_configuration: Configuration::new(),
       kratos_browser_url: String::new(),
       directory_path: tantivy_path,
+      r_script: None,
+      python_script: None,
+      events: ProjectEvents::new(),
+      timezone: fts::Tz::UTC,
+      webhook_hosts: Default::default(),
+      graph_status_db: graph_status_pool(&database_url)
+        .expect("Failed to create graph status pool"),
       database_url,
-      r_script: PathBuf::new(),
-      python_script: PathBuf::new(),
     }
   }
 }
@@ -118,6 +132,12 @@ impl FromRef<AppState> for TopKDatabase {
   }
 }
 
+impl FromRef<AppState> for fts::Tz {
+  fn from_ref(input: &AppState) -> Self {
+    input.timezone
+  }
+}
+
 pub struct Databases {
   pub web_database_path: PathBuf,
   pub topk_database_path: PathBuf,
@@ -129,9 +149,28 @@ pub struct Kratos {
   pub kratos_browser_url: String,
 }
 
+/// Scripts R / Python historiques, les graphes sont calculés en Rust lorsqu'ils sont absents.
 pub struct Scripts {
-  pub r_script: PathBuf,
-  pub python_script: PathBuf,
+  pub r_script: Option<PathBuf>,
+  pub python_script: Option<PathBuf>,
+}
+
+/// Nombre de workers exécutant les jobs du pipeline d'automatisation et envoi des alertes
+/// évaluées après chacun d'eux
+pub struct Jobs {
+  pub workers: usize,
+  /// relais SMTP des courriels d'alerte, aucun courriel n'est envoyé sans lui
+  pub smtp_address: Option<String>,
+  pub mail_from: String,
+  /// hôtes autorisés pour les webhooks des alertes, aucun webhook n'est appelé sans eux
+  pub webhook_hosts: Vec<String>,
+  /// options des index que le pipeline crée
+  pub index_options: fts::IndexOptions,
+}
+
+/// Fuseau horaire des intervalles des graphiques
+pub struct Charts {
+  pub timezone: fts::Tz,
 }
 
 pub async fn run(
@@ -146,6 +185,14 @@ pub async fn run(
     r_script,
     python_script,
   }: Scripts,
+  Jobs {
+    workers,
+    smtp_address,
+    mail_from,
+    webhook_hosts,
+    index_options,
+  }: Jobs,
+  Charts { timezone }: Charts,
 ) -> Result<(), WebError> {
   cocktail_db_web::create_database(&databases.web_database_path).await;
 
@@ -194,8 +241,7 @@ pub async fn run(
   .expect("ah bah marde");
   opts_topk_db.log_statements(LevelFilter::Trace);
 
-  let pool_topk = SqlitePoolOptions::new()
-    .connect_with(opts_topk_db)
+  let topk_db = TopKDatabase::connect(opts_topk_db)
     .await
     .expect("erreur : impossible de se connecter à la base de données `topk`.");
   cocktail_db_web::migrate(pool.clone())
@@ -204,10 +250,23 @@ pub async fn run(
 
   let turbo_stream = middleware::from_fn(turbo_stream);
 
+  let events = ProjectEvents::new();
+  let mailer = smtp_address.map(|address| {
+    Arc::new(alerts::SmtpSender::new(address, mail_from)) as Arc<dyn alerts::MailSender>
+  });
+  let job_queue = automation::JobQueue::new(
+    WebDatabase::new(pool.clone()),
+    events.clone(),
+    alerts::AlertNotifier::new(mailer, webhook_hosts.clone()),
+    index_options,
+  );
+  job_queue.start(workers).await?;
+
   let search_index = fts::retrieve_index(tantivy_path.clone())?;
+  let graph_status_db = graph_status_pool(&databases.pg_uri)?;
   let state = AppState {
     db: WebDatabase::new(pool),
-    topk_db: TopKDatabase::new(pool_topk),
+    topk_db,
     index: search_index,
     kratos_configuration,
     database_url: databases.pg_uri,
@@ -215,6 +274,10 @@ pub async fn run(
     python_script,
     directory_path: tantivy_path,
     kratos_browser_url,
+    events,
+    timezone,
+    webhook_hosts: Arc::new(webhook_hosts),
+    graph_status_db,
   };
 
   let routes: Router<AppState> = Router::with_state(state.clone())
@@ -229,6 +292,7 @@ pub async fn run(
     .typed_post(rename_project.layer(turbo_stream))
     .typed_post(duplicate_project)
     .typed_get(download_project)
+    .typed_get(project_events)
     .typed_get(daterange)
     .typed_get(collect)
     .typed_get(hashtags)
@@ -241,6 +305,12 @@ pub async fn run(
     .typed_get(hashtags_search)
     .typed_get(request)
     .typed_post(request_update.layer(turbo_stream))
+    .typed_get(request_explain)
+    .typed_get(api_request_explain)
+    .typed_post(api_request_explain_dry_run)
+    .typed_get(project_alerts)
+    .typed_post(create_project_alert)
+    .typed_post(delete_project_alert)
     .typed_get(keywords_popup.layer(turbo_stream))
     .typed_get(accounts_popup.layer(turbo_stream))
     .typed_post(add_to_include_basket.layer(turbo_stream))
@@ -265,13 +335,20 @@ pub async fn run(
     .typed_get(authors_tab)
     .typed_get(chart_hashtags::hashtags)
     .typed_get(chart_hashtags::hashtags_tab)
+    .typed_get(keywords::keywords)
+    .typed_get(keywords::keywords_tab)
+    .typed_get(links::links)
+    .typed_get(links::links_tab)
     .typed_get(communities)
     .typed_get(communities_tab)
     .typed_post(reload_communities)
     .route("/static/*file", get(static_handler))
     .route("/projets/:project_id/import", get(import))
-    .merge(csv_import::routes())
-    .merge(automation::routes())
+    .merge(csv_import::routes(job_queue.clone()))
+    .merge(automation::routes(
+      job_queue,
+      state.kratos_configuration.clone(),
+    ))
     .fallback(fallback)
     .layer(Extension(handlebars_registry))
     .layer(TraceLayer::new_for_http());
@@ -498,8 +575,8 @@ pub async fn clear_anynomous_studies(databases: Databases) -> Result<(), WebErro
     let graph_generator = cocktail_graph_utils::GraphGenerator::new(
       databases.pg_uri.clone(),
       project_id.to_string(),
-      PathBuf::new(),
-      PathBuf::new(),
+      None,
+      None,
       "".to_string(),
       "".to_string(),
       "".to_string(),
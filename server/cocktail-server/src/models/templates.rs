@@ -9,15 +9,21 @@ use axum::{
 use chrono::{NaiveDate, NaiveDateTime};
 use cocktail_db_web::{Bloc, HashtagWithCount, TweetsChart};
 use cocktail_graph_utils::{JsonDataGraph, Status};
-use fts::{Author, AuthorCount, Frequence, FrequenceCooccurence, Tweet};
+use fts::{
+  AnnotatedBurst, AssociationMeasure, Author, AuthorCount, BurstMethod, CooccurenceOptions,
+  Frequence, FrequenceCooccurence, Granularity, HashtagBurst, HighlightedTweet, KeywordCount,
+  LanguageCount, SearchCursor, SharedLink, Tweet,
+};
 use hyper::header;
 use uuid::Uuid;
 
+use crate::events::ProjectEvent;
 use crate::routes::{
   paths::{
     self, ProjectAllToggle, ProjectAsideHashtag, ProjectCooccurenceToggle, ProjectHashtagToggle,
     ProjectImport, ProjectCollect,
   },
+  projects::request::FiltersForm,
   study::results::FilterAuthor,
 };
 
@@ -49,6 +55,7 @@ pub(crate) struct DateRange {
   pub delete_popup_path: paths::PopupDeleteProject,
   pub rename_popup_path: paths::PopupRenameProject,
   pub download_path: paths::DownloadProject,
+  pub events_path: paths::ProjectEvents,
   pub duplicate_popup_path: paths::PopupDuplicateProject,
   pub analysis_preview_popup_path: paths::PopupAnalysisPreview,
   pub analysis_path: paths::ProjectAnalysis,
@@ -57,6 +64,8 @@ pub(crate) struct DateRange {
   pub tweets_graph_path: paths::ProjectTweetsGraph,
   pub authors_path: paths::ProjectAuthors,
   pub result_hashtags_path: paths::ProjectResultHashtags,
+  pub keywords_path: paths::ProjectResultKeywords,
+  pub links_path: paths::ProjectResultLinks,
   pub communities_path: paths::Communities,
   pub logout_url: String,
   pub include_count: i64,
@@ -82,6 +91,7 @@ pub(crate) struct Hashtags {
   pub delete_popup_path: paths::PopupDeleteProject,
   pub rename_popup_path: paths::PopupRenameProject,
   pub download_path: paths::DownloadProject,
+  pub events_path: paths::ProjectEvents,
   pub duplicate_popup_path: paths::PopupDuplicateProject,
   pub include_basket_path: paths::ProjectBasketInclude,
   pub exclude_basket_path: paths::ProjectBasketExclude,
@@ -97,6 +107,8 @@ pub(crate) struct Hashtags {
   pub tweets_graph_path: paths::ProjectTweetsGraph,
   pub authors_path: paths::ProjectAuthors,
   pub result_hashtags_path: paths::ProjectResultHashtags,
+  pub keywords_path: paths::ProjectResultKeywords,
+  pub links_path: paths::ProjectResultLinks,
   pub communities_path: paths::Communities,
   pub logout_url: String,
   pub niveau: i64,
@@ -125,7 +137,12 @@ pub(crate) struct PopupHashtagsCorpus;
 #[derive(Debug, Template)]
 #[template(path = "hashtags-popup/_topk.html")]
 pub(crate) struct PopupHashtagsTopK {
+  pub topk_path: paths::PopupHashtagsTopK,
   pub hashtags: Vec<Item>,
+  /// hashtags en tendance sur `trend_window`, de scores `trend_scores`
+  pub trending: bool,
+  pub trend_window: cocktail_db_twitter::TrendWindow,
+  pub trend_scores: Vec<f64>,
   // pub basket_path: ProjectBasket,
   pub include_basket_path: paths::ProjectBasketInclude,
   pub exclude_basket_path: paths::ProjectBasketExclude,
@@ -181,6 +198,11 @@ pub(crate) struct Request {
   pub collect_path: paths::ProjectCollect,
   pub import_path: paths::ProjectImport,
   pub request_params: Vec<Vec<Bloc>>,
+  pub query_text: String,
+  pub query_error: Option<String>,
+  pub filters: FiltersForm,
+  pub explain_path: paths::ProjectRequestExplain,
+  pub alerts_path: paths::ProjectAlerts,
   pub popup_hashtags_path: paths::PopupHashtags,
   pub popup_keywords_path: paths::PopupKeywords,
   pub popup_accounts_path: paths::PopupAccounts,
@@ -191,10 +213,13 @@ pub(crate) struct Request {
   pub tweets_graph_path: paths::ProjectTweetsGraph,
   pub authors_path: paths::ProjectAuthors,
   pub result_hashtags_path: paths::ProjectResultHashtags,
+  pub keywords_path: paths::ProjectResultKeywords,
+  pub links_path: paths::ProjectResultLinks,
   pub communities_path: paths::Communities,
   pub delete_popup_path: paths::PopupDeleteProject,
   pub rename_popup_path: paths::PopupRenameProject,
   pub download_path: paths::DownloadProject,
+  pub events_path: paths::ProjectEvents,
   pub duplicate_popup_path: paths::PopupDuplicateProject,
   pub logout_url: String,
   pub include_count: i64,
@@ -219,6 +244,11 @@ pub(crate) struct Request {
 )]
 pub(crate) struct BlocsUpdate {
   pub request_params: Vec<Vec<Bloc>>,
+  pub query_text: String,
+  pub query_error: Option<String>,
+  pub filters: FiltersForm,
+  pub explain_path: paths::ProjectRequestExplain,
+  pub alerts_path: paths::ProjectAlerts,
   pub popup_hashtags_path: paths::PopupHashtags,
   pub popup_keywords_path: paths::PopupKeywords,
   pub popup_accounts_path: paths::PopupAccounts,
@@ -259,26 +289,33 @@ pub(crate) struct Results {
   pub tweets_graph_path: paths::ProjectTweetsGraph,
   pub authors_path: paths::ProjectAuthors,
   pub result_hashtags_path: paths::ProjectResultHashtags,
+  pub keywords_path: paths::ProjectResultKeywords,
+  pub links_path: paths::ProjectResultLinks,
   pub communities_path: paths::Communities,
   pub delete_popup_path: paths::PopupDeleteProject,
   pub rename_popup_path: paths::PopupRenameProject,
   pub duplicate_popup_path: paths::PopupDuplicateProject,
   pub download_path: paths::DownloadProject,
+  pub events_path: paths::ProjectEvents,
   pub logout_url: String,
   pub include_count: i64,
   pub exclude_count: i64,
   pub niveau: i64,
   pub last_login_datetime: NaiveDateTime,
   pub title: String,
-  pub tweets: Vec<Tweet>,
+  pub tweets: Vec<HighlightedTweet>,
+  /// position de lecture de la page suivante, absente sur la dernière page
+  pub next_cursor: Option<SearchCursor>,
   pub frequences: Vec<Frequence>,
   pub frequences_topk: Vec<Frequence>,
   pub frequences_cooccurence: Vec<FrequenceCooccurence>,
   pub authors: Vec<FilterAuthor>,
+  pub languages: Vec<LanguageCount>,
   pub tab: String,
   pub aside_hashtag_tab: String,
   pub user_screen_name: String,
   pub date: Option<NaiveDate>,
+  pub end_date: Option<NaiveDate>,
   pub hashtag: Option<String>,
   pub page: u32,
   pub order: String,
@@ -301,10 +338,13 @@ pub(crate) struct Authors {
   pub tweets_graph_path: paths::ProjectTweetsGraph,
   pub authors_path: paths::ProjectAuthors,
   pub result_hashtags_path: paths::ProjectResultHashtags,
+  pub keywords_path: paths::ProjectResultKeywords,
+  pub links_path: paths::ProjectResultLinks,
   pub communities_path: paths::Communities,
   pub delete_popup_path: paths::PopupDeleteProject,
   pub rename_popup_path: paths::PopupRenameProject,
   pub download_path: paths::DownloadProject,
+  pub events_path: paths::ProjectEvents,
   pub duplicate_popup_path: paths::PopupDuplicateProject,
   pub logout_url: String,
   pub include_count: i64,
@@ -319,6 +359,81 @@ pub(crate) struct Authors {
   pub authors_count: i64,
 }
 
+/// Classement des mots clés ou des liens de l'étude, et histogramme de ses premiers éléments
+pub(crate) struct RankingChart {
+  /// page du panneau des résultats
+  pub active_page: &'static str,
+  pub path: String,
+  /// paramètre d'URL du classement, `classement` ou `vue`
+  pub ranking_param: &'static str,
+  pub ranking: String,
+  pub tab: String,
+  pub granularity: Granularity,
+  pub frequences: Vec<Frequence>,
+}
+
+#[derive(Template)]
+#[template(path = "result_keywords.html")]
+pub(crate) struct ResultKeywords {
+  pub daterange_path: paths::ProjectDateRange,
+  pub request_path: paths::ProjectRequest,
+  pub collect_path: paths::ProjectCollect,
+  pub import_path: paths::ProjectImport,
+  pub analysis_preview_popup_path: paths::PopupAnalysisPreview,
+  pub analysis_path: paths::ProjectAnalysis,
+  pub results_path: paths::ProjectResults,
+  pub tweets_graph_path: paths::ProjectTweetsGraph,
+  pub authors_path: paths::ProjectAuthors,
+  pub result_hashtags_path: paths::ProjectResultHashtags,
+  pub keywords_path: paths::ProjectResultKeywords,
+  pub links_path: paths::ProjectResultLinks,
+  pub communities_path: paths::Communities,
+  pub delete_popup_path: paths::PopupDeleteProject,
+  pub rename_popup_path: paths::PopupRenameProject,
+  pub download_path: paths::DownloadProject,
+  pub events_path: paths::ProjectEvents,
+  pub duplicate_popup_path: paths::PopupDuplicateProject,
+  pub logout_url: String,
+  pub niveau: i64,
+  pub last_login_datetime: NaiveDateTime,
+  pub title: String,
+  pub keywords: Vec<KeywordCount>,
+  pub chart: RankingChart,
+  pub tweets_count: i64,
+  pub authors_count: i64,
+}
+
+#[derive(Template)]
+#[template(path = "result_links.html")]
+pub(crate) struct ResultLinks {
+  pub daterange_path: paths::ProjectDateRange,
+  pub request_path: paths::ProjectRequest,
+  pub collect_path: paths::ProjectCollect,
+  pub import_path: paths::ProjectImport,
+  pub analysis_preview_popup_path: paths::PopupAnalysisPreview,
+  pub analysis_path: paths::ProjectAnalysis,
+  pub results_path: paths::ProjectResults,
+  pub tweets_graph_path: paths::ProjectTweetsGraph,
+  pub authors_path: paths::ProjectAuthors,
+  pub result_hashtags_path: paths::ProjectResultHashtags,
+  pub keywords_path: paths::ProjectResultKeywords,
+  pub links_path: paths::ProjectResultLinks,
+  pub communities_path: paths::Communities,
+  pub delete_popup_path: paths::PopupDeleteProject,
+  pub rename_popup_path: paths::PopupRenameProject,
+  pub download_path: paths::DownloadProject,
+  pub events_path: paths::ProjectEvents,
+  pub duplicate_popup_path: paths::PopupDuplicateProject,
+  pub logout_url: String,
+  pub niveau: i64,
+  pub last_login_datetime: NaiveDateTime,
+  pub title: String,
+  pub links: Vec<SharedLink>,
+  pub chart: RankingChart,
+  pub tweets_count: i64,
+  pub authors_count: i64,
+}
+
 #[derive(Template)]
 #[template(path = "result_hashtags.html")]
 pub(crate) struct ResultHashtags {
@@ -332,10 +447,13 @@ pub(crate) struct ResultHashtags {
   pub results_path: paths::ProjectResults,
   pub tweets_graph_path: paths::ProjectTweetsGraph,
   pub result_hashtags_path: paths::ProjectResultHashtags,
+  pub keywords_path: paths::ProjectResultKeywords,
+  pub links_path: paths::ProjectResultLinks,
   pub communities_path: paths::Communities,
   pub delete_popup_path: paths::PopupDeleteProject,
   pub rename_popup_path: paths::PopupRenameProject,
   pub download_path: paths::DownloadProject,
+  pub events_path: paths::ProjectEvents,
   pub duplicate_popup_path: paths::PopupDuplicateProject,
   pub aside_hashtag_path: ProjectAsideHashtag,
   pub authors_path: paths::ProjectAuthors,
@@ -354,6 +472,8 @@ pub(crate) struct ResultHashtags {
   pub authors_count: i64,
   pub aside_hashtag_tab: String,
   pub superpose: bool,
+  pub granularity: Granularity,
+  pub cooccurence_options: CooccurenceOptions,
 }
 
 #[derive(Template)]
@@ -370,10 +490,13 @@ pub(crate) struct Tweets {
   pub tweets_graph_path: paths::ProjectTweetsGraph,
   pub authors_path: paths::ProjectAuthors,
   pub result_hashtags_path: paths::ProjectResultHashtags,
+  pub keywords_path: paths::ProjectResultKeywords,
+  pub links_path: paths::ProjectResultLinks,
   pub communities_path: paths::Communities,
   pub delete_popup_path: paths::PopupDeleteProject,
   pub rename_popup_path: paths::PopupRenameProject,
   pub download_path: paths::DownloadProject,
+  pub events_path: paths::ProjectEvents,
   pub duplicate_popup_path: paths::PopupDuplicateProject,
   pub authors_select_path: paths::ProjectAuthorsSelect,
   pub logout_url: String,
@@ -392,6 +515,54 @@ pub(crate) struct Tweets {
   pub tweets_count: i64,
   pub authors_count: i64,
   pub hidden: bool,
+  pub granularity: Granularity,
+  pub burst_method: BurstMethod,
+  /// pics de l'histogramme des tweets et dates de leurs intervalles, à surligner
+  pub bursts: Vec<AnnotatedBurst>,
+  pub burst_dates: Vec<String>,
+  pub hashtag_bursts: Vec<HashtagBurst>,
+}
+
+/// Ligne de l'arbre de la requête, aplati pour l'affichage
+pub(crate) struct ExplainRow {
+  pub depth: usize,
+  pub occur: &'static str,
+  pub label: String,
+  pub hits: usize,
+}
+
+#[derive(Template)]
+#[template(path = "_request_explain.html")]
+pub(crate) struct RequestExplain {
+  pub total: usize,
+  pub rows: Vec<ExplainRow>,
+}
+
+/// Alerte du projet, telle qu'affichée sous le requêteur
+pub(crate) struct AlertRow {
+  pub name: String,
+  pub condition: String,
+  pub schedule: &'static str,
+  pub recipients: String,
+  pub active: bool,
+  pub delete_path: paths::ProjectAlertDelete,
+}
+
+/// Déclenchement d'une alerte du projet
+pub(crate) struct AlertEventRow {
+  pub alert_name: String,
+  pub triggered_at: NaiveDateTime,
+  pub summary: String,
+  pub delivery: String,
+}
+
+#[derive(Template)]
+#[template(path = "_alerts.html")]
+pub(crate) struct ProjectAlerts {
+  pub alerts_path: paths::ProjectAlerts,
+  pub alerts: Vec<AlertRow>,
+  pub events: Vec<AlertEventRow>,
+  pub error: Option<String>,
 }
 
 #[derive(Template)]
@@ -606,6 +777,8 @@ pub(crate) struct AllToggled {
   pub tab: String,
   pub aside_hashtag_tab: String,
   pub superpose: bool,
+  pub cooccurence_options: CooccurenceOptions,
+  pub measures: [AssociationMeasure; 4],
 }
 
 #[derive(Template)]
@@ -660,10 +833,13 @@ pub(crate) struct Communities {
   pub tweets_graph_path: paths::ProjectTweetsGraph,
   pub authors_path: paths::ProjectAuthors,
   pub result_hashtags_path: paths::ProjectResultHashtags,
+  pub keywords_path: paths::ProjectResultKeywords,
+  pub links_path: paths::ProjectResultLinks,
   pub communities_path: paths::Communities,
   pub delete_popup_path: paths::PopupDeleteProject,
   pub rename_popup_path: paths::PopupRenameProject,
   pub download_path: paths::DownloadProject,
+  pub events_path: paths::ProjectEvents,
   pub duplicate_popup_path: paths::PopupDuplicateProject,
   pub logout_url: String,
   pub include_count: i64,
@@ -709,6 +885,29 @@ impl Display for ItemKind {
   }
 }
 
+/// Message SSE : met à jour la ligne de progression de l'étape dans le bandeau du projet
+#[derive(Template)]
+#[template(
+  source = r#"
+<turbo-stream action="update" target="project-progress-{{ event.step.id() }}">
+    <template>
+        <span class="project-progress__status project-progress__status--{{ status }}">
+            {{ event.datetime|datetime }} : {{ event.step.label() }} {{ event.status.label() }}
+            {% if let Some(detail) = event.detail %}({{ detail }}){% endif %}
+        </span>
+        {% if refresh %}<a href="">Actualiser</a>{% endif %}
+    </template>
+</turbo-stream>
+  "#,
+  ext = "html"
+)]
+pub(crate) struct ProjectProgress<'a> {
+  pub event: &'a ProjectEvent,
+  pub status: &'static str,
+  /// les données affichées par la page ont changé : on propose de la recharger
+  pub refresh: bool,
+}
+
 pub(crate) struct HtmlTemplate<T>(pub T);
 
 impl<T> IntoResponse for HtmlTemplate<T>
@@ -792,10 +991,18 @@ mod filters {
     Ok(p.format("%d/%m/%Y à %H:%M").to_string())
   }
 
-  pub fn num_format(p: &i64) -> askama::Result<String> {
+  pub fn num_format<T: ToFormattedString>(p: &T) -> askama::Result<String> {
     Ok(p.to_formatted_string(&Locale::fr))
   }
 
+  /// Élément de bloc tel qu'affiché : les motifs dans l'écriture du langage de requête
+  pub fn keyword_item(item: &str) -> askama::Result<String> {
+    Ok(match fts::KeywordPattern::from_item(item) {
+      Some(pattern) => pattern.to_string(),
+      None => item.to_string(),
+    })
+  }
+
   pub fn current_year(_: &str) -> askama::Result<String> {
     Ok(Local::now().naive_local().format("%Y").to_string())
   }
@@ -821,6 +1028,7 @@ pub(crate) struct Collect {
     pub rename_popup_path: paths::PopupRenameProject,
     pub duplicate_popup_path: paths::PopupDuplicateProject,
     pub download_path: paths::DownloadProject,
+    pub events_path: paths::ProjectEvents,
     pub analysis_preview_popup_path: paths::PopupAnalysisPreview,
     pub analysis_path: paths::ProjectAnalysis,
     pub is_analyzed: bool,
@@ -828,6 +1036,8 @@ pub(crate) struct Collect {
     pub tweets_graph_path: paths::ProjectTweetsGraph,
     pub authors_path: paths::ProjectAuthors,
     pub result_hashtags_path: paths::ProjectResultHashtags,
+    pub keywords_path: paths::ProjectResultKeywords,
+    pub links_path: paths::ProjectResultLinks,
     pub communities_path: paths::Communities,
     pub logout_url: String,
     pub include_count: i64,
@@ -853,6 +1063,7 @@ pub struct ImportTemplate {
     pub delete_popup_path: paths::PopupDeleteProject,
     pub rename_popup_path: paths::PopupRenameProject,
     pub download_path: paths::DownloadProject,
+    pub events_path: paths::ProjectEvents,
     pub duplicate_popup_path: paths::PopupDuplicateProject,
     pub analysis_preview_popup_path: paths::PopupAnalysisPreview,
     pub analysis_path: paths::ProjectAnalysis,
@@ -860,6 +1071,8 @@ pub struct ImportTemplate {
     pub tweets_graph_path: paths::ProjectTweetsGraph,
     pub authors_path: paths::ProjectAuthors,
     pub result_hashtags_path: paths::ProjectResultHashtags,
+    pub keywords_path: paths::ProjectResultKeywords,
+    pub links_path: paths::ProjectResultLinks,
     pub communities_path: paths::Communities,
     pub logout_url: String,
     pub include_count: i64,
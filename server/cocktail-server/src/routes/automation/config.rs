@@ -17,22 +17,27 @@ pub const TANTIVY_DATA_DIR: &str = "tantivy-data";
 #[allow(dead_code)]
 pub const FULL_TEXT_DATA_DIR: &str = "full-text-data";
 
-#[allow(dead_code)]
 pub const TEMP_INDEX_DIR: &str = "temp-index";
 
+/// Fichier présent dans tout index tantivy complet
+pub const INDEX_META_FILE: &str = "meta.json";
+
 /// Configuration des noms de fichiers
 pub const TOPK_DB_FILE: &str = "topk.db";
-pub const HASHTAG_TABLE: &str = "hashtag";
-pub const COOCCURRENCE_TABLE: &str = "hashtag_cooccurence";
+
+/// Nombre de paires de hashtags conservées dans la table des cooccurrences
+pub const COOCCURRENCE_LIMIT: usize = 10;
+
+/// Nombre de jours, jusqu'au tweet le plus récent, dont les comptes de hashtags sont conservés
+/// pour le calcul des tendances
+pub const TRENDING_HISTORY_DAYS: u32 = 120;
+
+/// Nombre de hashtags conservés pour chaque jour
+pub const TRENDING_DAILY_SIZE: u32 = 1000;
 
 /// Configuration des commandes externes
 pub const CARGO_CMD: &str = "cargo";
 pub const GZIP_CMD: &str = "gzip";
-pub const SQLITE3_CMD: &str = "sqlite3";
-
-/// Configuration des images Docker
-#[allow(dead_code)]
-pub const SQLITE_UTILS_IMAGE: &str = "ghcr.io/williamjacksn/sqlite-utils:latest";
 
 /// Configuration des variables d'environnement requises
 #[allow(dead_code)]
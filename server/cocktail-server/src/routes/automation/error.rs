@@ -30,7 +30,8 @@ pub enum CleanupError {
 /// Erreurs spécifiques à l'étape de création d'index
 #[derive(Debug)]
 pub enum IndexCreationError {
-    CommandFailed(String),
+    IndexError(tantivy::TantivyError),
+    TaskFailed(String),
     FileOperationError(io::Error),
     TimeoutError,
 }
@@ -41,45 +42,59 @@ impl From<io::Error> for IndexCreationError {
     }
 }
 
+impl From<tantivy::TantivyError> for IndexCreationError {
+    fn from(error: tantivy::TantivyError) -> Self {
+        IndexCreationError::IndexError(error)
+    }
+}
+
 /// Erreurs spécifiques à l'étape d'ingestion
 #[derive(Debug)]
 pub enum IngestionError {
-    CommandFailed(String),
+    IndexError(tantivy::TantivyError),
+    TaskFailed(String),
     TimeoutError,
 }
 
-impl From<io::Error> for IngestionError {
-    fn from(error: io::Error) -> Self {
-        IngestionError::CommandFailed(error.to_string())
+impl From<tantivy::TantivyError> for IngestionError {
+    fn from(error: tantivy::TantivyError) -> Self {
+        IngestionError::IndexError(error)
     }
 }
 
 /// Erreurs spécifiques à l'étape des top hashtags
 #[derive(Debug)]
 pub enum TopHashtagsError {
-    CommandFailed(String),
+    IndexError(String),
     DatabaseError(String),
+    TaskFailed(String),
     TimeoutError,
-    InvalidJson,
 }
 
-impl From<io::Error> for TopHashtagsError {
-    fn from(error: io::Error) -> Self {
-        TopHashtagsError::CommandFailed(error.to_string())
+impl From<cocktail_db_twitter::DbTwitterError> for TopHashtagsError {
+    fn from(error: cocktail_db_twitter::DbTwitterError) -> Self {
+        TopHashtagsError::DatabaseError(format!("{:?}", error))
     }
 }
 
 /// Erreurs spécifiques à l'étape des cooccurrences
 #[derive(Debug)]
 pub enum CooccurrenceError {
-    CommandFailed(String),
+    IndexError(String),
     DatabaseError(String),
+    TaskFailed(String),
     TimeoutError,
 }
 
-impl From<io::Error> for CooccurrenceError {
-    fn from(error: io::Error) -> Self {
-        CooccurrenceError::CommandFailed(error.to_string())
+impl From<cocktail_db_twitter::DbTwitterError> for CooccurrenceError {
+    fn from(error: cocktail_db_twitter::DbTwitterError) -> Self {
+        CooccurrenceError::DatabaseError(format!("{:?}", error))
+    }
+}
+
+impl From<sqlx::Error> for CooccurrenceError {
+    fn from(error: sqlx::Error) -> Self {
+        CooccurrenceError::DatabaseError(error.to_string())
     }
 }
 
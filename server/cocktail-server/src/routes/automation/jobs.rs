@@ -0,0 +1,193 @@
+use std::{sync::Arc, time::Duration};
+
+use cocktail_db_web::{Job, JobStatus, WebDatabase};
+use chrono::Utc;
+use tokio::sync::Notify;
+use tracing::{info, error, warn};
+
+use crate::alerts::{evaluate_alerts, AlertNotifier};
+use crate::events::{ProjectEvent, ProjectEvents, ProjectStep, StepStatus};
+use crate::routes::automation::{run_step, AutomationContext, STEPS};
+
+/// Délai maximal entre deux consultations de la file d'attente par un worker inactif
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// File d'attente persistante des pipelines d'automatisation.
+///
+/// Les jobs et l'état de leurs étapes sont stockés dans la base web : un job interrompu
+/// (arrêt du serveur, échec, annulation) reprend après sa dernière étape terminée.
+#[derive(Debug, Clone)]
+pub struct JobQueue {
+    db: WebDatabase,
+    events: ProjectEvents,
+    notify: Arc<Notify>,
+    notifier: AlertNotifier,
+    index_options: fts::IndexOptions,
+}
+
+impl JobQueue {
+    pub fn new(
+        db: WebDatabase,
+        events: ProjectEvents,
+        notifier: AlertNotifier,
+        index_options: fts::IndexOptions,
+    ) -> Self {
+        Self {
+            db,
+            events,
+            notify: Arc::new(Notify::new()),
+            notifier,
+            index_options,
+        }
+    }
+
+    pub fn db(&self) -> &WebDatabase {
+        &self.db
+    }
+
+    /// Réveille un worker inactif pour qu'il consulte la file d'attente
+    pub fn wake(&self) {
+        self.notify.notify_one();
+    }
+
+    /// Remet en attente les jobs interrompus puis lance `workers` workers
+    pub async fn start(&self, workers: usize) -> sqlx::Result<()> {
+        let requeued = cocktail_db_web::requeue_interrupted_jobs(&self.db).await?;
+        if requeued > 0 {
+            info!("{} job(s) interrompu(s) remis en file d'attente", requeued);
+        }
+
+        for worker in 0..workers.max(1) {
+            tokio::spawn(self.clone().work(worker));
+        }
+
+        Ok(())
+    }
+
+    /// Crée un job pour le schéma `schema_name` et le place en file d'attente
+    pub async fn enqueue(&self, schema_name: &str, project_id: Option<String>) -> sqlx::Result<Job> {
+        let run_date = chrono::Local::now().format("%Y_%m_%d").to_string();
+        let job = cocktail_db_web::create_job(&self.db, schema_name, project_id, &run_date, &STEPS).await?;
+        info!("Job {} mis en file d'attente pour le schéma {}", job.job_id, schema_name);
+        self.wake();
+        Ok(job)
+    }
+
+    async fn work(self, worker: usize) {
+        loop {
+            match cocktail_db_web::claim_next_job(&self.db).await {
+                Ok(Some(job)) => {
+                    info!("Worker {}: exécution du job {}", worker, job.job_id);
+                    self.run(job).await;
+                },
+                Ok(None) => {
+                    let _ = tokio::time::timeout(POLL_INTERVAL, self.notify.notified()).await;
+                },
+                Err(e) => {
+                    error!("Worker {}: impossible de lire la file d'attente: {}", worker, e);
+                    tokio::time::sleep(POLL_INTERVAL).await;
+                }
+            }
+        }
+    }
+
+    /// Exécute les étapes restantes du job ; l'annulation est prise en compte entre deux étapes
+    async fn run(&self, job: Job) {
+        let context = match AutomationContext::new(&job.schema_name, job.project_id.clone(), &job.run_date, self.index_options) {
+            Ok(context) => context,
+            Err(e) => {
+                error!("Job {}: contexte invalide: {}", job.job_id, e);
+                self.record(cocktail_db_web::fail_job(&self.db, &job.job_id, None, &e.to_string()).await);
+                return;
+            }
+        };
+
+        for index in job.completed_steps as usize..STEPS.len() {
+            if self.stop_if_cancelled(&job.job_id).await {
+                info!("Job {} annulé avant l'étape {}", job.job_id, index + 1);
+                return;
+            }
+
+            let step = index as i64 + 1;
+            self.record(cocktail_db_web::start_job_step(&self.db, &job.job_id, step).await);
+            self.publish(&job, index, StepStatus::Started, None);
+
+            match run_step(index, &context).await {
+                Ok(()) => {
+                    self.record(cocktail_db_web::complete_job_step(&self.db, &job.job_id, step).await);
+                    self.publish(&job, index, StepStatus::Completed, None);
+                },
+                Err(e) => {
+                    error!("Job {}: erreur à l'étape {}: {}", job.job_id, step, e);
+                    self.record(cocktail_db_web::fail_job(&self.db, &job.job_id, Some(step), &e.to_string()).await);
+                    self.publish(&job, index, StepStatus::Failed, Some(e.to_string()));
+                    self.stop_if_cancelled(&job.job_id).await;
+                    return;
+                }
+            }
+        }
+
+        if self.stop_if_cancelled(&job.job_id).await {
+            info!("Job {} annulé après sa dernière étape", job.job_id);
+            return;
+        }
+
+        info!("Job {} terminé avec succès", job.job_id);
+        self.record(cocktail_db_web::complete_job(&self.db, &job.job_id).await);
+        if let Some(project_id) = &job.project_id {
+            self.events.publish(ProjectEvent::new(project_id, ProjectStep::Automation, StepStatus::Completed));
+        }
+        if job.schema_name == "public" {
+            self.evaluate_alerts(&context).await;
+        }
+    }
+
+    /// Évalue les alertes sur l'index public qui vient d'être reconstruit ; une erreur
+    /// ne remet pas en cause le job
+    async fn evaluate_alerts(&self, context: &AutomationContext) {
+        let index = match fts::retrieve_index(&context.tantivy_dir) {
+            Ok(index) => index,
+            Err(e) => {
+                error!("Alertes: impossible d'ouvrir l'index {:?}: {}", context.tantivy_dir, e);
+                return;
+            }
+        };
+
+        match evaluate_alerts(&self.db, &index, &self.notifier, Utc::now().naive_utc()).await {
+            Ok(triggered) => info!("{} alerte(s) déclenchée(s)", triggered),
+            Err(e) => error!("Alertes: évaluation interrompue: {}", e),
+        }
+    }
+
+    /// Vrai si l'annulation du job a été demandée ; un job en cours d'annulation passe alors
+    /// à `cancelled`, ce qui le rend relançable
+    async fn stop_if_cancelled(&self, job_id: &str) -> bool {
+        match cocktail_db_web::job(&self.db, job_id).await {
+            Ok(Some(Job { status: JobStatus::Cancelling, .. })) => {
+                if let Err(e) = cocktail_db_web::acknowledge_cancel(&self.db, job_id).await {
+                    warn!("Impossible d'enregistrer l'annulation du job: {}", e);
+                }
+                true
+            },
+            Ok(Some(Job { status: JobStatus::Cancelled, .. })) => true,
+            _ => false,
+        }
+    }
+
+    /// Diffuse la transition d'étape aux pages du projet concerné par le job
+    fn publish(&self, job: &Job, index: usize, status: StepStatus, error: Option<String>) {
+        if let Some(project_id) = &job.project_id {
+            let detail = match error {
+                Some(error) => format!("{}/{} {} : {}", index + 1, STEPS.len(), STEPS[index], error),
+                None => format!("{}/{} {}", index + 1, STEPS.len(), STEPS[index]),
+            };
+            self.events.publish(ProjectEvent::new(project_id, ProjectStep::Automation, status).detail(detail));
+        }
+    }
+
+    fn record(&self, result: sqlx::Result<()>) {
+        if let Err(e) = result {
+            warn!("Impossible d'enregistrer l'état du job: {}", e);
+        }
+    }
+}
@@ -1,17 +1,24 @@
 use axum::{
-    response::IntoResponse,
+    extract::{FromRef, Path, State},
+    response::{IntoResponse, Response},
     http::StatusCode,
     Json,
     Router,
-    routing::post,
+    routing::{get, post},
 };
+use cocktail_db_web::{Job, JobStatus, JobStep};
+use ory_kratos_client::apis::configuration::Configuration;
 use serde::{Deserialize, Serialize};
 use tracing::{info, error};
 
 mod config;
 mod error;
+mod jobs;
 mod steps;
 
+pub use jobs::JobQueue;
+
+use crate::models::auth::AuthenticatedUser;
 use crate::routes::automation::error::AutomationError;
 use crate::routes::automation::steps::{
     export::run_export,
@@ -23,6 +30,17 @@ use crate::routes::automation::steps::{
     schema_copy::run_schema_copy,
 };
 
+/// Noms des étapes du pipeline, dans leur ordre d'exécution
+pub const STEPS: [&str; 7] = [
+    "Export et compression des tweets",
+    "Nettoyage des anciens index",
+    "Création de l'index Tantivy",
+    "Ingestion des tweets",
+    "Copie des données vers le schéma du projet",
+    "Génération des top hashtags",
+    "Calcul des cooccurrences",
+];
+
 /// Structure de réponse pour l'API d'automatisation
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AutomationResponse {
@@ -30,6 +48,7 @@ pub struct AutomationResponse {
     status: String,
     progress: u32,
     total_steps: u32,
+    job_id: Option<String>,
 }
 
 /// Structure pour suivre la progression de l'automatisation
@@ -44,9 +63,38 @@ pub struct AutomationProgress {
 /// État de progression de l'automatisation
 #[derive(Debug, Serialize, Deserialize)]
 pub enum AutomationStatus {
+    Pending,
     InProgress,
     Completed,
     Failed(String),
+    Cancelled,
+}
+
+impl From<&JobStep> for AutomationProgress {
+    fn from(step: &JobStep) -> Self {
+        let status = match step.status {
+            JobStatus::Pending => AutomationStatus::Pending,
+            JobStatus::Running | JobStatus::Cancelling => AutomationStatus::InProgress,
+            JobStatus::Completed => AutomationStatus::Completed,
+            JobStatus::Failed => AutomationStatus::Failed(step.error.clone().unwrap_or_default()),
+            JobStatus::Cancelled => AutomationStatus::Cancelled,
+        };
+
+        AutomationProgress {
+            current_step: step.step as u32,
+            total_steps: STEPS.len() as u32,
+            step_name: step.name.clone(),
+            status,
+        }
+    }
+}
+
+/// Détail d'un job et de la progression de chacune de ses étapes
+#[derive(Debug, Serialize)]
+pub struct JobDetail {
+    #[serde(flatten)]
+    job: Job,
+    steps: Vec<AutomationProgress>,
 }
 
 /// Contexte partagé entre les étapes de l'automatisation
@@ -60,77 +108,224 @@ pub struct AutomationContext {
     pub database_url: String,
     pub date_str: String,
     pub gzip_file: String,
+    /// options des index créés par le pipeline
+    pub index_options: fts::IndexOptions,
+}
+
+impl AutomationContext {
+    /// Construit le contexte d'un job ; `date_str` est figée à la création du job pour
+    /// qu'une reprise retrouve les fichiers produits par les étapes déjà terminées.
+    pub fn new(
+        schema_name: &str,
+        project_id: Option<String>,
+        date_str: &str,
+        index_options: fts::IndexOptions,
+    ) -> Result<Self, AutomationError> {
+        let tantivy_dir = std::path::PathBuf::from(format!("tantivy-data/{}", schema_name));
+        Ok(AutomationContext {
+            schema_name: schema_name.to_string(),
+            project_id,
+            workspace_dir: std::env::current_dir()?,
+            project_dir: tantivy_dir.parent().unwrap().to_path_buf(),
+            tantivy_dir,
+            database_url: std::env::var("PG_DATABASE_URL")?,
+            date_str: date_str.to_string(),
+            gzip_file: format!("tweets_collecte_{}.json.gz", date_str),
+            index_options,
+        })
+    }
+}
+
+/// Exécute l'étape `index` (à partir de 0) du pipeline
+pub async fn run_step(index: usize, context: &AutomationContext) -> Result<(), AutomationError> {
+    info!("Étape {}/{}: {}", index + 1, STEPS.len(), STEPS[index]);
+
+    match index {
+        0 => run_export(context).await,
+        1 => run_cleanup(context).await,
+        2 => run_index_creation(context).await,
+        3 => run_ingestion(context).await,
+        4 => run_schema_copy(context).await,
+        5 => run_top_hashtags(context).await,
+        6 => run_cooccurrence(context).await,
+        _ => Err(AutomationError::ConfigurationError(format!("étape inconnue : {}", index + 1))),
+    }
+}
+
+/// État des routes d'automatisation : la file des jobs, et la configuration Kratos qui
+/// identifie l'utilisateur qui les consulte
+#[derive(Debug, Clone)]
+pub struct AutomationState {
+    queue: JobQueue,
+    kratos_configuration: Configuration,
+}
+
+impl FromRef<AutomationState> for JobQueue {
+    fn from_ref(input: &AutomationState) -> Self {
+        input.queue.clone()
+    }
 }
 
-/// Point d'entrée pour les routes d'automatisation
-pub fn routes() -> Router {
-    Router::new()
+impl FromRef<AutomationState> for Configuration {
+    fn from_ref(input: &AutomationState) -> Self {
+        input.kratos_configuration.clone()
+    }
+}
+
+/// Point d'entrée pour les routes d'automatisation et de suivi des jobs
+pub fn routes(queue: JobQueue, kratos_configuration: Configuration) -> Router<AutomationState> {
+    Router::with_state(AutomationState { queue, kratos_configuration })
         .route("/api/automation/run", post(run_automation))
+        .route("/api/jobs", get(list_jobs))
+        .route("/api/jobs/:job_id", get(show_job))
+        .route("/api/jobs/:job_id/cancel", post(cancel_job))
+        .route("/api/jobs/:job_id/retry", post(retry_job))
 }
 
-/// Endpoint principal pour lancer l'automatisation
-pub async fn run_automation() -> impl IntoResponse {
-    info!("Début du pipeline d'automatisation");
-    
-    match run_automation_pipeline("public", None).await {
-        Ok(_) => {
-            info!("Pipeline d'automatisation terminé avec succès");
-            (StatusCode::OK, Json(AutomationResponse {
-                message: "Pipeline d'automatisation terminé avec succès".to_string(),
-                status: "success".to_string(),
-                progress: 100,
-                total_steps: 7,
+/// Endpoint principal pour lancer l'automatisation : le pipeline est mis en file d'attente
+pub async fn run_automation(State(queue): State<JobQueue>) -> impl IntoResponse {
+    info!("Mise en file d'attente du pipeline d'automatisation");
+
+    match queue.enqueue("public", None).await {
+        Ok(job) => {
+            (StatusCode::ACCEPTED, Json(AutomationResponse {
+                message: "Pipeline d'automatisation mis en file d'attente".to_string(),
+                status: "pending".to_string(),
+                progress: 0,
+                total_steps: STEPS.len() as u32,
+                job_id: Some(job.job_id),
             })).into_response()
         },
         Err(e) => {
-            error!("Erreur lors du pipeline d'automatisation: {}", e);
+            error!("Erreur lors de la mise en file d'attente du pipeline: {}", e);
             (StatusCode::INTERNAL_SERVER_ERROR, Json(AutomationResponse {
                 message: format!("Erreur lors de l'automatisation : {}", e),
                 status: "error".to_string(),
                 progress: 0,
-                total_steps: 7,
+                total_steps: STEPS.len() as u32,
+                job_id: None,
             })).into_response()
         }
     }
 }
 
-/// Fonction principale qui orchestre toutes les étapes de l'automatisation
-pub async fn run_automation_pipeline(schema_name: &str, project_id: Option<String>) -> Result<(), AutomationError> {
-    // Initialisation du contexte
-    let tantivy_dir = std::path::PathBuf::from(format!("tantivy-data/{}", schema_name));
-    let context = AutomationContext {
-        schema_name: schema_name.to_string(),
-        project_id: project_id,
-        workspace_dir: std::env::current_dir()?,
-        project_dir: tantivy_dir.parent().unwrap().to_path_buf(),
-        tantivy_dir,
-        database_url: std::env::var("PG_DATABASE_URL")?,
-        date_str: chrono::Local::now().format("%Y_%m_%d").to_string(),
-        gzip_file: format!("tweets_collecte_{}.json.gz", chrono::Local::now().format("%Y_%m_%d")),
+/// Identifiants des projets de `user` : il ne suit que les jobs de ses projets, jamais
+/// ceux des autres utilisateurs ni le pipeline général
+async fn owned_projects(queue: &JobQueue, user: &AuthenticatedUser) -> Result<Vec<String>, Response> {
+    if user.niveau == 0 {
+        return Err(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    match cocktail_db_web::projects(queue.db(), &user.user_id).await {
+        Ok(projects) => Ok(projects.iter().map(|project| project.project_id.to_string()).collect()),
+        Err(e) => {
+            error!("Erreur lors de la lecture des projets de {}: {}", user.user_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR.into_response())
+        }
+    }
+}
+
+/// Job `job_id` s'il appartient à un projet de `user`, 404 sinon pour ne pas révéler
+/// l'existence des autres jobs
+async fn owned_job(queue: &JobQueue, user: &AuthenticatedUser, job_id: &str) -> Result<Job, Response> {
+    let projects = owned_projects(queue, user).await?;
+
+    match cocktail_db_web::job(queue.db(), job_id).await {
+        Ok(Some(job)) if job.project_id.as_ref().is_some_and(|id| projects.contains(id)) => Ok(job),
+        Ok(_) => Err(StatusCode::NOT_FOUND.into_response()),
+        Err(e) => {
+            error!("Erreur lors de la lecture du job {}: {}", job_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR.into_response())
+        }
+    }
+}
+
+async fn list_jobs(user: AuthenticatedUser, State(queue): State<JobQueue>) -> impl IntoResponse {
+    let projects = match owned_projects(&queue, &user).await {
+        Ok(projects) => projects,
+        Err(response) => return response,
     };
 
-    // Exécution des étapes
-    info!("Étape 1/7: Export et compression des tweets");
-    run_export(&context).await?;
+    match cocktail_db_web::jobs(queue.db()).await {
+        Ok(jobs) => {
+            let jobs: Vec<Job> = jobs
+                .into_iter()
+                .filter(|job| job.project_id.as_ref().is_some_and(|id| projects.contains(id)))
+                .collect();
+            (StatusCode::OK, Json(jobs)).into_response()
+        },
+        Err(e) => {
+            error!("Erreur lors de la lecture des jobs: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+async fn show_job(
+    user: AuthenticatedUser,
+    State(queue): State<JobQueue>,
+    Path(job_id): Path<String>,
+) -> impl IntoResponse {
+    let job = match owned_job(&queue, &user, &job_id).await {
+        Ok(job) => job,
+        Err(response) => return response,
+    };
 
-    info!("Étape 2/7: Nettoyage des anciens index");
-    run_cleanup(&context).await?;
+    match cocktail_db_web::job_steps(queue.db(), &job_id).await {
+        Ok(steps) => (StatusCode::OK, Json(JobDetail {
+            job,
+            steps: steps.iter().map(AutomationProgress::from).collect(),
+        })).into_response(),
+        Err(e) => {
+            error!("Erreur lors de la lecture des étapes du job {}: {}", job_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
 
-    info!("Étape 3/7: Création de l'index Tantivy");
-    run_index_creation(&context).await?;
+async fn cancel_job(
+    user: AuthenticatedUser,
+    State(queue): State<JobQueue>,
+    Path(job_id): Path<String>,
+) -> impl IntoResponse {
+    if let Err(response) = owned_job(&queue, &user, &job_id).await {
+        return response;
+    }
 
-    info!("Étape 4/7: Ingestion des tweets");
-    run_ingestion(&context).await?;
+    job_transition(&queue, &job_id, cocktail_db_web::cancel_job(queue.db(), &job_id).await).await
+}
 
-    info!("Étape 5/7: Copie des données vers le schéma du projet");
-    run_schema_copy(&context).await?;
+async fn retry_job(
+    user: AuthenticatedUser,
+    State(queue): State<JobQueue>,
+    Path(job_id): Path<String>,
+) -> impl IntoResponse {
+    if let Err(response) = owned_job(&queue, &user, &job_id).await {
+        return response;
+    }
 
-    info!("Étape 6/7: Génération des top hashtags");
-    run_top_hashtags(&context).await?;
+    let result = cocktail_db_web::retry_job(queue.db(), &job_id).await;
+    if let Ok(true) = result {
+        queue.wake();
+    }
+    job_transition(&queue, &job_id, result).await
+}
 
-    info!("Étape 7/7: Calcul des cooccurrences");
-    run_cooccurrence(&context).await?;
+/// Réponse commune à l'annulation et à la relance : 404 si le job n'existe pas,
+/// 409 si son état ne permet pas la transition demandée
+async fn job_transition(queue: &JobQueue, job_id: &str, result: sqlx::Result<bool>) -> Response {
+    let job = match result {
+        Ok(changed) => cocktail_db_web::job(queue.db(), job_id).await.map(|job| (changed, job)),
+        Err(e) => Err(e),
+    };
 
-    info!("Pipeline d'automatisation terminé avec succès");
-    Ok(())
-} 
\ No newline at end of file
+    match job {
+        Ok((true, Some(job))) => (StatusCode::OK, Json(job)).into_response(),
+        Ok((false, Some(_))) => StatusCode::CONFLICT.into_response(),
+        Ok((_, None)) => StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            error!("Erreur lors de la mise à jour du job {}: {}", job_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
@@ -3,9 +3,10 @@ use axum::{
   response::{IntoResponse, Redirect},
   Form,
 };
-use cocktail_db_twitter::{HashtagCooccurence, TopKDatabase};
 use cocktail_db_web::{ParsedProjectCriteria, WebDatabase};
-use fts::{Frequence, FrequenceCooccurence};
+use fts::{
+  AssociationMeasure, CooccurenceOptions, Frequence, FrequenceCooccurence, TimeBuckets, Tz,
+};
 use hyper::HeaderMap;
 use ory_kratos_client::apis::configuration::Configuration;
 use serde::Deserialize;
@@ -24,6 +25,8 @@ use crate::{
   },
 };
 
+use super::time_buckets;
+
 #[derive(Debug, Deserialize)]
 pub struct ToggleHashtag {
   pub hashtag: String,
@@ -45,6 +48,29 @@ pub struct ToggleAll {
 #[derive(Deserialize)]
 pub struct QueryParams {
   pub superpose: Option<bool>,
+  pub granularite: Option<String>,
+  /// mesure d'association classant les cooccurrences
+  pub mesure: Option<String>,
+  /// nombre minimal de tweets contenant une paire de hashtags
+  pub support_min: Option<u64>,
+  /// nombre de paires de hashtags affichées
+  pub paires: Option<usize>,
+}
+
+impl QueryParams {
+  /// Paramètres des cooccurrences, ceux par défaut pour les valeurs absentes ou invalides
+  pub fn cooccurence_options(&self) -> CooccurenceOptions {
+    let default = CooccurenceOptions::default();
+    CooccurenceOptions {
+      top: self.paires.filter(|top| *top > 0).unwrap_or(default.top),
+      min_support: self.support_min.unwrap_or(default.min_support).max(1),
+      measure: self
+        .mesure
+        .as_deref()
+        .and_then(|measure| measure.parse::<AssociationMeasure>().ok())
+        .unwrap_or(default.measure),
+    }
+  }
 }
 
 pub async fn get_hashtags_chart(
@@ -53,19 +79,31 @@ pub async fn get_hashtags_chart(
   user_id: &String,
   tab: &String,
   hidden_hashtags: &Vec<String>,
-  cooccurences: &Vec<HashtagCooccurence>,
+  buckets: &TimeBuckets,
+  cooccurence: &CooccurenceOptions,
 ) -> Result<(Vec<Frequence>, Vec<Frequence>, Vec<FrequenceCooccurence>), WebError> {
   let directory_path = format!("project-data/{}", project_id.to_string());
   let project = cocktail_db_web::project(&db, project_id.to_hyphenated(), &user_id).await?;
   let parsed_criteria = ParsedProjectCriteria::from(&project);
-  let chart =
-    cocktail_db_web::get_chart(&db, &project_id.to_string(), &"hashtags".to_string(), &tab).await;
+  let granularity = buckets.granularity.to_string();
+  let timezone = buckets.timezone.to_string();
+  // seuls les graphiques aux cooccurrences par défaut sont mis en cache
+  let cached = *cooccurence == CooccurenceOptions::default();
+  let chart = cocktail_db_web::get_chart(
+    &db,
+    &project_id.to_string(),
+    &"hashtags".to_string(),
+    tab,
+    &granularity,
+    &timezone,
+  )
+  .await;
   let mut is_error = true;
   let mut frequences: Vec<Frequence> = vec![];
   let mut frequences_topk: Vec<Frequence> = vec![];
   let mut frequences_cooccurence: Vec<FrequenceCooccurence> = vec![];
 
-  if chart.is_ok() {
+  if cached && chart.is_ok() {
     (frequences, frequences_topk, frequences_cooccurence) =
       serde_json::from_str::<(Vec<Frequence>, Vec<Frequence>, Vec<FrequenceCooccurence>)>(
         chart.unwrap().as_str(),
@@ -76,7 +114,7 @@ pub async fn get_hashtags_chart(
   }
 
   if is_error {
-    let index = fts::Index::open_in_dir(directory_path)?;
+    let index = fts::retrieve_index(directory_path)?;
     let parsed_criteria = ParsedProjectCriteria::from(&project);
     frequences = fts::search_study_hashtags_count_per_day(
       &index,
@@ -84,35 +122,40 @@ pub async fn get_hashtags_chart(
       &project.end_date,
       &parsed_criteria.hashtag_list,
       &tab,
+      buckets,
+    )?;
+    frequences_topk = fts::search_top_hashtags_count_per_day(
+      &index,
+      &project.start_date,
+      &project.end_date,
+      tab,
+      buckets,
     )?;
-    frequences_topk =
-      fts::search_top_hashtags_count_per_day(&index, &project.start_date, &project.end_date, &tab)?;
     frequences_cooccurence = fts::search_top_hashtags_cooccurence_count_per_day(
       &index,
       &project.start_date,
       &project.end_date,
       &tab,
-      &cooccurences
-        .iter()
-        .map(|c| fts::HashtagCooccurence {
-          hashtag1: c.hashtag1.clone(),
-          hashtag2: c.hashtag2.clone(),
-        })
-        .collect(),
+      buckets,
+      cooccurence,
     )?;
 
-    let _ = cocktail_db_web::save_chart(
-      &db,
-      project_id.to_string(),
-      "hashtags".to_string(),
-      tab.to_string(),
-      (
-        frequences.clone(),
-        frequences_topk.clone(),
-        frequences_cooccurence.clone(),
-      ),
-    )
-    .await;
+    if cached {
+      let _ = cocktail_db_web::save_chart(
+        &db,
+        project_id.to_string(),
+        "hashtags".to_string(),
+        tab.to_string(),
+        granularity,
+        timezone,
+        (
+          frequences.clone(),
+          frequences_topk.clone(),
+          frequences_cooccurence.clone(),
+        ),
+      )
+      .await;
+    }
   }
 
   Ok((
@@ -164,24 +207,29 @@ pub async fn get_results(
     user_id,
   }: AuthenticatedUser,
   headers: HeaderMap,
-  State(conn): State<TopKDatabase>,
   State(db): State<WebDatabase>,
   State(kratos_configuration): State<Configuration>,
+  State(timezone): State<Tz>,
   query_params: Query<QueryParams>,
 ) -> Result<impl IntoResponse, WebError> {
   let project = cocktail_db_web::project(&db, project_id.to_hyphenated(), &user_id).await?;
+  let buckets = time_buckets(
+    query_params.granularite.as_deref(),
+    &project.start_date,
+    &project.end_date,
+    timezone,
+  );
 
   let hidden_hashtags =
     cocktail_db_web::hidden_hashtag_list(&db, project_id.to_hyphenated(), &user_id).await?;
-  let cooccurences = cocktail_db_twitter::search_topk_hashtags_cooccurence(conn.clone()).await?;
-
   let (frequences, frequences_topk, frequences_cooccurence) = get_hashtags_chart(
     &db,
     &project_id,
     &user_id,
     &tab.to_string(),
     &hidden_hashtags,
-    &cooccurences,
+    &buckets,
+    &query_params.cooccurence_options(),
   )
   .await?;
 
@@ -230,11 +278,14 @@ pub async fn get_results(
     results_path: paths::ProjectResults { project_id },
     tweets_graph_path: paths::ProjectTweetsGraph { project_id },
     result_hashtags_path: paths::ProjectResultHashtags { project_id },
+    keywords_path: paths::ProjectResultKeywords { project_id },
+    links_path: paths::ProjectResultLinks { project_id },
     communities_path: paths::Communities { project_id },
     delete_popup_path: paths::PopupDeleteProject { project_id },
     rename_popup_path: paths::PopupRenameProject { project_id },
     duplicate_popup_path: paths::PopupDuplicateProject { project_id },
     download_path: paths::DownloadProject { project_id },
+    events_path: paths::ProjectEvents { project_id },
     authors_path: paths::ProjectAuthors { project_id },
     aside_hashtag_path: ProjectAsideHashtag {
       project_id,
@@ -259,6 +310,8 @@ pub async fn get_results(
       Some(true) => true,
       _ => false,
     },
+    granularity: buckets.granularity,
+    cooccurence_options: query_params.cooccurence_options(),
   }))
 }
 
@@ -270,9 +323,9 @@ pub async fn hashtags(
     user_id,
   }: AuthenticatedUser,
   headers: HeaderMap,
-  State(conn): State<TopKDatabase>,
   State(db): State<WebDatabase>,
   State(kratos_configuration): State<Configuration>,
+  State(timezone): State<Tz>,
   query_params: Query<QueryParams>,
 ) -> Result<impl IntoResponse, WebError> {
   get_results(
@@ -287,9 +340,9 @@ pub async fn hashtags(
       user_id,
     },
     headers,
-    State(conn),
     State(db),
     State(kratos_configuration),
+    State(timezone),
     query_params,
   )
   .await
@@ -307,9 +360,9 @@ pub async fn hashtags_tab(
     user_id,
   }: AuthenticatedUser,
   headers: HeaderMap,
-  State(conn): State<TopKDatabase>,
   State(db): State<WebDatabase>,
   State(kratos_configuration): State<Configuration>,
+  State(timezone): State<Tz>,
   query_params: Query<QueryParams>,
 ) -> Result<impl IntoResponse, WebError> {
   if niveau != 2 && tab != "total" {
@@ -335,9 +388,9 @@ pub async fn hashtags_tab(
         user_id,
       },
       headers,
-      State(conn),
       State(db),
       State(kratos_configuration),
+      State(timezone),
       query_params,
     )
     .await
@@ -388,18 +441,19 @@ pub async fn toggle_all(
     user_id,
   }: AuthenticatedUser,
   State(db): State<WebDatabase>,
-  State(conn): State<TopKDatabase>,
+  State(timezone): State<Tz>,
   Form(toggle): Form<ToggleAll>,
 ) -> Result<impl IntoResponse, WebError> {
-  let cooccurences = cocktail_db_twitter::search_topk_hashtags_cooccurence(conn.clone()).await?;
-
+  let project = cocktail_db_web::project(&db, project_id.to_hyphenated(), &user_id).await?;
+  let buckets = time_buckets(None, &project.start_date, &project.end_date, timezone);
   let (mut frequences, mut frequences_topk, mut frequences_cooccurence) = get_hashtags_chart(
     &db,
     &project_id,
     &user_id,
     &"total".to_string(),
     &vec![],
-    &cooccurences,
+    &buckets,
+    &CooccurenceOptions::default(),
   )
   .await?;
 
@@ -484,6 +538,8 @@ pub async fn toggle_all(
     tab,
     aside_hashtag_tab,
     superpose: toggle.superpose,
+    cooccurence_options: CooccurenceOptions::default(),
+    measures: AssociationMeasure::ALL,
   }))
 }
 
@@ -499,18 +555,24 @@ pub async fn aside_hashtag_tab(
     user_id,
   }: AuthenticatedUser,
   State(db): State<WebDatabase>,
-  State(conn): State<TopKDatabase>,
+  State(timezone): State<Tz>,
   query_params: Query<QueryParams>,
 ) -> Result<impl IntoResponse, WebError> {
-  let cooccurences = cocktail_db_twitter::search_topk_hashtags_cooccurence(conn.clone()).await?;
-
+  let project = cocktail_db_web::project(&db, project_id.to_hyphenated(), &user_id).await?;
+  let buckets = time_buckets(
+    query_params.granularite.as_deref(),
+    &project.start_date,
+    &project.end_date,
+    timezone,
+  );
   let (frequences, frequences_topk, frequences_cooccurence) = get_hashtags_chart(
     &db,
     &project_id,
     &user_id,
     &"total".to_string(),
     &vec![],
-    &cooccurences,
+    &buckets,
+    &query_params.cooccurence_options(),
   )
   .await?;
 
@@ -567,6 +629,8 @@ pub async fn aside_hashtag_tab(
       Some(true) => true,
       _ => false,
     },
+    cooccurence_options: query_params.cooccurence_options(),
+    measures: AssociationMeasure::ALL,
   }))
 }
 
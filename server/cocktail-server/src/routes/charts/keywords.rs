@@ -0,0 +1,166 @@
+use axum::{
+  extract::{Query, State},
+  response::{IntoResponse, Redirect},
+};
+use cocktail_db_web::Project;
+use fts::{Frequence, KeywordOptions, KeywordTrends, TimeBuckets};
+use hyper::HeaderMap;
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::{
+  error::WebError,
+  get_logout_url,
+  models::{
+    auth::AuthenticatedUser,
+    templates::{HtmlTemplate, RankingChart, ResultKeywords},
+  },
+  routes::paths,
+  AppState,
+};
+
+use super::{cached_chart, time_buckets};
+
+/// Nombre de mots clés de chaque classement dont l'histogramme est affiché
+const CHARTED_KEYWORDS: usize = 10;
+
+#[derive(Deserialize)]
+pub struct QueryParams {
+  pub classement: Option<String>,
+  pub granularite: Option<String>,
+}
+
+/// Mots clés de l'étude, avec les histogrammes des plus fréquents et des plus
+/// caractéristiques
+type KeywordsChart = (KeywordTrends, Vec<Frequence>, Vec<Frequence>);
+
+async fn get_keywords_chart(
+  state: &AppState,
+  project: &Project,
+  project_id: &Uuid,
+  tab: &str,
+  buckets: &TimeBuckets,
+) -> Result<KeywordsChart, WebError> {
+  let background = state.index.clone();
+  let (start_date, end_date) = (project.start_date, project.end_date);
+  let (tab_owned, buckets_owned) = (tab.to_string(), buckets.clone());
+  // lecture de tous les tweets de l'étude et recherches dans l'index public
+  cached_chart(state, project_id, "keywords", tab, buckets, move |index| {
+    let trends = fts::keyword_trends(index, &background, &KeywordOptions::default())?;
+    let series = |keywords: &[fts::KeywordCount]| {
+      let keywords: Vec<String> = keywords
+        .iter()
+        .take(CHARTED_KEYWORDS)
+        .map(|keyword| keyword.keyword.clone())
+        .collect();
+      fts::search_keywords_count_per_day(
+        index,
+        &start_date,
+        &end_date,
+        &keywords,
+        &tab_owned,
+        &buckets_owned,
+      )
+    };
+    let frequent = series(&trends.frequent)?;
+    let distinctive = series(&trends.distinctive)?;
+
+    Ok((trends, frequent, distinctive))
+  })
+  .await
+}
+
+pub async fn keywords(
+  paths::ProjectResultKeywords { project_id }: paths::ProjectResultKeywords,
+  authenticated_user: AuthenticatedUser,
+  headers: HeaderMap,
+  State(state): State<AppState>,
+  query_params: Query<QueryParams>,
+) -> Result<impl IntoResponse, WebError> {
+  keywords_tab(
+    paths::ProjectResultKeywordsTab {
+      project_id,
+      tab: "total".to_string(),
+    },
+    authenticated_user,
+    headers,
+    State(state),
+    query_params,
+  )
+  .await
+}
+
+pub async fn keywords_tab(
+  paths::ProjectResultKeywordsTab { project_id, tab }: paths::ProjectResultKeywordsTab,
+  AuthenticatedUser {
+    niveau,
+    last_login_datetime,
+    user_id,
+  }: AuthenticatedUser,
+  headers: HeaderMap,
+  State(state): State<AppState>,
+  query_params: Query<QueryParams>,
+) -> Result<impl IntoResponse, WebError> {
+  if niveau != 2 && tab != "total" {
+    return Ok(
+      Redirect::to(paths::ProjectResultKeywords { project_id }.to_string().as_str())
+        .into_response(),
+    );
+  }
+
+  let project = cocktail_db_web::project(&state.db, project_id.to_hyphenated(), &user_id).await?;
+  let buckets = time_buckets(
+    query_params.granularite.as_deref(),
+    &project.start_date,
+    &project.end_date,
+    state.timezone,
+  );
+  let (trends, frequent, distinctive) =
+    get_keywords_chart(&state, &project, &project_id, &tab, &buckets).await?;
+  let (ranking, keywords, frequences) = match query_params.classement.as_deref() {
+    Some("caracteristiques") => ("caracteristiques", trends.distinctive, distinctive),
+    _ => ("frequents", trends.frequent, frequent),
+  };
+
+  let logout_url = get_logout_url(state.kratos_configuration.clone(), headers).await;
+
+  Ok(
+    HtmlTemplate(ResultKeywords {
+      daterange_path: paths::ProjectDateRange { project_id },
+      request_path: paths::ProjectRequest { project_id },
+      collect_path: paths::ProjectCollect { project_id },
+      import_path: paths::ProjectImport { project_id },
+      analysis_preview_popup_path: paths::PopupAnalysisPreview { project_id },
+      analysis_path: paths::ProjectAnalysis { project_id },
+      results_path: paths::ProjectResults { project_id },
+      tweets_graph_path: paths::ProjectTweetsGraph { project_id },
+      authors_path: paths::ProjectAuthors { project_id },
+      result_hashtags_path: paths::ProjectResultHashtags { project_id },
+      keywords_path: paths::ProjectResultKeywords { project_id },
+      links_path: paths::ProjectResultLinks { project_id },
+      communities_path: paths::Communities { project_id },
+      delete_popup_path: paths::PopupDeleteProject { project_id },
+      rename_popup_path: paths::PopupRenameProject { project_id },
+      duplicate_popup_path: paths::PopupDuplicateProject { project_id },
+      download_path: paths::DownloadProject { project_id },
+      events_path: paths::ProjectEvents { project_id },
+      logout_url,
+      niveau,
+      last_login_datetime,
+      title: project.title,
+      keywords,
+      chart: RankingChart {
+        active_page: "keywords",
+        path: paths::ProjectResultKeywords { project_id }.to_string(),
+        ranking_param: "classement",
+        ranking: ranking.to_string(),
+        tab,
+        granularity: buckets.granularity,
+        frequences,
+      },
+      tweets_count: project.tweets_count,
+      authors_count: project.authors_count,
+    })
+    .into_response(),
+  )
+}
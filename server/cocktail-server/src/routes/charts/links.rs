@@ -0,0 +1,169 @@
+use axum::{
+  extract::{Query, State},
+  response::{IntoResponse, Redirect},
+};
+use cocktail_db_web::Project;
+use fts::{Frequence, LinkOptions, SharedLink, TimeBuckets};
+use hyper::HeaderMap;
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::{
+  error::WebError,
+  get_logout_url,
+  models::{
+    auth::AuthenticatedUser,
+    templates::{HtmlTemplate, RankingChart, ResultLinks},
+  },
+  routes::paths,
+  AppState,
+};
+
+use super::{cached_chart, time_buckets};
+
+/// Nombre de liens de chaque classement dont l'histogramme est affiché
+const CHARTED_LINKS: usize = 10;
+
+#[derive(Deserialize)]
+pub struct QueryParams {
+  pub vue: Option<String>,
+  pub granularite: Option<String>,
+}
+
+/// URLs et domaines les plus partagés de l'étude
+type LinksChart = (Vec<SharedLink>, Vec<SharedLink>);
+
+async fn get_links_chart(
+  state: &AppState,
+  project: &Project,
+  project_id: &Uuid,
+  tab: &str,
+  buckets: &TimeBuckets,
+) -> Result<LinksChart, WebError> {
+  let (start_date, end_date) = (project.start_date, project.end_date);
+  let (tab_owned, buckets_owned) = (tab.to_string(), buckets.clone());
+  cached_chart(state, project_id, "links", tab, buckets, move |index| {
+    let options = LinkOptions::default();
+    let urls = fts::top_shared_urls(
+      index,
+      &start_date,
+      &end_date,
+      &tab_owned,
+      &buckets_owned,
+      &options,
+    )?;
+    let domains = fts::top_shared_domains(
+      index,
+      &start_date,
+      &end_date,
+      &tab_owned,
+      &buckets_owned,
+      &options,
+    )?;
+
+    Ok((urls, domains))
+  })
+  .await
+}
+
+pub async fn links(
+  paths::ProjectResultLinks { project_id }: paths::ProjectResultLinks,
+  authenticated_user: AuthenticatedUser,
+  headers: HeaderMap,
+  State(state): State<AppState>,
+  query_params: Query<QueryParams>,
+) -> Result<impl IntoResponse, WebError> {
+  links_tab(
+    paths::ProjectResultLinksTab {
+      project_id,
+      tab: "total".to_string(),
+    },
+    authenticated_user,
+    headers,
+    State(state),
+    query_params,
+  )
+  .await
+}
+
+pub async fn links_tab(
+  paths::ProjectResultLinksTab { project_id, tab }: paths::ProjectResultLinksTab,
+  AuthenticatedUser {
+    niveau,
+    last_login_datetime,
+    user_id,
+  }: AuthenticatedUser,
+  headers: HeaderMap,
+  State(state): State<AppState>,
+  query_params: Query<QueryParams>,
+) -> Result<impl IntoResponse, WebError> {
+  if niveau != 2 && tab != "total" {
+    return Ok(
+      Redirect::to(paths::ProjectResultLinks { project_id }.to_string().as_str()).into_response(),
+    );
+  }
+
+  let project = cocktail_db_web::project(&state.db, project_id.to_hyphenated(), &user_id).await?;
+  let buckets = time_buckets(
+    query_params.granularite.as_deref(),
+    &project.start_date,
+    &project.end_date,
+    state.timezone,
+  );
+  let (urls, domains) = get_links_chart(&state, &project, &project_id, &tab, &buckets).await?;
+  let (view, links) = match query_params.vue.as_deref() {
+    Some("domaines") => ("domaines", domains),
+    _ => ("urls", urls),
+  };
+  let frequences = links
+    .iter()
+    .take(CHARTED_LINKS)
+    .map(|link| Frequence {
+      hashtag: link.key.clone(),
+      hidden: false,
+      data: link.frequences.clone(),
+    })
+    .collect();
+
+  let logout_url = get_logout_url(state.kratos_configuration.clone(), headers).await;
+
+  Ok(
+    HtmlTemplate(ResultLinks {
+      daterange_path: paths::ProjectDateRange { project_id },
+      request_path: paths::ProjectRequest { project_id },
+      collect_path: paths::ProjectCollect { project_id },
+      import_path: paths::ProjectImport { project_id },
+      analysis_preview_popup_path: paths::PopupAnalysisPreview { project_id },
+      analysis_path: paths::ProjectAnalysis { project_id },
+      results_path: paths::ProjectResults { project_id },
+      tweets_graph_path: paths::ProjectTweetsGraph { project_id },
+      authors_path: paths::ProjectAuthors { project_id },
+      result_hashtags_path: paths::ProjectResultHashtags { project_id },
+      keywords_path: paths::ProjectResultKeywords { project_id },
+      links_path: paths::ProjectResultLinks { project_id },
+      communities_path: paths::Communities { project_id },
+      delete_popup_path: paths::PopupDeleteProject { project_id },
+      rename_popup_path: paths::PopupRenameProject { project_id },
+      duplicate_popup_path: paths::PopupDuplicateProject { project_id },
+      download_path: paths::DownloadProject { project_id },
+      events_path: paths::ProjectEvents { project_id },
+      logout_url,
+      niveau,
+      last_login_datetime,
+      title: project.title,
+      links,
+      chart: RankingChart {
+        active_page: "links",
+        path: paths::ProjectResultLinks { project_id }.to_string(),
+        ranking_param: "vue",
+        ranking: view.to_string(),
+        tab,
+        granularity: buckets.granularity,
+        frequences,
+      },
+      tweets_count: project.tweets_count,
+      authors_count: project.authors_count,
+    })
+    .into_response(),
+  )
+}
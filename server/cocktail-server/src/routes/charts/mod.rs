@@ -1,2 +1,77 @@
+use chrono::NaiveDate;
+use fts::{Granularity, Index, TimeBuckets, Tz};
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::task;
+use uuid::Uuid;
+
+use crate::{error::WebError, AppState};
+
 pub mod hashtags;
+pub mod keywords;
+pub mod links;
 pub mod tweets;
+
+/// Intervalles des graphiques d'une étude : la granularité demandée par le paramètre
+/// `granularite`, sinon celle adaptée à la durée de l'étude
+pub fn time_buckets(
+  granularite: Option<&str>,
+  start_date: &NaiveDate,
+  end_date: &NaiveDate,
+  timezone: Tz,
+) -> TimeBuckets {
+  let granularity = granularite
+    .and_then(|granularite| granularite.parse().ok())
+    .unwrap_or_else(|| Granularity::for_period(start_date, end_date));
+  TimeBuckets::new(granularity, timezone)
+}
+
+/// Graphique `name` de l'étude lu dans le cache, ou calculé par `compute` sur l'index de
+/// l'étude dans un thread bloquant puis mis en cache
+pub(crate) async fn cached_chart<T, F>(
+  state: &AppState,
+  project_id: &Uuid,
+  name: &str,
+  tab: &str,
+  buckets: &TimeBuckets,
+  compute: F,
+) -> Result<T, WebError>
+where
+  T: Serialize + DeserializeOwned + std::fmt::Debug + Send + 'static,
+  F: FnOnce(&Index) -> Result<T, fts::SearchError> + Send + 'static,
+{
+  let granularity = buckets.granularity.to_string();
+  let timezone = buckets.timezone.to_string();
+  let chart = cocktail_db_web::get_chart(
+    &state.db,
+    &project_id.to_string(),
+    &name.to_string(),
+    &tab.to_string(),
+    &granularity,
+    &timezone,
+  )
+  .await;
+  if let Ok(chart) = chart {
+    return Ok(serde_json::from_str(&chart)?);
+  }
+
+  let directory_path = format!("project-data/{}", project_id);
+  let chart = task::spawn_blocking(move || {
+    let index = fts::retrieve_index(directory_path)?;
+    compute(&index)
+  })
+  .await
+  .map_err(|e| WebError::WTFError(e.to_string()))??;
+
+  let _ = cocktail_db_web::save_chart(
+    &state.db,
+    project_id.to_string(),
+    name.to_string(),
+    tab.to_string(),
+    granularity,
+    timezone,
+    &chart,
+  )
+  .await;
+
+  Ok(chart)
+}
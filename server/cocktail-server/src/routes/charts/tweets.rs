@@ -3,9 +3,8 @@ use axum::{
   response::{IntoResponse, Redirect},
   Form,
 };
-use cocktail_db_twitter::TopKDatabase;
 use cocktail_db_web::{TweetsChart, WebDatabase};
-use fts::Author;
+use fts::{AnnotatedBurst, Author, BurstMethod, FrequenceByDate, TimeBuckets, Tz};
 use hyper::HeaderMap;
 use ory_kratos_client::apis::configuration::Configuration;
 use serde::Deserialize;
@@ -22,13 +21,18 @@ use crate::{
   routes::paths,
 };
 
-use super::hashtags::get_hashtags_chart;
+use super::{hashtags::get_hashtags_chart, time_buckets};
 
 #[derive(Deserialize)]
 pub struct QueryParams {
   pub auteur: Option<String>,
+  pub granularite: Option<String>,
+  pub pics: Option<String>,
 }
 
+/// Nombre de hashtags, de comptes et de tweets affichés pour chaque pic
+const BURST_DETAILS: usize = 5;
+
 #[derive(Debug, Deserialize)]
 pub struct ToggleElement {
   #[serde(deserialize_with = "deserialize_stringified_list")]
@@ -44,12 +48,22 @@ async fn get_chart(
   tab: &String,
   hidden_hashtags: &Vec<String>,
   author: Option<String>,
+  buckets: &TimeBuckets,
 ) -> Result<TweetsChart, WebError> {
   let directory_path = format!("project-data/{}", project_id.to_string());
   let project = cocktail_db_web::project(&db, project_id.to_hyphenated(), &user_id).await?;
+  let granularity = buckets.granularity.to_string();
+  let timezone = buckets.timezone.to_string();
   if author.is_none() && hidden_hashtags.len() == 0 {
-    let chart =
-      cocktail_db_web::get_chart(&db, &project_id.to_string(), &"tweets".to_string(), &tab).await;
+    let chart = cocktail_db_web::get_chart(
+      &db,
+      &project_id.to_string(),
+      &"tweets".to_string(),
+      tab,
+      &granularity,
+      &timezone,
+    )
+    .await;
     let tweets_chart: TweetsChart;
 
     if chart.is_ok() {
@@ -65,7 +79,7 @@ async fn get_chart(
       }
     }
   }
-  let index = fts::Index::open_in_dir(directory_path)?;
+  let index = fts::retrieve_index(directory_path)?;
 
   let tweets_counts = fts::search_tweets_count_per_day(
     &index,
@@ -77,6 +91,7 @@ async fn get_chart(
     &project.start_date,
     &project.end_date,
     &tab,
+    buckets,
   )?;
 
   if author.is_none() && hidden_hashtags.len() == 0 {
@@ -85,6 +100,8 @@ async fn get_chart(
       project_id.to_string(),
       "tweets".to_string(),
       tab.to_string(),
+      granularity,
+      timezone,
       TweetsChart {
         data: tweets_counts.clone(),
       },
@@ -110,12 +127,18 @@ pub async fn get_results(
   }: AuthenticatedUser,
   headers: HeaderMap,
   State(db): State<WebDatabase>,
-  State(conn): State<TopKDatabase>,
   State(kratos_configuration): State<Configuration>,
+  State(timezone): State<Tz>,
   query_params: Query<QueryParams>,
 ) -> Result<impl IntoResponse, WebError> {
   let project = cocktail_db_web::project(&db, project_id.to_hyphenated(), &user_id).await?;
   let author = query_params.auteur.clone().unwrap_or("".to_string());
+  let buckets = time_buckets(
+    query_params.granularite.as_deref(),
+    &project.start_date,
+    &project.end_date,
+    timezone,
+  );
   let hidden_hashtags =
     cocktail_db_web::hidden_hashtag_tweet_graph_list(&db, project_id.to_hyphenated(), &user_id)
       .await?;
@@ -127,26 +150,46 @@ pub async fn get_results(
     &tab.to_string(),
     &hidden_hashtags,
     query_params.auteur.clone(),
+    &buckets,
   )
   .await?;
 
+  let burst_method: BurstMethod = query_params
+    .pics
+    .as_deref()
+    .and_then(|pics| pics.parse().ok())
+    .unwrap_or_default();
+  let bursts = fts::search_tweets_bursts(
+    &fts::retrieve_index(format!("project-data/{}", project_id))?,
+    &match query_params.auteur.clone() {
+      Some(val) => vec![val],
+      None => vec![],
+    },
+    &hidden_hashtags,
+    &tweets_chart.data,
+    &buckets,
+    &burst_method,
+    BURST_DETAILS,
+  )?;
+  let burst_dates = burst_dates(&tweets_chart.data, &bursts);
+
   let logout_url = get_logout_url(kratos_configuration, headers).await;
 
   let (include_count, exclude_count) =
     cocktail_db_web::include_exclude_hashtag_count(&db, project_id.to_hyphenated(), &user_id)
       .await?;
 
-  let cooccurences = cocktail_db_twitter::search_topk_hashtags_cooccurence(conn.clone()).await?;
-
   let (frequences, frequences_topk, frequences_cooccurence) = get_hashtags_chart(
     &db,
     &project_id,
     &user_id,
     &tab.to_string(),
     &hidden_hashtags,
-    &cooccurences,
+    &buckets,
+    &fts::CooccurenceOptions::default(),
   )
   .await?;
+  let hashtag_bursts = fts::hashtag_bursts(&frequences, &burst_method);
 
   Ok(HtmlTemplate(Tweets {
     daterange_path: paths::ProjectDateRange { project_id },
@@ -163,7 +206,10 @@ pub async fn get_results(
     rename_popup_path: paths::PopupRenameProject { project_id },
     duplicate_popup_path: paths::PopupDuplicateProject { project_id },
     download_path: paths::DownloadProject { project_id },
+    events_path: paths::ProjectEvents { project_id },
     result_hashtags_path: paths::ProjectResultHashtags { project_id },
+    keywords_path: paths::ProjectResultKeywords { project_id },
+    links_path: paths::ProjectResultLinks { project_id },
     communities_path: paths::Communities { project_id },
     authors_select_path: paths::ProjectAuthorsSelect {
       project_id,
@@ -185,9 +231,31 @@ pub async fn get_results(
     tweets_count: project.tweets_count,
     authors_count: project.authors_count,
     hidden: !&hidden_hashtags.is_empty(),
+    granularity: buckets.granularity,
+    burst_method,
+    bursts,
+    burst_dates,
+    hashtag_bursts,
   }))
 }
 
+/// Abscisses des intervalles de `series` compris dans un pic, telles que sérialisées pour
+/// le graphique
+fn burst_dates(series: &[FrequenceByDate], bursts: &[AnnotatedBurst]) -> Vec<String> {
+  series
+    .iter()
+    .filter(|frequence| {
+      bursts
+        .iter()
+        .any(|burst| burst.burst.start <= frequence.date && frequence.date <= burst.burst.last)
+    })
+    .filter_map(|frequence| match serde_json::to_value(frequence) {
+      Ok(serde_json::Value::Object(point)) => point.get("x").and_then(|x| x.as_str()).map(String::from),
+      _ => None,
+    })
+    .collect()
+}
+
 pub async fn tweets(
   paths::ProjectTweetsGraph { project_id }: paths::ProjectTweetsGraph,
   AuthenticatedUser {
@@ -197,8 +265,8 @@ pub async fn tweets(
   }: AuthenticatedUser,
   headers: HeaderMap,
   State(db): State<WebDatabase>,
-  State(conn): State<TopKDatabase>,
   State(kratos_configuration): State<Configuration>,
+  State(timezone): State<Tz>,
   query_params: Query<QueryParams>,
 ) -> Result<impl IntoResponse, WebError> {
   get_results(
@@ -214,8 +282,8 @@ pub async fn tweets(
     },
     headers,
     State(db),
-    State(conn),
     State(kratos_configuration),
+    State(timezone),
     query_params,
   )
   .await
@@ -234,9 +302,9 @@ pub async fn tweets_tab(
   }: AuthenticatedUser,
   headers: HeaderMap,
   State(db): State<WebDatabase>,
-  State(conn): State<TopKDatabase>,
 
   State(kratos_configuration): State<Configuration>,
+  State(timezone): State<Tz>,
   query_params: Query<QueryParams>,
 ) -> Result<impl IntoResponse, WebError> {
   if niveau != 2 && tab != "total" {
@@ -263,8 +331,8 @@ pub async fn tweets_tab(
       },
       headers,
       State(db),
-      State(conn),
       State(kratos_configuration),
+      State(timezone),
       query_params,
     )
     .await
@@ -318,7 +386,7 @@ pub async fn authors_select(
   query_params: Query<QueryParams>,
 ) -> Result<impl IntoResponse, WebError> {
   let directory_path = format!("project-data/{}", project_id.to_string());
-  let index = fts::Index::open_in_dir(directory_path)?;
+  let index = fts::retrieve_index(directory_path)?;
 
   let authors_infos = fts::aggregate_authors(&index, &"total".to_string(), 0)?;
   let authors: Vec<Author> = authors_infos.into_iter().map(|e| e.author).collect();
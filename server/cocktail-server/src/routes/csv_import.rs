@@ -1,5 +1,5 @@
 use axum::{
-    extract::{Multipart, Query},
+    extract::{Multipart, Query, State},
     response::{IntoResponse, Response},
     http::StatusCode,
     Json,
@@ -11,13 +11,14 @@ use std::error::Error;
 use sqlx::postgres::PgPool;
 use chrono::Local;
 use tracing::{info, error, warn, debug};
-use crate::routes::automation::run_automation_pipeline;
+use crate::routes::automation::JobQueue;
 use csv;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CsvImportResponse {
     message: String,
     rows_imported: usize,
+    job_id: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -25,19 +26,20 @@ pub struct ImportParams {
     project_id: Option<String>,
 }
 
-pub fn routes() -> Router {
-    Router::new()
+pub fn routes(queue: JobQueue) -> Router<JobQueue> {
+    Router::with_state(queue)
         .route("/api/import/csv", post(import_csv))
 }
 
 async fn import_csv(
+    State(queue): State<JobQueue>,
     query: Query<ImportParams>,
     multipart: Multipart,
 ) -> impl IntoResponse {
     info!("Début de l'importation CSV");
     let database_url = std::env::var("PG_DATABASE_URL").expect("PG_DATABASE_URL must be set");
     
-    match import_csv_internal(database_url, multipart, query.project_id.clone()).await {
+    match import_csv_internal(&queue, database_url, multipart, query.project_id.clone()).await {
         Ok(response) => {
             info!("Importation CSV réussie");
             response
@@ -47,12 +49,14 @@ async fn import_csv(
             (StatusCode::INTERNAL_SERVER_ERROR, Json(CsvImportResponse {
                 message: format!("Erreur lors de l'import : {}", e),
                 rows_imported: 0,
+                job_id: None,
             })).into_response()
         }
     }
 }
 
 async fn import_csv_internal(
+    queue: &JobQueue,
     database_url: String,
     mut multipart: Multipart,
     project_id: Option<String>,
@@ -127,21 +131,28 @@ async fn import_csv_internal(
 
     info!("Importation terminée: {} lignes importées, {} erreurs", total_rows_imported, total_errors);
 
-    // Lancer le pipeline d'automatisation avec le schéma créé et l'ID du projet
+    // Mettre en file d'attente le pipeline d'automatisation avec le schéma créé et l'ID du projet
+    let mut job_id = None;
     if total_rows_imported > 0 {
-        info!("Démarrage du pipeline d'automatisation pour le schéma {}", schema_name);
-        if let Err(e) = run_automation_pipeline(&schema_name, project_id).await {
-            error!("Erreur lors du pipeline d'automatisation: {}", e);
-            return Ok((StatusCode::INTERNAL_SERVER_ERROR, Json(CsvImportResponse {
-                message: format!("Import réussi mais erreur lors de l'automatisation : {}", e),
-                rows_imported: total_rows_imported,
-            })).into_response());
+        info!("Mise en file d'attente du pipeline d'automatisation pour le schéma {}", schema_name);
+        match queue.enqueue(&schema_name, project_id).await {
+            Ok(job) => job_id = Some(job.job_id),
+            Err(e) => {
+                error!("Erreur lors de la mise en file d'attente du pipeline: {}", e);
+                return Ok((StatusCode::INTERNAL_SERVER_ERROR, Json(CsvImportResponse {
+                    message: format!("Import réussi mais erreur lors de l'automatisation : {}", e),
+                    rows_imported: total_rows_imported,
+                    job_id: None,
+                })).into_response());
+            }
         }
     }
 
-    Ok((StatusCode::OK, Json(CsvImportResponse {
+    let status = if job_id.is_some() { StatusCode::ACCEPTED } else { StatusCode::OK };
+    Ok((status, Json(CsvImportResponse {
         message: format!("Import réussi avec {} erreurs", total_errors),
         rows_imported: total_rows_imported,
+        job_id,
     })).into_response())
 }
 
@@ -53,7 +53,7 @@ pub async fn home(
     let directory_path = std::env::var("DIRECTORY_PATH")?;
 
     let tweets = fts::search_tweets(
-      &fts::Index::open_in_dir(directory_path)?,
+      &fts::retrieve_index(directory_path)?,
       "text:lubrizol",
       &Some(OrderBy::RetweetCount),
     )?;
@@ -42,6 +42,31 @@ pub struct ProjectRequest {
   pub project_id: Uuid,
 }
 
+#[derive(Debug, Deserialize, TypedPath)]
+#[typed_path("/projets/:project_id/requete/explication")]
+pub struct ProjectRequestExplain {
+  pub project_id: Uuid,
+}
+
+#[derive(Debug, Deserialize, TypedPath)]
+#[typed_path("/api/projets/:project_id/requete/explication")]
+pub struct ApiProjectRequestExplain {
+  pub project_id: Uuid,
+}
+
+#[derive(Debug, Deserialize, TypedPath)]
+#[typed_path("/projets/:project_id/alertes")]
+pub struct ProjectAlerts {
+  pub project_id: Uuid,
+}
+
+#[derive(Debug, Deserialize, TypedPath)]
+#[typed_path("/projets/:project_id/alertes/:alert_id/suppression")]
+pub struct ProjectAlertDelete {
+  pub project_id: Uuid,
+  pub alert_id: Uuid,
+}
+
 // #[derive(Debug, Deserialize, TypedPath)]
 // #[typed_path("/projets/:project_id/basket")]
 // pub struct ProjectBasket {
@@ -153,6 +178,32 @@ pub struct ProjectAuthorsTab {
   pub tab: String,
 }
 
+#[derive(Debug, Deserialize, TypedPath)]
+#[typed_path("/projets/:project_id/resultat_mots_cles")]
+pub struct ProjectResultKeywords {
+  pub project_id: Uuid,
+}
+
+#[derive(Debug, Deserialize, TypedPath)]
+#[typed_path("/projets/:project_id/resultat_mots_cles/:tab")]
+pub struct ProjectResultKeywordsTab {
+  pub project_id: Uuid,
+  pub tab: String,
+}
+
+#[derive(Debug, Deserialize, TypedPath)]
+#[typed_path("/projets/:project_id/resultat_liens")]
+pub struct ProjectResultLinks {
+  pub project_id: Uuid,
+}
+
+#[derive(Debug, Deserialize, TypedPath)]
+#[typed_path("/projets/:project_id/resultat_liens/:tab")]
+pub struct ProjectResultLinksTab {
+  pub project_id: Uuid,
+  pub tab: String,
+}
+
 #[derive(Debug, Deserialize, TypedPath)]
 #[typed_path("/projets/:project_id/tweets")]
 pub struct ProjectTweetsGraph {
@@ -223,6 +274,12 @@ pub struct PopupDuplicateProject {
   pub project_id: Uuid,
 }
 
+#[derive(Debug, Deserialize, TypedPath)]
+#[typed_path("/projets/:project_id/evenements")]
+pub struct ProjectEvents {
+  pub project_id: Uuid,
+}
+
 #[derive(Debug, Deserialize, TypedPath)]
 #[typed_path("/projets/:project_id/download")]
 pub struct DownloadProject {
@@ -0,0 +1,219 @@
+use axum::{
+  extract::State,
+  http::StatusCode,
+  response::{IntoResponse, Redirect, Response},
+  Form,
+};
+use cocktail_db_web::{AlertSchedule, NewAlert};
+use fts::AlertCondition;
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::{
+  alerts::{check_webhook_url, is_valid_email, ALERT_HISTORY_SIZE},
+  error::WebError,
+  models::{
+    auth::AuthenticatedUser,
+    templates::{self, AlertEventRow, AlertRow, HtmlTemplate},
+  },
+  routes::paths,
+  AppState,
+};
+
+/// Formulaire de création d'une alerte ; `threshold` est le nombre de nouveaux tweets
+/// ou le facteur du pic de hashtag selon `condition`
+#[derive(Debug, Deserialize)]
+pub struct AlertForm {
+  name: String,
+  condition: String,
+  threshold: String,
+  #[serde(default)]
+  hashtag: String,
+  schedule: AlertSchedule,
+  #[serde(default)]
+  email: String,
+  #[serde(default)]
+  webhook_url: String,
+}
+
+impl AlertForm {
+  fn new_alert(self, webhook_hosts: &[String]) -> Result<NewAlert, String> {
+    let name = self.name.trim().to_string();
+    if name.is_empty() {
+      return Err("l'alerte doit avoir un nom".to_string());
+    }
+    if name.chars().any(char::is_control) {
+      return Err("le nom de l'alerte ne peut pas contenir de saut de ligne".to_string());
+    }
+
+    let threshold = self.threshold.trim();
+    let condition = match self.condition.as_str() {
+      "new_tweets" => AlertCondition::NewTweets {
+        threshold: threshold
+          .parse()
+          .map_err(|_| format!("nombre de tweets invalide : {threshold}"))?,
+      },
+      "hashtag_surge" => {
+        let hashtag = self.hashtag.trim().trim_start_matches('#').to_string();
+        if hashtag.is_empty() {
+          return Err("le pic d'un hashtag demande un hashtag".to_string());
+        }
+        let factor = threshold
+          .replace(',', ".")
+          .parse::<f64>()
+          .ok()
+          .filter(|factor| factor.is_finite() && *factor > 0.0)
+          .ok_or_else(|| format!("facteur invalide : {threshold}"))?;
+        AlertCondition::HashtagSurge { hashtag, factor }
+      }
+      condition => return Err(format!("condition inconnue : {condition}")),
+    };
+
+    let optional = |value: String| {
+      let value = value.trim().to_string();
+      match value.is_empty() {
+        true => None,
+        false => Some(value),
+      }
+    };
+    let email = optional(self.email);
+    if let Some(email) = &email {
+      if !is_valid_email(email) {
+        return Err(format!("adresse de courriel invalide : {email}"));
+      }
+    }
+    let webhook_url = optional(self.webhook_url);
+    if let Some(webhook_url) = &webhook_url {
+      check_webhook_url(webhook_url, webhook_hosts)?;
+    }
+
+    Ok(NewAlert {
+      name,
+      condition,
+      schedule: self.schedule,
+      email,
+      webhook_url,
+    })
+  }
+}
+
+/// Alertes du projet et leurs derniers déclenchements, affichées sous le requêteur
+#[tracing::instrument]
+pub async fn project_alerts(
+  paths::ProjectAlerts { project_id }: paths::ProjectAlerts,
+  AuthenticatedUser {
+    niveau: _,
+    last_login_datetime: _,
+    user_id,
+  }: AuthenticatedUser,
+  State(state): State<AppState>,
+) -> Result<impl IntoResponse, WebError> {
+  alerts_frame(&state, project_id, &user_id, None).await
+}
+
+#[tracing::instrument]
+pub async fn create_project_alert(
+  paths::ProjectAlerts { project_id }: paths::ProjectAlerts,
+  AuthenticatedUser {
+    niveau: _,
+    last_login_datetime: _,
+    user_id,
+  }: AuthenticatedUser,
+  State(state): State<AppState>,
+  Form(form): Form<AlertForm>,
+) -> Result<Response, WebError> {
+  cocktail_db_web::project(&state.db, project_id.to_hyphenated(), &user_id).await?;
+
+  match form.new_alert(&state.webhook_hosts) {
+    Ok(alert) => {
+      cocktail_db_web::create_alert(&state.db, &project_id.to_string(), &user_id, &alert).await?;
+      Ok(Redirect::to(&paths::ProjectAlerts { project_id }.to_string()).into_response())
+    }
+    // Turbo n'affiche la réponse d'un formulaire sans redirection qu'en cas d'erreur
+    Err(error) => Ok(
+      (
+        StatusCode::UNPROCESSABLE_ENTITY,
+        alerts_frame(&state, project_id, &user_id, Some(error)).await?,
+      )
+        .into_response(),
+    ),
+  }
+}
+
+#[tracing::instrument]
+pub async fn delete_project_alert(
+  paths::ProjectAlertDelete {
+    project_id,
+    alert_id,
+  }: paths::ProjectAlertDelete,
+  AuthenticatedUser {
+    niveau: _,
+    last_login_datetime: _,
+    user_id,
+  }: AuthenticatedUser,
+  State(state): State<AppState>,
+) -> Result<impl IntoResponse, WebError> {
+  if !cocktail_db_web::delete_alert(&state.db, &alert_id.to_string(), &user_id).await? {
+    return Err(WebError::NotFound);
+  }
+
+  Ok(Redirect::to(
+    &paths::ProjectAlerts { project_id }.to_string(),
+  ))
+}
+
+async fn alerts_frame(
+  state: &AppState,
+  project_id: Uuid,
+  user_id: &str,
+  error: Option<String>,
+) -> Result<HtmlTemplate<templates::ProjectAlerts>, WebError> {
+  let alerts = cocktail_db_web::project_alerts(&state.db, &project_id.to_string(), user_id).await?;
+  let events = cocktail_db_web::project_alert_events(
+    &state.db,
+    &project_id.to_string(),
+    user_id,
+    ALERT_HISTORY_SIZE,
+  )
+  .await?;
+
+  let alerts = alerts
+    .into_iter()
+    .map(|alert| AlertRow {
+      condition: alert.condition.describe(),
+      schedule: match alert.schedule {
+        AlertSchedule::EachRun => "à chaque mise à jour",
+        AlertSchedule::Daily => "une fois par jour",
+        AlertSchedule::Weekly => "une fois par semaine",
+      },
+      recipients: [alert.email, alert.webhook_url]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(", "),
+      active: alert.active,
+      delete_path: paths::ProjectAlertDelete {
+        project_id,
+        // identifiant créé par create_alert
+        alert_id: Uuid::parse_str(&alert.alert_id).unwrap_or_default(),
+      },
+      name: alert.name,
+    })
+    .collect();
+  let events = events
+    .into_iter()
+    .map(|event| AlertEventRow {
+      alert_name: event.alert_name,
+      triggered_at: event.triggered_at,
+      summary: event.summary,
+      delivery: event.delivery.unwrap_or_default(),
+    })
+    .collect();
+
+  Ok(HtmlTemplate(templates::ProjectAlerts {
+    alerts_path: paths::ProjectAlerts { project_id },
+    alerts,
+    events,
+    error,
+  }))
+}
@@ -50,6 +50,7 @@ pub async fn daterange(
     rename_popup_path: paths::PopupRenameProject { project_id },
     duplicate_popup_path: paths::PopupDuplicateProject { project_id },
     download_path: paths::DownloadProject { project_id },
+    events_path: paths::ProjectEvents { project_id },
     analysis_preview_popup_path: paths::PopupAnalysisPreview { project_id },
     analysis_path: paths::ProjectAnalysis { project_id },
     is_analyzed: project.is_analyzed == 1,
@@ -57,6 +58,8 @@ pub async fn daterange(
     tweets_graph_path: paths::ProjectTweetsGraph { project_id },
     authors_path: paths::ProjectAuthors { project_id },
     result_hashtags_path: paths::ProjectResultHashtags { project_id },
+    keywords_path: paths::ProjectResultKeywords { project_id },
+    links_path: paths::ProjectResultLinks { project_id },
     communities_path: paths::Communities { project_id },
     logout_url,
     include_count,
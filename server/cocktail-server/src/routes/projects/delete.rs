@@ -45,8 +45,8 @@ pub async fn delete_project(
   let graph_generator = cocktail_graph_utils::GraphGenerator::new(
     state.database_url,
     project_id.to_string(),
-    PathBuf::new(),
-    PathBuf::new(),
+    None,
+    None,
     "".to_string(),
     "".to_string(),
     "".to_string(),
@@ -1,7 +1,9 @@
 use crate::{error::WebError, models::auth::AuthenticatedUser, routes::paths::DownloadProject};
-use axum::{extract::State, response::IntoResponse};
+use axum::{body::StreamBody, extract::State, response::IntoResponse};
 use cocktail_db_web::WebDatabase;
+use futures::stream;
 use hyper::header;
+use tokio::{sync::mpsc, task};
 
 pub async fn download_project(
   DownloadProject { project_id }: DownloadProject,
@@ -15,18 +17,13 @@ pub async fn download_project(
   let directory_path = format!("project-data/{}", project_id.to_string());
   let project = cocktail_db_web::project(&db, project_id.to_hyphenated(), &user_id).await?;
 
-  let index = fts::Index::open_in_dir(directory_path)?;
+  let index = fts::retrieve_index(directory_path)?;
+  let tweets = fts::all_tweets(&index)?;
 
-  if project.tweets_count > 5000 {
-    return Err(WebError::Community(
-      "Nombre de tweets trop élevé".to_owned(),
-    ));
-  }
-  let tweets = fts::get_all_tweets(&index)?;
-
-  let mut content = "".to_owned();
-  content.push_str(
-    &vec![
+  // le fichier est envoyé au fil de la lecture de l'index, ligne par ligne
+  let (sender, receiver) = mpsc::channel::<Result<String, fts::SearchError>>(1024);
+  task::spawn_blocking(move || {
+    let header = [
       "ID".to_owned(),
       "Publication Date".to_owned(),
       "Direct Link".to_owned(),
@@ -37,30 +34,21 @@ pub async fn download_project(
       "Nombre RT".to_owned(),
       "Nombre Citations".to_owned(),
     ]
-    .join(","),
-  );
-  content.push('\n');
-  for tweet in tweets {
-    content.push_str(
-      &vec![
-        tweet.id.to_string(),
-        tweet.published_time.to_string(),
-        format!(
-          "https://twitter.com/{}/status/{}",
-          tweet.user_screen_name, tweet.id
-        ),
-        format!("\"{}\"", tweet.text.replace("\"", "\"\"")),
-        tweet.user_name,
-        format!("\"{}\"", tweet.urls.join(",")),
-        tweet.reply_count.to_string(),
-        tweet.retweet_count.to_string(),
-        tweet.quote_count.to_string(),
-      ]
-      .join(","),
-    );
-
-    content.push('\n');
-  }
+    .join(",");
+    if sender.blocking_send(Ok(header + "\n")).is_err() {
+      return;
+    }
+    for tweet in tweets {
+      let line = tweet.map(|tweet| csv_line(tweet) + "\n");
+      // téléchargement interrompu par le client
+      if sender.blocking_send(line).is_err() {
+        return;
+      }
+    }
+  });
+  let content = StreamBody::new(stream::unfold(receiver, |mut receiver| async move {
+    receiver.recv().await.map(|line| (line, receiver))
+  }));
 
   let headers = [
     (header::CONTENT_TYPE, "text/csv; charset=utf-8"),
@@ -72,3 +60,21 @@ pub async fn download_project(
 
   Ok((headers, content).into_response())
 }
+
+fn csv_line(tweet: fts::Tweet) -> String {
+  [
+    tweet.id.to_string(),
+    tweet.published_time.to_string(),
+    format!(
+      "https://twitter.com/{}/status/{}",
+      tweet.user_screen_name, tweet.id
+    ),
+    format!("\"{}\"", tweet.text.replace("\"", "\"\"")),
+    tweet.user_name,
+    format!("\"{}\"", tweet.urls.join(",")),
+    tweet.reply_count.to_string(),
+    tweet.retweet_count.to_string(),
+    tweet.quote_count.to_string(),
+  ]
+  .join(",")
+}
@@ -0,0 +1,165 @@
+use std::{collections::VecDeque, convert::Infallible, time::Duration};
+
+use askama::Template;
+use axum::{
+  extract::State,
+  response::{
+    sse::{Event, KeepAlive},
+    IntoResponse, Sse,
+  },
+};
+use cocktail_graph_utils::GraphStatus;
+use futures::stream;
+use sqlx::{postgres::PgPoolOptions, PgPool};
+use tokio::{
+  sync::broadcast::{error::RecvError, Receiver},
+  time::{interval, Interval, MissedTickBehavior},
+};
+
+use crate::{
+  error::WebError,
+  events::{ProjectEvent, ProjectStep, StepStatus},
+  models::{auth::AuthenticatedUser, templates::ProjectProgress},
+  routes::paths::ProjectEvents,
+  AppState,
+};
+
+/// Intervalle de consultation de la table `status` des graphes, alimentée hors du serveur
+/// lorsque les scripts R / Python sont utilisés
+const GRAPH_STATUS_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Connexions à PostgreSQL partagées par tous les flux ouverts, quel que soit leur nombre
+const GRAPH_STATUS_CONNECTIONS: u32 = 2;
+
+/// Pool de lecture de la table `status` des graphes, ouvert à la première consultation
+pub fn graph_status_pool(database_url: &str) -> sqlx::Result<PgPool> {
+  PgPoolOptions::new()
+    .max_connections(GRAPH_STATUS_CONNECTIONS)
+    .connect_lazy(database_url)
+}
+
+/// Flux Server-Sent Events des étapes de traitement d'un projet, au format Turbo Stream
+#[tracing::instrument(skip(state))]
+pub async fn project_events(
+  ProjectEvents { project_id }: ProjectEvents,
+  AuthenticatedUser {
+    niveau: _,
+    last_login_datetime: _,
+    user_id,
+  }: AuthenticatedUser,
+  State(state): State<AppState>,
+) -> Result<impl IntoResponse, WebError> {
+  cocktail_db_web::project(&state.db, project_id.to_hyphenated(), &user_id).await?;
+
+  let watcher = Watcher {
+    project_id: project_id.to_string(),
+    receiver: state.events.subscribe(),
+    pg_pool: state.graph_status_db.clone(),
+    interval: {
+      let mut interval = interval(GRAPH_STATUS_INTERVAL);
+      interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+      interval
+    },
+    graph_status_seen: None,
+    pending: VecDeque::new(),
+  };
+
+  let stream = stream::unfold(watcher, |mut watcher| async move {
+    let event = watcher.next().await?;
+    Some((Ok::<_, Infallible>(to_sse(&event)), watcher))
+  });
+
+  Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+fn to_sse(event: &ProjectEvent) -> Event {
+  let status = match event.status {
+    StepStatus::Started => "started",
+    StepStatus::Completed => "completed",
+    StepStatus::Failed => "failed",
+  };
+  // l'import n'est terminé qu'avec l'évènement final, publié sans détail d'étape
+  let refresh = event.status == StepStatus::Completed
+    && match event.step {
+      ProjectStep::Charts | ProjectStep::Graph => true,
+      ProjectStep::Automation => event.detail.is_none(),
+      ProjectStep::IndexCopy | ProjectStep::Ingestion => false,
+    };
+
+  let html = ProjectProgress {
+    event,
+    status,
+    refresh,
+  }
+  .render()
+  .unwrap_or_default();
+
+  Event::default().data(html)
+}
+
+/// Évènements d'un projet : ceux publiés par le serveur et les nouvelles lignes de la
+/// table `status` des graphes
+struct Watcher {
+  project_id: String,
+  receiver: Receiver<ProjectEvent>,
+  pg_pool: PgPool,
+  interval: Interval,
+  /// nombre de lignes de `status` déjà vues, `None` avant la première lecture
+  graph_status_seen: Option<usize>,
+  pending: VecDeque<ProjectEvent>,
+}
+
+impl Watcher {
+  async fn next(&mut self) -> Option<ProjectEvent> {
+    loop {
+      if let Some(event) = self.pending.pop_front() {
+        return Some(event);
+      }
+
+      tokio::select! {
+        received = self.receiver.recv() => match received {
+          Ok(event) if event.project_id == self.project_id => return Some(event),
+          Ok(_) | Err(RecvError::Lagged(_)) => continue,
+          Err(RecvError::Closed) => return None,
+        },
+        _ = self.interval.tick() => self.poll_graph_status().await,
+      }
+    }
+  }
+
+  async fn poll_graph_status(&mut self) {
+    let history =
+      match cocktail_graph_utils::graph_status_history(&self.pg_pool, &self.project_id).await {
+        Ok(history) => history,
+        Err(e) => {
+          tracing::debug!("statut des graphes indisponible : {e}");
+          return;
+        }
+      };
+
+    // la première lecture sert de référence : seules les transitions suivantes sont envoyées
+    if let Some(seen) = self.graph_status_seen.replace(history.len()) {
+      self.pending.extend(
+        history
+          .into_iter()
+          .skip(seen)
+          .map(|status| graph_event(&self.project_id, status)),
+      );
+    }
+  }
+}
+
+fn graph_event(project_id: &str, status: GraphStatus) -> ProjectEvent {
+  let step_status = match status.status.as_str() {
+    "started" => StepStatus::Started,
+    "done" => StepStatus::Completed,
+    _ => StepStatus::Failed,
+  };
+
+  let mut event = ProjectEvent::new(project_id, ProjectStep::Graph, step_status).detail(format!(
+    "{} / {} / {}",
+    status.graph_name, status.community, status.centrality
+  ));
+  event.datetime = status.datetime;
+  event
+}
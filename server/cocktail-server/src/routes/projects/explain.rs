@@ -0,0 +1,163 @@
+use axum::{
+  extract::State,
+  http::StatusCode,
+  response::{IntoResponse, Response},
+  Json,
+};
+use chrono::NaiveDate;
+use cocktail_db_web::{Bloc, ParsedProjectCriteria, Project, StudyFilters};
+use fts::{ClauseOccur, QueryClause, QueryExplanation};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{
+  error::WebError,
+  models::{
+    auth::AuthenticatedUser,
+    templates::{ExplainRow, HtmlTemplate, RequestExplain},
+  },
+  routes::paths,
+  AppState,
+};
+
+/// Critères à essayer à la place de ceux enregistrés dans le projet (dry-run)
+#[derive(Debug, Default, Deserialize)]
+pub struct ExplainCriteria {
+  pub start_date: Option<NaiveDate>,
+  pub end_date: Option<NaiveDate>,
+  pub hashtag_list: Option<Vec<String>>,
+  pub exclude_hashtag_list: Option<Vec<String>>,
+  pub request_params: Option<Vec<Vec<Bloc>>>,
+  pub filters: Option<StudyFilters>,
+}
+
+/// Décompte de la requête affiché sous le requêteur
+#[tracing::instrument]
+pub async fn request_explain(
+  paths::ProjectRequestExplain { project_id }: paths::ProjectRequestExplain,
+  AuthenticatedUser {
+    niveau: _,
+    last_login_datetime: _,
+    user_id,
+  }: AuthenticatedUser,
+  State(state): State<AppState>,
+) -> Result<impl IntoResponse, WebError> {
+  let project = cocktail_db_web::project(&state.db, project_id.to_hyphenated(), &user_id).await?;
+  let explanation = explain(&state, &project)?;
+
+  let mut rows = vec![];
+  flatten(&explanation.query, 0, &mut rows);
+
+  Ok(HtmlTemplate(RequestExplain {
+    total: explanation.total,
+    rows,
+  }))
+}
+
+/// Arbre de la requête du projet, avec le nombre de tweets de chaque clause
+#[tracing::instrument]
+pub async fn api_request_explain(
+  paths::ApiProjectRequestExplain { project_id }: paths::ApiProjectRequestExplain,
+  AuthenticatedUser {
+    niveau: _,
+    last_login_datetime: _,
+    user_id,
+  }: AuthenticatedUser,
+  State(state): State<AppState>,
+) -> Result<Response, WebError> {
+  let project = cocktail_db_web::project(&state.db, project_id.to_hyphenated(), &user_id).await?;
+
+  Ok(Json(explain(&state, &project)?).into_response())
+}
+
+/// Comme [`api_request_explain`], avec les critères du corps de la requête à la place
+/// de ceux du projet, sans rien enregistrer
+#[tracing::instrument]
+pub async fn api_request_explain_dry_run(
+  paths::ApiProjectRequestExplain { project_id }: paths::ApiProjectRequestExplain,
+  AuthenticatedUser {
+    niveau: _,
+    last_login_datetime: _,
+    user_id,
+  }: AuthenticatedUser,
+  State(state): State<AppState>,
+  Json(criteria): Json<ExplainCriteria>,
+) -> Result<Response, WebError> {
+  let mut project =
+    cocktail_db_web::project(&state.db, project_id.to_hyphenated(), &user_id).await?;
+
+  if let Some(start_date) = criteria.start_date {
+    project.start_date = start_date;
+  }
+  if let Some(end_date) = criteria.end_date {
+    project.end_date = end_date;
+  }
+  if let Some(request_params) = criteria.request_params {
+    // fts::get_query attend la sélection puis un bloc d'exclusion
+    if request_params.len() != 2 || request_params[1].is_empty() {
+      return Ok(
+        (
+          StatusCode::BAD_REQUEST,
+          Json(json!({ "error": "request_params doit contenir la sélection et l'exclusion" })),
+        )
+          .into_response(),
+      );
+    }
+    project.request_params.0 = request_params;
+  }
+  if let Some(filters) = criteria.filters {
+    project.filters.0 = filters;
+  }
+
+  let mut parsed_criteria = ParsedProjectCriteria::from(&project);
+  if let Some(hashtag_list) = criteria.hashtag_list {
+    parsed_criteria.hashtag_list = hashtag_list;
+  }
+  if let Some(exclude_hashtag_list) = criteria.exclude_hashtag_list {
+    parsed_criteria.exclude_hashtag_list = exclude_hashtag_list;
+  }
+
+  let explanation = fts::explain_query(
+    &fts::retrieve_index(state.directory_path.clone())?,
+    &project.start_date,
+    &project.end_date,
+    &parsed_criteria.hashtag_list,
+    &parsed_criteria.exclude_hashtag_list,
+    &project.request_params,
+    &project.filters,
+  )?;
+
+  Ok(Json(explanation).into_response())
+}
+
+fn explain(state: &AppState, project: &Project) -> Result<QueryExplanation, WebError> {
+  let parsed_criteria = ParsedProjectCriteria::from(project);
+
+  Ok(fts::explain_query(
+    &fts::retrieve_index(state.directory_path.clone())?,
+    &project.start_date,
+    &project.end_date,
+    &parsed_criteria.hashtag_list,
+    &parsed_criteria.exclude_hashtag_list,
+    &project.request_params,
+    &project.filters,
+  )?)
+}
+
+fn flatten(clause: &QueryClause, depth: usize, rows: &mut Vec<ExplainRow>) {
+  rows.push(ExplainRow {
+    depth,
+    occur: match clause.occur {
+      None => "",
+      Some(ClauseOccur::Must) => "doit contenir",
+      Some(ClauseOccur::Should) => "peut contenir",
+      Some(ClauseOccur::MustNot) => "ne doit pas contenir",
+    },
+    label: clause.label.clone(),
+    hits: clause.hits,
+  });
+
+  for child in &clause.children {
+    flatten(child, depth + 1, rows);
+  }
+}
@@ -5,7 +5,7 @@ use axum::{
   http::HeaderMap,
   response::IntoResponse,
 };
-use cocktail_db_twitter::{HashtagQuery, TopKDatabase};
+use cocktail_db_twitter::{HashtagQuery, TopKDatabase, TrendWindow};
 use cocktail_db_web::WebDatabase;
 use ory_kratos_client::apis::configuration::Configuration;
 use serde::Deserialize;
@@ -54,6 +54,7 @@ pub async fn hashtags(
     rename_popup_path: paths::PopupRenameProject { project_id },
     duplicate_popup_path: paths::PopupDuplicateProject { project_id },
     download_path: paths::DownloadProject { project_id },
+    events_path: paths::ProjectEvents { project_id },
     include_basket_path: paths::ProjectBasketInclude { project_id },
     exclude_basket_path: paths::ProjectBasketExclude { project_id },
     daterange_path: paths::ProjectDateRange { project_id },
@@ -67,6 +68,8 @@ pub async fn hashtags(
     tweets_graph_path: paths::ProjectTweetsGraph { project_id },
     authors_path: paths::ProjectAuthors { project_id },
     result_hashtags_path: paths::ProjectResultHashtags { project_id },
+    keywords_path: paths::ProjectResultKeywords { project_id },
+    links_path: paths::ProjectResultLinks { project_id },
     communities_path: paths::Communities { project_id },
     logout_url,
 
@@ -133,6 +136,21 @@ pub async fn hashtags_corpus(
   Ok(HtmlTemplate(templates::PopupHashtagsCorpus))
 }
 
+/// Nombre de hashtags en tendance affichés, comme pour les plus utilisés
+const TRENDING_SIZE: usize = 10;
+
+#[derive(Debug, Deserialize, Default)]
+pub struct TopKQueryParams {
+  pub exclude: bool,
+  pub block_id: Option<i32>,
+  /// hashtags en tendance sur les `recents` derniers jours par rapport aux `reference`
+  /// jours précédents, plutôt que les plus utilisés
+  #[serde(default)]
+  pub tendance: bool,
+  pub recents: Option<i64>,
+  pub reference: Option<i64>,
+}
+
 #[tracing::instrument]
 pub async fn hashtags_topk(
   paths::PopupHashtagsTopK { project_id }: paths::PopupHashtagsTopK,
@@ -141,16 +159,40 @@ pub async fn hashtags_topk(
     last_login_datetime: _,
     user_id,
   }: AuthenticatedUser,
-  params: Option<Query<ExcludeQueryParam>>,
+  params: Option<Query<TopKQueryParams>>,
   State(conn): State<TopKDatabase>,
   State(db): State<WebDatabase>,
 ) -> Result<impl IntoResponse, WebError> {
   let params_unwraped = params.unwrap_or_default();
   let exclude = params_unwraped.exclude;
   let block_id = params_unwraped.block_id;
+  let trending = params_unwraped.tendance;
 
-  let mut topk =
-    cocktail_db_twitter::search_topk_hashtags(conn.clone(), HashtagQuery::default()).await?;
+  let default_window = TrendWindow::default();
+  let trend_window = TrendWindow {
+    end: None,
+    recent_days: params_unwraped
+      .recents
+      .unwrap_or(default_window.recent_days)
+      .clamp(1, 60),
+    baseline_days: params_unwraped
+      .reference
+      .unwrap_or(default_window.baseline_days)
+      .clamp(1, 120),
+  };
+
+  let (mut topk, trend_scores) = if trending {
+    let trending =
+      cocktail_db_twitter::search_trending_hashtags(conn.clone(), &trend_window, TRENDING_SIZE)
+        .await?;
+    let scores = trending.iter().map(|t| t.score).collect();
+    (trending.into_iter().map(Into::into).collect(), scores)
+  } else {
+    (
+      cocktail_db_twitter::search_topk_hashtags(conn.clone(), HashtagQuery::default()).await?,
+      vec![],
+    )
+  };
 
   match block_id {
     None => {
@@ -194,7 +236,11 @@ pub async fn hashtags_topk(
   let topk = topk.into_iter().map(Into::into).collect();
 
   Ok(HtmlTemplate(templates::PopupHashtagsTopK {
+    topk_path: paths::PopupHashtagsTopK { project_id },
     hashtags: topk,
+    trending,
+    trend_window,
+    trend_scores,
     include_basket_path: paths::ProjectBasketInclude { project_id },
     exclude_basket_path: paths::ProjectBasketExclude { project_id },
     exclude_popup_style: exclude,
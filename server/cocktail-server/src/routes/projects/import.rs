@@ -3,9 +3,9 @@ use crate::{
     models::templates::{HtmlTemplate, ImportTemplate},
     routes::paths::{
         ProjectImport, ProjectCollect, ProjectDateRange, ProjectHashtags, ProjectRequest,
-        PopupDeleteProject, PopupRenameProject, DownloadProject, PopupDuplicateProject,
+        PopupDeleteProject, PopupRenameProject, DownloadProject, ProjectEvents, PopupDuplicateProject,
         PopupAnalysisPreview, ProjectAnalysis, ProjectResults, ProjectTweetsGraph, ProjectAuthors,
-        ProjectResultHashtags, Communities,
+        ProjectResultHashtags, ProjectResultKeywords, ProjectResultLinks, Communities,
     },
     AppState,
 };
@@ -42,6 +42,7 @@ pub async fn import(
         delete_popup_path: PopupDeleteProject { project_id },
         rename_popup_path: PopupRenameProject { project_id },
         download_path: DownloadProject { project_id },
+        events_path: ProjectEvents { project_id },
         duplicate_popup_path: PopupDuplicateProject { project_id },
         analysis_preview_popup_path: PopupAnalysisPreview { project_id },
         analysis_path: ProjectAnalysis { project_id },
@@ -49,6 +50,8 @@ pub async fn import(
         tweets_graph_path: ProjectTweetsGraph { project_id },
         authors_path: ProjectAuthors { project_id },
         result_hashtags_path: ProjectResultHashtags { project_id },
+        keywords_path: ProjectResultKeywords { project_id },
+        links_path: ProjectResultLinks { project_id },
         communities_path: Communities { project_id },
         logout_url,
         include_count,
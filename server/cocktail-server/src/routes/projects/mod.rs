@@ -9,12 +9,15 @@ use crate::{
   routes::paths::Projects,
 };
 
+pub mod alerts;
 pub mod basket;
 pub mod collect;
 pub mod daterange;
 pub mod delete;
 pub mod download;
 pub mod duplicate;
+pub mod events;
+pub mod explain;
 pub mod hashtags;
 pub mod nouveau;
 pub mod rename;
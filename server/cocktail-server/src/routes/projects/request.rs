@@ -17,7 +17,8 @@ use hyper::HeaderMap;
 use ory_kratos_client::apis::configuration::Configuration;
 use regex::Regex;
 
-use cocktail_db_web::Bloc;
+use cocktail_db_web::{Bloc, StudyFilters};
+use fts::{BoundingBox, KeywordPattern, QueryExpr};
 use serde::Deserialize;
 
 use crate::{models::templates::HtmlTemplate, routes::paths};
@@ -31,8 +32,100 @@ pub struct UpdateRequest {
   pub switch_exclusion: Option<String>,
   pub exact_keywords: Option<String>,
   pub exact_group_keywords: Option<String>,
+  pub exact_match: Option<String>,
+  pub pattern_keywords: Option<String>,
+  pub pattern_mode: Option<String>,
+  /// distance d'un mot approché ou écart d'une expression, vide pour la valeur par défaut
+  pub pattern_parameter: Option<String>,
   pub accounts: Option<String>,
   pub block_id: Option<i32>,
+  pub query_text: Option<String>,
+  /// présent quand le formulaire des filtres est envoyé
+  pub filters: Option<String>,
+  pub languages: Option<String>,
+  pub west: Option<String>,
+  pub south: Option<String>,
+  pub east: Option<String>,
+  pub north: Option<String>,
+  pub exclude_sensitive: Option<String>,
+}
+
+/// Filtres de l'étude tels qu'affichés dans le formulaire
+pub struct FiltersForm {
+  pub languages: String,
+  pub west: String,
+  pub south: String,
+  pub east: String,
+  pub north: String,
+  pub exclude_sensitive: bool,
+  pub error: Option<String>,
+}
+
+impl From<&StudyFilters> for FiltersForm {
+  fn from(filters: &StudyFilters) -> Self {
+    let coordinate = |value: fn(&BoundingBox) -> f64| {
+      filters
+        .bounding_box
+        .as_ref()
+        .map(|bounding_box| value(bounding_box).to_string())
+        .unwrap_or_default()
+    };
+
+    FiltersForm {
+      languages: filters.languages.join(", "),
+      west: coordinate(|bounding_box| bounding_box.west),
+      south: coordinate(|bounding_box| bounding_box.south),
+      east: coordinate(|bounding_box| bounding_box.east),
+      north: coordinate(|bounding_box| bounding_box.north),
+      exclude_sensitive: filters.exclude_sensitive,
+      error: None,
+    }
+  }
+}
+
+/// Filtres saisis dans le formulaire, erreur si la zone est incomplète ou invalide
+fn parse_filters(update_request: &UpdateRequest) -> Result<StudyFilters, String> {
+  let mut languages: Vec<String> = vec![];
+  for language in update_request
+    .languages
+    .as_deref()
+    .unwrap_or_default()
+    .split(|c: char| c == ',' || c.is_whitespace())
+    .filter_map(fts::normalize_language)
+  {
+    if !languages.contains(&language) {
+      languages.push(language);
+    }
+  }
+
+  let coordinates: Vec<&str> = [
+    &update_request.west,
+    &update_request.south,
+    &update_request.east,
+    &update_request.north,
+  ]
+  .iter()
+  .map(|value| value.as_deref().unwrap_or_default().trim())
+  .collect();
+  let bounding_box = if coordinates.iter().all(|value| value.is_empty()) {
+    None
+  } else {
+    let coordinates = coordinates
+      .iter()
+      .map(|value| value.replace(',', ".").parse::<f64>())
+      .collect::<Result<Vec<f64>, _>>()
+      .map_err(|_| "La zone doit avoir quatre coordonnées en degrés".to_string())?;
+    Some(
+      BoundingBox::new(coordinates[0], coordinates[1], coordinates[2], coordinates[3])
+        .map_err(|e| e.to_string())?,
+    )
+  };
+
+  Ok(StudyFilters {
+    languages,
+    bounding_box,
+    exclude_sensitive: update_request.exclude_sensitive.is_some(),
+  })
 }
 
 #[tracing::instrument]
@@ -61,6 +154,7 @@ pub async fn request(
       .await?;
 
   let request_params = project.request_params.to_vec();
+  let query_text = QueryExpr::from_blocs(&request_params).to_string();
 
   Ok(
     HtmlTemplate(Request {
@@ -70,6 +164,11 @@ pub async fn request(
       collect_path: paths::ProjectCollect { project_id },
       import_path: paths::ProjectImport { project_id },
       request_params,
+      query_text,
+      query_error: None,
+      filters: FiltersForm::from(&project.filters.0),
+      explain_path: paths::ProjectRequestExplain { project_id },
+      alerts_path: paths::ProjectAlerts { project_id },
       popup_hashtags_path: paths::PopupHashtags { project_id },
       popup_keywords_path: paths::PopupKeywords { project_id },
       popup_accounts_path: paths::PopupAccounts { project_id },
@@ -83,8 +182,11 @@ pub async fn request(
       tweets_graph_path: paths::ProjectTweetsGraph { project_id },
       authors_path: paths::ProjectAuthors { project_id },
       result_hashtags_path: paths::ProjectResultHashtags { project_id },
+      keywords_path: paths::ProjectResultKeywords { project_id },
+      links_path: paths::ProjectResultLinks { project_id },
       communities_path: paths::Communities { project_id },
       download_path: paths::DownloadProject { project_id },
+      events_path: paths::ProjectEvents { project_id },
       logout_url,
       include_count,
       exclude_count,
@@ -115,6 +217,34 @@ pub async fn request_update(
 
   let mut request_params = project.request_params.to_vec();
 
+  // filtres : enregistrés s'ils sont valides, la saisie est sinon réaffichée avec l'erreur
+  let mut filters = FiltersForm::from(&project.filters.0);
+  if update_request.filters.is_some() {
+    match parse_filters(&update_request) {
+      Ok(study_filters) => {
+        cocktail_db_web::update_project_filters(
+          &db,
+          project_id.to_hyphenated(),
+          &user_id,
+          study_filters.clone(),
+        )
+        .await?;
+        filters = FiltersForm::from(&study_filters);
+      }
+      Err(error) => {
+        filters = FiltersForm {
+          languages: update_request.languages.clone().unwrap_or_default(),
+          west: update_request.west.clone().unwrap_or_default(),
+          south: update_request.south.clone().unwrap_or_default(),
+          east: update_request.east.clone().unwrap_or_default(),
+          north: update_request.north.clone().unwrap_or_default(),
+          exclude_sensitive: update_request.exclude_sensitive.is_some(),
+          error: Some(error),
+        };
+      }
+    }
+  }
+
   match update_request.block_delete_id {
     None => (),
     Some(block_delete_id) => {
@@ -191,7 +321,50 @@ pub async fn request_update(
     }
   }
 
+  // requête textuelle : remplace l'ensemble des blocs si elle est valide, ainsi que la
+  // période et les langues de l'étude si elle les précise
+  let mut query_error = None;
+  match update_request.query_text {
+    None => (),
+    Some(query_text) => match QueryExpr::parse(&query_text).and_then(|query| query.to_criteria()) {
+      Ok(criteria) => {
+        request_params = criteria.request_params;
+        if let Some((start_date, end_date)) = criteria.dates {
+          cocktail_db_web::update_project_daterange(
+            &db,
+            project_id.to_hyphenated(),
+            &user_id,
+            &start_date.to_string(),
+            &end_date.to_string(),
+            1,
+          )
+          .await?;
+        }
+        if !criteria.languages.is_empty() {
+          let study_filters = StudyFilters {
+            languages: criteria.languages,
+            ..project.filters.0.clone()
+          };
+          cocktail_db_web::update_project_filters(
+            &db,
+            project_id.to_hyphenated(),
+            &user_id,
+            study_filters.clone(),
+          )
+          .await?;
+          filters = FiltersForm::from(&study_filters);
+        }
+      }
+      Err(e) => query_error = Some((query_text, e.to_string())),
+    },
+  }
+
   let block_id = update_request.block_id.unwrap_or_default();
+  // mots clés cherchés sans racinisation
+  let keyword_prefix = match update_request.exact_match {
+    Some(_) => fts::EXACT_PREFIX.to_string(),
+    None => String::new(),
+  };
 
   match update_request.exact_group_keywords {
     None => (),
@@ -199,13 +372,15 @@ pub async fn request_update(
       if !included_exact_group_keywords.trim().is_empty() {
         if block_id != -1 {
           let index: usize = block_id as usize;
-          request_params[0][index]
-            .data
-            .push(included_exact_group_keywords.trim().to_string());
+          request_params[0][index].data.push(format!(
+            "{keyword_prefix}{}",
+            included_exact_group_keywords.trim()
+          ));
         } else {
-          request_params[1][0]
-            .data
-            .push(included_exact_group_keywords.trim().to_string());
+          request_params[1][0].data.push(format!(
+            "{keyword_prefix}{}",
+            included_exact_group_keywords.trim()
+          ));
         }
       }
     }
@@ -220,15 +395,49 @@ pub async fn request_update(
             let index: usize = block_id as usize;
             request_params[0][index]
               .data
-              .push(keyword.trim().to_string());
+              .push(format!("{keyword_prefix}{}", keyword.trim()));
           } else {
-            request_params[1][0].data.push(keyword.trim().to_string());
+            request_params[1][0]
+              .data
+              .push(format!("{keyword_prefix}{}", keyword.trim()));
           }
         }
       }
     }
   }
 
+  // préfixe, jokers, expression régulière, mot approché ou expression avec écart
+  let mut pattern_error = None;
+  match update_request.pattern_keywords {
+    Some(pattern_keywords) if !pattern_keywords.trim().is_empty() => {
+      let mode = update_request.pattern_mode.as_deref().unwrap_or("prefixe");
+      let parameter = match update_request.pattern_parameter.as_deref().map(str::trim) {
+        None | Some("") => Ok(None),
+        Some(parameter) => parameter
+          .parse()
+          .map(Some)
+          .map_err(|_| format!("nombre attendu : {parameter}")),
+      };
+      let pattern = parameter.and_then(|parameter| {
+        KeywordPattern::from_mode(mode, &pattern_keywords, parameter).map_err(|e| e.to_string())
+      });
+
+      match pattern {
+        Ok(pattern) => {
+          if block_id != -1 {
+            request_params[0][block_id as usize]
+              .data
+              .push(pattern.to_item());
+          } else {
+            request_params[1][0].data.push(pattern.to_item());
+          }
+        }
+        Err(error) => pattern_error = Some(error),
+      }
+    }
+    _ => (),
+  }
+
   match update_request.accounts {
     None => (),
     Some(accounts) => {
@@ -262,8 +471,21 @@ pub async fn request_update(
   )
   .await?;
 
+  let (query_text, query_error) = match query_error {
+    Some((query_text, error)) => (query_text, Some(error)),
+    None => (
+      QueryExpr::from_blocs(&request_params).to_string(),
+      pattern_error,
+    ),
+  };
+
   let response = templates::BlocsUpdate {
     request_params,
+    query_text,
+    query_error,
+    filters,
+    explain_path: paths::ProjectRequestExplain { project_id },
+    alerts_path: paths::ProjectAlerts { project_id },
     popup_hashtags_path: paths::PopupHashtags { project_id },
     popup_keywords_path: paths::PopupKeywords { project_id },
     popup_accounts_path: paths::PopupAccounts { project_id },
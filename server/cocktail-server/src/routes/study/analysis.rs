@@ -1,4 +1,4 @@
-use std::{fs, path::PathBuf, str::FromStr};
+use std::{collections::HashSet, fs, path::PathBuf, str::FromStr};
 use glob::glob;
 use tracing;
 
@@ -6,19 +6,19 @@ use axum::{
   extract::State,
   response::{IntoResponse, Redirect},
 };
-use cocktail_db_twitter::TopKDatabase;
 use cocktail_db_web::{ParsedProjectCriteria, TweetsChart};
-use fts::{copy_index_data, create_index_config};
+use fts::{copy_index_data, create_index_config_with_options};
 use futures::future;
 use tokio::task;
 
 use crate::{
   error::WebError,
+  events::{ProjectEvent, ProjectStep, StepStatus},
   models::{
     auth::AuthenticatedUser,
     templates::{HtmlTemplate, PopupAnalysisPreview},
   },
-  routes::paths,
+  routes::{charts::time_buckets, paths},
   AppState,
 };
 
@@ -58,7 +58,6 @@ pub async fn analyse(
     user_id,
   }: AuthenticatedUser,
   State(state): State<AppState>,
-  State(conn): State<TopKDatabase>,
 ) -> Result<impl IntoResponse, WebError> {
   let project = cocktail_db_web::project(&state.db, project_id.to_hyphenated(), &user_id).await?;
 
@@ -66,55 +65,91 @@ pub async fn analyse(
 
   let parsed_criteria = ParsedProjectCriteria::from(&project);
 
+  let search_index = fts::retrieve_index(state.directory_path.clone())?;
+  // l'index du projet reprend les options de l'index principal (engagement, mots vides)
+  let index_options = fts::index_stamp(&state.directory_path, &search_index)?.options;
   let tweets = fts::search_tweets_for_analysis(
-    &fts::Index::open_in_dir(state.directory_path.clone())?,
+    &search_index,
     &project.start_date,
     &project.end_date,
     &parsed_criteria.hashtag_list,
     &parsed_criteria.exclude_hashtag_list,
     &project.request_params,
+    &project.filters,
   )?;
 
   let directory_path = PathBuf::from_str(format!("project-data/{}", project_id.to_string()).as_str())?;
   let _ = fs::remove_dir_all(&directory_path);
-  let tweets_count: i64 = *&tweets.len() as i64;
-  let mut authors_list: Vec<String> = tweets.iter().map(|t| t.user_id.to_string()).collect();
-  authors_list.sort_unstable();
-  authors_list.dedup();
 
-  let authors_count = authors_list.len() as i64;
+  let events = state.events.clone();
+  events.publish(ProjectEvent::new(project_id, ProjectStep::IndexCopy, StepStatus::Started));
 
-  create_index_config(&directory_path)?;
+  // les tweets sont copiés au fil de leur lecture, seuls les identifiants des auteurs sont gardés
+  let copy_directory = directory_path.clone();
+  let copied = task::spawn_blocking(move || {
+    let mut authors = HashSet::new();
+    let tweets = tweets.inspect(|tweet| {
+      if let Ok(tweet) = tweet {
+        authors.insert(tweet.user_id.clone());
+      }
+    });
+    create_index_config_with_options(&copy_directory, &index_options)
+      .map_err(fts::SearchError::from)
+      .and_then(|_| copy_index_data(&copy_directory, tweets))
+      .map(|tweets_count| (tweets_count, authors.len()))
+  })
+  .await
+  .map_err(|e| WebError::WTFError(e.to_string()))?;
+
+  let (tweets_count, authors_count) = match copied {
+    Ok((tweets_count, authors_count)) => (tweets_count as i64, authors_count as i64),
+    Err(e) => {
+      events.publish(
+        ProjectEvent::new(project_id, ProjectStep::IndexCopy, StepStatus::Failed).detail(e.to_string()),
+      );
+      return Err(e.into());
+    }
+  };
 
-  copy_index_data(&directory_path, tweets)?;
+  events.publish(ProjectEvent::new(project_id, ProjectStep::IndexCopy, StepStatus::Completed));
 
-  // Maintenant que le dossier est créé, on peut exécuter la commande d'ingestion
+  // Maintenant que le dossier est créé, on peut ingérer le dernier fichier de tweets collectés
   let latest_tweets_file = find_latest_tweets_file()?;
-  
-  let command = format!(
-    "gunzip -c {} | ./target/debug/cocktail index ingest --directory-path {}",
+
+  tracing::info!(
+    "Ingestion de {} dans {}",
     latest_tweets_file,
-    directory_path.to_str().unwrap()
+    directory_path.display()
   );
-  tracing::info!("Exécution de la commande d'ingestion: {}", command);
-  
-  let output = tokio::process::Command::new("sh")
-    .arg("-c")
-    .arg(&command)
-    .output()
+  events.publish(ProjectEvent::new(project_id, ProjectStep::Ingestion, StepStatus::Started));
+
+  let ingest_directory = directory_path.clone();
+  let ingested = task::spawn_blocking(move || fts::ingest_file(ingest_directory, latest_tweets_file))
     .await
     .map_err(|e| WebError::WTFError(e.to_string()))?;
 
-  if !output.status.success() {
-    let error = format!(
-      "Ingestion failed: {}",
-      String::from_utf8_lossy(&output.stderr)
-    );
-    tracing::error!("{}", error);
-    return Err(WebError::WTFError(error));
-  }
+  let stats = match ingested {
+    Ok(stats) => stats,
+    Err(e) => {
+      let error = format!("Ingestion failed: {e}");
+      tracing::error!("{}", error);
+      events.publish(
+        ProjectEvent::new(project_id, ProjectStep::Ingestion, StepStatus::Failed).detail(error.clone()),
+      );
+      return Err(WebError::WTFError(error));
+    }
+  };
+
+  events.publish(
+    ProjectEvent::new(project_id, ProjectStep::Ingestion, StepStatus::Completed).detail(format!(
+      "{} tweets ajoutés, {} mis à jour, {} lignes rejetées",
+      stats.documents_added,
+      stats.documents_updated,
+      stats.rejected.len()
+    )),
+  );
 
-  tracing::info!("Commande d'ingestion terminée avec succès pour le projet: {}", project_id);
+  tracing::info!("Ingestion terminée avec succès pour le projet: {}", project_id);
 
   let graph_generator = cocktail_graph_utils::GraphGenerator::new(
     state.database_url,
@@ -139,14 +174,25 @@ pub async fn analyse(
   )
   .await?;
 
+  let graph_events = events.clone();
   let _ = task::spawn(async move {
-    let _ = graph_generator.process_search().await;
+    // les transitions réussies sont lues dans la table `status` par le flux SSE
+    if let Err(e) = graph_generator.process_search().await {
+      graph_events.publish(
+        ProjectEvent::new(project_id, ProjectStep::Graph, StepStatus::Failed).detail(e.to_string()),
+      );
+    }
   });
 
   let _ = task::spawn(async move {
+    events.publish(ProjectEvent::new(project_id, ProjectStep::Charts, StepStatus::Started));
     let tabs = vec!["total", "retweets", "citations", "repondus"];
     let directory_path = format!("project-data/{}", project_id.to_string());
-    let index = fts::Index::open_in_dir(directory_path).unwrap();
+    let index = fts::retrieve_index(directory_path).unwrap();
+    // graphiques à la granularité affichée par défaut
+    let buckets = time_buckets(None, &project.start_date, &project.end_date, state.timezone);
+    let granularity = buckets.granularity.to_string();
+    let timezone = buckets.timezone.to_string();
 
     future::join_all(tabs.into_iter().map(|tab| async {
       let tweets_counts = fts::search_tweets_count_per_day(
@@ -156,6 +202,7 @@ pub async fn analyse(
         &project.start_date,
         &project.end_date,
         &tab.to_string(),
+        &buckets,
       );
       if tweets_counts.is_ok() {
         let _ = cocktail_db_web::save_chart(
@@ -163,6 +210,8 @@ pub async fn analyse(
           project_id.to_string(),
           "tweets".to_string(),
           tab.to_string(),
+          granularity.clone(),
+          timezone.clone(),
           TweetsChart {
             data: tweets_counts.unwrap(),
           },
@@ -176,29 +225,22 @@ pub async fn analyse(
         &project.end_date,
         &parsed_criteria.hashtag_list,
         &tab.to_string(),
+        &buckets,
       );
       let frequences_topk = fts::search_top_hashtags_count_per_day(
         &index,
         &project.start_date,
         &project.end_date,
         &tab.to_string(),
+        &buckets,
       );
-      let cooccurences = cocktail_db_twitter::search_topk_hashtags_cooccurence(conn.clone())
-        .await
-        .unwrap();
-
       let frequences_cooccurence = fts::search_top_hashtags_cooccurence_count_per_day(
         &index,
         &project.start_date,
         &project.end_date,
         &tab.to_string(),
-        &cooccurences
-          .iter()
-          .map(|c| fts::HashtagCooccurence {
-            hashtag1: c.hashtag1.clone(),
-            hashtag2: c.hashtag2.clone(),
-          })
-          .collect(),
+        &buckets,
+        &fts::CooccurenceOptions::default(),
       );
 
       if frequences.is_ok() && frequences_topk.is_ok() && frequences_cooccurence.is_ok() {
@@ -207,6 +249,8 @@ pub async fn analyse(
           project_id.to_string(),
           "hashtags".to_string(),
           tab.to_string(),
+          granularity.clone(),
+          timezone.clone(),
           (
             frequences.unwrap(),
             frequences_topk.unwrap(),
@@ -217,6 +261,8 @@ pub async fn analyse(
       }
     }))
     .await;
+
+    events.publish(ProjectEvent::new(project_id, ProjectStep::Charts, StepStatus::Completed));
   });
 
   Ok(Redirect::to(
@@ -238,12 +284,13 @@ pub async fn preview_analysis(
   let parsed_criteria = ParsedProjectCriteria::from(&project);
 
   let tweets_preview = fts::search_tweets_for_preview(
-    &fts::Index::open_in_dir(state.directory_path.clone())?,
+    &fts::retrieve_index(state.directory_path.clone())?,
     &project.start_date,
     &project.end_date,
     &parsed_criteria.hashtag_list,
     &parsed_criteria.exclude_hashtag_list,
     &project.request_params,
+    &project.filters,
   )?;
 
   Ok(HtmlTemplate(PopupAnalysisPreview {
@@ -50,7 +50,7 @@ pub async fn authors(
     .unwrap_or(1);
 
   let author_counts = fts::aggregate_authors(
-    &fts::Index::open_in_dir(directory_path)?,
+    &fts::retrieve_index(directory_path)?,
     &"total".to_string(),
     page,
   )?;
@@ -74,11 +74,14 @@ pub async fn authors(
       tweets_graph_path: paths::ProjectTweetsGraph { project_id },
       authors_path: paths::ProjectAuthors { project_id },
       result_hashtags_path: paths::ProjectResultHashtags { project_id },
+      keywords_path: paths::ProjectResultKeywords { project_id },
+      links_path: paths::ProjectResultLinks { project_id },
       communities_path: paths::Communities { project_id },
       delete_popup_path: paths::PopupDeleteProject { project_id },
       rename_popup_path: paths::PopupRenameProject { project_id },
       duplicate_popup_path: paths::PopupDuplicateProject { project_id },
       download_path: paths::DownloadProject { project_id },
+      events_path: paths::ProjectEvents { project_id },
       logout_url,
       include_count,
       exclude_count,
@@ -123,7 +126,7 @@ pub async fn authors_tab(
     .unwrap_or(1);
 
   let author_counts =
-    fts::aggregate_authors(&fts::Index::open_in_dir(directory_path)?, &tab, page)?;
+    fts::aggregate_authors(&fts::retrieve_index(directory_path)?, &tab, page)?;
 
   let logout_url = get_logout_url(kratos_configuration, headers).await;
 
@@ -144,11 +147,14 @@ pub async fn authors_tab(
       tweets_graph_path: paths::ProjectTweetsGraph { project_id },
       authors_path: paths::ProjectAuthors { project_id },
       result_hashtags_path: paths::ProjectResultHashtags { project_id },
+      keywords_path: paths::ProjectResultKeywords { project_id },
+      links_path: paths::ProjectResultLinks { project_id },
       communities_path: paths::Communities { project_id },
       delete_popup_path: paths::PopupDeleteProject { project_id },
       rename_popup_path: paths::PopupRenameProject { project_id },
       duplicate_popup_path: paths::PopupDuplicateProject { project_id },
       download_path: paths::DownloadProject { project_id },
+      events_path: paths::ProjectEvents { project_id },
       logout_url,
       include_count,
       exclude_count,
@@ -83,11 +83,14 @@ pub async fn communities(
       tweets_graph_path: paths::ProjectTweetsGraph { project_id },
       authors_path: paths::ProjectAuthors { project_id },
       result_hashtags_path: paths::ProjectResultHashtags { project_id },
+      keywords_path: paths::ProjectResultKeywords { project_id },
+      links_path: paths::ProjectResultLinks { project_id },
       communities_path: paths::Communities { project_id },
       delete_popup_path: paths::PopupDeleteProject { project_id },
       rename_popup_path: paths::PopupRenameProject { project_id },
       duplicate_popup_path: paths::PopupDuplicateProject { project_id },
       download_path: paths::DownloadProject { project_id },
+      events_path: paths::ProjectEvents { project_id },
       logout_url,
       include_count,
       exclude_count,
@@ -185,11 +188,14 @@ pub async fn communities_tab(
       tweets_graph_path: paths::ProjectTweetsGraph { project_id },
       authors_path: paths::ProjectAuthors { project_id },
       result_hashtags_path: paths::ProjectResultHashtags { project_id },
+      keywords_path: paths::ProjectResultKeywords { project_id },
+      links_path: paths::ProjectResultLinks { project_id },
       communities_path: paths::Communities { project_id },
       delete_popup_path: paths::PopupDeleteProject { project_id },
       rename_popup_path: paths::PopupRenameProject { project_id },
       duplicate_popup_path: paths::PopupDuplicateProject { project_id },
       download_path: paths::DownloadProject { project_id },
+      events_path: paths::ProjectEvents { project_id },
       logout_url,
       include_count,
       exclude_count,
@@ -4,9 +4,8 @@ use axum::{
   Form,
 };
 use chrono::{NaiveDate, Utc};
-use cocktail_db_twitter::TopKDatabase;
 use cocktail_db_web::{HiddenElementTweetsList, ParsedProjectCriteria, WebDatabase};
-use fts::{Frequence, FrequenceCooccurence, OrderBy};
+use fts::{Frequence, FrequenceCooccurence, OrderBy, TimeBuckets};
 use hyper::HeaderMap;
 use ory_kratos_client::apis::configuration::Configuration;
 use serde::Deserialize;
@@ -26,9 +25,14 @@ use crate::{
 pub struct QueryParams {
   pub page: Option<String>,
   pub auteur: Option<String>,
+  /// premier jour des tweets affichés
   pub date: Option<String>,
+  /// dernier jour des tweets affichés, celui de `date` si absent
+  pub fin: Option<String>,
   pub hashtag: Option<String>,
   pub ordre: Option<String>,
+  /// curseur de la page précédente, pour lire la suivante
+  pub apres: Option<String>,
 }
 
 pub struct FilterAuthor {
@@ -47,6 +51,7 @@ pub struct ToggleElement {
   pub query_page: String,
   pub query_auteur: String,
   pub query_date: String,
+  pub query_fin: String,
   pub query_hashtag: String,
   pub query_ordre: String,
 }
@@ -65,7 +70,6 @@ pub async fn get_results(
   headers: HeaderMap,
   State(db): State<WebDatabase>,
   State(kratos_configuration): State<Configuration>,
-  State(conn): State<TopKDatabase>,
   query_params: Query<QueryParams>,
 ) -> Result<impl IntoResponse, WebError> {
   let directory_path = format!("project-data/{}", project_id.to_string());
@@ -77,13 +81,16 @@ pub async fn get_results(
     .parse::<u32>()
     .unwrap_or(1);
   let author = query_params.auteur.clone().unwrap_or("".to_string());
-  let date = match query_params.date.clone() {
-    Some(date_string) => Some(
-      NaiveDate::parse_from_str(&date_string, "%Y-%m-%d").unwrap_or(Utc::now().date().naive_utc()),
-    ),
-    None => None,
+  let parse_date = |date_string: &String| {
+    NaiveDate::parse_from_str(date_string, "%Y-%m-%d").unwrap_or(Utc::now().date().naive_utc())
   };
+  let date = query_params.date.as_ref().map(parse_date);
+  let end_date = query_params.fin.as_ref().map(parse_date);
   let hashtag = query_params.hashtag.clone();
+  let after = query_params
+    .apres
+    .as_ref()
+    .and_then(|cursor| cursor.parse::<fts::SearchCursor>().ok());
   let order = query_params
     .ordre
     .clone()
@@ -101,50 +108,50 @@ pub async fn get_results(
   let hidden_element: HiddenElementTweetsList =
     cocktail_db_web::hidden_hashtag_tweet_list(&db, project_id.to_hyphenated(), &user_id).await?;
 
-  let index = fts::Index::open_in_dir(directory_path)?;
+  let index = fts::retrieve_index(directory_path)?;
 
+  let results = fts::ResultsQuery {
+    authors: query_params.auteur.clone().into_iter().collect(),
+    hidden_hashtags: hidden_element.hidden_hashtag_list.clone(),
+    hidden_authors: hidden_element.hidden_author_list.clone(),
+    exclude_retweets,
+    dates: date.map(|date| (date, end_date.unwrap_or(date))),
+    hashtag: hashtag.clone(),
+    order_by: OrderBy::from(&*tab),
+    order: fts::SortOrder::from(order.as_str()),
+  };
   let tweets = fts::search_tweets_for_result(
     &index,
-    &match query_params.auteur.clone() {
-      Some(author) => vec![author],
-      None => vec![],
-    },
-    &hidden_element.hidden_hashtag_list,
-    &hidden_element.hidden_author_list,
-    exclude_retweets,
-    OrderBy::from(&*tab),
-    &order,
-    &date,
-    &hashtag,
+    &results,
     page,
+    &after,
+    &parsed_criteria.hashtag_list,
+    &project.request_params,
   )?;
-  let cooccurences = cocktail_db_twitter::search_topk_hashtags_cooccurence(conn.clone()).await?;
-
+  // seuls les hashtags sont affichés à côté des tweets, pas leurs histogrammes
+  let buckets = TimeBuckets::default();
   let frequences = fts::search_study_hashtags_count_per_day(
     &index,
     &project.start_date,
     &project.end_date,
     &parsed_criteria.hashtag_list,
     &"total".to_string(),
+    &buckets,
   )?;
   let frequences_topk = fts::search_top_hashtags_count_per_day(
     &index,
     &project.start_date,
     &project.end_date,
     &"total".to_string(),
+    &buckets,
   )?;
   let frequences_cooccurence = fts::search_top_hashtags_cooccurence_count_per_day(
     &index,
     &project.start_date,
     &project.end_date,
     &tab,
-    &cooccurences
-      .into_iter()
-      .map(|c| fts::HashtagCooccurence {
-        hashtag1: c.hashtag1,
-        hashtag2: c.hashtag2,
-      })
-      .collect(),
+    &buckets,
+    &fts::CooccurenceOptions::default(),
   )?;
 
   let mut authors: Vec<fts::AuthorCount> = fts::aggregate_authors(&index, &"total".to_string(), 1)?;
@@ -160,6 +167,8 @@ pub async fn get_results(
     3,
   )?);
 
+  let languages = fts::language_distribution(&index)?;
+
   let logout_url = get_logout_url(kratos_configuration, headers).await;
 
   let (include_count, exclude_count) =
@@ -178,10 +187,13 @@ pub async fn get_results(
     tweets_graph_path: paths::ProjectTweetsGraph { project_id },
     authors_path: paths::ProjectAuthors { project_id },
     result_hashtags_path: paths::ProjectResultHashtags { project_id },
+    keywords_path: paths::ProjectResultKeywords { project_id },
+    links_path: paths::ProjectResultLinks { project_id },
     communities_path: paths::Communities { project_id },
     delete_popup_path: paths::PopupDeleteProject { project_id },
     rename_popup_path: paths::PopupRenameProject { project_id },
     download_path: paths::DownloadProject { project_id },
+    events_path: paths::ProjectEvents { project_id },
     duplicate_popup_path: paths::PopupDuplicateProject { project_id },
     logout_url,
     include_count,
@@ -189,7 +201,8 @@ pub async fn get_results(
     niveau,
     last_login_datetime,
     title: project.title,
-    tweets,
+    tweets: tweets.tweets,
+    next_cursor: tweets.next,
     frequences: frequences
       .into_iter()
       .map(|frequence| {
@@ -243,9 +256,11 @@ pub async fn get_results(
           .any(|a| a == &count.author.user_screen_name),
       })
       .collect(),
+    languages,
     tab,
     user_screen_name: author,
     date,
+    end_date,
     hashtag,
     page,
     order,
@@ -269,7 +284,6 @@ pub async fn results(
   headers: HeaderMap,
   State(db): State<WebDatabase>,
   State(kratos_configuration): State<Configuration>,
-  State(conn): State<TopKDatabase>,
   query_params: Query<QueryParams>,
 ) -> Result<impl IntoResponse, WebError> {
   get_results(
@@ -286,7 +300,6 @@ pub async fn results(
     headers,
     State(db),
     State(kratos_configuration),
-    State(conn),
     query_params,
   )
   .await
@@ -306,7 +319,6 @@ pub async fn results_tab(
   headers: HeaderMap,
   State(db): State<WebDatabase>,
   State(kratos_configuration): State<Configuration>,
-  State(conn): State<TopKDatabase>,
   query_params: Query<QueryParams>,
 ) -> Result<impl IntoResponse, WebError> {
   if niveau != 2 && tab == "engageants" {
@@ -329,7 +341,6 @@ pub async fn results_tab(
       headers,
       State(db),
       State(kratos_configuration),
-      State(conn),
       query_params,
     )
     .await
@@ -380,6 +391,10 @@ pub async fn toggle_hashtag(
     query.push_str("&date=");
     query.push_str(&toggle.query_date);
   }
+  if !toggle.query_fin.is_empty() {
+    query.push_str("&fin=");
+    query.push_str(&toggle.query_fin);
+  }
   if &toggle.query_hashtag != "" {
     query.push_str("&hashtag=");
     query.push_str(&toggle.query_hashtag);
@@ -51,7 +51,7 @@ document.addEventListener("alpine:init", () => {
             x: {
               type: "time",
               time: {
-                unit: "day",
+                unit: time_unit(this.$el),
                 displayFormats: "d/mm/Y",
               },
               min: min,
@@ -59,8 +59,9 @@ document.addEventListener("alpine:init", () => {
             },
           },
           onClick: (evt, el, chart) => {
-            let date =
-              chart.data.datasets[el[0]?.datasetIndex]?.data[el[0].index].x;
+            let date = chart.data.datasets[el[0]?.datasetIndex]?.data[
+              el[0].index
+            ].x.substring(0, 10);
             let hashtag = chart.data.datasets[el[0]?.datasetIndex]?.label;
 
             if (date != undefined && hashtag != undefined) {
@@ -108,10 +109,70 @@ document.addEventListener("alpine:init", () => {
     },
   }));
 
+  Alpine.data("keywordChartPremium", (frequences) => ({
+    init() {
+      let chart = new Chart(this.$el, {
+        parsing: false,
+        type: "line",
+        data: {
+          labels: [],
+          datasets: frequences.map((f) => {
+            let { color } = uniqolor(f.label);
+            return {
+              ...f,
+              backgroundColor: color,
+              borderColor: color,
+            };
+          }),
+        },
+        options: {
+          animation: false,
+          maintainAspectRatio: false,
+          scales: {
+            y: {
+              title: {
+                display: true,
+                text: this.$el.getAttribute("data-label"),
+              },
+              beginAtZero: true,
+              ticks: {
+                precision: 0,
+              },
+              grace: "5%",
+            },
+            x: {
+              type: "time",
+              time: {
+                unit: time_unit(this.$el),
+                displayFormats: "d/mm/Y",
+              },
+            },
+          },
+          onClick: (evt, el, chart) => {
+            let date = chart.data.datasets[el[0]?.datasetIndex]?.data[
+              el[0].index
+            ].x.substring(0, 10);
+
+            if (date != undefined) {
+              window.location.replace(
+                this.$el.getAttribute("data-result-path") + "?date=" + date
+              );
+            }
+          },
+        },
+      });
+
+      chart.canvas.parentNode.style.height = "400px";
+    },
+  }));
+
   Alpine.data("tweetsChartPremium", (initialData) => ({
     init() {
       let { color } = uniqolor("Tweets");
       this.data = initialData;
+      // intervalles des pics détectés par le serveur
+      let bursts = JSON.parse(this.$el.getAttribute("data-bursts") || "[]");
+      let inBurst = (point) => bursts.includes(point.x);
       tweetsChart = new Chart(this.$el, {
         parsing: false,
         type: "line",
@@ -122,6 +183,10 @@ document.addEventListener("alpine:init", () => {
               ...this.data,
               borderColor: color,
               borderColor: color,
+              pointBackgroundColor: this.data.data.map((point) =>
+                inBurst(point) ? "#d9534f" : color
+              ),
+              pointRadius: this.data.data.map((point) => (inBurst(point) ? 5 : 3)),
             },
           ],
         },
@@ -148,14 +213,15 @@ document.addEventListener("alpine:init", () => {
             x: {
               type: "time",
               time: {
-                unit: "day",
+                unit: time_unit(this.$el),
                 displayFormats: "d/mm/Y",
               },
             },
           },
           onClick: (evt, el, chart) => {
-            let date =
-              chart.data.datasets[el[0]?.datasetIndex]?.data[el[0].index].x;
+            let date = chart.data.datasets[el[0]?.datasetIndex]?.data[
+              el[0].index
+            ].x.substring(0, 10);
 
             if (date != undefined) {
               window.location.replace(
@@ -175,187 +241,35 @@ document.addEventListener("alpine:init", () => {
   }));
 });
 
-function update_periodicity_tweets() {
-  if (tweetsChart === null) {
-    return;
-  }
-  let periodicity = document.getElementById("displayBy").value;
-  let data = JSON.parse(
-    document.getElementById("tweets-chart-premium").getAttribute("data-json")
-  );
-  let { color } = uniqolor("Tweets");
-
-  if (periodicity === "jour") {
-    tweetsChart.data.datasets = [
-      {
-        ...data,
-        borderColor: color,
-        borderColor: color,
-      },
-    ];
-    tweetsChart.options.scales.x.time.unit = "day";
-    tweetsChart.update();
-
-    return;
-  }
-
-  let finalData = { data: [] };
-  if (periodicity === "semaine") {
-    data.data.forEach((value) => {
-      if (
-        (index = finalData.data.findIndex(
-          (i) =>
-            luxon.DateTime.fromISO(i.x).toFormat("yyyy-W") ===
-            luxon.DateTime.fromISO(value.x).toFormat("yyyy-W")
-        )) != -1
-      ) {
-        finalData.data[index].y = finalData.data[index].y + value.y;
-      } else {
-        finalData.data.push(value);
-      }
-    });
-    tweetsChart.options.scales.x.time.unit = "week";
-  } else if (periodicity === "mois") {
-    data.data.forEach((value) => {
-      if (
-        (index = finalData.data.findIndex(
-          (i) =>
-            luxon.DateTime.fromISO(i.x).toFormat("yyyy-M") ===
-            luxon.DateTime.fromISO(value.x).toFormat("yyyy-M")
-        )) != -1
-      ) {
-        finalData.data[index].y = finalData.data[index].y + value.y;
-      } else {
-        finalData.data.push(value);
-      }
-    });
-    tweetsChart.options.scales.x.time.unit = "month";
-  } else if (periodicity === "annee") {
-    data.data.forEach((value) => {
-      if (
-        (index = finalData.data.findIndex(
-          (i) =>
-            luxon.DateTime.fromISO(i.x).toFormat("yyyy") ===
-            luxon.DateTime.fromISO(value.x).toFormat("yyyy")
-        )) != -1
-      ) {
-        finalData.data[index].y = finalData.data[index].y + value.y;
-      } else {
-        finalData.data.push(value);
-      }
-    });
-    tweetsChart.options.scales.x.time.unit = "year";
-  }
-  tweetsChart.data.datasets = [
-    {
-      ...finalData,
-      borderColor: color,
-      borderColor: color,
-    },
-  ];
+// les intervalles sont calculés par le serveur, dans le fuseau horaire de l'instance
+function update_periodicity(select) {
+  let url = new URL(window.location.href);
+  url.searchParams.set("granularite", select.value);
+  window.location.assign(url.toString());
+}
 
-  tweetsChart.update();
+function update_periodicity_tweets() {
+  update_periodicity(document.getElementById("displayBy"));
 }
 
 function update_periodicity_hashtags() {
-  if (hashtagsChart === null) {
-    return;
-  }
-  let periodicity = document.getElementById("displayBy").value;
-  let data = JSON.parse(
-    document.getElementById("hashtags-chart-premium").getAttribute("data-json")
-  );
-
-  if (periodicity === "jour") {
-    hashtagsChart.data.datasets = data.map((f) => {
-      let { color } = uniqolor(f.label);
-      return {
-        ...f,
-        backgroundColor: color,
-        borderColor: color,
-      };
-    });
-    hashtagsChart.options.scales.x.time.unit = "day";
-    hashtagsChart.update();
-
-    hashtagsChartHidden.forEach((i) => {
-      Alpine.raw(hashtagsChart)["hide"](i);
-    });
-
-    return;
-  }
-
-  let finalData = [];
-  data.forEach((hashtags) => {
-    finalData[finalData.length] = {
-      label: hashtags.label,
-      hidden: false,
-      data: [],
-    };
-
-    let hashtagIndex = finalData.length - 1;
-    if (periodicity === "semaine") {
-      hashtags.data.forEach((value) => {
-        if (
-          (index = finalData[hashtagIndex].data.findIndex(
-            (i) =>
-              luxon.DateTime.fromISO(i.x).toFormat("yyyy-W") ===
-              luxon.DateTime.fromISO(value.x).toFormat("yyyy-W")
-          )) != -1
-        ) {
-          finalData[hashtagIndex].data[index].y =
-            finalData[hashtagIndex].data[index].y + value.y;
-        } else {
-          finalData[hashtagIndex].data.push(value);
-        }
-      });
-      hashtagsChart.options.scales.x.time.unit = "week";
-    } else if (periodicity === "mois") {
-      hashtags.data.forEach((value) => {
-        if (
-          (index = finalData[hashtagIndex].data.findIndex(
-            (i) =>
-              luxon.DateTime.fromISO(i.x).toFormat("yyyy-M") ===
-              luxon.DateTime.fromISO(value.x).toFormat("yyyy-M")
-          )) != -1
-        ) {
-          finalData[hashtagIndex].data[index].y =
-            finalData[hashtagIndex].data[index].y + value.y;
-        } else {
-          finalData[hashtagIndex].data.push(value);
-        }
-      });
-      hashtagsChart.options.scales.x.time.unit = "month";
-    } else if (periodicity === "annee") {
-      hashtags.data.forEach((value) => {
-        if (
-          (index = finalData[hashtagIndex].data.findIndex(
-            (i) =>
-              luxon.DateTime.fromISO(i.x).toFormat("yyyy") ===
-              luxon.DateTime.fromISO(value.x).toFormat("yyyy")
-          )) != -1
-        ) {
-          finalData[hashtagIndex].data[index].y =
-            finalData[hashtagIndex].data[index].y + value.y;
-        } else {
-          finalData[hashtagIndex].data.push(value);
-        }
-      });
-      hashtagsChart.options.scales.x.time.unit = "year";
-    }
-  });
-  hashtagsChart.data.datasets = finalData.map((f) => {
-    let { color } = uniqolor(f.label);
-    return {
-      ...f,
-      backgroundColor: color,
-      borderColor: color,
-    };
-  });
+  update_periodicity(document.getElementById("displayBy"));
+}
 
-  hashtagsChart.update();
+function update_burst_method() {
+  let url = new URL(window.location.href);
+  url.searchParams.set("pics", document.getElementById("burstMethod").value);
+  window.location.assign(url.toString());
+}
 
-  hashtagsChartHidden.forEach((i) => {
-    Alpine.raw(hashtagsChart)["hide"](i);
-  });
+function time_unit(canvas) {
+  return (
+    {
+      heure: "hour",
+      jour: "day",
+      semaine: "week",
+      mois: "month",
+      annee: "year",
+    }[canvas.getAttribute("data-granularity")] || "day"
+  );
 }
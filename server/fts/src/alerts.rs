@@ -0,0 +1,254 @@
+//! Conditions des alertes d'une étude, évaluées sur l'index public : nouveaux tweets
+//! correspondant aux critères de l'étude, ou pic d'un hashtag parmi ces tweets.
+
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use tantivy::{
+  collector::{Count, TopDocs},
+  query::{BooleanQuery, Occur, Query, RangeQuery, TermQuery},
+  schema::IndexRecordOption,
+  Index, Term,
+};
+
+use crate::{get_query, Bloc, SearchError, StudyFilters, Tweet, TweetSchema};
+
+/// Nombre de tweets repris dans le résumé d'une alerte déclenchée
+pub const DIGEST_SIZE: usize = 10;
+/// Nombre de jours de la moyenne à laquelle est comparé le volume d'un hashtag
+pub const SURGE_REFERENCE_DAYS: i64 = 7;
+
+const DAY_MS: i64 = 24 * 60 * 60 * 1000;
+
+/// Seuil au-delà duquel une alerte est déclenchée
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AlertCondition {
+  /// plus de `threshold` nouveaux tweets depuis l'évaluation précédente
+  NewTweets { threshold: u64 },
+  /// tweets du hashtag sur les dernières 24 heures plus nombreux que `factor` fois leur
+  /// moyenne quotidienne des 7 jours précédents
+  HashtagSurge { hashtag: String, factor: f64 },
+}
+
+/// Critères de l'étude surveillée, sans sa période : une alerte porte sur les tweets
+/// publiés depuis sa dernière évaluation
+#[derive(Debug, Clone, Copy)]
+pub struct AlertCriteria<'a> {
+  pub hashtag_list: &'a Vec<String>,
+  pub exclude_hashtag_list: &'a Vec<String>,
+  pub request_params: &'a Vec<Vec<Bloc>>,
+  pub filters: &'a StudyFilters,
+}
+
+/// Résultat de l'évaluation d'une alerte
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertEvaluation {
+  pub triggered: bool,
+  pub summary: String,
+  /// nombre de nouveaux tweets, ou de tweets du hashtag sur les dernières 24 heures
+  pub observed: u64,
+  /// valeur que `observed` doit dépasser
+  pub threshold: f64,
+  /// tweets les plus récents parmi ceux comptés, au plus [`DIGEST_SIZE`]
+  pub tweets: Vec<Tweet>,
+}
+
+impl AlertCondition {
+  /// Description du seuil, pour l'historique et les notifications
+  pub fn describe(&self) -> String {
+    match self {
+      AlertCondition::NewTweets { threshold } => format!("plus de {threshold} nouveaux tweets"),
+      AlertCondition::HashtagSurge { hashtag, factor } => {
+        format!("#{hashtag} au-dessus de {factor} fois sa moyenne sur {SURGE_REFERENCE_DAYS} jours")
+      }
+    }
+  }
+}
+
+/// Évalue `condition` sur les tweets de `index` correspondant à `criteria`, publiés
+/// après `since` pour les nouveaux tweets, jusqu'à `now`
+pub fn evaluate_alert(
+  index: &Index,
+  criteria: AlertCriteria,
+  condition: &AlertCondition,
+  since: NaiveDateTime,
+  now: NaiveDateTime,
+) -> Result<AlertEvaluation, SearchError> {
+  let now_ms = now.timestamp_millis();
+
+  match condition {
+    AlertCondition::NewTweets { threshold } => {
+      let (count, tweets) = window(index, criteria, None, since.timestamp_millis(), now_ms)?;
+      Ok(AlertEvaluation {
+        triggered: count > *threshold,
+        summary: format!("{count} nouveau(x) tweet(s), seuil : {threshold}"),
+        observed: count,
+        threshold: *threshold as f64,
+        tweets,
+      })
+    }
+    AlertCondition::HashtagSurge { hashtag, factor } => {
+      let hashtag = hashtag.trim_start_matches('#');
+      let day_start = now_ms - DAY_MS;
+      let (count, tweets) = window(index, criteria, Some(hashtag), day_start, now_ms)?;
+      let (reference, _) = window(
+        index,
+        criteria,
+        Some(hashtag),
+        day_start - SURGE_REFERENCE_DAYS * DAY_MS,
+        day_start,
+      )?;
+      let mean = reference as f64 / SURGE_REFERENCE_DAYS as f64;
+      let threshold = factor * mean;
+
+      Ok(AlertEvaluation {
+        triggered: count as f64 > threshold,
+        summary: format!(
+          "#{hashtag} : {count} tweet(s) sur 24 heures, moyenne de {mean:.1} par jour sur {SURGE_REFERENCE_DAYS} jours"
+        ),
+        observed: count,
+        threshold,
+        tweets,
+      })
+    }
+  }
+}
+
+/// Nombre et tweets les plus récents publiés dans `]start_ms, end_ms]` correspondant à
+/// `criteria` et, s'il est donné, au hashtag
+fn window(
+  index: &Index,
+  criteria: AlertCriteria,
+  hashtag: Option<&str>,
+  start_ms: i64,
+  end_ms: i64,
+) -> Result<(u64, Vec<Tweet>), SearchError> {
+  let searcher = index.reader()?.searcher();
+  let tweet_schema = TweetSchema::from_index(index)?;
+
+  // la période de get_query est en jours entiers, précisée par l'intervalle en millisecondes
+  let timestamp = |ms: i64| NaiveDateTime::from_timestamp_opt(ms.div_euclid(1000), 0);
+  let (start, end) = match (timestamp(start_ms.max(0)), timestamp(end_ms.max(0))) {
+    (Some(start), Some(end)) => (start, end),
+    _ => return Ok((0, vec![])),
+  };
+  let study_query = get_query(
+    index,
+    &start.date(),
+    &end.date(),
+    criteria.hashtag_list,
+    criteria.exclude_hashtag_list,
+    criteria.request_params,
+    criteria.filters,
+  )?;
+
+  let mut clauses: Vec<(Occur, Box<dyn Query>)> = vec![
+    (Occur::Must, Box::new(study_query)),
+    (
+      Occur::Must,
+      Box::new(RangeQuery::new_u64(
+        tweet_schema.published_time_ms,
+        (start_ms.max(0) as u64 + 1)..(end_ms.max(0) as u64 + 1),
+      )),
+    ),
+  ];
+  if let Some(hashtag) = hashtag {
+    clauses.push((
+      Occur::Must,
+      Box::new(TermQuery::new(
+        Term::from_field_text(tweet_schema.hashtags, hashtag),
+        IndexRecordOption::Basic,
+      )),
+    ));
+  }
+
+  let (top_docs, count) = searcher.search(
+    &BooleanQuery::new(clauses),
+    &(
+      TopDocs::with_limit(DIGEST_SIZE).order_by_fast_field::<u64>(tweet_schema.published_time_ms),
+      Count,
+    ),
+  )?;
+  let tweets = top_docs
+    .into_iter()
+    .filter_map(|(_, doc_address)| searcher.doc(doc_address).ok())
+    .map(|doc| tweet_schema.to_tweet(&doc))
+    .collect::<Result<Vec<_>, _>>()?;
+
+  Ok((count as u64, tweets))
+}
+
+#[cfg(test)]
+mod tests {
+  use chrono::{Duration, TimeZone, Utc};
+
+  use super::*;
+  use crate::test_utils::{index_with, tweet};
+
+  #[test]
+  fn test_evaluate_alert() {
+    let now = Utc.ymd(2022, 6, 20).and_hms(12, 0, 0).naive_utc();
+    // (heures avant maintenant, texte, hashtags)
+    let tweets: Vec<_> = [
+      (1, "le loup est de retour", vec!["loup"]),
+      (2, "encore le loup", vec!["loup"]),
+      (3, "le loup et la brebis", vec!["loup", "brebis"]),
+      (5, "la brebis", vec!["brebis"]),
+      (30, "le loup hier", vec!["loup"]),
+      (24 * 4, "le loup il y a quatre jours", vec!["loup"]),
+      (24 * 10, "le loup il y a longtemps", vec!["loup"]),
+    ]
+    .into_iter()
+    .enumerate()
+    .map(|(id, (hours, text, hashtags))| {
+      let published_time = Utc.from_utc_datetime(&(now - Duration::hours(hours)));
+      Tweet {
+        text: text.to_string(),
+        ..tweet(id).at(published_time).with_hashtags(&hashtags)
+      }
+    })
+    .collect();
+    let index = index_with(&tweets);
+
+    let request_params = vec![
+      vec![Bloc {
+        data: vec!["loup".to_string()],
+        link: "".to_string(),
+      }],
+      vec![Bloc {
+        data: vec![],
+        link: "ET".to_string(),
+      }],
+    ];
+    let filters = StudyFilters::default();
+    let criteria = AlertCriteria {
+      hashtag_list: &vec![],
+      exclude_hashtag_list: &vec![],
+      request_params: &request_params,
+      filters: &filters,
+    };
+
+    let new_tweets = AlertCondition::NewTweets { threshold: 2 };
+    let evaluation =
+      evaluate_alert(&index, criteria, &new_tweets, now - Duration::hours(4), now).unwrap();
+    assert!(evaluation.triggered);
+    assert_eq!(evaluation.observed, 3);
+    // les plus récents d'abord
+    assert_eq!(evaluation.tweets[0].text, "le loup est de retour");
+    let evaluation =
+      evaluate_alert(&index, criteria, &new_tweets, now - Duration::hours(2), now).unwrap();
+    assert!(!evaluation.triggered);
+    assert_eq!(evaluation.observed, 1);
+
+    // 3 tweets #loup sur 24 heures, 2 sur les 7 jours précédents
+    let surge = |factor| AlertCondition::HashtagSurge {
+      hashtag: "#loup".to_string(),
+      factor,
+    };
+    let evaluation = evaluate_alert(&index, criteria, &surge(3.0), now, now).unwrap();
+    assert_eq!(evaluation.observed, 3);
+    assert!(evaluation.triggered);
+    let evaluation = evaluate_alert(&index, criteria, &surge(11.0), now, now).unwrap();
+    assert!(!evaluation.triggered);
+  }
+}
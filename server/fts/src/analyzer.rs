@@ -0,0 +1,236 @@
+//! Analyse du texte des tweets, majoritairement en français.
+//!
+//! Le champ `text` est analysé avec racinisation (« écologistes » et « Écologie » donnent
+//! tous deux `ecolog`), le champ `text_exact` sans : minuscules et accents retirés
+//! seulement. Chaque mot clé d'un bloc choisit son champ, `=` en préfixe demandant une
+//! correspondance exacte.
+
+use serde::{Deserialize, Serialize};
+use tantivy::{
+  query::{BooleanQuery, PhraseQuery, Query, TermQuery},
+  schema::{Field, IndexRecordOption, TextFieldIndexing, TextOptions},
+  tokenizer::{
+    AsciiFoldingFilter, Language, LowerCaser, RemoveLongFilter, SimpleTokenizer, Stemmer,
+    StopWordFilter, TextAnalyzer,
+  },
+  Index, Term,
+};
+
+use crate::TweetSchema;
+
+/// Minuscules, accents retirés, racinisation
+pub const FRENCH_STEM_TOKENIZER: &str = "fr_stem";
+/// Comme [`FRENCH_STEM_TOKENIZER`], mots vides retirés avant la racinisation
+pub const FRENCH_STEM_STOPWORDS_TOKENIZER: &str = "fr_stem_stopwords";
+/// Minuscules et accents retirés, sans racinisation
+pub const FRENCH_EXACT_TOKENIZER: &str = "fr_exact";
+
+/// Préfixe d'un mot clé de bloc à chercher sans racinisation
+pub const EXACT_PREFIX: char = '=';
+
+/// Mots vides français, sans accents puisqu'ils sont filtrés après le repliement, triés
+const FRENCH_STOPWORDS: &[&str] = &[
+  "a", "au", "aux", "avec", "c", "ce", "ces", "cet", "cette", "d", "dans", "de", "des", "du",
+  "elle", "elles", "en", "est", "et", "ete", "etre", "eux", "il", "ils", "j", "je", "l", "la",
+  "le", "les", "leur", "leurs", "lui", "m", "ma", "mais", "me", "mes", "moi", "mon", "n", "ne",
+  "nos", "notre", "nous", "on", "ou", "par", "pas", "pour", "qu", "que", "qui", "s", "sa", "se",
+  "ses", "son", "sont", "sur", "t", "ta", "te", "tes", "toi", "ton", "tu", "un", "une", "vos",
+  "votre", "vous", "y",
+];
+
+/// Options d'analyse du texte, fixées à la création de l'index
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TextAnalysisOptions {
+  /// retire les mots vides du champ `text`
+  pub stopwords: bool,
+}
+
+/// Type de correspondance d'un mot clé
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchMode {
+  Stemmed,
+  Exact,
+}
+
+/// Les analyseurs ne sont pas enregistrés dans l'index : à appeler à chaque ouverture,
+/// avant toute écriture ou recherche sur les champs texte
+pub fn register_tokenizers(index: &Index) {
+  let tokenizers = index.tokenizers();
+  tokenizers.register(FRENCH_STEM_TOKENIZER, french_analyzer(false, true));
+  tokenizers.register(FRENCH_STEM_STOPWORDS_TOKENIZER, french_analyzer(true, true));
+  tokenizers.register(FRENCH_EXACT_TOKENIZER, french_analyzer(false, false));
+}
+
+/// Analyse du champ `text_exact`, pour découper un texte comme l'index
+pub(crate) fn exact_analyzer() -> TextAnalyzer {
+  french_analyzer(false, false)
+}
+
+/// Mot vide français, `word` étant analysé comme par [`exact_analyzer`]
+pub(crate) fn is_stopword(word: &str) -> bool {
+  FRENCH_STOPWORDS.binary_search(&word).is_ok()
+}
+
+fn french_analyzer(stopwords: bool, stemming: bool) -> TextAnalyzer {
+  let analyzer = TextAnalyzer::from(SimpleTokenizer)
+    .filter(RemoveLongFilter::limit(40))
+    .filter(LowerCaser)
+    .filter(AsciiFoldingFilter);
+  let analyzer = if stopwords {
+    analyzer.filter(StopWordFilter::remove(
+      FRENCH_STOPWORDS
+        .iter()
+        .map(|word| word.to_string())
+        .collect(),
+    ))
+  } else {
+    analyzer
+  };
+
+  if stemming {
+    analyzer.filter(Stemmer::new(Language::French))
+  } else {
+    analyzer
+  }
+}
+
+/// Options d'indexation du champ `text`
+pub fn stemmed_text_options(options: &TextAnalysisOptions) -> TextOptions {
+  let tokenizer = if options.stopwords {
+    FRENCH_STEM_STOPWORDS_TOKENIZER
+  } else {
+    FRENCH_STEM_TOKENIZER
+  };
+  text_options(tokenizer).set_stored()
+}
+
+/// Options d'indexation du champ `text_exact`, non stocké : le texte l'est déjà dans `text`
+pub fn exact_text_options() -> TextOptions {
+  text_options(FRENCH_EXACT_TOKENIZER)
+}
+
+fn text_options(tokenizer: &str) -> TextOptions {
+  TextOptions::default().set_indexing_options(
+    TextFieldIndexing::default()
+      .set_tokenizer(tokenizer)
+      .set_index_option(IndexRecordOption::WithFreqsAndPositions),
+  )
+}
+
+/// Sépare le préfixe de correspondance exacte d'un mot clé de bloc
+pub fn keyword_match_mode(keyword: &str) -> (MatchMode, &str) {
+  match keyword.strip_prefix(EXACT_PREFIX) {
+    Some(keyword) => (MatchMode::Exact, keyword),
+    None => (MatchMode::Stemmed, keyword),
+  }
+}
+
+/// Requête sur le texte des tweets pour un mot ou une expression, analysé comme le champ
+/// interrogé : un terme, ou une expression si l'analyse en produit plusieurs.
+///
+/// Un index créé avant l'ajout de `text_exact` n'a que `text` : les deux modes l'utilisent.
+pub fn keyword_query(
+  index: &Index,
+  tweet_schema: &TweetSchema,
+  keyword: &str,
+  mode: MatchMode,
+) -> Box<dyn Query> {
+  let TweetSchema {
+    text, text_exact, ..
+  } = *tweet_schema;
+
+  let field = match mode {
+    MatchMode::Exact => text_exact.unwrap_or(text),
+    MatchMode::Stemmed => text,
+  };
+
+  match analyzed_query(index, field, keyword) {
+    Some(query) => query,
+    // que des mots vides : seul le champ exact les a conservés
+    None => text_exact
+      .filter(|text_exact| *text_exact != field)
+      .and_then(|text_exact| analyzed_query(index, text_exact, keyword))
+      .unwrap_or_else(|| Box::new(BooleanQuery::new(vec![]))),
+  }
+}
+
+fn analyzed_query(index: &Index, field: Field, keyword: &str) -> Option<Box<dyn Query>> {
+  let mut terms = analyzed_terms(index, field, keyword);
+
+  match terms.len() {
+    0 => None,
+    1 => Some(Box::new(TermQuery::new(
+      terms.pop().unwrap().1,
+      IndexRecordOption::Basic,
+    ))),
+    _ => Some(Box::new(PhraseQuery::new_with_offset(terms))),
+  }
+}
+
+/// Termes de `text` analysé comme le champ `field`, avec leur position
+pub(crate) fn analyzed_terms(index: &Index, field: Field, text: &str) -> Vec<(usize, Term)> {
+  let analyzer = match index.tokenizer_for_field(field) {
+    Ok(analyzer) => analyzer,
+    Err(e) => {
+      tracing::warn!("analyseur du champ indisponible, enregistrement manquant ? {e}");
+      register_tokenizers(index);
+      match index.tokenizer_for_field(field) {
+        Ok(analyzer) => analyzer,
+        Err(_) => return vec![],
+      }
+    }
+  };
+
+  let mut terms = vec![];
+  analyzer.token_stream(text).process(&mut |token| {
+    terms.push((token.position, Term::from_field_text(field, &token.text)));
+  });
+  terms
+}
+
+#[cfg(test)]
+mod tests {
+  use tantivy::{collector::Count, doc};
+
+  use super::*;
+
+  #[test]
+  fn test_stemmed_and_exact_matching() {
+    let index = Index::create_in_ram(TweetSchema::build(&TextAnalysisOptions { stopwords: true }));
+    register_tokenizers(&index);
+    let tweet_schema = TweetSchema::from_index(&index).unwrap();
+    let (text, text_exact) = (tweet_schema.text, tweet_schema.text_exact.unwrap());
+
+    let mut writer = index.writer(15_000_000).unwrap();
+    for content in [
+      "Les écologistes manifestent",
+      "ECOLOGIE et climat",
+      "la pomme de terre",
+    ] {
+      writer
+        .add_document(doc!(text => content, text_exact => content))
+        .unwrap();
+    }
+    writer.commit().unwrap();
+    let searcher = index.reader().unwrap().searcher();
+    let count = |keyword: &str| {
+      let (mode, keyword) = keyword_match_mode(keyword);
+      searcher
+        .search(
+          keyword_query(&index, &tweet_schema, keyword, mode).as_ref(),
+          &Count,
+        )
+        .unwrap()
+    };
+
+    assert_eq!(count("écologie"), 2);
+    assert_eq!(count("Écologiste"), 2);
+    assert_eq!(count("=écologie"), 1);
+    assert_eq!(count("=ecologistes"), 1);
+    assert_eq!(count("pommes de terre"), 1);
+    assert_eq!(count("=pomme terre"), 0);
+    // mots vides uniquement : recherche sur le champ exact
+    assert_eq!(count("et"), 1);
+  }
+}
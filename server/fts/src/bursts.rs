@@ -0,0 +1,499 @@
+//! Détection des pics d'activité dans les histogrammes de tweets ou de hashtags.
+//!
+//! Deux méthodes sont proposées : l'écart d'un intervalle à la moyenne des intervalles
+//! précédents (z-score sur une fenêtre glissante), et l'automate à deux états de Kleinberg,
+//! où chaque intervalle est émis au taux de base ou à un taux multiplié, le passage au taux
+//! élevé ayant un coût. Un pic couvre des intervalles consécutifs ; il est ensuite annoté
+//! par les hashtags, les comptes et les tweets de sa période.
+
+use std::{collections::HashMap, fmt, str::FromStr};
+
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use tantivy::{
+  query::{BooleanQuery, Occur, Query, RangeQuery},
+  Index,
+};
+
+use crate::{
+  get_results_query, Author, AuthorCount, Frequence, FrequenceByDate, HashtagCount, ResultsQuery,
+  SearchError, TimeBuckets, Tweet, TweetSchema, TweetStream,
+};
+
+/// Méthode de détection des pics
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub enum BurstMethod {
+  /// intervalles dépassant d'au moins `threshold` écarts types la moyenne des `window`
+  /// intervalles précédents
+  ZScore { window: usize, threshold: f64 },
+  /// intervalles émis au taux de base multiplié par `scale` selon l'automate de Kleinberg,
+  /// `gamma` pondérant le coût du passage au taux élevé
+  Kleinberg { scale: f64, gamma: f64 },
+}
+
+impl Default for BurstMethod {
+  fn default() -> Self {
+    BurstMethod::ZScore {
+      window: 7,
+      threshold: 3.0,
+    }
+  }
+}
+
+impl BurstMethod {
+  pub const ALL: [BurstMethod; 2] = [
+    BurstMethod::ZScore {
+      window: 7,
+      threshold: 3.0,
+    },
+    BurstMethod::Kleinberg {
+      scale: 2.0,
+      gamma: 1.0,
+    },
+  ];
+
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      BurstMethod::ZScore { .. } => "zscore",
+      BurstMethod::Kleinberg { .. } => "kleinberg",
+    }
+  }
+}
+
+impl fmt::Display for BurstMethod {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(self.as_str())
+  }
+}
+
+/// Méthode désignée par son nom, avec ses paramètres par défaut
+impl FromStr for BurstMethod {
+  type Err = SearchError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    BurstMethod::ALL
+      .into_iter()
+      .find(|method| method.as_str() == s)
+      .ok_or(SearchError::ParseError)
+  }
+}
+
+/// Intervalles consécutifs d'un histogramme formant un pic
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Burst {
+  /// début du premier intervalle du pic
+  pub start: NaiveDateTime,
+  /// début du dernier intervalle du pic
+  pub last: NaiveDateTime,
+  /// début de l'intervalle le plus haut
+  pub peak: NaiveDateTime,
+  pub peak_frequence: u64,
+  /// valeur attendue de l'intervalle le plus haut hors pic
+  pub expected: f64,
+  /// z-score maximal, ou poids du pic dans l'automate de Kleinberg
+  pub score: f64,
+}
+
+/// Pic de l'histogramme d'un hashtag
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HashtagBurst {
+  pub hashtag: String,
+  #[serde(flatten)]
+  pub burst: Burst,
+}
+
+/// Pic de l'histogramme des tweets, avec ce qui l'a alimenté
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AnnotatedBurst {
+  #[serde(flatten)]
+  pub burst: Burst,
+  /// nombre de tweets publiés pendant le pic
+  pub tweets_count: u64,
+  /// hashtags les plus utilisés pendant le pic
+  pub hashtags: Vec<HashtagCount>,
+  /// comptes ayant le plus publié pendant le pic
+  pub authors: Vec<AuthorCount>,
+  /// tweets les plus retweetés, cités ou répondus pendant le pic
+  pub tweets: Vec<Tweet>,
+}
+
+/// Pics de `series` selon `method`, dans l'ordre chronologique
+pub fn detect_bursts(series: &[FrequenceByDate], method: &BurstMethod) -> Vec<Burst> {
+  // (intervalle en pic, valeur attendue, score)
+  let states: Vec<(bool, f64, f64)> = match *method {
+    BurstMethod::ZScore { window, threshold } => z_scores(series, window)
+      .into_iter()
+      .map(|(mean, z)| (z >= threshold, mean, z))
+      .collect(),
+    BurstMethod::Kleinberg { scale, gamma } => kleinberg_states(series, scale, gamma),
+  };
+
+  let mut bursts: Vec<Burst> = vec![];
+  let mut previous_in_burst = false;
+  for (frequence, (in_burst, expected, score)) in series.iter().zip(states) {
+    if !in_burst {
+      previous_in_burst = false;
+      continue;
+    }
+
+    match bursts.last_mut() {
+      Some(burst) if previous_in_burst => {
+        burst.last = frequence.date;
+        if frequence.frequence > burst.peak_frequence {
+          burst.peak = frequence.date;
+          burst.peak_frequence = frequence.frequence;
+          burst.expected = expected;
+        }
+        burst.score = match method {
+          BurstMethod::ZScore { .. } => burst.score.max(score),
+          BurstMethod::Kleinberg { .. } => burst.score + score,
+        };
+      }
+      _ => bursts.push(Burst {
+        start: frequence.date,
+        last: frequence.date,
+        peak: frequence.date,
+        peak_frequence: frequence.frequence,
+        expected,
+        score,
+      }),
+    }
+    previous_in_burst = true;
+  }
+
+  if let BurstMethod::Kleinberg { gamma, .. } = *method {
+    // le poids d'un pic est le coût épargné par l'état élevé, moins celui d'y entrer
+    let transition = gamma * (series.len().max(2) as f64).ln();
+    bursts.iter_mut().for_each(|burst| burst.score -= transition);
+  }
+
+  bursts
+}
+
+/// Pics des histogrammes des hashtags, par score décroissant
+pub fn hashtag_bursts(frequences: &[Frequence], method: &BurstMethod) -> Vec<HashtagBurst> {
+  let mut bursts: Vec<HashtagBurst> = frequences
+    .iter()
+    .flat_map(|frequence| {
+      detect_bursts(&frequence.data, method)
+        .into_iter()
+        .map(|burst| HashtagBurst {
+          hashtag: frequence.hashtag.clone(),
+          burst,
+        })
+    })
+    .collect();
+  bursts.sort_by(|a, b| b.burst.score.total_cmp(&a.burst.score));
+
+  bursts
+}
+
+/// Moyenne des `window` intervalles précédant chaque intervalle et écart de celui-ci à la
+/// moyenne, en écarts types. L'écart type est au moins 1 pour qu'une série plate ne
+/// déclenche pas un pic au premier tweet ; les deux premiers intervalles ont un score nul.
+fn z_scores(series: &[FrequenceByDate], window: usize) -> Vec<(f64, f64)> {
+  (0..series.len())
+    .map(|i| {
+      let previous = &series[i.saturating_sub(window.max(1))..i];
+      if previous.len() < 2 {
+        return (series[i].frequence as f64, 0.0);
+      }
+
+      let n = previous.len() as f64;
+      let mean = previous.iter().map(|f| f.frequence as f64).sum::<f64>() / n;
+      let variance = previous
+        .iter()
+        .map(|f| (f.frequence as f64 - mean).powi(2))
+        .sum::<f64>()
+        / n;
+
+      (mean, (series[i].frequence as f64 - mean) / variance.sqrt().max(1.0))
+    })
+    .collect()
+}
+
+/// États de l'automate de Kleinberg à deux états, les intervalles suivant une loi de
+/// Poisson de moyenne la moyenne de la série, ou `scale` fois celle-ci pendant un pic.
+/// Chaque intervalle en pic est accompagné de la valeur attendue et du coût épargné.
+fn kleinberg_states(series: &[FrequenceByDate], scale: f64, gamma: f64) -> Vec<(bool, f64, f64)> {
+  let n = series.len();
+  let base = series.iter().map(|f| f.frequence as f64).sum::<f64>() / n.max(1) as f64;
+  if base == 0.0 {
+    return vec![(false, 0.0, 0.0); n];
+  }
+  let rates = [base, base * scale.max(1.0 + f64::EPSILON)];
+  // -ln P(x | taux), sans le terme ln(x!) commun aux deux états
+  let cost = |state: usize, x: u64| rates[state] - x as f64 * rates[state].ln();
+  let transition = gamma * (n.max(2) as f64).ln();
+
+  // algorithme de Viterbi, en partant de l'état de base
+  let mut costs = [0.0, transition];
+  let mut from: Vec<[usize; 2]> = Vec::with_capacity(n);
+  for frequence in series {
+    let mut next = [0.0; 2];
+    let mut previous = [0; 2];
+    for state in 0..2 {
+      // seul le passage à l'état élevé coûte
+      let stay = costs[state];
+      let change = costs[1 - state] + if state == 1 { transition } else { 0.0 };
+      (next[state], previous[state]) = match stay <= change {
+        true => (stay, state),
+        false => (change, 1 - state),
+      };
+      next[state] += cost(state, frequence.frequence);
+    }
+    costs = next;
+    from.push(previous);
+  }
+
+  let mut state = if costs[0] <= costs[1] { 0 } else { 1 };
+  let mut states = vec![0; n];
+  for i in (0..n).rev() {
+    states[i] = state;
+    state = from[i][state];
+  }
+
+  series
+    .iter()
+    .zip(states)
+    .map(|(frequence, state)| {
+      (
+        state == 1,
+        base,
+        cost(0, frequence.frequence) - cost(1, frequence.frequence),
+      )
+    })
+    .collect()
+}
+
+/// Pics de l'histogramme des tweets de l'étude (voir [`crate::search_tweets_count_per_day`]),
+/// annotés des `limit` hashtags, comptes et tweets les plus présents dans chacun
+pub fn search_tweets_bursts(
+  index: &Index,
+  included_user_names: &Vec<String>,
+  hidden_hashtags: &Vec<String>,
+  series: &[FrequenceByDate],
+  buckets: &TimeBuckets,
+  method: &BurstMethod,
+  limit: usize,
+) -> Result<Vec<AnnotatedBurst>, SearchError> {
+  let tweet_schema = TweetSchema::from_index(index)?;
+  let query = get_results_query(
+    index,
+    &ResultsQuery {
+      authors: included_user_names.clone(),
+      hidden_hashtags: hidden_hashtags.clone(),
+      ..Default::default()
+    },
+  )?;
+
+  detect_bursts(series, method)
+    .into_iter()
+    .map(|burst| {
+      let (start_ms, end_ms) = buckets.span_ms(burst.start, burst.last);
+      let period = BooleanQuery::new(vec![
+        (Occur::Must, Box::new(query.clone()) as Box<dyn Query>),
+        (
+          Occur::Must,
+          Box::new(RangeQuery::new_u64(
+            tweet_schema.published_time_ms,
+            start_ms..end_ms,
+          )),
+        ),
+      ]);
+      annotate(index, &period, burst, limit)
+    })
+    .collect()
+}
+
+fn annotate(
+  index: &Index,
+  query: &dyn Query,
+  burst: Burst,
+  limit: usize,
+) -> Result<AnnotatedBurst, SearchError> {
+  let engagement = |tweet: &Tweet| tweet.retweet_count + tweet.quote_count + tweet.reply_count;
+  let mut tweets_count = 0;
+  let mut hashtags: HashMap<String, u64> = HashMap::new();
+  let mut authors: HashMap<String, AuthorCount> = HashMap::new();
+  let mut tweets: Vec<Tweet> = vec![];
+
+  for tweet in TweetStream::new(index, query)? {
+    let tweet = tweet?;
+    tweets_count += 1;
+    for hashtag in &tweet.hashtags {
+      *hashtags.entry(hashtag.clone()).or_default() += 1;
+    }
+    authors
+      .entry(tweet.user_id.clone())
+      .or_insert_with(|| AuthorCount {
+        author: Author {
+          user_name: tweet.user_name.clone(),
+          user_screen_name: tweet.user_screen_name.clone(),
+        },
+        count: 0,
+      })
+      .count += 1;
+
+    // les `limit` tweets les plus engageants, triés à la fin
+    if tweets.len() < limit {
+      tweets.push(tweet);
+    } else if let Some(least) = tweets
+      .iter_mut()
+      .min_by_key(|kept| engagement(kept))
+      .filter(|least| engagement(least) < engagement(&tweet))
+    {
+      *least = tweet;
+    }
+  }
+
+  let mut hashtags: Vec<HashtagCount> = hashtags
+    .into_iter()
+    .map(|(key, doc_count)| HashtagCount { key, doc_count })
+    .collect();
+  hashtags.sort_by(|a, b| b.doc_count.cmp(&a.doc_count).then_with(|| a.key.cmp(&b.key)));
+  hashtags.truncate(limit);
+  let mut authors: Vec<AuthorCount> = authors.into_values().collect();
+  authors.sort_by(|a, b| {
+    b.count
+      .cmp(&a.count)
+      .then_with(|| a.author.user_screen_name.cmp(&b.author.user_screen_name))
+  });
+  authors.truncate(limit);
+  tweets.sort_by(|a, b| engagement(b).cmp(&engagement(a)).then_with(|| a.id.cmp(&b.id)));
+
+  Ok(AnnotatedBurst {
+    burst,
+    tweets_count,
+    hashtags,
+    authors,
+    tweets,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use chrono::{Duration, NaiveDate, TimeZone, Utc};
+
+  use super::*;
+  use crate::{
+    search_tweets_count_per_day,
+    test_utils::{index_with, tweet},
+    Granularity, Tz,
+  };
+
+  fn series(counts: &[u64]) -> Vec<FrequenceByDate> {
+    let start = NaiveDate::from_ymd(2022, 6, 1).and_hms(0, 0, 0);
+    counts
+      .iter()
+      .enumerate()
+      .map(|(day, frequence)| FrequenceByDate {
+        date: start + Duration::days(day as i64),
+        frequence: *frequence,
+      })
+      .collect()
+  }
+
+  #[test]
+  fn test_detect_bursts() {
+    let counts = [5, 6, 4, 5, 30, 28, 5, 4, 6, 5, 5, 4];
+    let day = |day: i64| NaiveDate::from_ymd(2022, 6, 1).and_hms(0, 0, 0) + Duration::days(day);
+
+    let bursts = detect_bursts(&series(&counts), &BurstMethod::default());
+    assert_eq!(bursts.len(), 1);
+    assert_eq!(bursts[0].start, day(4));
+    assert_eq!(bursts[0].peak_frequence, 30);
+    assert_eq!(bursts[0].expected, 5.0);
+    assert!(bursts[0].score > 3.0);
+
+    let kleinberg = BurstMethod::from_str("kleinberg").unwrap();
+    let bursts = detect_bursts(&series(&counts), &kleinberg);
+    assert_eq!(bursts.len(), 1);
+    assert_eq!((bursts[0].start, bursts[0].last), (day(4), day(5)));
+    assert!(bursts[0].score > 0.0);
+
+    // ni pic dans une série plate, ni dans une série vide
+    for method in BurstMethod::ALL {
+      assert!(detect_bursts(&series(&[3; 12]), &method).is_empty());
+      assert!(detect_bursts(&series(&[0; 12]), &method).is_empty());
+      assert!(detect_bursts(&[], &method).is_empty());
+    }
+
+    let frequences = vec![
+      Frequence {
+        hashtag: "loup".to_string(),
+        hidden: false,
+        data: series(&counts),
+      },
+      Frequence {
+        hashtag: "brebis".to_string(),
+        hidden: false,
+        data: series(&[2; 12]),
+      },
+    ];
+    let bursts = hashtag_bursts(&frequences, &BurstMethod::default());
+    assert_eq!(bursts.len(), 1);
+    assert_eq!(bursts[0].hashtag, "loup");
+  }
+
+  #[test]
+  fn test_search_tweets_bursts() {
+    let start = Utc.ymd(2022, 6, 1).and_hms(12, 0, 0);
+    let mut tweets = vec![];
+    for (day, count) in [2, 2, 3, 2, 12, 2, 2].into_iter().enumerate() {
+      for i in 0..count {
+        let id = tweets.len() as u64 + 1;
+        // le pic du cinquième jour est porté par #loup et @eleveurs_fr
+        let published = tweet(id).at(start + Duration::days(day as i64));
+        let published = match day == 4 && i < 10 {
+          true => published.by("eleveurs_fr").with_hashtags(&["loup"]),
+          false => published.by("bergers"),
+        };
+        tweets.push(Tweet {
+          text: "le loup".to_string(),
+          retweet_count: id,
+          ..published
+        });
+      }
+    }
+    let index = index_with(&tweets);
+
+    let buckets = TimeBuckets::new(Granularity::Day, Tz::UTC);
+    let (start_date, end_date) = (
+      NaiveDate::from_ymd(2022, 6, 1),
+      NaiveDate::from_ymd(2022, 6, 7),
+    );
+    let series = search_tweets_count_per_day(
+      &index,
+      &vec![],
+      &vec![],
+      &start_date,
+      &end_date,
+      &"total".to_string(),
+      &buckets,
+    )
+    .unwrap();
+    let bursts = search_tweets_bursts(
+      &index,
+      &vec![],
+      &vec![],
+      &series,
+      &buckets,
+      &BurstMethod::default(),
+      3,
+    )
+    .unwrap();
+
+    assert_eq!(bursts.len(), 1);
+    let burst = &bursts[0];
+    assert_eq!(burst.burst.start.date(), NaiveDate::from_ymd(2022, 6, 5));
+    assert_eq!(burst.tweets_count, 12);
+    assert_eq!(burst.hashtags[0].key, "loup");
+    assert_eq!(burst.hashtags[0].doc_count, 10);
+    assert_eq!(burst.authors[0].author.user_screen_name, "eleveurs_fr");
+    // les plus retweetés d'abord
+    assert_eq!(burst.tweets.len(), 3);
+    assert_eq!(burst.tweets[0].id, "21");
+  }
+}
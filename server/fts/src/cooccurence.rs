@@ -0,0 +1,439 @@
+use std::{cmp::Ordering, collections::HashMap, fmt, str::FromStr, sync::Arc};
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use tantivy::{
+  collector::{Collector, SegmentCollector},
+  fastfield::MultiValuedFastFieldReader,
+  query::{Query, QueryParser, RangeQuery},
+  schema::Field,
+  DocId, Index, InvertedIndexReader, Score, SegmentOrdinal, SegmentReader,
+};
+
+use crate::{HashtagCooccurence, SearchError, TweetSchema};
+
+/// Mesure d'association utilisée pour classer les paires de hashtags
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AssociationMeasure {
+  /// nombre de tweets contenant les deux hashtags
+  #[default]
+  Count,
+  /// information mutuelle ponctuelle, log2(P(a,b) / (P(a) P(b)))
+  Pmi,
+  /// |a ∩ b| / |a ∪ b|
+  Jaccard,
+  /// P(a,b) / (P(a) P(b))
+  Lift,
+}
+
+impl AssociationMeasure {
+  pub const ALL: [AssociationMeasure; 4] = [
+    AssociationMeasure::Count,
+    AssociationMeasure::Pmi,
+    AssociationMeasure::Jaccard,
+    AssociationMeasure::Lift,
+  ];
+
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      AssociationMeasure::Count => "count",
+      AssociationMeasure::Pmi => "pmi",
+      AssociationMeasure::Jaccard => "jaccard",
+      AssociationMeasure::Lift => "lift",
+    }
+  }
+
+  /// Nom affiché de la mesure
+  pub fn label(&self) -> &'static str {
+    match self {
+      AssociationMeasure::Count => "Nombre de tweets",
+      AssociationMeasure::Pmi => "PMI",
+      AssociationMeasure::Jaccard => "Jaccard",
+      AssociationMeasure::Lift => "Lift",
+    }
+  }
+}
+
+impl fmt::Display for AssociationMeasure {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(self.as_str())
+  }
+}
+
+impl FromStr for AssociationMeasure {
+  type Err = SearchError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    AssociationMeasure::ALL
+      .into_iter()
+      .find(|measure| measure.as_str() == s)
+      .ok_or(SearchError::ParseError)
+  }
+}
+
+/// Paramètres du calcul des cooccurrences
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CooccurenceOptions {
+  /// nombre de paires retournées, toutes si 0
+  pub top: usize,
+  /// nombre minimal de tweets contenant la paire
+  pub min_support: u64,
+  pub measure: AssociationMeasure,
+}
+
+impl Default for CooccurenceOptions {
+  fn default() -> Self {
+    Self {
+      top: 10,
+      min_support: 1,
+      measure: AssociationMeasure::Count,
+    }
+  }
+}
+
+/// Paire de hashtags utilisés dans les mêmes tweets, `hashtag1` < `hashtag2`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ScoredCooccurence {
+  pub hashtag1: String,
+  pub hashtag2: String,
+  /// nombre de tweets contenant les deux hashtags
+  pub count: u64,
+  pub hashtag1_count: u64,
+  pub hashtag2_count: u64,
+  pub pmi: f64,
+  pub jaccard: f64,
+  pub lift: f64,
+}
+
+impl ScoredCooccurence {
+  pub fn score(&self, measure: AssociationMeasure) -> f64 {
+    match measure {
+      AssociationMeasure::Count => self.count as f64,
+      AssociationMeasure::Pmi => self.pmi,
+      AssociationMeasure::Jaccard => self.jaccard,
+      AssociationMeasure::Lift => self.lift,
+    }
+  }
+}
+
+impl From<&ScoredCooccurence> for HashtagCooccurence {
+  fn from(cooccurence: &ScoredCooccurence) -> Self {
+    Self {
+      hashtag1: cooccurence.hashtag1.clone(),
+      hashtag2: cooccurence.hashtag2.clone(),
+    }
+  }
+}
+
+/// Cooccurrences de hashtags d'un ensemble de tweets
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Cooccurences {
+  /// nombre de tweets correspondant à la requête
+  pub documents: u64,
+  pub pairs: Vec<ScoredCooccurence>,
+}
+
+/// Cooccurrences des hashtags des tweets correspondant à `query`, calculées à partir du
+/// champ multivalué `hashtags` de l'index
+pub fn hashtags_cooccurence(
+  index: &Index,
+  query: &dyn Query,
+  options: &CooccurenceOptions,
+) -> Result<Cooccurences, SearchError> {
+  let searcher = index.reader()?.searcher();
+  let hashtags = TweetSchema::from_index(index)?.hashtags;
+
+  let counts = searcher.search(query, &CooccurenceCollector { field: hashtags })?;
+
+  Ok(counts.into_cooccurences(options))
+}
+
+/// Cooccurrences des hashtags des tweets correspondant à la requête tantivy `query`
+/// (`*` pour tout l'index)
+pub fn hashtags_cooccurence_for_query(
+  index: &Index,
+  query: &str,
+  options: &CooccurenceOptions,
+) -> Result<Cooccurences, SearchError> {
+  let text = TweetSchema::from_index(index)?.text;
+  let query = QueryParser::for_index(index, vec![text]).parse_query(query)?;
+
+  hashtags_cooccurence(index, query.as_ref(), options)
+}
+
+/// Cooccurrences des hashtags des tweets publiés entre `start_date` et `end_date` inclus
+pub fn hashtags_cooccurence_between(
+  index: &Index,
+  start_date: &NaiveDate,
+  end_date: &NaiveDate,
+  options: &CooccurenceOptions,
+) -> Result<Cooccurences, SearchError> {
+  let published_time_ms = TweetSchema::from_index(index)?.published_time_ms;
+  let query = RangeQuery::new_u64(
+    published_time_ms,
+    start_date.and_hms(0, 0, 0).timestamp_millis() as u64
+      ..end_date.and_hms(23, 59, 59).timestamp_millis() as u64 + 1,
+  );
+
+  hashtags_cooccurence(index, &query, options)
+}
+
+/// Nombres de tweets par hashtag et par paire de hashtags
+#[derive(Debug, Default)]
+struct CooccurenceCounts {
+  documents: u64,
+  hashtags: HashMap<String, u64>,
+  pairs: HashMap<(String, String), u64>,
+}
+
+impl CooccurenceCounts {
+  fn merge(&mut self, other: CooccurenceCounts) {
+    self.documents += other.documents;
+    for (hashtag, count) in other.hashtags {
+      *self.hashtags.entry(hashtag).or_default() += count;
+    }
+    for (pair, count) in other.pairs {
+      *self.pairs.entry(pair).or_default() += count;
+    }
+  }
+
+  fn into_cooccurences(self, options: &CooccurenceOptions) -> Cooccurences {
+    let documents = self.documents as f64;
+    let hashtags = self.hashtags;
+
+    let mut pairs: Vec<ScoredCooccurence> = self
+      .pairs
+      .into_iter()
+      .filter(|(_, count)| *count >= options.min_support)
+      .map(|((hashtag1, hashtag2), count)| {
+        let hashtag1_count = hashtags.get(&hashtag1).copied().unwrap_or(count);
+        let hashtag2_count = hashtags.get(&hashtag2).copied().unwrap_or(count);
+        let lift = count as f64 * documents / (hashtag1_count as f64 * hashtag2_count as f64);
+
+        ScoredCooccurence {
+          hashtag1,
+          hashtag2,
+          count,
+          hashtag1_count,
+          hashtag2_count,
+          pmi: lift.log2(),
+          jaccard: count as f64 / (hashtag1_count + hashtag2_count - count) as f64,
+          lift,
+        }
+      })
+      .collect();
+
+    pairs.sort_by(|a, b| {
+      b.score(options.measure)
+        .partial_cmp(&a.score(options.measure))
+        .unwrap_or(Ordering::Equal)
+        .then(b.count.cmp(&a.count))
+        .then_with(|| (&a.hashtag1, &a.hashtag2).cmp(&(&b.hashtag1, &b.hashtag2)))
+    });
+    if options.top > 0 {
+      pairs.truncate(options.top);
+    }
+
+    Cooccurences {
+      documents: self.documents,
+      pairs,
+    }
+  }
+}
+
+struct CooccurenceCollector {
+  field: Field,
+}
+
+impl Collector for CooccurenceCollector {
+  type Fruit = CooccurenceCounts;
+  type Child = CooccurenceSegmentCollector;
+
+  fn for_segment(
+    &self,
+    _segment_local_id: SegmentOrdinal,
+    segment: &SegmentReader,
+  ) -> tantivy::Result<Self::Child> {
+    Ok(CooccurenceSegmentCollector {
+      hashtags: segment.fast_fields().u64s_lenient(self.field)?,
+      inverted_index: segment.inverted_index(self.field)?,
+      buffer: Vec::new(),
+      documents: 0,
+      hashtag_counts: HashMap::new(),
+      pair_counts: HashMap::new(),
+    })
+  }
+
+  fn requires_scoring(&self) -> bool {
+    false
+  }
+
+  fn merge_fruits(
+    &self,
+    segment_fruits: Vec<CooccurenceCounts>,
+  ) -> tantivy::Result<CooccurenceCounts> {
+    let mut counts = CooccurenceCounts::default();
+    for fruit in segment_fruits {
+      counts.merge(fruit);
+    }
+    Ok(counts)
+  }
+}
+
+/// Compte les hashtags par ordinal de terme, propre au segment, puis les traduit en texte
+/// une fois le segment parcouru
+struct CooccurenceSegmentCollector {
+  hashtags: MultiValuedFastFieldReader<u64>,
+  inverted_index: Arc<InvertedIndexReader>,
+  buffer: Vec<u64>,
+  documents: u64,
+  hashtag_counts: HashMap<u64, u64>,
+  pair_counts: HashMap<(u64, u64), u64>,
+}
+
+impl SegmentCollector for CooccurenceSegmentCollector {
+  type Fruit = CooccurenceCounts;
+
+  fn collect(&mut self, doc: DocId, _score: Score) {
+    self.documents += 1;
+    self.hashtags.get_vals(doc, &mut self.buffer);
+    self.buffer.sort_unstable();
+    self.buffer.dedup();
+
+    for (i, hashtag1) in self.buffer.iter().enumerate() {
+      *self.hashtag_counts.entry(*hashtag1).or_default() += 1;
+      for hashtag2 in &self.buffer[i + 1..] {
+        *self.pair_counts.entry((*hashtag1, *hashtag2)).or_default() += 1;
+      }
+    }
+  }
+
+  fn harvest(self) -> CooccurenceCounts {
+    // les ordinaux suivent l'ordre lexicographique des termes : la paire reste ordonnée
+    let terms = self.inverted_index.terms();
+    let mut names = HashMap::with_capacity(self.hashtag_counts.len());
+    let mut bytes = Vec::new();
+    for ord in self.hashtag_counts.keys() {
+      match terms.ord_to_term(*ord, &mut bytes) {
+        Ok(true) => {
+          names.insert(*ord, String::from_utf8_lossy(&bytes).to_string());
+        }
+        Ok(false) => tracing::warn!("hashtag d'ordinal {ord} introuvable"),
+        Err(e) => tracing::warn!("lecture du hashtag d'ordinal {ord} impossible : {e}"),
+      }
+    }
+
+    CooccurenceCounts {
+      documents: self.documents,
+      hashtags: self
+        .hashtag_counts
+        .iter()
+        .filter_map(|(ord, count)| Some((names.get(ord)?.clone(), *count)))
+        .collect(),
+      pairs: self
+        .pair_counts
+        .into_iter()
+        .filter_map(|((ord1, ord2), count)| {
+          Some((
+            (names.get(&ord1)?.clone(), names.get(&ord2)?.clone()),
+            count,
+          ))
+        })
+        .collect(),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use tantivy::{doc, query::AllQuery};
+
+  use super::*;
+  use crate::register_tokenizers;
+
+  fn index_with(hashtags: &[&[&str]]) -> Index {
+    let index = Index::create_in_ram(TweetSchema::build(&Default::default()));
+    register_tokenizers(&index);
+    let field = TweetSchema::from_index(&index).unwrap().hashtags;
+
+    let mut writer = index.writer(15_000_000).unwrap();
+    for tweet in hashtags {
+      let mut document = doc!();
+      tweet.iter().for_each(|h| document.add_text(field, h));
+      writer.add_document(document).unwrap();
+    }
+    writer.commit().unwrap();
+
+    index
+  }
+
+  #[test]
+  fn test_cooccurence_measures() {
+    let index = index_with(&[
+      &["vegan", "pac"],
+      &["vegan", "pac", "climat"],
+      &["vegan"],
+      &["climat", "climat"],
+    ]);
+
+    let options = CooccurenceOptions {
+      top: 0,
+      ..Default::default()
+    };
+    let result = hashtags_cooccurence(&index, &AllQuery, &options).unwrap();
+    assert_eq!(result.documents, 4);
+    assert_eq!(result.pairs.len(), 3);
+
+    let first = &result.pairs[0];
+    assert_eq!(
+      (first.hashtag1.as_str(), first.hashtag2.as_str()),
+      ("pac", "vegan")
+    );
+    assert_eq!(
+      (first.count, first.hashtag1_count, first.hashtag2_count),
+      (2, 2, 3)
+    );
+    assert!((first.jaccard - 2.0 / 3.0).abs() < 1e-9);
+    assert!((first.lift - 4.0 / 3.0).abs() < 1e-9);
+    assert!((first.pmi - (4.0f64 / 3.0).log2()).abs() < 1e-9);
+
+    let options = CooccurenceOptions {
+      top: 2,
+      min_support: 1,
+      measure: AssociationMeasure::Lift,
+    };
+    let result = hashtags_cooccurence(&index, &AllQuery, &options).unwrap();
+    let pairs: Vec<_> = result
+      .pairs
+      .iter()
+      .map(|p| (p.hashtag1.as_str(), p.hashtag2.as_str(), p.lift))
+      .collect();
+    assert_eq!(
+      pairs,
+      vec![("pac", "vegan", 4.0 / 3.0), ("climat", "pac", 1.0)]
+    );
+
+    let options = CooccurenceOptions {
+      top: 0,
+      min_support: 2,
+      ..Default::default()
+    };
+    let result = hashtags_cooccurence(&index, &AllQuery, &options).unwrap();
+    assert_eq!(result.pairs.len(), 1);
+  }
+
+  #[test]
+  fn test_cooccurence_for_query() {
+    let index = index_with(&[&["vegan", "pac"], &["vegan", "climat"], &["pac", "climat"]]);
+    let options = CooccurenceOptions {
+      measure: "jaccard".parse().unwrap(),
+      ..Default::default()
+    };
+
+    let result = hashtags_cooccurence_for_query(&index, "hashtags:vegan", &options).unwrap();
+    assert_eq!(result.documents, 2);
+    assert_eq!(result.pairs.len(), 2);
+    assert!("cosinus".parse::<AssociationMeasure>().is_err());
+  }
+}
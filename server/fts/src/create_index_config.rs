@@ -1,36 +1,37 @@
 use std::{fs, path::Path};
 
-use tantivy::{
-  schema::{Schema, FAST, INDEXED, STORED, STRING, TEXT},
-  Index, TantivyError,
+use serde::{Deserialize, Serialize};
+use tantivy::{Index, TantivyError};
+
+use crate::{
+  register_tokenizers, write_index_stamp, EngagementWeights, IndexStamp, TextAnalysisOptions,
+  TweetSchema,
 };
 
-pub fn create_index_config<P: AsRef<Path>>(directory_path: P) -> Result<(), TantivyError> {
-  fs::create_dir_all(&directory_path)?;
-  let mut schema_builder = Schema::builder();
+/// Options fixées à la création d'un index, conservées par sa migration
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexOptions {
+  pub text_analysis: TextAnalysisOptions,
+  /// absent des index créés avant la pondération de l'engagement
+  #[serde(default)]
+  pub engagement: EngagementWeights,
+}
 
-  schema_builder.add_text_field("id", STRING | FAST | STORED);
-  schema_builder.add_date_field("published_time", INDEXED | FAST | STORED);
-  schema_builder.add_u64_field("published_time_ms", INDEXED | FAST | STORED);
-  schema_builder.add_u64_field("asc_published_time_ms", INDEXED | FAST | STORED);
-  schema_builder.add_text_field("user_id", STRING | FAST | STORED);
-  schema_builder.add_text_field("user_name", STRING | STORED);
-  schema_builder.add_text_field("user_screen_name", STRING | STORED);
-  schema_builder.add_text_field("text", TEXT | STORED);
-  schema_builder.add_text_field("urls", TEXT | STORED);
-  schema_builder.add_text_field("hashtags", STRING | FAST | STORED);
-  schema_builder.add_u64_field("retweet_count", INDEXED | FAST | STORED);
-  schema_builder.add_u64_field("reply_count", INDEXED | FAST | STORED);
-  schema_builder.add_u64_field("quote_count", INDEXED | FAST | STORED);
-  schema_builder.add_u64_field("engagement_count", INDEXED | FAST | STORED);
-  schema_builder.add_u64_field("asc_retweet_count", INDEXED | FAST | STORED);
-  schema_builder.add_u64_field("asc_reply_count", INDEXED | FAST | STORED);
-  schema_builder.add_u64_field("asc_quote_count", INDEXED | FAST | STORED);
-  schema_builder.add_u64_field("asc_engagement_count", INDEXED | FAST | STORED);
+pub fn create_index_config<P: AsRef<Path>>(directory_path: P) -> Result<(), TantivyError> {
+  create_index_config_with_options(directory_path, &IndexOptions::default())
+}
 
-  let schema = schema_builder.build();
+/// Crée un index vide, le champ `text` étant analysé et l'engagement calculé selon `options`
+pub fn create_index_config_with_options<P: AsRef<Path>>(
+  directory_path: P,
+  options: &IndexOptions,
+) -> Result<(), TantivyError> {
+  fs::create_dir_all(&directory_path)?;
+  let schema = TweetSchema::build(&options.text_analysis);
 
-  Index::create_in_dir(directory_path, schema)?;
+  let index = Index::create_in_dir(&directory_path, schema)?;
+  register_tokenizers(&index);
+  write_index_stamp(&directory_path, &IndexStamp::current(*options))?;
 
   Ok(())
 }
@@ -0,0 +1,427 @@
+//! Histogrammes de dates des graphiques : nombre de tweets, de retweets, de citations ou de
+//! réponses par heure, jour, semaine, mois ou année.
+//!
+//! Les intervalles suivent le calendrier du fuseau horaire choisi : une journée commence à
+//! minuit heure locale et dure 23 ou 25 heures aux changements d'heure, une semaine commence
+//! le lundi et un mois le premier.
+
+use std::{fmt, str::FromStr};
+
+use chrono::{
+  Datelike, Duration, LocalResult, NaiveDate, NaiveDateTime, Offset, TimeZone, Timelike,
+};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use tantivy::{
+  collector::{Collector, SegmentCollector},
+  fastfield::{DynamicFastFieldReader, FastFieldReader},
+  query::Query,
+  schema::Field,
+  DocId, Score, Searcher, SegmentOrdinal, SegmentReader,
+};
+
+use crate::{FrequenceByDate, SearchError, TweetSchema};
+
+/// Largeur des intervalles d'un histogramme
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Granularity {
+  #[serde(rename = "heure")]
+  Hour,
+  #[serde(rename = "jour")]
+  Day,
+  #[serde(rename = "semaine")]
+  Week,
+  #[serde(rename = "mois")]
+  Month,
+  #[serde(rename = "annee")]
+  Year,
+}
+
+impl Granularity {
+  pub const ALL: [Granularity; 5] = [
+    Granularity::Hour,
+    Granularity::Day,
+    Granularity::Week,
+    Granularity::Month,
+    Granularity::Year,
+  ];
+
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      Granularity::Hour => "heure",
+      Granularity::Day => "jour",
+      Granularity::Week => "semaine",
+      Granularity::Month => "mois",
+      Granularity::Year => "annee",
+    }
+  }
+
+  /// Intervalle lisible pour une étude du `start_date` au `end_date` : à l'heure pour une
+  /// soirée, au jour jusqu'à six mois, à la semaine jusqu'à trois ans, au mois au-delà
+  pub fn for_period(start_date: &NaiveDate, end_date: &NaiveDate) -> Self {
+    match (*end_date - *start_date).num_days() + 1 {
+      days if days <= 3 => Granularity::Hour,
+      days if days <= 183 => Granularity::Day,
+      days if days <= 3 * 365 => Granularity::Week,
+      _ => Granularity::Month,
+    }
+  }
+
+  /// Début de l'intervalle contenant l'heure locale `local`
+  fn truncate(&self, local: NaiveDateTime) -> NaiveDateTime {
+    let date = local.date();
+    match self {
+      Granularity::Hour => date.and_hms(local.hour(), 0, 0),
+      Granularity::Day => date.and_hms(0, 0, 0),
+      Granularity::Week => {
+        (date - Duration::days(i64::from(date.weekday().num_days_from_monday()))).and_hms(0, 0, 0)
+      }
+      Granularity::Month => NaiveDate::from_ymd(date.year(), date.month(), 1).and_hms(0, 0, 0),
+      Granularity::Year => NaiveDate::from_ymd(date.year(), 1, 1).and_hms(0, 0, 0),
+    }
+  }
+
+  /// Début de l'intervalle suivant celui commençant à `start`
+  fn next(&self, start: NaiveDateTime) -> NaiveDateTime {
+    match self {
+      Granularity::Hour => start + Duration::hours(1),
+      Granularity::Day => start + Duration::days(1),
+      Granularity::Week => start + Duration::weeks(1),
+      Granularity::Month => match start.month() {
+        12 => NaiveDate::from_ymd(start.year() + 1, 1, 1),
+        month => NaiveDate::from_ymd(start.year(), month + 1, 1),
+      }
+      .and_hms(0, 0, 0),
+      Granularity::Year => NaiveDate::from_ymd(start.year() + 1, 1, 1).and_hms(0, 0, 0),
+    }
+  }
+}
+
+impl fmt::Display for Granularity {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(self.as_str())
+  }
+}
+
+impl FromStr for Granularity {
+  type Err = SearchError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    Granularity::ALL
+      .into_iter()
+      .find(|granularity| granularity.as_str() == s)
+      .ok_or(SearchError::ParseError)
+  }
+}
+
+/// Fuseau horaire IANA `name` (`Europe/Paris`…), de la base tz embarquée par `chrono-tz`
+pub fn parse_timezone(name: &str) -> Result<Tz, SearchError> {
+  name.parse().map_err(SearchError::UnknownTimezone)
+}
+
+/// Instant en secondes de l'heure locale `local` : une heure sautée au passage à l'heure
+/// d'été est décalée d'autant, une heure répétée donne son premier instant
+fn local_timestamp(timezone: &Tz, local: NaiveDateTime) -> i64 {
+  match timezone.from_local_datetime(&local) {
+    LocalResult::Single(date) | LocalResult::Ambiguous(date, _) => date.timestamp(),
+    LocalResult::None => {
+      let before = timezone.offset_from_utc_datetime(&(local - Duration::days(1)));
+      local.timestamp() - before.fix().local_minus_utc() as i64
+    }
+  }
+}
+
+/// Découpage des dates d'un histogramme
+#[derive(Debug, Clone)]
+pub struct TimeBuckets {
+  pub granularity: Granularity,
+  pub timezone: Tz,
+}
+
+impl Default for TimeBuckets {
+  fn default() -> Self {
+    TimeBuckets {
+      granularity: Granularity::Day,
+      timezone: Tz::UTC,
+    }
+  }
+}
+
+impl TimeBuckets {
+  pub fn new(granularity: Granularity, timezone: Tz) -> Self {
+    TimeBuckets {
+      granularity,
+      timezone,
+    }
+  }
+
+  /// Début, en heure locale, des intervalles couvrant les journées du `start_date` au
+  /// `end_date`, avec leurs instants en millisecondes ; le dernier instant termine le
+  /// dernier intervalle
+  fn bounds(&self, start_date: &NaiveDate, end_date: &NaiveDate) -> (Vec<NaiveDateTime>, Vec<u64>) {
+    let end = end_date.and_hms(0, 0, 0) + Duration::days(1);
+    let mut starts = vec![];
+    let mut start = self.granularity.truncate(start_date.and_hms(0, 0, 0));
+    while start < end {
+      starts.push(start);
+      start = self.granularity.next(start);
+    }
+
+    let instants = starts
+      .iter()
+      .chain(std::iter::once(&start))
+      .map(|local| (local_timestamp(&self.timezone, *local).max(0) as u64) * 1_000)
+      .collect();
+
+    (starts, instants)
+  }
+
+  /// Instants en millisecondes du début de l'intervalle commençant à `first` et de la fin
+  /// de celui commençant à `last`
+  pub(crate) fn span_ms(&self, first: NaiveDateTime, last: NaiveDateTime) -> (u64, u64) {
+    let instant = |local| (local_timestamp(&self.timezone, local).max(0) as u64) * 1_000;
+
+    (instant(first), instant(self.granularity.next(last)))
+  }
+}
+
+/// Compteur additionné par un histogramme selon l'onglet : le nombre de retweets, de
+/// citations ou de réponses des tweets, ou les tweets eux-mêmes
+fn tab_field(tweet_schema: &TweetSchema, tab: &str) -> Option<Field> {
+  match tab {
+    "retweets" => Some(tweet_schema.retweet_count),
+    "citations" => Some(tweet_schema.quote_count),
+    "repondus" => Some(tweet_schema.reply_count),
+    _ => None,
+  }
+}
+
+/// Histogramme des tweets de `query` publiés du `start_date` au `end_date`, pour l'onglet
+/// `tab`. Tous les intervalles de la période figurent, vides compris.
+pub fn frequence_by_date(
+  searcher: &Searcher,
+  tweet_schema: &TweetSchema,
+  query: &dyn Query,
+  start_date: &NaiveDate,
+  end_date: &NaiveDate,
+  tab: &str,
+  buckets: &TimeBuckets,
+) -> Result<Vec<FrequenceByDate>, SearchError> {
+  let (starts, instants) = buckets.bounds(start_date, end_date);
+  let collector = DateHistogram {
+    published_time_ms: tweet_schema.published_time_ms,
+    value: tab_field(tweet_schema, tab),
+    bounds: instants,
+  };
+  let counts = searcher.search(query, &collector)?;
+
+  Ok(
+    starts
+      .into_iter()
+      .zip(counts)
+      .map(|(date, frequence)| FrequenceByDate { date, frequence })
+      .collect(),
+  )
+}
+
+/// Additionne `value`, ou compte les documents, dans les intervalles délimités par `bounds`
+struct DateHistogram {
+  published_time_ms: Field,
+  value: Option<Field>,
+  bounds: Vec<u64>,
+}
+
+impl Collector for DateHistogram {
+  type Fruit = Vec<u64>;
+  type Child = DateHistogramSegmentCollector;
+
+  fn for_segment(
+    &self,
+    _segment_ord: SegmentOrdinal,
+    segment_reader: &SegmentReader,
+  ) -> tantivy::Result<Self::Child> {
+    Ok(DateHistogramSegmentCollector {
+      published_time_ms: segment_reader.fast_fields().u64(self.published_time_ms)?,
+      value: self
+        .value
+        .map(|value| segment_reader.fast_fields().u64(value))
+        .transpose()?,
+      bounds: self.bounds.clone(),
+      counts: vec![0; self.bounds.len().saturating_sub(1)],
+    })
+  }
+
+  fn requires_scoring(&self) -> bool {
+    false
+  }
+
+  fn merge_fruits(&self, segment_fruits: Vec<Vec<u64>>) -> tantivy::Result<Self::Fruit> {
+    let mut counts = vec![0; self.bounds.len().saturating_sub(1)];
+    for fruit in segment_fruits {
+      for (count, segment_count) in counts.iter_mut().zip(fruit) {
+        *count += segment_count;
+      }
+    }
+
+    Ok(counts)
+  }
+}
+
+struct DateHistogramSegmentCollector {
+  published_time_ms: DynamicFastFieldReader<u64>,
+  value: Option<DynamicFastFieldReader<u64>>,
+  bounds: Vec<u64>,
+  counts: Vec<u64>,
+}
+
+impl SegmentCollector for DateHistogramSegmentCollector {
+  type Fruit = Vec<u64>;
+
+  fn collect(&mut self, doc: DocId, _score: Score) {
+    let published_time_ms = self.published_time_ms.get(doc);
+    let bucket = self
+      .bounds
+      .partition_point(|bound| *bound <= published_time_ms);
+    if bucket == 0 || bucket == self.bounds.len() {
+      return;
+    }
+    self.counts[bucket - 1] += match &self.value {
+      Some(value) => value.get(doc),
+      None => 1,
+    };
+  }
+
+  fn harvest(self) -> Self::Fruit {
+    self.counts
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use chrono::{TimeZone, Utc};
+  use tantivy::query::AllQuery;
+
+  use super::*;
+  use crate::{
+    test_utils::{index_with, tweet},
+    Tweet,
+  };
+
+  #[test]
+  fn test_granularity_and_timezone() {
+    // 11 juin 22 h 30 UTC : le 12 à 0 h 30 à Paris
+    let tweets: Vec<_> = [
+      (1, 1_654_986_600_000, 3),
+      (2, 1_655_035_200_000, 1),
+      (3, 1_656_626_400_000, 5),
+    ]
+    .into_iter()
+    .map(|(id, published_time_ms, retweet_count)| Tweet {
+      retweet_count,
+      ..tweet(id).at(Utc.timestamp_millis(published_time_ms))
+    })
+    .collect();
+    let index = index_with(&tweets);
+    let tweet_schema = TweetSchema::from_index(&index).unwrap();
+    let searcher = index.reader().unwrap().searcher();
+
+    let histogram = |start: (u32, u32), end: (u32, u32), tab: &str, buckets: &TimeBuckets| {
+      frequence_by_date(
+        &searcher,
+        &tweet_schema,
+        &AllQuery,
+        &NaiveDate::from_ymd(2022, start.0, start.1),
+        &NaiveDate::from_ymd(2022, end.0, end.1),
+        tab,
+        buckets,
+      )
+      .unwrap()
+      .into_iter()
+      .filter(|frequence| frequence.frequence > 0)
+      .map(|frequence| (frequence.date.to_string(), frequence.frequence))
+      .collect::<Vec<_>>()
+    };
+
+    let utc = TimeBuckets::default();
+    assert_eq!(
+      histogram((6, 11), (6, 12), "total", &utc),
+      vec![
+        ("2022-06-11 00:00:00".to_string(), 1),
+        ("2022-06-12 00:00:00".to_string(), 1)
+      ]
+    );
+
+    let paris = parse_timezone("Europe/Paris").unwrap();
+    let daily = TimeBuckets::new(Granularity::Day, paris);
+    assert_eq!(
+      histogram((6, 11), (6, 12), "total", &daily),
+      vec![("2022-06-12 00:00:00".to_string(), 2)]
+    );
+    let hourly = TimeBuckets::new(Granularity::Hour, paris);
+    assert_eq!(
+      histogram((6, 12), (6, 12), "retweets", &hourly),
+      vec![
+        ("2022-06-12 00:00:00".to_string(), 3),
+        ("2022-06-12 14:00:00".to_string(), 1)
+      ]
+    );
+    let monthly = TimeBuckets::new(Granularity::Month, paris);
+    assert_eq!(
+      histogram((6, 1), (7, 31), "retweets", &monthly),
+      vec![
+        ("2022-06-01 00:00:00".to_string(), 4),
+        ("2022-07-01 00:00:00".to_string(), 5)
+      ]
+    );
+
+    // tous les intervalles de la période, le premier ramené au lundi
+    let weekly = TimeBuckets::new(Granularity::Week, Tz::UTC);
+    let (starts, _) = weekly.bounds(
+      &NaiveDate::from_ymd(2022, 6, 15),
+      &NaiveDate::from_ymd(2022, 6, 30),
+    );
+    assert_eq!(
+      starts,
+      vec![
+        NaiveDate::from_ymd(2022, 6, 13).and_hms(0, 0, 0),
+        NaiveDate::from_ymd(2022, 6, 20).and_hms(0, 0, 0),
+        NaiveDate::from_ymd(2022, 6, 27).and_hms(0, 0, 0)
+      ]
+    );
+
+    assert_eq!(
+      Granularity::for_period(
+        &NaiveDate::from_ymd(2022, 6, 12),
+        &NaiveDate::from_ymd(2022, 6, 12)
+      ),
+      Granularity::Hour
+    );
+    assert_eq!("semaine".parse::<Granularity>().unwrap(), Granularity::Week);
+    assert!("quinzaine".parse::<Granularity>().is_err());
+  }
+
+  #[test]
+  fn test_local_timestamp() {
+    let timestamp = |month, day, hour| Utc.ymd(2022, month, day).and_hms(hour, 0, 0).timestamp();
+    let paris = parse_timezone("Europe/Paris").unwrap();
+
+    let midnight = NaiveDate::from_ymd(2022, 6, 12).and_hms(0, 0, 0);
+    assert_eq!(local_timestamp(&paris, midnight), timestamp(6, 11, 22));
+    // 2 h 30 n'existe pas le jour du passage à l'heure d'été : 3 h 30
+    let skipped = NaiveDate::from_ymd(2022, 3, 27).and_hms(2, 30, 0);
+    assert_eq!(
+      local_timestamp(&paris, skipped),
+      timestamp(3, 27, 1) + 30 * 60
+    );
+    // 2 h 30 a lieu deux fois le jour du retour à l'heure d'hiver : la première
+    let repeated = NaiveDate::from_ymd(2022, 10, 30).and_hms(2, 30, 0);
+    assert_eq!(
+      local_timestamp(&paris, repeated),
+      timestamp(10, 30, 0) + 30 * 60
+    );
+
+    assert!(parse_timezone("Europe/Atlantide").is_err());
+    assert!(parse_timezone("../etc/passwd").is_err());
+    assert_eq!(local_timestamp(&Tz::UTC, midnight), timestamp(6, 12, 0));
+  }
+}
@@ -0,0 +1,135 @@
+use std::path::Path;
+
+use serde::Serialize;
+use tantivy::{
+  collector::Count,
+  query::{QueryParser, TermQuery},
+  schema::IndexRecordOption,
+  Index, Term,
+};
+
+use crate::{register_tokenizers, writable_schema, SearchError, TweetSchema, TweetStream};
+
+/// Bilan d'une suppression
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct DeleteStats {
+  pub documents_deleted: u64,
+}
+
+/// Supprime de l'index `directory_path` les tweets dont l'identifiant figure dans `ids`
+pub fn delete_tweets<P: AsRef<Path>>(
+  directory_path: P,
+  ids: &[String],
+) -> Result<DeleteStats, SearchError> {
+  let (index, tweet_schema) = open_writable(&directory_path)?;
+  let searcher = index.reader()?.searcher();
+
+  let mut index_writer = index.writer(50_000_000)?;
+  let mut stats = DeleteStats::default();
+  for id in ids {
+    let term = Term::from_field_text(tweet_schema.id, id);
+    stats.documents_deleted += searcher.search(
+      &TermQuery::new(term.clone(), IndexRecordOption::Basic),
+      &Count,
+    )? as u64;
+    index_writer.delete_term(term);
+  }
+  index_writer.commit()?;
+
+  tracing::info!("{} documents supprimés", stats.documents_deleted);
+
+  Ok(stats)
+}
+
+/// Supprime de l'index `directory_path` les tweets correspondant à `query`, dans la syntaxe
+/// de requête de tantivy (`user_screen_name:compte`, `published_time_ms:[0 TO 1000]`…), le
+/// champ `text` étant cherché par défaut
+pub fn delete_tweets_by_query<P: AsRef<Path>>(
+  directory_path: P,
+  query: &str,
+) -> Result<DeleteStats, SearchError> {
+  let (index, tweet_schema) = open_writable(&directory_path)?;
+  let query = QueryParser::for_index(&index, vec![tweet_schema.text]).parse_query(query)?;
+
+  let ids = TweetStream::new(&index, query.as_ref())?
+    .map(|tweet| tweet.map(|tweet| tweet.id))
+    .collect::<Result<Vec<_>, _>>()?;
+
+  delete_tweets(directory_path, &ids)
+}
+
+fn open_writable<P: AsRef<Path>>(directory_path: P) -> Result<(Index, TweetSchema), SearchError> {
+  let index = Index::open_in_dir(&directory_path)?;
+  register_tokenizers(&index);
+  let tweet_schema = writable_schema(&directory_path, &index)?;
+
+  Ok((index, tweet_schema))
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{fs, io::Cursor};
+
+  use super::*;
+  use crate::{all_tweets, create_index_config, ingest, retrieve_index};
+
+  fn line(id: &str, retweet_count: u64, user_screen_name: &str) -> String {
+    format!(
+      r#"{{"id":"{id}","published_time":1655000000000,"user_id":"42","user_name":"Éleveurs","user_screen_name":"{user_screen_name}","text":"Retour du loup","hashtags":["loup"],"urls":[],"retweet_count":{retweet_count}}}"#
+    )
+  }
+
+  #[test]
+  fn test_upsert_and_delete() {
+    let directory = std::env::temp_dir().join(format!("cocktail-delete-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    create_index_config(&directory).unwrap();
+
+    let first = [line("1", 0, "a"), line("2", 0, "a"), line("3", 0, "b")].join("\n");
+    let stats = ingest(&directory, Cursor::new(first)).unwrap();
+    assert_eq!(stats.documents_added, 3);
+
+    // import recouvrant le précédent : un tweet modifié, un identique, un nouveau en double
+    let second = [
+      line("2", 4, "a"),
+      line("3", 0, "b"),
+      line("4", 0, "b"),
+      line("4", 1, "b"),
+    ]
+    .join("\n");
+    let stats = ingest(&directory, Cursor::new(second)).unwrap();
+    // le second « 4 » remplace le premier, pas encore commité, sans être compté deux fois
+    assert_eq!(stats.documents_added, 1);
+    assert_eq!(stats.documents_updated, 2);
+    assert_eq!(stats.documents_unchanged, 1);
+
+    let tweets = |directory: &Path| {
+      let mut tweets = all_tweets(&retrieve_index(directory).unwrap())
+        .unwrap()
+        .map(|tweet| {
+          let tweet = tweet.unwrap();
+          (tweet.id, tweet.retweet_count)
+        })
+        .collect::<Vec<_>>();
+      tweets.sort_unstable();
+      tweets
+    };
+    assert_eq!(
+      tweets(&directory),
+      vec![
+        ("1".to_string(), 0),
+        ("2".to_string(), 4),
+        ("3".to_string(), 0),
+        ("4".to_string(), 1)
+      ]
+    );
+
+    let stats = delete_tweets(&directory, &["1".to_string(), "5".to_string()]).unwrap();
+    assert_eq!(stats.documents_deleted, 1);
+    let stats = delete_tweets_by_query(&directory, "user_screen_name:b").unwrap();
+    assert_eq!(stats.documents_deleted, 2);
+    assert_eq!(tweets(&directory), vec![("2".to_string(), 4)]);
+
+    fs::remove_dir_all(&directory).unwrap();
+  }
+}
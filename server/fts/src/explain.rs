@@ -0,0 +1,349 @@
+use std::ops::Bound;
+
+use chrono::{NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use tantivy::{
+  collector::Count,
+  query::{AllQuery, BooleanQuery, Occur, PhraseQuery, Query, RangeQuery, TermQuery},
+  schema::Schema,
+  u64_to_f64, Index, Searcher, Term,
+};
+
+use crate::{get_query, Bloc, PatternQuery, SearchError, StudyFilters};
+
+/// Rôle d'une clause dans la requête booléenne qui la contient
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClauseOccur {
+  Must,
+  Should,
+  MustNot,
+}
+
+impl From<Occur> for ClauseOccur {
+  fn from(occur: Occur) -> Self {
+    match occur {
+      Occur::Must => ClauseOccur::Must,
+      Occur::Should => ClauseOccur::Should,
+      Occur::MustNot => ClauseOccur::MustNot,
+    }
+  }
+}
+
+/// Nature d'une clause de la requête
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClauseKind {
+  /// combinaison des clauses filles
+  Boolean,
+  Term {
+    field: String,
+    value: String,
+  },
+  Phrase {
+    field: String,
+    terms: Vec<String>,
+  },
+  /// période de publication, bornes en millisecondes
+  DateRange {
+    start: Option<u64>,
+    end: Option<u64>,
+  },
+  /// intervalle d'un champ numérique, les coordonnées par exemple
+  Range {
+    field: String,
+    start: Option<f64>,
+    end: Option<f64>,
+  },
+  /// motif de mot clé, écrit dans le langage de requête
+  Pattern {
+    pattern: String,
+  },
+  All,
+  /// clause non reconnue, décrite par son `Debug`
+  Other {
+    description: String,
+  },
+}
+
+/// Clause de la requête et nombre de tweets de l'index qu'elle sélectionne à elle seule
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryClause {
+  /// absent pour la racine
+  pub occur: Option<ClauseOccur>,
+  pub kind: ClauseKind,
+  pub label: String,
+  pub hits: usize,
+  pub children: Vec<QueryClause>,
+}
+
+/// Arbre de la requête d'une étude, avec le nombre de tweets de chaque clause
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryExplanation {
+  /// nombre de tweets sélectionnés par la requête complète
+  pub total: usize,
+  pub query: QueryClause,
+}
+
+/// Construit la requête d'une étude comme le fait l'analyse, puis compte les tweets
+/// sélectionnés par chacune de ses clauses, sans rien récupérer
+pub fn explain_query(
+  index: &Index,
+  start_date: &NaiveDate,
+  end_date: &NaiveDate,
+  hashtag_list: &Vec<String>,
+  exclude_hashtag_list: &Vec<String>,
+  request_params: &Vec<Vec<Bloc>>,
+  filters: &StudyFilters,
+) -> Result<QueryExplanation, SearchError> {
+  let searcher = index.reader()?.searcher();
+  let schema = index.schema();
+
+  let query = get_query(
+    index,
+    start_date,
+    end_date,
+    hashtag_list,
+    exclude_hashtag_list,
+    request_params,
+    filters,
+  )?;
+
+  let query = explain_clause(&searcher, &schema, None, &query)?;
+
+  Ok(QueryExplanation {
+    total: query.hits,
+    query,
+  })
+}
+
+fn explain_clause(
+  searcher: &Searcher,
+  schema: &Schema,
+  occur: Option<ClauseOccur>,
+  query: &dyn Query,
+) -> Result<QueryClause, SearchError> {
+  // la période, issue du QueryParser, est une Box<dyn Query> emboîtée
+  if let Some(inner) = query.downcast_ref::<Box<dyn Query>>() {
+    return explain_clause(searcher, schema, occur, inner.as_ref());
+  }
+
+  let hits = searcher.search(query, &Count)?;
+
+  let mut children = vec![];
+  let kind = if let Some(boolean) = query.downcast_ref::<BooleanQuery>() {
+    for (occur, clause) in boolean.clauses() {
+      children.push(explain_clause(
+        searcher,
+        schema,
+        Some((*occur).into()),
+        clause.as_ref(),
+      )?);
+    }
+    ClauseKind::Boolean
+  } else if let Some(term) = query.downcast_ref::<TermQuery>() {
+    ClauseKind::Term {
+      field: schema.get_field_name(term.term().field()).to_string(),
+      value: term_text(term.term()),
+    }
+  } else if let Some(phrase) = query.downcast_ref::<PhraseQuery>() {
+    ClauseKind::Phrase {
+      field: schema.get_field_name(phrase.field()).to_string(),
+      terms: phrase.phrase_terms().iter().map(term_text).collect(),
+    }
+  } else if let Some(range) = query.downcast_ref::<RangeQuery>() {
+    match schema.get_field_name(range.field()) {
+      "longitude" | "latitude" => ClauseKind::Range {
+        field: schema.get_field_name(range.field()).to_string(),
+        start: bound_value(range.left_bound()).map(u64_to_f64),
+        end: bound_value(range.right_bound()).map(u64_to_f64),
+      },
+      _ => ClauseKind::DateRange {
+        start: bound_value(range.left_bound()),
+        end: bound_value(range.right_bound()),
+      },
+    }
+  } else if let Some(pattern) = query.downcast_ref::<PatternQuery>() {
+    ClauseKind::Pattern {
+      pattern: pattern.pattern().to_string(),
+    }
+  } else if query.downcast_ref::<AllQuery>().is_some() {
+    ClauseKind::All
+  } else {
+    ClauseKind::Other {
+      description: format!("{query:?}"),
+    }
+  };
+
+  Ok(QueryClause {
+    occur,
+    label: label(&kind, &children),
+    kind,
+    hits,
+    children,
+  })
+}
+
+fn term_text(term: &Term) -> String {
+  term
+    .as_str()
+    .map(str::to_string)
+    .or_else(|| term.as_u64().map(|value| value.to_string()))
+    .unwrap_or_default()
+}
+
+fn bound_value(bound: Bound<Term>) -> Option<u64> {
+  match bound {
+    // les bornes sont restituées comme des octets bruts : u64 big-endian
+    Bound::Included(term) | Bound::Excluded(term) => {
+      term.value_bytes().try_into().ok().map(u64::from_be_bytes)
+    }
+    Bound::Unbounded => None,
+  }
+}
+
+/// Libellé dans les termes du requêteur : ET / OU, #hashtag, @compte, "expression"…
+fn label(kind: &ClauseKind, children: &[QueryClause]) -> String {
+  match kind {
+    ClauseKind::Boolean => {
+      if children
+        .iter()
+        .all(|c| c.occur == Some(ClauseOccur::MustNot))
+      {
+        "SAUF".to_string()
+      } else if children
+        .iter()
+        .all(|c| c.occur == Some(ClauseOccur::Should))
+      {
+        "OU".to_string()
+      } else {
+        "ET".to_string()
+      }
+    }
+    ClauseKind::Term { field, value } => match field.as_str() {
+      "hashtags" => format!("#{value}"),
+      "user_screen_name" => format!("@{value}"),
+      "text" => value.clone(),
+      "text_exact" => format!("={value}"),
+      "language" => format!("langue {value}"),
+      "possibly_sensitive" => "contenu sensible".to_string(),
+      _ => format!("{field}:{value}"),
+    },
+    ClauseKind::Phrase { field, terms } if field == "text_exact" => {
+      format!("=\"{}\"", terms.join(" "))
+    }
+    ClauseKind::Phrase { terms, .. } => format!("\"{}\"", terms.join(" ")),
+    ClauseKind::DateRange { start, end } => format!(
+      "publiés du {} au {}",
+      format_date(*start),
+      format_date(*end)
+    ),
+    ClauseKind::Range { field, start, end } => format!(
+      "{field} de {} à {}",
+      format_bound(*start),
+      format_bound(*end)
+    ),
+    ClauseKind::Pattern { pattern } => pattern.clone(),
+    ClauseKind::All => "tous les tweets".to_string(),
+    ClauseKind::Other { description } => description.clone(),
+  }
+}
+
+fn format_date(timestamp_ms: Option<u64>) -> String {
+  timestamp_ms
+    .and_then(|ms| NaiveDateTime::from_timestamp_opt((ms / 1000) as i64, 0))
+    .map(|date| date.format("%d/%m/%Y").to_string())
+    .unwrap_or_else(|| "…".to_string())
+}
+
+fn format_bound(value: Option<f64>) -> String {
+  value
+    .map(|value| value.to_string())
+    .unwrap_or_else(|| "…".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+  use tantivy::doc;
+
+  use super::*;
+  use crate::{register_tokenizers, TweetSchema};
+
+  #[test]
+  fn test_explain_query_counts_each_clause() {
+    let index = Index::create_in_ram(TweetSchema::build(&Default::default()));
+    register_tokenizers(&index);
+    let TweetSchema {
+      text,
+      user_screen_name,
+      hashtags,
+      published_time_ms,
+      ..
+    } = TweetSchema::from_index(&index).unwrap();
+
+    let day = |d: u32| {
+      NaiveDate::from_ymd(2022, 6, d)
+        .and_hms(12, 0, 0)
+        .timestamp_millis() as u64
+    };
+    let mut writer = index.writer(15_000_000).unwrap();
+    for (content, tags, author, date) in [
+      ("le loup revient", vec!["loup"], "alice", day(1)),
+      (
+        "le loup et la brebis",
+        vec!["loup", "pastoralisme"],
+        "bob",
+        day(2),
+      ),
+      ("la brebis", vec!["pastoralisme"], "alice", day(3)),
+      ("hors période", vec!["loup"], "bob", day(20)),
+    ] {
+      let mut document = doc!(
+        text => content,
+        user_screen_name => author,
+        published_time_ms => date,
+      );
+      tags.iter().for_each(|h| document.add_text(hashtags, h));
+      writer.add_document(document).unwrap();
+    }
+    writer.commit().unwrap();
+
+    let request_params = vec![
+      vec![Bloc {
+        data: vec!["#loup".to_string()],
+        link: "".to_string(),
+      }],
+      vec![Bloc {
+        data: vec!["@bob".to_string()],
+        link: "ET".to_string(),
+      }],
+    ];
+    let explanation = explain_query(
+      &index,
+      &NaiveDate::from_ymd(2022, 6, 1),
+      &NaiveDate::from_ymd(2022, 6, 10),
+      &vec![],
+      &vec![],
+      &request_params,
+      &StudyFilters::default(),
+    )
+    .unwrap();
+
+    assert_eq!(explanation.total, 1);
+
+    let clauses: Vec<_> = explanation
+      .query
+      .children
+      .iter()
+      .map(|c| (c.occur.unwrap(), c.label.as_str(), c.hits))
+      .collect();
+    assert_eq!(
+      clauses,
+      vec![
+        (ClauseOccur::Must, "publiés du 01/06/2022 au 10/06/2022", 3),
+        (ClauseOccur::MustNot, "OU", 2),
+        (ClauseOccur::Must, "OU", 3),
+      ]
+    );
+  }
+}
@@ -0,0 +1,281 @@
+//! Filtres d'une étude sur la langue, la position et le caractère sensible des tweets.
+//!
+//! La langue est indexée dans la facette `language` (`/fr`, `/en`…), les coordonnées dans
+//! les champs `longitude` et `latitude`, absents des tweets non géolocalisés : une zone
+//! écarte donc ces derniers.
+
+use std::ops::Bound;
+
+use serde::{Deserialize, Serialize};
+use tantivy::{
+  collector::FacetCollector,
+  query::{AllQuery, BooleanQuery, Occur, Query, RangeQuery, TermQuery},
+  schema::{Facet, IndexRecordOption},
+  Index, Term,
+};
+
+use crate::{SearchError, TweetSchema};
+
+/// Filtres appliqués aux tweets d'une étude en plus de sa requête
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StudyFilters {
+  /// codes des langues retenues (`fr`, `en`…), toutes si vide
+  pub languages: Vec<String>,
+  /// zone dans laquelle les tweets doivent avoir été publiés
+  pub bounding_box: Option<BoundingBox>,
+  /// écarte les tweets signalés comme sensibles
+  pub exclude_sensitive: bool,
+}
+
+/// Zone géographique, en degrés. Une zone dont l'ouest est à l'est de l'est traverse
+/// l'antiméridien.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BoundingBox {
+  pub west: f64,
+  pub south: f64,
+  pub east: f64,
+  pub north: f64,
+}
+
+/// Nombre de tweets d'une langue
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LanguageCount {
+  pub language: String,
+  pub count: u64,
+}
+
+impl BoundingBox {
+  pub fn new(west: f64, south: f64, east: f64, north: f64) -> Result<Self, SearchError> {
+    let longitudes = -180.0..=180.0;
+    let latitudes = -90.0..=90.0;
+    if !longitudes.contains(&west) || !longitudes.contains(&east) {
+      return Err(SearchError::InvalidBoundingBox(
+        "longitudes hors de [-180, 180]".to_string(),
+      ));
+    }
+    if !latitudes.contains(&south) || !latitudes.contains(&north) {
+      return Err(SearchError::InvalidBoundingBox(
+        "latitudes hors de [-90, 90]".to_string(),
+      ));
+    }
+    if south > north {
+      return Err(SearchError::InvalidBoundingBox(
+        "le sud est au nord du nord".to_string(),
+      ));
+    }
+
+    Ok(BoundingBox {
+      west,
+      south,
+      east,
+      north,
+    })
+  }
+
+  pub fn contains(&self, longitude: f64, latitude: f64) -> bool {
+    let in_longitude = if self.west <= self.east {
+      self.west <= longitude && longitude <= self.east
+    } else {
+      self.west <= longitude || longitude <= self.east
+    };
+
+    in_longitude && self.south <= latitude && latitude <= self.north
+  }
+
+  fn query(&self, tweet_schema: &TweetSchema) -> BooleanQuery {
+    let range = |field, start: f64, end: f64| -> Box<dyn Query> {
+      Box::new(RangeQuery::new_f64_bounds(
+        field,
+        Bound::Included(start),
+        Bound::Included(end),
+      ))
+    };
+    let longitude: Box<dyn Query> = if self.west <= self.east {
+      range(tweet_schema.longitude, self.west, self.east)
+    } else {
+      Box::new(BooleanQuery::new(vec![
+        (
+          Occur::Should,
+          range(tweet_schema.longitude, self.west, 180.0),
+        ),
+        (
+          Occur::Should,
+          range(tweet_schema.longitude, -180.0, self.east),
+        ),
+      ]))
+    };
+
+    BooleanQuery::new(vec![
+      (Occur::Must, longitude),
+      (
+        Occur::Must,
+        range(tweet_schema.latitude, self.south, self.north),
+      ),
+    ])
+  }
+}
+
+impl StudyFilters {
+  /// Clauses à ajouter à la requête de l'étude
+  pub(crate) fn clauses(&self, tweet_schema: &TweetSchema) -> Vec<(Occur, Box<dyn Query>)> {
+    let mut clauses: Vec<(Occur, Box<dyn Query>)> = vec![];
+
+    if !self.languages.is_empty() {
+      let languages = self
+        .languages
+        .iter()
+        .map(|language| {
+          let term_query: Box<dyn Query> = Box::new(TermQuery::new(
+            Term::from_facet(tweet_schema.language, &language_facet(language)),
+            IndexRecordOption::Basic,
+          ));
+          (Occur::Should, term_query)
+        })
+        .collect();
+      clauses.push((Occur::Must, Box::new(BooleanQuery::new(languages))));
+    }
+
+    if let Some(bounding_box) = &self.bounding_box {
+      clauses.push((Occur::Must, Box::new(bounding_box.query(tweet_schema))));
+    }
+
+    if self.exclude_sensitive {
+      clauses.push((
+        Occur::MustNot,
+        Box::new(TermQuery::new(
+          Term::from_field_u64(tweet_schema.possibly_sensitive, 1),
+          IndexRecordOption::Basic,
+        )),
+      ));
+    }
+
+    clauses
+  }
+}
+
+/// Code de langue tel qu'il est indexé, `None` s'il est vide
+pub fn normalize_language(language: &str) -> Option<String> {
+  let language = language.trim().to_lowercase();
+
+  match language.is_empty() {
+    true => None,
+    false => Some(language),
+  }
+}
+
+pub(crate) fn language_facet(language: &str) -> Facet {
+  Facet::from_path([language.trim().to_lowercase()])
+}
+
+/// Nombre de tweets de `index` par langue, par nombre décroissant
+pub fn language_distribution(index: &Index) -> Result<Vec<LanguageCount>, SearchError> {
+  let searcher = index.reader()?.searcher();
+  let tweet_schema = TweetSchema::from_index(index)?;
+
+  let mut collector = FacetCollector::for_field(tweet_schema.language);
+  collector.add_facet(Facet::root());
+  let facet_counts = searcher.search(&AllQuery, &collector)?;
+  let mut languages: Vec<LanguageCount> = facet_counts
+    .get(Facet::root())
+    .map(|(facet, count)| LanguageCount {
+      language: facet.to_path().concat(),
+      count,
+    })
+    .collect();
+  languages.sort_by(|a, b| {
+    b.count
+      .cmp(&a.count)
+      .then_with(|| a.language.cmp(&b.language))
+  });
+
+  Ok(languages)
+}
+
+#[cfg(test)]
+mod tests {
+  use tantivy::collector::Count;
+
+  use super::*;
+  use crate::{
+    test_utils::{index_with, tweet},
+    Tweet, TweetMetadata,
+  };
+
+  #[test]
+  fn test_study_filters() {
+    assert!(BoundingBox::new(-5.0, 50.0, 10.0, 41.0).is_err());
+    assert!(BoundingBox::new(-200.0, 41.0, 10.0, 51.0).is_err());
+    let pacific = BoundingBox::new(170.0, -50.0, -170.0, 0.0).unwrap();
+    assert!(pacific.contains(179.0, -10.0));
+    assert!(pacific.contains(-175.0, -10.0));
+    assert!(!pacific.contains(0.0, -10.0));
+
+    // Lyon, Marseille, Montréal, sans position, Paris
+    let tweets: Vec<_> = [
+      (1, "fr", Some((4.83, 45.76)), false),
+      (2, "FR", Some((5.37, 43.30)), true),
+      (3, "fr", Some((-73.57, 45.50)), false),
+      (4, "en", None, false),
+      (5, "", Some((2.35, 48.86)), false),
+    ]
+    .into_iter()
+    .map(|(id, language, coordinates, possibly_sensitive)| Tweet {
+      metadata: TweetMetadata {
+        language: normalize_language(language),
+        longitude: coordinates.map(|(longitude, _)| longitude),
+        latitude: coordinates.map(|(_, latitude)| latitude),
+        possibly_sensitive,
+      },
+      ..tweet(id)
+    })
+    .collect();
+    let index = index_with(&tweets);
+    let tweet_schema = TweetSchema::from_index(&index).unwrap();
+
+    let searcher = index.reader().unwrap().searcher();
+    let count = |filters: StudyFilters| {
+      let mut clauses = filters.clauses(&tweet_schema);
+      clauses.push((Occur::Must, Box::new(AllQuery)));
+      searcher
+        .search(&BooleanQuery::new(clauses), &Count)
+        .unwrap()
+    };
+    assert_eq!(count(StudyFilters::default()), 5);
+    let french = StudyFilters {
+      languages: vec!["fr".to_string()],
+      ..Default::default()
+    };
+    assert_eq!(count(french.clone()), 3);
+    let france = BoundingBox::new(-5.0, 41.0, 10.0, 51.0).unwrap();
+    assert_eq!(
+      count(StudyFilters {
+        bounding_box: Some(france),
+        ..Default::default()
+      }),
+      3
+    );
+    assert_eq!(
+      count(StudyFilters {
+        bounding_box: Some(france),
+        exclude_sensitive: true,
+        ..french
+      }),
+      1
+    );
+
+    assert_eq!(
+      language_distribution(&index).unwrap(),
+      vec![
+        LanguageCount {
+          language: "fr".to_string(),
+          count: 3
+        },
+        LanguageCount {
+          language: "en".to_string(),
+          count: 1
+        }
+      ]
+    );
+  }
+}
@@ -0,0 +1,174 @@
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+use tantivy::{
+  query::{BooleanQuery, Occur, Query},
+  Index, Searcher, SnippetGenerator,
+};
+
+use crate::{
+  keyword_match_mode, keyword_query, Bloc, KeywordPattern, MatchMode, SearchError, Tweet,
+  TweetSchema,
+};
+
+/// Longueur maximale d'un extrait, en caractères : un tweet tient en entier
+pub const SNIPPET_MAX_CHARS: usize = 280;
+
+/// Extrait du texte d'un tweet et positions (en octets) des termes de la requête
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TweetHighlight {
+  pub fragment: String,
+  pub highlighted: Vec<Range<usize>>,
+}
+
+impl TweetHighlight {
+  /// Extrait échappé, termes de la requête entourés de `<mark>`
+  pub fn to_html(&self) -> String {
+    let mut html = String::new();
+    let mut start = 0;
+    for range in &self.highlighted {
+      html.push_str(&escape_html(&self.fragment[start..range.start]));
+      html.push_str("<mark>");
+      html.push_str(&escape_html(&self.fragment[range.clone()]));
+      html.push_str("</mark>");
+      start = range.end;
+    }
+    html.push_str(&escape_html(&self.fragment[start..]));
+    html
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.highlighted.is_empty()
+  }
+}
+
+/// Tweet accompagné de l'extrait expliquant sa sélection
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HighlightedTweet {
+  #[serde(flatten)]
+  pub tweet: Tweet,
+  pub highlight: TweetHighlight,
+}
+
+/// Requête regroupant les termes à surligner : mots clés, expressions, hashtags et comptes
+/// de la sélection de l'étude, l'exclusion étant ignorée. Tous sont cherchés dans le champ
+/// `text`, racinisé, seul champ stocké dont l'extrait peut être tiré.
+pub fn highlight_query(
+  index: &Index,
+  tweet_schema: &TweetSchema,
+  hashtag_list: &[String],
+  request_params: &[Vec<Bloc>],
+) -> BooleanQuery {
+  let items = request_params
+    .first()
+    .into_iter()
+    .flatten()
+    .flat_map(|bloc| bloc.data.iter());
+
+  let clauses = hashtag_list
+    .iter()
+    .map(|hashtag| hashtag.as_str())
+    .chain(items.filter_map(|item| match KeywordPattern::from_item(item) {
+      // les jokers et expressions régulières ne désignent pas de mots à retrouver
+      Some(KeywordPattern::Wildcard(_) | KeywordPattern::Regex(_)) => None,
+      Some(_) => item.split_once(':').map(|(_, words)| words),
+      None => Some(
+        item
+          .strip_prefix(['#', '@'])
+          .unwrap_or_else(|| keyword_match_mode(item).1),
+      ),
+    }))
+    .map(|value| {
+      (
+        Occur::Should,
+        keyword_query(index, tweet_schema, value, MatchMode::Stemmed),
+      )
+    })
+    .collect();
+
+  BooleanQuery::new(clauses)
+}
+
+/// Associe à chaque tweet l'extrait de son texte contenant les termes de `query`
+pub fn highlight_tweets(
+  searcher: &Searcher,
+  query: &dyn Query,
+  tweets: Vec<Tweet>,
+) -> Result<Vec<HighlightedTweet>, SearchError> {
+  let text = TweetSchema::new(searcher.schema())?.text;
+  let mut generator = SnippetGenerator::create(searcher, query, text)?;
+  generator.set_max_num_chars(SNIPPET_MAX_CHARS);
+
+  Ok(
+    tweets
+      .into_iter()
+      .map(|tweet| {
+        let snippet = generator.snippet(&tweet.text);
+        let highlight = if snippet.highlighted().is_empty() {
+          TweetHighlight {
+            fragment: tweet.text.clone(),
+            highlighted: vec![],
+          }
+        } else {
+          TweetHighlight {
+            fragment: snippet.fragment().to_string(),
+            highlighted: snippet.highlighted().to_vec(),
+          }
+        };
+        HighlightedTweet { tweet, highlight }
+      })
+      .collect(),
+  )
+}
+
+fn escape_html(text: &str) -> String {
+  let mut escaped = String::with_capacity(text.len());
+  for c in text.chars() {
+    match c {
+      '&' => escaped.push_str("&amp;"),
+      '<' => escaped.push_str("&lt;"),
+      '>' => escaped.push_str("&gt;"),
+      '"' => escaped.push_str("&quot;"),
+      '\'' => escaped.push_str("&#x27;"),
+      c => escaped.push(c),
+    }
+  }
+  escaped
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_utils::{index_with, tweet};
+
+  #[test]
+  fn test_highlight_study_terms() {
+    let content = "Les <loups> reviennent dans les Alpes #pastoralisme avec @eleveurs_fr";
+    let tweet = Tweet {
+      text: content.to_string(),
+      ..tweet(1)
+    }
+    .with_hashtags(&["pastoralisme"]);
+    let index = index_with(std::slice::from_ref(&tweet));
+
+    let request_params = vec![
+      vec![Bloc {
+        data: vec!["=loup".to_string(), "#pastoralisme".to_string()],
+        link: "".to_string(),
+      }],
+      vec![Bloc {
+        data: vec!["alpes".to_string()],
+        link: "ET".to_string(),
+      }],
+    ];
+    let tweet_schema = TweetSchema::from_index(&index).unwrap();
+    let query = highlight_query(&index, &tweet_schema, &[], &request_params);
+    let searcher = index.reader().unwrap().searcher();
+    let highlighted = highlight_tweets(&searcher, &query, vec![tweet]).unwrap();
+
+    assert_eq!(
+      highlighted[0].highlight.to_html(),
+      "Les &lt;<mark>loups</mark>&gt; reviennent dans les Alpes #<mark>pastoralisme</mark> avec @eleveurs_fr"
+    );
+  }
+}
@@ -1,64 +1,175 @@
 use std::{
-  io::{stdin, BufRead, BufReader},
+  collections::HashMap,
+  fs::File,
+  io::{BufRead, BufReader, Read},
   path::Path,
 };
 
-use serde::Deserialize;
-use tantivy::{doc, Index, TantivyError};
-
-pub fn ingest<P: AsRef<Path>>(directory_path: P) -> Result<(), TantivyError> {
-  let index = Index::open_in_dir(directory_path)?;
-  let schema = index.schema();
-  let id = schema.get_field("id").unwrap();
-  let published_time = schema.get_field("published_time").unwrap();
-  let published_time_ms = schema.get_field("published_time_ms").unwrap();
-  let user_id = schema.get_field("user_id").unwrap();
-  let user_name = schema.get_field("user_name").unwrap();
-  let user_screen_name = schema.get_field("user_screen_name").unwrap();
-  let text = schema.get_field("text").unwrap();
-  let urls = schema.get_field("urls").unwrap();
-  let hashtags = schema.get_field("hashtags").unwrap();
-  let retweet_count = schema.get_field("retweet_count").unwrap();
-  let reply_count = schema.get_field("reply_count").unwrap();
-  let quote_count = schema.get_field("quote_count").unwrap();
-
-  let rdr = BufReader::new(stdin());
+use chrono::{TimeZone, Utc};
+use flate2::read::MultiGzDecoder;
+use serde::{Deserialize, Serialize};
+use tantivy::{
+  collector::TopDocs, query::TermQuery, schema::IndexRecordOption, Index, ReloadPolicy, Searcher,
+  TantivyError, Term,
+};
+
+use crate::{register_tokenizers, writable_schema, TweetSchema};
+
+/// Nombre de documents entre deux commits de l'index
+const COMMIT_EVERY: u64 = 100_000;
+
+/// Bilan d'une ingestion
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct IngestStats {
+  /// tweets absents de l'index
+  pub documents_added: u64,
+  /// tweets déjà indexés dont les compteurs ou les relations ont changé, remplacés
+  pub documents_updated: u64,
+  /// tweets déjà indexés à l'identique, ignorés
+  pub documents_unchanged: u64,
+  pub rejected: Vec<RejectedLine>,
+  pub commits: u64,
+}
+
+/// Ligne NDJSON ignorée car elle ne décrit pas un tweet valide
+#[derive(Debug, Clone, Serialize)]
+pub struct RejectedLine {
+  /// numéro de la ligne, à partir de 1
+  pub line: u64,
+  pub error: String,
+}
+
+/// Ingère dans l'index `directory_path` les tweets au format NDJSON lus depuis `reader`
+/// (entrée standard, fichier décompressé…). Les lignes invalides sont ignorées et listées
+/// dans le bilan, seule une erreur de lecture ou d'écriture de l'index interrompt l'ingestion.
+///
+/// Les tweets sont identifiés par leur `id` : un tweet déjà indexé est remplacé si ses
+/// nombres de retweets, réponses ou citations ont changé, ignoré sinon. Ingérer deux fois
+/// le même fichier ne duplique donc aucun document.
+pub fn ingest<P: AsRef<Path>, R: Read>(
+  directory_path: P,
+  reader: R,
+) -> Result<IngestStats, TantivyError> {
+  ingest_with_commit_every(directory_path, reader, COMMIT_EVERY)
+}
+
+/// [`ingest`] avec un commit tous les `commit_every` documents écrits
+fn ingest_with_commit_every<P: AsRef<Path>, R: Read>(
+  directory_path: P,
+  reader: R,
+  commit_every: u64,
+) -> Result<IngestStats, TantivyError> {
+  let index = Index::open_in_dir(&directory_path)?;
+  register_tokenizers(&index);
+  let tweet_schema = writable_schema(&directory_path, &index)
+    .map_err(|e| TantivyError::SchemaError(e.to_string()))?;
+
+  // l'index tel qu'avant l'ingestion, rechargé à chaque commit
+  let index_reader = index
+    .reader_builder()
+    .reload_policy(ReloadPolicy::Manual)
+    .try_into()?;
+  let rdr = BufReader::new(reader);
   let mut index_writer = index.writer(125_000_000)?;
-  let mut cpt = 0u64;
-  for json_line in rdr.lines() {
-    let tweet: Tweet = serde_json::from_str(&json_line?)?;
-    let mut document = doc!(
-        id => tweet.id,
-        user_id => tweet.user_id,
-        user_name => tweet.user_name,
-        user_screen_name => tweet.user_screen_name,
-        text => tweet.text,
-        published_time => tantivy::DateTime::from_unix_timestamp(tweet.published_time / 1_000),
-        published_time_ms => tweet.published_time as u64,
-        retweet_count => tweet.retweet_count,
-        reply_count => tweet.reply_count,
-        quote_count => tweet.quote_count
-    );
-    tweet
-      .hashtags
-      .unwrap_or_default()
-      .iter()
-      .for_each(|f| document.add_text(hashtags, f));
-
-    tweet
-      .urls
-      .unwrap_or_default()
-      .iter()
-      .for_each(|f| document.add_text(urls, f));
-    let _ = index_writer.add_document(document);
-    cpt += 1;
-    if cpt % 100_000 == 0 {
+  let mut stats = IngestStats::default();
+  // tweets écrits depuis le dernier commit, que le lecteur ne voit pas encore
+  let mut pending: HashMap<String, crate::Tweet> = HashMap::new();
+  for (line, json_line) in (1u64..).zip(rdr.lines()) {
+    let json_line = json_line?;
+    if json_line.trim().is_empty() {
+      continue;
+    }
+
+    let tweet: Tweet = match serde_json::from_str(&json_line) {
+      Ok(tweet) => tweet,
+      Err(e) => {
+        tracing::warn!("ligne {line} ignorée : {e}");
+        stats.rejected.push(RejectedLine {
+          line,
+          error: e.to_string(),
+        });
+        continue;
+      }
+    };
+
+    let tweet: crate::Tweet = tweet.into();
+    let indexed = match pending.get(&tweet.id) {
+      Some(written) => Some(written.clone()),
+      None => indexed_tweet(&index_reader.searcher(), &tweet_schema, &tweet.id)?,
+    };
+    match indexed {
+      Some(indexed) if is_unchanged(&indexed, &tweet) => {
+        stats.documents_unchanged += 1;
+        continue;
+      }
+      Some(_) => stats.documents_updated += 1,
+      None => stats.documents_added += 1,
+    }
+
+    // supprime aussi un doublon ajouté plus haut dans le même lot, pas encore visible
+    index_writer.delete_term(Term::from_field_text(tweet_schema.id, &tweet.id));
+    index_writer.add_document(tweet_schema.to_document(&tweet))?;
+    pending.insert(tweet.id.clone(), tweet);
+    if (stats.documents_added + stats.documents_updated).is_multiple_of(commit_every) {
       index_writer.commit()?;
+      index_reader.reload()?;
+      pending.clear();
+      stats.commits += 1;
     }
   }
   index_writer.commit()?;
+  stats.commits += 1;
+
+  tracing::info!(
+    "{} documents ajoutés, {} mis à jour, {} inchangés, {} lignes rejetées, {} commits",
+    stats.documents_added,
+    stats.documents_updated,
+    stats.documents_unchanged,
+    stats.rejected.len(),
+    stats.commits
+  );
 
-  Ok(())
+  Ok(stats)
+}
+
+/// Tweet indexé sous l'identifiant `id`, s'il existe
+pub fn indexed_tweet(
+  searcher: &Searcher,
+  tweet_schema: &TweetSchema,
+  id: &str,
+) -> Result<Option<crate::Tweet>, TantivyError> {
+  let query = TermQuery::new(
+    Term::from_field_text(tweet_schema.id, id),
+    IndexRecordOption::Basic,
+  );
+  match searcher.search(&query, &TopDocs::with_limit(1))?.first() {
+    Some((_score, doc_address)) => Ok(tweet_schema.to_tweet(&searcher.doc(*doc_address)?).ok()),
+    None => Ok(None),
+  }
+}
+
+/// Compteurs, relations et métadonnées identiques : celles d'un index migré sont complétées
+/// par une nouvelle ingestion
+fn is_unchanged(indexed: &crate::Tweet, tweet: &crate::Tweet) -> bool {
+  indexed.retweet_count == tweet.retweet_count
+    && indexed.reply_count == tweet.reply_count
+    && indexed.quote_count == tweet.quote_count
+    && indexed.relations == tweet.relations
+    && indexed.metadata == tweet.metadata
+}
+
+/// Ingère le fichier NDJSON `file`, décompressé à la volée si son extension est `.gz`
+pub fn ingest_file<P: AsRef<Path>, F: AsRef<Path>>(
+  directory_path: P,
+  file: F,
+) -> Result<IngestStats, TantivyError> {
+  let file = file.as_ref();
+  let reader = File::open(file)?;
+
+  match file.extension() {
+    Some(extension) if extension == "gz" => ingest(directory_path, MultiGzDecoder::new(reader)),
+    _ => ingest(directory_path, reader),
+  }
 }
 
 #[allow(dead_code)]
@@ -74,13 +185,10 @@ struct Tweet {
   text: String,
   #[serde(skip)]
   source: Option<String>,
-  #[serde(skip)]
+  #[serde(default)]
   language: String,
-  #[serde(skip)]
   coordinates_longitude: Option<String>,
-  #[serde(skip)]
   coordinates_latitude: Option<String>,
-  #[serde(skip)]
   possibly_sensitive: Option<bool>,
   hashtags: Option<Vec<String>>,
   urls: Option<Vec<String>>,
@@ -89,4 +197,203 @@ struct Tweet {
   reply_count: u64,
   #[serde(default)]
   quote_count: u64,
+  mentions: Option<Vec<String>>,
+  retweeted_id: Option<String>,
+  retweeted_user_id: Option<String>,
+  in_reply_to_id: Option<String>,
+  in_reply_to_user_id: Option<String>,
+  quoted_id: Option<String>,
+  quoted_user_id: Option<String>,
+}
+
+impl From<Tweet> for crate::Tweet {
+  fn from(tweet: Tweet) -> Self {
+    // les exports sans table des retweets n'ont que le préfixe du texte
+    let is_retweet = tweet.retweeted_id.is_some() || is_retweet_text(&tweet.text);
+    crate::Tweet {
+      id: tweet.id,
+      user_id: tweet.user_id,
+      user_name: tweet.user_name,
+      user_screen_name: tweet.user_screen_name,
+      text: tweet.text,
+      published_time: Utc.timestamp_millis(tweet.published_time),
+      published_time_ms: tweet.published_time as u64,
+      retweet_count: tweet.retweet_count,
+      reply_count: tweet.reply_count,
+      quote_count: tweet.quote_count,
+      hashtags: tweet.hashtags.unwrap_or_default(),
+      urls: tweet.urls.unwrap_or_default(),
+      relations: crate::TweetRelations {
+        mentions: tweet.mentions.unwrap_or_default(),
+        is_retweet,
+        retweeted_id: tweet.retweeted_id,
+        retweeted_user_id: tweet.retweeted_user_id,
+        in_reply_to_id: tweet.in_reply_to_id,
+        in_reply_to_user_id: tweet.in_reply_to_user_id,
+        quoted_id: tweet.quoted_id,
+        quoted_user_id: tweet.quoted_user_id,
+      },
+      metadata: crate::TweetMetadata {
+        language: crate::normalize_language(&tweet.language),
+        longitude: coordinate(&tweet.coordinates_longitude),
+        latitude: coordinate(&tweet.coordinates_latitude),
+        possibly_sensitive: tweet.possibly_sensitive.unwrap_or_default(),
+      },
+    }
+  }
+}
+
+/// Coordonnée exportée en texte, `None` si elle est absente ou illisible
+fn coordinate(value: &Option<String>) -> Option<f64> {
+  value
+    .as_deref()
+    .and_then(|value| value.trim().parse::<f64>().ok())
+    .filter(|value| value.is_finite())
+}
+
+/// Texte d'un retweet, pour les tweets exportés sans leur tweet d'origine
+pub(crate) fn is_retweet_text(text: &str) -> bool {
+  text.starts_with("RT @")
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{fs, io::Cursor, path::PathBuf};
+
+  use super::*;
+  use crate::{all_tweets, create_index_config, retrieve_index};
+
+  fn line(id: &str, retweet_count: u64, reply_count: u64, text: &str) -> String {
+    format!(
+      r#"{{"id":"{id}","published_time":1655000000000,"user_id":"42","user_name":"Éleveurs","user_screen_name":"eleveurs","text":"{text}","retweet_count":{retweet_count},"reply_count":{reply_count}}}"#
+    )
+  }
+
+  fn test_index(name: &str) -> PathBuf {
+    let directory =
+      std::env::temp_dir().join(format!("cocktail-ingest-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    create_index_config(&directory).unwrap();
+    directory
+  }
+
+  /// (id, retweets, réponses, texte) des tweets indexés, triés par identifiant
+  fn indexed(directory: &Path) -> Vec<(String, u64, u64, String)> {
+    let mut tweets = all_tweets(&retrieve_index(directory).unwrap())
+      .unwrap()
+      .map(|tweet| {
+        let tweet = tweet.unwrap();
+        (tweet.id, tweet.retweet_count, tweet.reply_count, tweet.text)
+      })
+      .collect::<Vec<_>>();
+    tweets.sort_unstable();
+    tweets
+  }
+
+  fn tweet(
+    id: &str,
+    retweet_count: u64,
+    reply_count: u64,
+    text: &str,
+  ) -> (String, u64, u64, String) {
+    (id.to_string(), retweet_count, reply_count, text.to_string())
+  }
+
+  #[test]
+  fn test_reingest_overlapping_file() {
+    let directory = test_index("overlap");
+    let first = [line("1", 0, 0, "loup"), line("2", 3, 1, "brebis")].join("\n");
+    ingest(&directory, Cursor::new(&first)).unwrap();
+
+    // le même fichier : rien n'est réécrit
+    let stats = ingest(&directory, Cursor::new(&first)).unwrap();
+    assert_eq!(stats.documents_added, 0);
+    assert_eq!(stats.documents_updated, 0);
+    assert_eq!(stats.documents_unchanged, 2);
+
+    // un fichier recouvrant le premier, un tweet inchangé et un nouveau
+    let second = [line("2", 3, 1, "brebis"), line("3", 0, 0, "berger")].join("\n");
+    let stats = ingest(&directory, Cursor::new(second)).unwrap();
+    assert_eq!(stats.documents_added, 1);
+    assert_eq!(stats.documents_updated, 0);
+    assert_eq!(stats.documents_unchanged, 1);
+    assert_eq!(
+      indexed(&directory),
+      vec![
+        tweet("1", 0, 0, "loup"),
+        tweet("2", 3, 1, "brebis"),
+        tweet("3", 0, 0, "berger")
+      ]
+    );
+
+    fs::remove_dir_all(&directory).unwrap();
+  }
+
+  #[test]
+  fn test_changed_counts_replace_tweet() {
+    let directory = test_index("changed");
+    ingest(&directory, Cursor::new(line("1", 0, 0, "loup"))).unwrap();
+
+    let second = [line("1", 5, 0, "loup"), line("1", 5, 2, "loup")].join("\n");
+    let stats = ingest(&directory, Cursor::new(second)).unwrap();
+    assert_eq!(stats.documents_added, 0);
+    assert_eq!(stats.documents_updated, 2);
+    assert_eq!(stats.documents_unchanged, 0);
+    assert_eq!(indexed(&directory), vec![tweet("1", 5, 2, "loup")]);
+
+    fs::remove_dir_all(&directory).unwrap();
+  }
+
+  #[test]
+  fn test_duplicate_in_batch() {
+    let directory = test_index("batch");
+    let lines = [
+      line("1", 0, 0, "loup"),
+      line("2", 0, 0, "brebis"),
+      line("1", 0, 0, "loup"),
+      line("1", 7, 0, "loup"),
+    ]
+    .join("\n");
+    let stats = ingest(&directory, Cursor::new(lines)).unwrap();
+    // le doublon identique, pas encore commité, est reconnu comme inchangé
+    assert_eq!(stats.documents_added, 2);
+    assert_eq!(stats.documents_updated, 1);
+    assert_eq!(stats.documents_unchanged, 1);
+    assert_eq!(stats.commits, 1);
+    assert_eq!(
+      indexed(&directory),
+      vec![tweet("1", 7, 0, "loup"), tweet("2", 0, 0, "brebis")]
+    );
+
+    fs::remove_dir_all(&directory).unwrap();
+  }
+
+  #[test]
+  fn test_duplicate_after_commit() {
+    let directory = test_index("commit");
+    let lines = [
+      line("1", 0, 0, "loup"),
+      line("2", 0, 0, "brebis"),
+      // commit : les deux premiers ne sont plus en attente mais lus dans l'index
+      line("1", 0, 0, "loup"),
+      line("2", 4, 0, "brebis"),
+      line("3", 0, 0, "berger"),
+    ]
+    .join("\n");
+    let stats = ingest_with_commit_every(&directory, Cursor::new(lines), 2).unwrap();
+    assert_eq!(stats.documents_added, 3);
+    assert_eq!(stats.documents_updated, 1);
+    assert_eq!(stats.documents_unchanged, 1);
+    assert_eq!(stats.commits, 3);
+    assert_eq!(
+      indexed(&directory),
+      vec![
+        tweet("1", 0, 0, "loup"),
+        tweet("2", 4, 0, "brebis"),
+        tweet("3", 0, 0, "berger")
+      ]
+    );
+
+    fs::remove_dir_all(&directory).unwrap();
+  }
 }
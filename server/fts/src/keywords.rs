@@ -0,0 +1,349 @@
+//! Mots et expressions de 2 ou 3 mots les plus fréquents d'une étude, et les plus
+//! caractéristiques par rapport à l'index public.
+//!
+//! Le texte est découpé comme le champ `text_exact` : minuscules et accents retirés, sans
+//! racinisation, pour que chaque expression se lise et se cherche telle quelle. Les liens,
+//! mentions et hashtags sont écartés, les mots vides coupent les expressions. Un mot compte
+//! une fois par tweet.
+//!
+//! Un mot est caractéristique lorsqu'il est plus fréquent dans les tweets de l'étude que
+//! dans le reste de l'index public. L'écart est mesuré par le rapport de vraisemblance
+//! (G²) de Dunning, sur les nombres de tweets contenant le mot.
+
+use std::collections::{HashMap, HashSet};
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use tantivy::{collector::Count, query::AllQuery, Index};
+
+use crate::{
+  analyzer::{exact_analyzer, is_stopword},
+  frequence_by_date, keyword_query, Frequence, MatchMode, SearchError, TimeBuckets, TweetSchema,
+  TweetStream,
+};
+
+/// Mots propres aux tweets, sans intérêt comme mots clés
+const TWEET_NOISE: &[&str] = &["amp", "rt", "via"];
+
+/// Options de l'extraction des mots clés
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeywordOptions {
+  /// nombre de mots maximal d'une expression
+  pub max_ngram: usize,
+  /// nombre de mots clés de chaque classement
+  pub limit: usize,
+  /// nombre de tweets minimal d'un mot clé
+  pub min_doc_count: u64,
+}
+
+impl Default for KeywordOptions {
+  fn default() -> Self {
+    KeywordOptions {
+      max_ngram: 3,
+      limit: 20,
+      min_doc_count: 2,
+    }
+  }
+}
+
+/// Mot ou expression de l'étude
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KeywordCount {
+  pub keyword: String,
+  /// nombre de tweets de l'étude le contenant
+  pub doc_count: u64,
+  /// nombre de tweets de l'index public le contenant
+  pub background_count: u64,
+  /// G², négatif lorsque le mot est moins fréquent dans l'étude que dans l'index public
+  pub score: f64,
+}
+
+/// Classements des mots clés d'une étude
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct KeywordTrends {
+  /// par nombre de tweets décroissant
+  pub frequent: Vec<KeywordCount>,
+  /// par G² décroissant, seulement les mots plus fréquents que dans l'index public
+  pub distinctive: Vec<KeywordCount>,
+}
+
+/// Mots clés des tweets de `index`, comparés aux tweets de `background`
+pub fn keyword_trends(
+  index: &Index,
+  background: &Index,
+  options: &KeywordOptions,
+) -> Result<KeywordTrends, SearchError> {
+  let mut counts: HashMap<String, u64> = HashMap::new();
+  let mut documents = 0u64;
+  for tweet in TweetStream::new(index, &AllQuery)? {
+    for keyword in tweet_keywords(&tweet?.text, options.max_ngram) {
+      *counts.entry(keyword).or_default() += 1;
+    }
+    documents += 1;
+  }
+
+  let mut candidates: Vec<(String, u64)> = counts
+    .into_iter()
+    .filter(|(_, doc_count)| *doc_count >= options.min_doc_count)
+    .collect();
+  candidates.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+  // les mots caractéristiques sont cherchés parmi les plus fréquents : chacun coûte une
+  // recherche dans l'index public
+  candidates.truncate(options.limit.saturating_mul(20));
+
+  let searcher = background.reader()?.searcher();
+  let background_schema = TweetSchema::from_index(background)?;
+  let background_documents = searcher.num_docs();
+  let mut keywords = candidates
+    .into_iter()
+    .map(|(keyword, doc_count)| {
+      let query = keyword_query(background, &background_schema, &keyword, MatchMode::Exact);
+      let background_count = searcher.search(query.as_ref(), &Count)? as u64;
+      // l'étude est extraite de l'index public : elle est comparée au reste de l'index
+      let score = log_likelihood(
+        doc_count,
+        documents,
+        background_count.saturating_sub(doc_count),
+        background_documents.saturating_sub(documents),
+      );
+      Ok(KeywordCount {
+        keyword,
+        doc_count,
+        background_count,
+        score,
+      })
+    })
+    .collect::<Result<Vec<_>, SearchError>>()?;
+
+  let mut distinctive: Vec<KeywordCount> = keywords
+    .iter()
+    .filter(|keyword| keyword.score > 0.0)
+    .cloned()
+    .collect();
+  distinctive.sort_by(|a, b| {
+    b.score
+      .total_cmp(&a.score)
+      .then_with(|| a.keyword.cmp(&b.keyword))
+  });
+  distinctive.truncate(options.limit);
+  keywords.truncate(options.limit);
+
+  Ok(KeywordTrends {
+    frequent: keywords,
+    distinctive,
+  })
+}
+
+/// Histogrammes des tweets contenant chacun des `keywords`, comme ceux des hashtags
+pub fn search_keywords_count_per_day(
+  index: &Index,
+  start_date: &NaiveDate,
+  end_date: &NaiveDate,
+  keywords: &[String],
+  tab: &str,
+  buckets: &TimeBuckets,
+) -> Result<Vec<Frequence>, SearchError> {
+  let searcher = index.reader()?.searcher();
+  let tweet_schema = TweetSchema::from_index(index)?;
+
+  keywords
+    .iter()
+    .map(|keyword| {
+      let query = keyword_query(index, &tweet_schema, keyword, MatchMode::Exact);
+      Ok(Frequence {
+        hashtag: keyword.clone(),
+        hidden: false,
+        data: frequence_by_date(
+          &searcher,
+          &tweet_schema,
+          query.as_ref(),
+          start_date,
+          end_date,
+          tab,
+          buckets,
+        )?,
+      })
+    })
+    .collect()
+}
+
+/// Mots et expressions distincts d'un tweet
+fn tweet_keywords(text: &str, max_ngram: usize) -> HashSet<String> {
+  let analyzer = exact_analyzer();
+  let mut keywords = HashSet::new();
+  // une expression ne franchit pas un lien, une mention ou un hashtag
+  let segments =
+    text
+      .split(|c: char| c.is_whitespace())
+      .fold(vec![String::new()], |mut segments, word| {
+        if word.starts_with("http") || word.starts_with('@') || word.starts_with('#') {
+          segments.push(String::new());
+        } else if let Some(segment) = segments.last_mut() {
+          segment.push(' ');
+          segment.push_str(word);
+        }
+        segments
+      });
+
+  for segment in segments {
+    let mut run: Vec<String> = vec![];
+    let mut flush = |run: &mut Vec<String>| {
+      for size in 1..=max_ngram.min(run.len()) {
+        for ngram in run.windows(size) {
+          keywords.insert(ngram.join(" "));
+        }
+      }
+      run.clear();
+    };
+    let mut words = vec![];
+    analyzer
+      .token_stream(&segment)
+      .process(&mut |token| words.push(token.text.clone()));
+    for word in words {
+      if is_keyword(&word) {
+        run.push(word);
+      } else {
+        flush(&mut run);
+      }
+    }
+    flush(&mut run);
+  }
+
+  keywords
+}
+
+fn is_keyword(word: &str) -> bool {
+  word.chars().count() > 1
+    && !word.chars().all(|c| c.is_ascii_digit())
+    && !is_stopword(word)
+    && !TWEET_NOISE.contains(&word)
+}
+
+/// G² d'un mot présent dans `a` des `n1` tweets de l'étude et `c` des `n2` autres tweets,
+/// de signe négatif lorsqu'il est moins fréquent dans l'étude
+fn log_likelihood(a: u64, n1: u64, c: u64, n2: u64) -> f64 {
+  let (a, n1, c, n2) = (a as f64, n1 as f64, c as f64, n2 as f64);
+  if n1 == 0.0 || n2 == 0.0 {
+    return 0.0;
+  }
+  let expected_a = n1 * (a + c) / (n1 + n2);
+  let expected_c = n2 * (a + c) / (n1 + n2);
+  let part = |observed: f64, expected: f64| {
+    if observed > 0.0 {
+      observed * (observed / expected).ln()
+    } else {
+      0.0
+    }
+  };
+  let g2 = 2.0 * (part(a, expected_a) + part(c, expected_c));
+
+  if a / n1 < c / n2 {
+    -g2
+  } else {
+    g2
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use chrono::{TimeZone, Utc};
+
+  use super::*;
+  use crate::{
+    test_utils::{self, tweet},
+    Tweet,
+  };
+
+  fn index_with(texts: &[&str]) -> Index {
+    // un tweet par jour à partir du 12 juin 2022
+    let tweets: Vec<_> = texts
+      .iter()
+      .enumerate()
+      .map(|(id, text)| Tweet {
+        text: text.to_string(),
+        ..tweet(id).at(Utc.timestamp(1_655_000_000 + id as i64 * 86_400, 0))
+      })
+      .collect();
+    test_utils::index_with(&tweets)
+  }
+
+  #[test]
+  fn test_keyword_trends() {
+    assert_eq!(
+      tweet_keywords("RT @eleveurs : le Retour du loup https://t.co/x #loup", 2),
+      ["retour", "loup"].map(String::from).into_iter().collect()
+    );
+    assert_eq!(
+      tweet_keywords("Retour loup 2022", 3),
+      ["retour", "loup", "retour loup"]
+        .map(String::from)
+        .into_iter()
+        .collect()
+    );
+
+    let study_texts = [
+      "Le retour du loup inquiète les éleveurs",
+      "Attaque de loup : les éleveurs inquiets",
+      "Le loup est de retour",
+      "Une journée au soleil",
+      "Une journée de pluie",
+    ];
+    let study = index_with(&study_texts);
+    let mut background_texts = study_texts.to_vec();
+    background_texts.extend([
+      "Une journée de grève",
+      "Une journée à la plage",
+      "Une journée sans voiture",
+      "Les éleveurs de chèvres",
+    ]);
+    let background = index_with(&background_texts);
+
+    let trends = keyword_trends(&study, &background, &KeywordOptions::default()).unwrap();
+    let keywords = |keywords: &[KeywordCount]| {
+      keywords
+        .iter()
+        .map(|keyword| (keyword.keyword.clone(), keyword.doc_count))
+        .collect::<Vec<_>>()
+    };
+    assert_eq!(
+      keywords(&trends.frequent),
+      vec![
+        ("loup".to_string(), 3),
+        ("eleveurs".to_string(), 2),
+        ("journee".to_string(), 2),
+        ("retour".to_string(), 2)
+      ]
+    );
+    // « journée » est plus fréquente dans le reste de l'index que dans l'étude
+    assert_eq!(
+      keywords(&trends.distinctive),
+      vec![
+        ("loup".to_string(), 3),
+        ("retour".to_string(), 2),
+        ("eleveurs".to_string(), 2)
+      ]
+    );
+    assert_eq!(trends.frequent[2].background_count, 5);
+    assert!(trends.frequent[2].score < 0.0);
+
+    let frequences = search_keywords_count_per_day(
+      &study,
+      &NaiveDate::from_ymd(2022, 6, 12),
+      &NaiveDate::from_ymd(2022, 6, 15),
+      &["loup".to_string(), "loup inquiete".to_string()],
+      "total",
+      &TimeBuckets::default(),
+    )
+    .unwrap();
+    let series = |frequence: &Frequence| {
+      frequence
+        .data
+        .iter()
+        .map(|frequence| frequence.frequence)
+        .collect::<Vec<_>>()
+    };
+    assert_eq!(series(&frequences[0]), vec![1, 1, 1, 0]);
+    // expression cherchée comme une phrase, sans racinisation
+    assert_eq!(series(&frequences[1]), vec![1, 0, 0, 0]);
+  }
+}
@@ -1,18 +1,15 @@
 use std::{fmt, path::Path, vec, collections::HashMap};
 
-use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Utc};
 use serde::Serializer;
 use serde::{Deserialize, Deserializer, Serialize};
 use tantivy::aggregation::agg_result::BucketEntry;
-use tantivy::aggregation::bucket::HistogramBounds;
-use tantivy::query::PhraseQuery;
-use tantivy::schema::Schema;
 use tantivy::LeasedItem;
 use tantivy::Searcher;
 use tantivy::{
   aggregation::{
     agg_req::{Aggregation, Aggregations, BucketAggregation, BucketAggregationType},
-    bucket::{CustomOrder, HistogramAggregation, Order, TermsAggregation},
+    bucket::{CustomOrder, Order, TermsAggregation},
     AggregationCollector,
   },
   collector::{Count, TopDocs},
@@ -23,14 +20,53 @@ use tantivy::{
   Document, TantivyError, Term,
 };
 
+pub use alerts::*;
+pub use analyzer::*;
+pub use bursts::*;
+pub use cooccurence::*;
 pub use copy_index_data::*;
 pub use create_index_config::*;
+pub use date_histogram::*;
+pub use delete::*;
+pub use explain::*;
+pub use filters::*;
+pub use highlight::*;
 pub use ingest::*;
+pub use keywords::*;
+pub use links::*;
+pub use maintenance::*;
+pub use migrate::*;
+pub use pattern::*;
+pub use query_language::*;
+pub use stream::*;
+pub use chrono_tz::Tz;
 pub use tantivy::{DocAddress, Index};
+pub use trending::*;
+pub use tweet_schema::*;
 
+pub mod alerts;
+pub mod analyzer;
+pub mod bursts;
+pub mod cooccurence;
 pub mod copy_index_data;
 pub mod create_index_config;
+pub mod date_histogram;
+pub mod delete;
+pub mod explain;
+pub mod filters;
+pub mod highlight;
 pub mod ingest;
+pub mod keywords;
+pub mod links;
+pub mod maintenance;
+pub mod migrate;
+pub mod pattern;
+pub mod query_language;
+pub mod stream;
+#[cfg(test)]
+mod test_utils;
+pub mod trending;
+pub mod tweet_schema;
 
 use sqlx::Decode;
 
@@ -46,6 +82,29 @@ pub enum SearchError {
   NotFound,
   #[error("Impossible de parser la query")]
   ParseError,
+  #[error("Schéma de l'index incompatible : {0}")]
+  SchemaMismatch(String),
+  #[error("Document de l'index invalide : {0}")]
+  InvalidDocument(String),
+  #[error(
+    "L'index {path} est en version {version}, la version {expected} est attendue : \
+     le migrer avec `cocktail index migrate --directory-path {path}`"
+  )]
+  OutdatedIndex {
+    path: String,
+    version: u32,
+    expected: u32,
+  },
+  #[error("Migration de l'index impossible : {0}")]
+  Migration(String),
+  #[error("Maintenance de l'index impossible : {0}")]
+  Maintenance(String),
+  #[error("Requête invalide : {0}")]
+  InvalidQuery(String),
+  #[error("Fuseau horaire inconnu : {0}")]
+  UnknownTimezone(String),
+  #[error("Zone géographique invalide : {0}")]
+  InvalidBoundingBox(String),
 }
 
 impl From<TantivyError> for SearchError {
@@ -69,7 +128,10 @@ impl From<serde_json::Error> for SearchError {
 
 pub const MAX: u32 = 200_000_000;
 
-#[derive(Debug, Deserialize)]
+/// Nombre de tweets par page de résultats
+pub const RESULTS_PAGE_SIZE: usize = 10;
+
+#[derive(Debug, Clone, Copy, Deserialize)]
 pub enum OrderBy {
   PublishedTime,
   RetweetCount,
@@ -102,68 +164,99 @@ impl From<&str> for OrderBy {
   }
 }
 
-pub fn retrieve_index<P: AsRef<Path>>(dir: P) -> Result<Index, SearchError> {
-  let index = Index::open_in_dir(dir)?;
-
-  Ok(index)
+/// Tweets affichés par la page de résultats d'une étude, et leur ordre
+#[derive(Debug, Clone)]
+pub struct ResultsQuery {
+  /// comptes dont les tweets sont affichés, tous si vide
+  pub authors: Vec<String>,
+  pub hidden_hashtags: Vec<String>,
+  pub hidden_authors: Vec<String>,
+  pub exclude_retweets: bool,
+  /// premier et dernier jours, compris, des tweets affichés
+  pub dates: Option<(NaiveDate, NaiveDate)>,
+  pub hashtag: Option<String>,
+  pub order_by: OrderBy,
+  pub order: SortOrder,
 }
 
-pub fn search_for_communities(
-  index: &Index,
-) -> Result<(Vec<(f32, DocAddress)>, usize), SearchError> {
-  let searcher = index.reader()?.searcher();
-
-  let query = AllQuery;
+impl Default for ResultsQuery {
+  fn default() -> Self {
+    ResultsQuery {
+      authors: vec![],
+      hidden_hashtags: vec![],
+      hidden_authors: vec![],
+      exclude_retweets: false,
+      dates: None,
+      hashtag: None,
+      order_by: OrderBy::PublishedTime,
+      order: SortOrder::Descending,
+    }
+  }
+}
 
-  let top_docs: (Vec<_>, usize) = {
-    let top_docs_collector = TopDocs::with_limit(10_000_000);
-    searcher.search(&query, &(top_docs_collector, Count))?
-  };
+/// Ouvre l'index `dir`, refusé s'il est antérieur au schéma courant
+pub fn retrieve_index<P: AsRef<Path>>(dir: P) -> Result<Index, SearchError> {
+  let index = Index::open_in_dir(&dir)?;
+  register_tokenizers(&index);
+  check_index_version(&dir, &index)?;
 
-  Ok(top_docs)
+  Ok(index)
 }
 
+/// Hashtags à une faute de frappe près de `query` (lettre ajoutée, retirée, remplacée ou deux
+/// lettres inversées), avec leur nombre de tweets, les plus fréquents d'abord
 pub fn hashtag_search<P: AsRef<str>>(
   index: &Index,
   query: P,
   limit: usize,
-) -> Result<(Vec<(f32, DocAddress)>, usize), SearchError> {
-  let schema = index.schema();
-  let searcher = index.reader()?.searcher();
-  let hashtags = schema.get_field("hashtags").unwrap(); // unwrap car **on sait** que text est un champ dans l'index
-  let term = Term::from_field_text(hashtags, query.as_ref());
-  let query = FuzzyTermQuery::new(term, 1, true);
-  let top_docs: (Vec<_>, usize) = {
-    let top_docs_collector = TopDocs::with_limit(limit);
-    searcher.search(&query, &(top_docs_collector, Count))?
-  };
+) -> Result<Vec<HashtagCount>, SearchError> {
+  let hashtags = TweetSchema::from_index(index)?.hashtags;
+  let query = query.as_ref();
+  let fuzzy_query = FuzzyTermQuery::new(Term::from_field_text(hashtags, query), 1, true);
+
+  // un tweet correspond dès qu'un de ses hashtags est proche : seuls ceux-là sont comptés
+  let mut counts: HashMap<String, u64> = HashMap::new();
+  for tweet in TweetStream::new(index, &fuzzy_query)? {
+    let mut hashtags = tweet?.hashtags;
+    hashtags.sort_unstable();
+    hashtags.dedup();
+    for hashtag in hashtags {
+      if edit_distance(query, &hashtag) <= 1 {
+        *counts.entry(hashtag).or_default() += 1;
+      }
+    }
+  }
 
-  let mut result: Vec<_> = top_docs
-    .0
+  let mut result: Vec<_> = counts
     .into_iter()
-    .map(|(_score, doc_address)| {
-      let doc = searcher.doc(doc_address);
-      doc
-        .map(|d| {
-          let doc = &d;
-          let field = &hashtags;
-          doc
-            .get_all(*field)
-            .map(extract_string)
-            .collect::<Vec<_>>()
-            .first()
-            .cloned()
-            .unwrap()
-        })
-        .unwrap_or_default()
-    })
+    .map(|(key, doc_count)| HashtagCount { key, doc_count })
     .collect();
+  result.sort_unstable_by(|a, b| b.doc_count.cmp(&a.doc_count).then_with(|| a.key.cmp(&b.key)));
+  result.truncate(limit);
 
-  result.sort_unstable();
-  result.dedup();
+  Ok(result)
+}
+
+/// Distance d'édition entre `a` et `b`, l'inversion de deux lettres voisines comptant pour
+/// une seule modification comme pour [`FuzzyTermQuery`]
+fn edit_distance(a: &str, b: &str) -> usize {
+  let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+  // distances des préfixes de `a` aux préfixes de `b`, sur les deux lignes précédentes
+  let mut before: Vec<usize> = vec![0; b.len() + 1];
+  let mut previous: Vec<usize> = (0..=b.len()).collect();
+  for i in 1..=a.len() {
+    let mut current = vec![i; b.len() + 1];
+    for j in 1..=b.len() {
+      let substitution = previous[j - 1] + usize::from(a[i - 1] != b[j - 1]);
+      current[j] = substitution.min(previous[j] + 1).min(current[j - 1] + 1);
+      if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+        current[j] = current[j].min(before[j - 2] + 1);
+      }
+    }
+    before = std::mem::replace(&mut previous, current);
+  }
 
-  // Ok(top_docs)
-  Ok((vec![], 0))
+  previous[b.len()]
 }
 
 pub fn doc_count(index: &Index, query: Option<String>) -> Result<usize, SearchError> {
@@ -180,15 +273,40 @@ pub fn doc_count(index: &Index, query: Option<String>) -> Result<usize, SearchEr
   }
 }
 
+/// Hashtag et nombre de tweets qui le contiennent
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct HashtagCount {
+  pub key: String,
+  pub doc_count: u64,
+}
+
+/// Top `size` des hashtags des tweets correspondant à `query`, au format JSON
 pub fn topk<T>(index: &Index, query: T, size: u32) -> Result<String, SearchError>
 where
   T: AsRef<str>,
 {
-  let schema = index.schema();
+  let s = serde_json::to_string(&topk_hashtags(index, query, size)?)?;
+  Ok(s)
+}
+
+/// Top `size` des hashtags des tweets correspondant à `query`, par nombre de tweets décroissant
+pub fn topk_hashtags<T>(index: &Index, query: T, size: u32) -> Result<Vec<HashtagCount>, SearchError>
+where
+  T: AsRef<str>,
+{
   let searcher = index.reader()?.searcher();
-  let hashtags = schema.get_field("hashtags").unwrap();
+  let hashtags = TweetSchema::from_index(index)?.hashtags;
   let query_parser = QueryParser::for_index(index, vec![hashtags]);
   let q = query_parser.parse_query(query.as_ref())?;
+  hashtags_terms(&searcher, &q, size)
+}
+
+/// Top `size` des hashtags des tweets correspondant à `query`, agrégés par `searcher`
+pub(crate) fn hashtags_terms(
+  searcher: &Searcher,
+  query: &dyn Query,
+  size: u32,
+) -> Result<Vec<HashtagCount>, SearchError> {
   let agg_req: Aggregations = vec![(
     "hashtags".to_string(),
     Aggregation::Bucket(BucketAggregation {
@@ -208,8 +326,8 @@ where
   .collect();
 
   let collector = AggregationCollector::from_aggs(agg_req);
-  let agg_res = searcher.search(&q, &collector)?;
-  
+  let agg_res = searcher.search(query, &collector)?;
+
   let buckets = if let tantivy::aggregation::agg_result::AggregationResult::BucketResult(br) =
     agg_res.0.get("hashtags").unwrap()
   {
@@ -225,57 +343,34 @@ where
     Vec::new()
   };
 
-  // Transformer les buckets en format compatible avec sqlite-utils
-  #[derive(serde::Serialize)]
-  struct HashtagEntry {
-    key: String,
-    doc_count: u64,
-  }
-
-  let formatted_buckets: Vec<HashtagEntry> = buckets
-    .into_iter()
-    .map(|bucket| HashtagEntry {
-      key: bucket.key.to_string(),
-      doc_count: bucket.doc_count,
-    })
-    .collect();
-
-  let s = serde_json::to_string(&formatted_buckets)?;
-  Ok(s)
+  Ok(
+    buckets
+      .into_iter()
+      .map(|bucket| HashtagCount {
+        key: bucket.key.to_string(),
+        doc_count: bucket.doc_count,
+      })
+      .collect(),
+  )
 }
 
+/// Les 10 premiers tweets correspondant à `query`, dans la syntaxe de requête de tantivy
+/// (`text:lubrizol`…), triés par `order_by` s'il est donné
 pub fn search_tweets(
   index: &Index,
   query: &str,
   order_by: &Option<OrderBy>,
 ) -> Result<Vec<Tweet>, SearchError> {
-  let schema = index.schema();
   let searcher = index.reader()?.searcher();
-  let id = schema.get_field("id").unwrap();
-  let text = schema.get_field("text").unwrap();
-  let user_id = schema.get_field("user_id").unwrap();
-  let user_screen_name = schema.get_field("user_screen_name").unwrap();
-  let user_name = schema.get_field("user_name").unwrap();
-  let published_time = schema.get_field("published_time").unwrap();
-  let published_time_ms = schema.get_field("published_time_ms").unwrap();
-  let retweet_count = schema.get_field("retweet_count").unwrap();
-  let reply_count = schema.get_field("reply_count").unwrap();
-  let quote_count = schema.get_field("quote_count").unwrap();
-  let hashtags = schema.get_field("hashtags").unwrap();
-  let urls = schema.get_field("urls").unwrap();
-
-  let query_parser = QueryParser::for_index(index, vec![text]);
+  let tweet_schema = TweetSchema::from_index(index)?;
+
+  let query_parser = QueryParser::for_index(index, vec![tweet_schema.text]);
   let query = query_parser.parse_query(query)?;
   let search_results: Vec<_> = if let Some(order_by) = order_by {
     searcher
       .search(
         &query,
-        &TopDocs::with_limit(10).order_by_fast_field::<u64>(match order_by {
-          OrderBy::RetweetCount => retweet_count,
-          OrderBy::ReplyCount => reply_count,
-          OrderBy::QuoteCount => quote_count,
-          _ => retweet_count,
-        }),
+        &TopDocs::with_limit(10).order_by_fast_field::<u64>(tweet_schema.sort_field(order_by)),
       )?
       .into_iter()
       .map(|(_score, doc_address)| doc_address)
@@ -287,28 +382,60 @@ pub fn search_tweets(
       .map(|(_score, doc_address)| doc_address)
       .collect()
   };
-  let tweets: Vec<_> = search_results
+  let tweets = search_results
     .iter()
     .filter_map(|doc_address| searcher.doc(*doc_address).ok())
-    .map(|doc| Tweet {
-      id: extract(&doc, &id, extract_string),
-      user_id: extract(&doc, &user_id, extract_string),
-      user_name: extract(&doc, &user_name, extract_string),
-      user_screen_name: extract(&doc, &user_screen_name, extract_string),
-      text: extract(&doc, &text, extract_string),
-      published_time: extract(&doc, &published_time, extract_date),
-      published_time_ms: extract(&doc, &published_time_ms, extract_u64),
-      retweet_count: extract(&doc, &retweet_count, extract_u64),
-      reply_count: extract(&doc, &reply_count, extract_u64),
-      quote_count: extract(&doc, &quote_count, extract_u64),
-      hashtags: extract_vec(&doc, &hashtags, extract_string),
-      urls: extract_vec(&doc, &urls, extract_string),
-    })
-    .collect();
+    .map(|doc| tweet_schema.to_tweet(&doc))
+    .collect::<Result<Vec<_>, _>>()?;
 
   Ok(tweets)
 }
 
+/// Les `limit` premiers tweets correspondant à `query` dans le langage de requête des études
+/// (`loup AND #pastoralisme`, `date:[2022-01-01 TO 2022-06-30]`, `lang:fr`…), triés par
+/// `order_by` ; sans `date:`, tous les tweets de l'index sont cherchés
+pub fn search_tweets_with_limit(
+  index: &Index,
+  query: &QueryExpr,
+  order_by: &OrderBy,
+  order: SortOrder,
+  limit: usize,
+) -> Result<Vec<Tweet>, SearchError> {
+  let searcher = index.reader()?.searcher();
+  let tweet_schema = TweetSchema::from_index(index)?;
+
+  let criteria = query
+    .to_criteria()
+    .map_err(|e| SearchError::InvalidQuery(e.to_string()))?;
+  let (start_date, end_date) = criteria
+    .dates
+    .unwrap_or((NaiveDate::from_ymd(1970, 1, 1), chrono::naive::MAX_DATE));
+  let filters = StudyFilters {
+    languages: criteria.languages,
+    ..Default::default()
+  };
+  let query = get_query(
+    index,
+    &start_date,
+    &end_date,
+    &vec![],
+    &vec![],
+    &criteria.request_params,
+    &filters,
+  )?;
+
+  let collector = SearchAfter::new(tweet_schema.sort_field(order_by), order, None, limit);
+  let tweets = searcher
+    .search(&query, &collector)?
+    .iter()
+    .filter_map(|cursor| searcher.doc(cursor.doc).ok())
+    .map(|doc| tweet_schema.to_tweet(&doc))
+    .collect::<Result<Vec<_>, _>>()?;
+
+  Ok(tweets)
+}
+
+/// Tweets de l'étude, lus au fil de l'eau
 pub fn search_tweets_for_analysis(
   index: &Index,
   start_date: &NaiveDate,
@@ -316,22 +443,8 @@ pub fn search_tweets_for_analysis(
   hashtag_list: &Vec<String>,
   exclude_hashtag_list: &Vec<String>,
   request_params: &Vec<Vec<Bloc>>,
-) -> Result<Vec<Tweet>, SearchError> {
-  let schema = index.schema();
-  let searcher = index.reader()?.searcher();
-  let id = schema.get_field("id").unwrap();
-  let text = schema.get_field("text").unwrap();
-  let user_id = schema.get_field("user_id").unwrap();
-  let user_screen_name = schema.get_field("user_screen_name").unwrap();
-  let user_name = schema.get_field("user_name").unwrap();
-  let published_time = schema.get_field("published_time").unwrap();
-  let published_time_ms = schema.get_field("published_time_ms").unwrap();
-  let retweet_count = schema.get_field("retweet_count").unwrap();
-  let reply_count = schema.get_field("reply_count").unwrap();
-  let quote_count = schema.get_field("quote_count").unwrap();
-  let hashtags = schema.get_field("hashtags").unwrap();
-  let urls = schema.get_field("urls").unwrap();
-
+  filters: &StudyFilters,
+) -> Result<TweetStream, SearchError> {
   let query = get_query(
     index,
     start_date,
@@ -339,34 +452,10 @@ pub fn search_tweets_for_analysis(
     hashtag_list,
     exclude_hashtag_list,
     request_params,
-  );
-
-  let search_results: Vec<_> = searcher
-    .search(&query, &TopDocs::with_limit(MAX as usize))?
-    .into_iter()
-    .map(|(_score, doc_address)| doc_address)
-    .collect();
+    filters,
+  )?;
 
-  let tweets: Vec<_> = search_results
-    .iter()
-    .filter_map(|doc_address| searcher.doc(*doc_address).ok())
-    .map(|doc| Tweet {
-      id: extract(&doc, &id, extract_string),
-      user_id: extract(&doc, &user_id, extract_string),
-      user_name: extract(&doc, &user_name, extract_string),
-      user_screen_name: extract(&doc, &user_screen_name, extract_string),
-      text: extract(&doc, &text, extract_string),
-      published_time: extract(&doc, &published_time, extract_date),
-      published_time_ms: extract(&doc, &published_time_ms, extract_u64),
-      retweet_count: extract(&doc, &retweet_count, extract_u64),
-      reply_count: extract(&doc, &reply_count, extract_u64),
-      quote_count: extract(&doc, &quote_count, extract_u64),
-      hashtags: extract_vec(&doc, &hashtags, extract_string),
-      urls: extract_vec(&doc, &urls, extract_string),
-    })
-    .collect();
-
-  Ok(tweets)
+  TweetStream::new(index, &query)
 }
 
 pub fn search_tweets_for_preview(
@@ -376,25 +465,12 @@ pub fn search_tweets_for_preview(
   hashtag_list: &Vec<String>,
   exclude_hashtag_list: &Vec<String>,
   request_params: &Vec<Vec<Bloc>>,
+  filters: &StudyFilters,
 ) -> Result<PreviewTweets, SearchError> {
-  let schema = index.schema();
   let searcher = index.reader()?.searcher();
-  let id = schema.get_field("id").unwrap();
-  let text = schema.get_field("text").unwrap();
-  let user_id = schema.get_field("user_id").unwrap();
-  let user_screen_name = schema.get_field("user_screen_name").unwrap();
-  let user_name = schema.get_field("user_name").unwrap();
-  let published_time = schema.get_field("published_time").unwrap();
-  let published_time_ms = schema.get_field("published_time_ms").unwrap();
-  let retweet_count = schema.get_field("retweet_count").unwrap();
-  let reply_count = schema.get_field("reply_count").unwrap();
-  let quote_count = schema.get_field("quote_count").unwrap();
-  let hashtags = schema.get_field("hashtags").unwrap();
-  let urls = schema.get_field("urls").unwrap();
-
-  let sort_field = schema
-    .get_field("engagement_count")
-    .unwrap_or(retweet_count);
+  let tweet_schema = TweetSchema::from_index(index)?;
+
+  let sort_field = tweet_schema.engagement_count;
 
   let query = get_query(
     index,
@@ -403,37 +479,26 @@ pub fn search_tweets_for_preview(
     hashtag_list,
     exclude_hashtag_list,
     request_params,
-  );
-
-  let mut search_results: Vec<_> = searcher
-    .search(
-      &query,
-      &TopDocs::with_limit(MAX as usize).order_by_fast_field::<u64>(sort_field),
-    )?
+    filters,
+  )?;
+
+  let (top_docs, count) = searcher.search(
+    &query,
+    &(
+      TopDocs::with_limit(10).order_by_fast_field::<u64>(sort_field),
+      Count,
+    ),
+  )?;
+  let search_results: Vec<_> = top_docs
     .into_iter()
     .map(|(_score, doc_address)| doc_address)
     .collect();
-  let count = search_results.len();
-  search_results.truncate(10);
 
-  let tweets: Vec<_> = search_results
+  let tweets = search_results
     .iter()
     .filter_map(|doc_address| searcher.doc(*doc_address).ok())
-    .map(|doc| Tweet {
-      id: extract(&doc, &id, extract_string),
-      user_id: extract(&doc, &user_id, extract_string),
-      user_name: extract(&doc, &user_name, extract_string),
-      user_screen_name: extract(&doc, &user_screen_name, extract_string),
-      text: extract(&doc, &text, extract_string),
-      published_time: extract(&doc, &published_time, extract_date),
-      published_time_ms: extract(&doc, &published_time_ms, extract_u64),
-      retweet_count: extract(&doc, &retweet_count, extract_u64),
-      reply_count: extract(&doc, &reply_count, extract_u64),
-      quote_count: extract(&doc, &quote_count, extract_u64),
-      hashtags: extract_vec(&doc, &hashtags, extract_string),
-      urls: extract_vec(&doc, &urls, extract_string),
-    })
-    .collect();
+    .map(|doc| tweet_schema.to_tweet(&doc))
+    .collect::<Result<Vec<_>, _>>()?;
 
   Ok(PreviewTweets {
     count: count as i64,
@@ -443,156 +508,47 @@ pub fn search_tweets_for_preview(
 
 pub fn search_tweets_for_result(
   index: &Index,
-  included_user_names: &Vec<String>,
-  hidden_hashtags: &Vec<String>,
-  hidden_authors: &Vec<String>,
-  exclude_retweets: bool,
-  order_by: OrderBy,
-  order: &String,
-  date: &Option<NaiveDate>,
-  hashtag: &Option<String>,
+  results: &ResultsQuery,
   page: u32,
-) -> Result<Vec<Tweet>, SearchError> {
-  let schema = index.schema();
+  after: &Option<SearchCursor>,
+  hashtag_list: &[String],
+  request_params: &[Vec<Bloc>],
+) -> Result<TweetPage<HighlightedTweet>, SearchError> {
   let searcher = index.reader()?.searcher();
-  let id = schema.get_field("id").unwrap();
-  let text = schema.get_field("text").unwrap();
-  let user_id = schema.get_field("user_id").unwrap();
-  let user_screen_name = schema.get_field("user_screen_name").unwrap();
-  let user_name = schema.get_field("user_name").unwrap();
-  let published_time = schema.get_field("published_time").unwrap();
-  let published_time_ms = schema.get_field("published_time_ms").unwrap();
-  let retweet_count = schema.get_field("retweet_count").unwrap();
-  let reply_count = schema.get_field("reply_count").unwrap();
-  let quote_count = schema.get_field("quote_count").unwrap();
-  let urls = schema.get_field("urls").unwrap();
-
-  let sort_field = match order_by {
-    OrderBy::PublishedTime => schema
-      .get_field(match order.as_str() {
-        "croissant" => "asc_published_time_ms",
-        _ => "published_time_ms",
-      })
-      .unwrap(),
-    OrderBy::RetweetCount => schema
-      .get_field(match order.as_str() {
-        "croissant" => "asc_retweet_count",
-        _ => "retweet_count",
-      })
-      .unwrap(),
-    OrderBy::ReplyCount => schema
-      .get_field(match order.as_str() {
-        "croissant" => "asc_reply_count",
-        _ => "reply_count",
-      })
-      .unwrap(),
-    OrderBy::QuoteCount => schema
-      .get_field(match order.as_str() {
-        "croissant" => "asc_quote_count",
-        _ => "quote_count",
-      })
-      .unwrap(),
-    OrderBy::EngagementCount => schema
-      .get_field(match order.as_str() {
-        "croissant" => "asc_engagement_count",
-        _ => "engagement_count",
-      })
-      .unwrap(),
-  };
-  let hashtags = schema.get_field("hashtags").unwrap();
+  let tweet_schema = TweetSchema::from_index(index)?;
 
-  let query = get_results_query(
-    index,
-    included_user_names,
-    hidden_hashtags,
-    hidden_authors,
-    exclude_retweets,
-    date,
-    hashtag,
-  );
-
-  let offset: usize = match page {
-    0 => 0,
-    _ => ((page - 1) * 10).try_into().unwrap_or(0),
-  };
-
-  let search_results: Vec<_> = searcher
-    .search(
-      &query,
-      &TopDocs::with_limit(10)
-        .and_offset(offset)
-        .order_by_fast_field::<u64>(sort_field),
-    )?
-    .into_iter()
-    .map(|(_score, doc_address)| doc_address)
-    .collect();
-
-  let tweets: Vec<_> = search_results
-    .iter()
-    .filter_map(|doc_address| searcher.doc(*doc_address).ok())
-    .map(|doc| Tweet {
-      id: extract(&doc, &id, extract_string),
-      user_id: extract(&doc, &user_id, extract_string),
-      user_name: extract(&doc, &user_name, extract_string),
-      user_screen_name: extract(&doc, &user_screen_name, extract_string),
-      text: extract(&doc, &text, extract_string),
-      published_time: extract(&doc, &published_time, extract_date),
-      published_time_ms: extract(&doc, &published_time_ms, extract_u64),
-      retweet_count: extract(&doc, &retweet_count, extract_u64),
-      reply_count: extract(&doc, &reply_count, extract_u64),
-      quote_count: extract(&doc, &quote_count, extract_u64),
-      hashtags: extract_vec(&doc, &hashtags, extract_string),
-      urls: extract_vec(&doc, &urls, extract_string),
-    })
-    .collect();
-
-  Ok(tweets)
-}
-
-pub fn get_all_tweets(index: &Index) -> Result<Vec<Tweet>, SearchError> {
-  let schema = index.schema();
-  let searcher = index.reader()?.searcher();
-  let id = schema.get_field("id").unwrap();
-  let text = schema.get_field("text").unwrap();
-  let user_id = schema.get_field("user_id").unwrap();
-  let user_screen_name = schema.get_field("user_screen_name").unwrap();
-  let user_name = schema.get_field("user_name").unwrap();
-  let published_time = schema.get_field("published_time").unwrap();
-  let published_time_ms = schema.get_field("published_time_ms").unwrap();
-  let retweet_count = schema.get_field("retweet_count").unwrap();
-  let reply_count = schema.get_field("reply_count").unwrap();
-  let quote_count = schema.get_field("quote_count").unwrap();
-  let hashtags = schema.get_field("hashtags").unwrap();
-  let urls = schema.get_field("urls").unwrap();
+  let sort_field = tweet_schema.sort_field(&results.order_by);
 
-  let query = AllQuery;
+  let query = get_results_query(index, results)?;
 
-  let search_results: Vec<_> = searcher
-    .search(&query, &TopDocs::with_limit(5000))?
+  // la page suivante est lue après le curseur de la précédente, les autres par décalage
+  let (after, offset) = match after {
+    Some(cursor) => (Some(*cursor), 0),
+    None => (None, (page.max(1) as usize - 1) * RESULTS_PAGE_SIZE),
+  };
+  let collector = SearchAfter::new(sort_field, results.order, after, offset + RESULTS_PAGE_SIZE);
+  let cursors: Vec<_> = searcher
+    .search(&query, &collector)?
     .into_iter()
-    .map(|(_score, doc_address)| doc_address)
+    .skip(offset)
     .collect();
+  let next = match cursors.len() {
+    RESULTS_PAGE_SIZE => cursors.last().copied(),
+    _ => None,
+  };
 
-  let tweets: Vec<_> = search_results
+  let tweets = cursors
     .iter()
-    .filter_map(|doc_address| searcher.doc(*doc_address).ok())
-    .map(|doc| Tweet {
-      id: extract(&doc, &id, extract_string),
-      user_id: extract(&doc, &user_id, extract_string),
-      user_name: extract(&doc, &user_name, extract_string),
-      user_screen_name: extract(&doc, &user_screen_name, extract_string),
-      text: extract(&doc, &text, extract_string),
-      published_time: extract(&doc, &published_time, extract_date),
-      published_time_ms: extract(&doc, &published_time_ms, extract_u64),
-      retweet_count: extract(&doc, &retweet_count, extract_u64),
-      reply_count: extract(&doc, &reply_count, extract_u64),
-      quote_count: extract(&doc, &quote_count, extract_u64),
-      hashtags: extract_vec(&doc, &hashtags, extract_string),
-      urls: extract_vec(&doc, &urls, extract_string),
-    })
-    .collect();
-
-  Ok(tweets)
+    .filter_map(|cursor| searcher.doc(cursor.doc).ok())
+    .map(|doc| tweet_schema.to_tweet(&doc))
+    .collect::<Result<Vec<_>, _>>()?;
+
+  // termes de l'étude surlignés dans le texte des tweets
+  let highlight = highlight_query(index, &tweet_schema, hashtag_list, request_params);
+  Ok(TweetPage {
+    tweets: highlight_tweets(&searcher, &highlight, tweets)?,
+    next,
+  })
 }
 
 pub fn search_tweets_count_per_day(
@@ -602,59 +558,29 @@ pub fn search_tweets_count_per_day(
   start_date: &NaiveDate,
   end_date: &NaiveDate,
   tab: &String,
+  buckets: &TimeBuckets,
 ) -> Result<Vec<FrequenceByDate>, SearchError> {
-  let schema = index.schema();
+  let tweet_schema = TweetSchema::from_index(index)?;
 
   let searcher = index.reader()?.searcher();
   let query = get_results_query(
     index,
-    included_user_names,
-    hidden_hashtags,
-    &vec![],
-    false,
-    &None,
-    &None,
-  );
-
-  let sub_agg: Aggregations = vec![(
-    "tweets_id".to_string(),
-    Aggregation::Bucket(BucketAggregation {
-      bucket_agg: BucketAggregationType::Terms(TermsAggregation {
-        field: "id".to_string(),
-        size: Some(MAX),
-        ..Default::default()
-      }),
-      sub_aggregation: Default::default(),
-    }),
-  )]
-  .into_iter()
-  .collect();
-
-  let agg_req: Aggregations = vec![(
-    "par_jour".to_string(),
-    Aggregation::Bucket(BucketAggregation {
-      bucket_agg: BucketAggregationType::Histogram(HistogramAggregation {
-        field: "published_time_ms".to_string(),
-        interval: 86_400_000f64,
-        extended_bounds: Some(HistogramBounds {
-          min: start_date.and_hms(00, 00, 00).timestamp_millis() as f64,
-          max: end_date.and_hms(00, 00, 00).timestamp_millis() as f64,
-        }),
-        ..Default::default()
-      }),
-      sub_aggregation: sub_agg,
-    }),
-  )]
-  .into_iter()
-  .collect();
-
-  let aggregation_collector = AggregationCollector::from_aggs(agg_req);
-
-  let aggregation_result = searcher.search(&query, &aggregation_collector)?;
-  let v = serde_json::to_value(&aggregation_result)?;
-  let result: ByDay = serde_json::from_value(v)?;
-
-  Ok(get_frequence_by_date(&schema, &searcher, result, tab))
+    &ResultsQuery {
+      authors: included_user_names.clone(),
+      hidden_hashtags: hidden_hashtags.clone(),
+      ..Default::default()
+    },
+  )?;
+
+  frequence_by_date(
+    &searcher,
+    &tweet_schema,
+    &query,
+    start_date,
+    end_date,
+    tab,
+    buckets,
+  )
 }
 
 pub fn aggregate_authors(
@@ -662,7 +588,7 @@ pub fn aggregate_authors(
   tab: &String,
   page: u32,
 ) -> Result<Vec<AuthorCount>, SearchError> {
-  let schema = index.schema();
+  let tweet_schema = TweetSchema::from_index(index)?;
   let searcher = index.reader()?.searcher();
 
   let query = AllQuery;
@@ -728,7 +654,7 @@ pub fn aggregate_authors(
       } else {
         bucket.tweets_id.buckets
       };
-      let tweets_stats = get_tweets_stats(&schema, &searcher, tweets_buckets);
+      let tweets_stats = get_tweets_stats(&tweet_schema, &searcher, tweets_buckets);
       let mut author: Option<Author> = None;
       let mut count = 0;
 
@@ -773,123 +699,65 @@ pub fn aggregate_authors(
 }
 
 pub fn get_frequence_for_hashtag(
-  schema: &Schema,
+  tweet_schema: &TweetSchema,
   searcher: &LeasedItem<Searcher>,
-  hashtags: Field,
   start_date: &NaiveDate,
   end_date: &NaiveDate,
   tab: &String,
   hashtag: &String,
+  buckets: &TimeBuckets,
 ) -> Result<Frequence, SearchError> {
   let query = TermQuery::new(
-    Term::from_field_text(hashtags, hashtag),
+    Term::from_field_text(tweet_schema.hashtags, hashtag),
     IndexRecordOption::Basic,
   );
 
-  let sub_agg: Aggregations = vec![(
-    "tweets_id".to_string(),
-    Aggregation::Bucket(BucketAggregation {
-      bucket_agg: BucketAggregationType::Terms(TermsAggregation {
-        field: "id".to_string(),
-        size: Some(MAX),
-        ..Default::default()
-      }),
-      sub_aggregation: Default::default(),
-    }),
-  )]
-  .into_iter()
-  .collect();
-
-  let agg_req: Aggregations = vec![(
-    "par_jour".to_string(),
-    Aggregation::Bucket(BucketAggregation {
-      bucket_agg: BucketAggregationType::Histogram(HistogramAggregation {
-        field: "published_time_ms".to_string(),
-        interval: 86_400_000f64,
-        extended_bounds: Some(HistogramBounds {
-          min: start_date.and_hms(00, 00, 00).timestamp_millis() as f64,
-          max: end_date.and_hms(00, 00, 00).timestamp_millis() as f64,
-        }),
-        ..Default::default()
-      }),
-      sub_aggregation: sub_agg,
-    }),
-  )]
-  .into_iter()
-  .collect();
-
-  let aggregation_collector = AggregationCollector::from_aggs(agg_req);
-
-  let aggregation_result = searcher.search(&query, &aggregation_collector)?;
-  let v = serde_json::to_value(&aggregation_result)?;
-  let result: ByDay = serde_json::from_value(v)?;
-
   Ok(Frequence {
     hashtag: hashtag.clone(),
     hidden: false,
-    data: get_frequence_by_date(&schema, &searcher, result, tab),
+    data: frequence_by_date(
+      searcher,
+      tweet_schema,
+      &query,
+      start_date,
+      end_date,
+      tab,
+      buckets,
+    )?,
   })
 }
 
 pub fn get_frequence_for_hashtag_cooccurence(
-  schema: &Schema,
+  tweet_schema: &TweetSchema,
   searcher: &LeasedItem<Searcher>,
-  hashtags: Field,
   start_date: &NaiveDate,
   end_date: &NaiveDate,
   tab: &String,
   cooccurence: &HashtagCooccurence,
+  buckets: &TimeBuckets,
 ) -> Result<FrequenceCooccurence, SearchError> {
   let mut query = Vec::new();
 
   let term_query1: Box<dyn Query> = Box::new(TermQuery::new(
-    Term::from_field_text(hashtags, &cooccurence.hashtag1),
+    Term::from_field_text(tweet_schema.hashtags, &cooccurence.hashtag1),
     IndexRecordOption::Basic,
   ));
   let term_query2: Box<dyn Query> = Box::new(TermQuery::new(
-    Term::from_field_text(hashtags, &cooccurence.hashtag2),
+    Term::from_field_text(tweet_schema.hashtags, &cooccurence.hashtag2),
     IndexRecordOption::Basic,
   ));
   query.push((Occur::Must, term_query1));
   query.push((Occur::Must, term_query2));
 
-  let sub_agg: Aggregations = vec![(
-    "tweets_id".to_string(),
-    Aggregation::Bucket(BucketAggregation {
-      bucket_agg: BucketAggregationType::Terms(TermsAggregation {
-        field: "id".to_string(),
-        size: Some(MAX),
-        ..Default::default()
-      }),
-      sub_aggregation: Default::default(),
-    }),
-  )]
-  .into_iter()
-  .collect();
-
-  let agg_req: Aggregations = vec![(
-    "par_jour".to_string(),
-    Aggregation::Bucket(BucketAggregation {
-      bucket_agg: BucketAggregationType::Histogram(HistogramAggregation {
-        field: "published_time_ms".to_string(),
-        interval: 86_400_000f64,
-        extended_bounds: Some(HistogramBounds {
-          min: start_date.and_hms(00, 00, 00).timestamp_millis() as f64,
-          max: end_date.and_hms(00, 00, 00).timestamp_millis() as f64,
-        }),
-        ..Default::default()
-      }),
-      sub_aggregation: sub_agg,
-    }),
-  )]
-  .into_iter()
-  .collect();
-
-  let aggregation_collector = AggregationCollector::from_aggs(agg_req);
-
-  let aggregation_result = searcher.search(&BooleanQuery::new(query), &aggregation_collector)?;
-  let v = serde_json::to_value(&aggregation_result)?;
-  let result: ByDay = serde_json::from_value(v)?;
+  let data = frequence_by_date(
+    searcher,
+    tweet_schema,
+    &BooleanQuery::new(query),
+    start_date,
+    end_date,
+    tab,
+    buckets,
+  )?;
   let mut label = cooccurence.hashtag1.clone();
   label.push_str("-");
   label.push_str(cooccurence.hashtag2.as_str());
@@ -897,7 +765,7 @@ pub fn get_frequence_for_hashtag_cooccurence(
   Ok(FrequenceCooccurence {
     label,
     hidden: false,
-    data: get_frequence_by_date(&schema, &searcher, result, tab),
+    data,
   })
 }
 
@@ -907,19 +775,24 @@ pub fn search_study_hashtags_count_per_day(
   end_date: &NaiveDate,
   hashtag_list: &Vec<String>,
   tab: &String,
+  buckets: &TimeBuckets,
 ) -> Result<Vec<Frequence>, SearchError> {
   let searcher = index.reader()?.searcher();
-  let schema = index.schema();
-  let hashtags = schema.get_field("hashtags").unwrap();
+  let tweet_schema = TweetSchema::from_index(index)?;
 
   let mut v_result = vec![];
 
   for hashtag in hashtag_list {
     v_result.push(
       get_frequence_for_hashtag(
-        &schema, &searcher, hashtags, start_date, end_date, tab, &hashtag,
-      )
-      .unwrap(),
+        &tweet_schema,
+        &searcher,
+        start_date,
+        end_date,
+        tab,
+        &hashtag,
+        buckets,
+      )?,
     );
   }
 
@@ -933,10 +806,10 @@ pub fn search_top_hashtags_count_per_day(
   start_date: &NaiveDate,
   end_date: &NaiveDate,
   tab: &String,
+  buckets: &TimeBuckets,
 ) -> Result<Vec<Frequence>, SearchError> {
   let searcher = index.reader()?.searcher();
-  let schema = index.schema();
-  let hashtags = schema.get_field("hashtags").unwrap();
+  let tweet_schema = TweetSchema::from_index(index)?;
 
   let mut v_result_topk = vec![];
 
@@ -946,15 +819,14 @@ pub fn search_top_hashtags_count_per_day(
   for hashtag in topk_hashtags {
     v_result_topk.push(
       get_frequence_for_hashtag(
-        &schema,
+        &tweet_schema,
         &searcher,
-        hashtags,
         start_date,
         end_date,
         tab,
         &hashtag.key.to_string(),
-      )
-      .unwrap(),
+        buckets,
+      )?,
     );
   }
 
@@ -968,26 +840,28 @@ pub fn search_top_hashtags_cooccurence_count_per_day(
   start_date: &NaiveDate,
   end_date: &NaiveDate,
   tab: &String,
-  cooccurences: &Vec<HashtagCooccurence>,
+  buckets: &TimeBuckets,
+  options: &CooccurenceOptions,
 ) -> Result<Vec<FrequenceCooccurence>, SearchError> {
   let searcher = index.reader()?.searcher();
-  let schema = index.schema();
-  let hashtags = schema.get_field("hashtags").unwrap();
+  let tweet_schema = TweetSchema::from_index(index)?;
 
   let mut v_result = vec![];
 
-  for cooccurence in cooccurences {
+  let cooccurences =
+    hashtags_cooccurence_between(index, start_date, end_date, options)?;
+
+  for cooccurence in cooccurences.pairs.iter().map(HashtagCooccurence::from) {
     v_result.push(
       get_frequence_for_hashtag_cooccurence(
-        &schema,
+        &tweet_schema,
         &searcher,
-        hashtags,
         start_date,
         end_date,
         tab,
-        cooccurence,
-      )
-      .unwrap(),
+        &cooccurence,
+        buckets,
+      )?,
     );
   }
 
@@ -1004,14 +878,6 @@ fn extract_string(un: &tantivy::schema::Value) -> String {
   }
 }
 
-fn extract_date(un: &tantivy::schema::Value) -> DateTime<Utc> {
-  if let tantivy::schema::Value::Date(d) = un {
-    Utc.timestamp_millis(d.into_unix_timestamp() * 1000)
-  } else {
-    Utc::now()
-  }
-}
-
 fn extract_u64(un: &tantivy::schema::Value) -> u64 {
   if let tantivy::schema::Value::U64(u) = un {
     *u
@@ -1034,14 +900,6 @@ where
     .unwrap()
 }
 
-fn extract_vec<F, T>(doc: &Document, field: &Field, extract_fn: F) -> Vec<T>
-where
-  F: Fn(&tantivy::schema::Value) -> T,
-  T: Clone,
-{
-  doc.get_all(*field).map(extract_fn).collect::<Vec<T>>()
-}
-
 fn get_query(
   index: &Index,
   start_date: &NaiveDate,
@@ -1049,11 +907,10 @@ fn get_query(
   hashtag_list: &Vec<String>,
   exclude_hashtag_list: &Vec<String>,
   request_params: &Vec<Vec<Bloc>>,
-) -> BooleanQuery {
-  let schema = index.schema();
-  let text: Field = schema.get_field("text").unwrap();
-  let user_screen_name = schema.get_field("user_screen_name").unwrap();
-  let hashtags = schema.get_field("hashtags").unwrap();
+  filters: &StudyFilters,
+) -> Result<BooleanQuery, SearchError> {
+  let tweet_schema = TweetSchema::from_index(index)?;
+  let TweetSchema { text, hashtags, .. } = tweet_schema;
   let query_parser = QueryParser::for_index(&index, vec![text]);
   let mut query = Vec::new();
 
@@ -1092,16 +949,16 @@ fn get_query(
   );
 
   query.push((Occur::Must, date_query));
+  query.extend(filters.clauses(&tweet_schema));
 
   let link = request_params[1][0].link.clone();
   sub_query = Vec::new();
 
   if request_params[1][0].data.len() > 0 {
     let query_exclude = create_query_from_bloc(
+      index,
+      &tweet_schema,
       request_params[1][0].data.clone(),
-      text,
-      user_screen_name,
-      hashtags,
       Occur::Should,
     );
 
@@ -1134,10 +991,9 @@ fn get_query(
         bloc_subquery.push((
           Occur::Should,
           Box::new(BooleanQuery::new(create_query_from_bloc(
+            index,
+            &tweet_schema,
             bloc.data.clone(),
-            text,
-            user_screen_name,
-            hashtags,
             Occur::Must,
           ))) as Box<dyn Query>,
         ));
@@ -1145,10 +1001,9 @@ fn get_query(
         query_bloc.push((
           Occur::Must,
           Box::new(BooleanQuery::new(create_query_from_bloc(
+            index,
+            &tweet_schema,
             bloc.data.clone(),
-            text,
-            user_screen_name,
-            hashtags,
             Occur::Must,
           ))) as Box<dyn Query>,
         ));
@@ -1183,71 +1038,66 @@ fn get_query(
     //Si aucune condition, on prend tout
     query.push((Occur::Must, Box::new(AllQuery) as Box<dyn Query>))
   }
-  BooleanQuery::new(query)
+  Ok(BooleanQuery::new(query))
 }
 
 // Créé une query à partir d'un Vec de String
 fn create_query_from_bloc(
+  index: &Index,
+  tweet_schema: &TweetSchema,
   data: Vec<String>,
-  text: Field,
-  user_screen_name: Field,
-  hashtags: Field,
   occur_type: Occur,
 ) -> Vec<(Occur, Box<dyn Query>)> {
+  let TweetSchema {
+    user_screen_name,
+    hashtags,
+    ..
+  } = *tweet_schema;
   let mut query_bloc: Vec<(Occur, Box<dyn Query>)> = Vec::new();
 
   for element in data {
-    let mut field = text;
-    let value: String;
-
-    if element.starts_with("@") {
-      field = user_screen_name;
-      value = element[1..].to_string();
-    } else if element.starts_with("#") {
-      field = hashtags;
-      value = element[1..].to_string();
-    } else {
-      value = element.to_ascii_lowercase().clone()
-    }
-
-    if value.contains(' ') {
-      //Groupe de mots
-      let phrase_query: Box<dyn Query> = Box::new(PhraseQuery::new(
-        value
-          .split(' ')
-          .map(|e| Term::from_field_text(text, e))
-          .collect(),
+    if let Some(value) = element.strip_prefix('@') {
+      let term_query: Box<dyn Query> = Box::new(TermQuery::new(
+        Term::from_field_text(user_screen_name, value),
+        IndexRecordOption::Basic,
       ));
-      query_bloc.push((occur_type, phrase_query));
-    } else {
+      query_bloc.push((occur_type, term_query));
+    } else if let Some(value) = element.strip_prefix('#') {
       let term_query: Box<dyn Query> = Box::new(TermQuery::new(
-        Term::from_field_text(field, &value),
+        Term::from_field_text(hashtags, value),
         IndexRecordOption::Basic,
       ));
       query_bloc.push((occur_type, term_query));
+    } else if let Some(pattern) = KeywordPattern::from_item(&element) {
+      query_bloc.push((occur_type, pattern.query(index, tweet_schema)));
+    } else {
+      // mot ou groupe de mots, analysé comme le texte indexé
+      let (mode, value) = keyword_match_mode(&element);
+      query_bloc.push((occur_type, keyword_query(index, tweet_schema, value, mode)));
     }
   }
 
   return query_bloc;
 }
 
-fn get_results_query(
-  index: &Index,
-  included_user_names: &Vec<String>,
-  hidden_hashtags: &Vec<String>,
-  hidden_authors: &Vec<String>,
-  exclude_retweets: bool,
-  date: &Option<NaiveDate>,
-  hashtag: &Option<String>,
-) -> BooleanQuery {
-  let schema = index.schema();
+fn get_results_query(index: &Index, results: &ResultsQuery) -> Result<BooleanQuery, SearchError> {
+  let ResultsQuery {
+    authors,
+    hidden_hashtags,
+    hidden_authors,
+    exclude_retweets,
+    dates,
+    hashtag,
+    ..
+  } = results;
+  let tweet_schema = TweetSchema::from_index(index)?;
   let mut query = Vec::new();
 
-  if included_user_names.len() > 0 {
-    let user_screen_name = schema.get_field("user_screen_name").unwrap();
+  if !authors.is_empty() {
+    let user_screen_name = tweet_schema.user_screen_name;
     let mut sub_query: Vec<(Occur, Box<dyn Query>)> = Vec::new();
 
-    for screen_name in included_user_names {
+    for screen_name in authors {
       let term_query: Box<dyn Query> = Box::new(TermQuery::new(
         Term::from_field_text(user_screen_name, &screen_name),
         IndexRecordOption::Basic,
@@ -1264,7 +1114,7 @@ fn get_results_query(
   }
 
   if hidden_hashtags.len() > 0 {
-    let hashtags = schema.get_field("hashtags").unwrap();
+    let hashtags = tweet_schema.hashtags;
 
     for hashtag in hidden_hashtags {
       //Coocurence
@@ -1302,7 +1152,7 @@ fn get_results_query(
   }
 
   if hidden_authors.len() > 0 {
-    let user_screen_name = schema.get_field("user_screen_name").unwrap();
+    let user_screen_name = tweet_schema.user_screen_name;
 
     for author in hidden_authors {
       let term_query: Box<dyn Query> = Box::new(TermQuery::new(
@@ -1313,27 +1163,25 @@ fn get_results_query(
     }
   }
 
-  if exclude_retweets {
-    let text = schema.get_field("text").unwrap();
-    let query_parser = QueryParser::for_index(&index, vec![text]);
-
-    let rt_query: Box<dyn Query> =
-      Box::new(query_parser.parse_query(&format!("text:RT @")).unwrap());
+  if *exclude_retweets {
+    let rt_query: Box<dyn Query> = Box::new(TermQuery::new(
+      Term::from_field_u64(tweet_schema.is_retweet, 1),
+      IndexRecordOption::Basic,
+    ));
 
     query.push((Occur::MustNot, rt_query));
   }
 
-  if date.is_some() {
-    let published_time_ms = schema.get_field("published_time_ms").unwrap();
+  if let Some((start_date, end_date)) = dates {
+    let published_time_ms = tweet_schema.published_time_ms;
     let query_parser = QueryParser::for_index(&index, vec![published_time_ms]);
-    let date = date.unwrap();
 
     let date_query: Box<dyn Query> = Box::new(
       query_parser
         .parse_query(&format!(
           "published_time_ms:[{start_date_timestamp} TO {end_date_timestamp}]",
-          start_date_timestamp = date.and_hms(0, 0, 0).timestamp_millis(),
-          end_date_timestamp = date.and_hms(23, 59, 59).timestamp_millis(),
+          start_date_timestamp = start_date.and_hms(0, 0, 0).timestamp_millis(),
+          end_date_timestamp = end_date.and_hms_milli(23, 59, 59, 999).timestamp_millis(),
         ))
         .unwrap(),
     );
@@ -1342,7 +1190,7 @@ fn get_results_query(
   }
 
   if hashtag.is_some() {
-    let hashtags = schema.get_field("hashtags").unwrap();
+    let hashtags = tweet_schema.hashtags;
 
     let term_query: Box<dyn Query> = Box::new(TermQuery::new(
       Term::from_field_text(hashtags, &hashtag.as_ref().unwrap()),
@@ -1352,28 +1200,21 @@ fn get_results_query(
     query.push((Occur::Must, term_query));
   }
 
-  BooleanQuery::new(query)
+  Ok(BooleanQuery::new(query))
 }
 
 fn get_tweets_stats(
-  schema: &Schema,
+  tweet_schema: &TweetSchema,
   searcher: &LeasedItem<Searcher>,
   buckets: Vec<TweetsBucket>,
 ) -> Vec<TweetStats> {
-  let id = schema.get_field("id").unwrap();
-  let user_screen_name = schema.get_field("user_screen_name").unwrap();
-  let user_name = schema.get_field("user_name").unwrap();
-  let retweet_count = schema.get_field("retweet_count").unwrap();
-  let reply_count = schema.get_field("reply_count").unwrap();
-  let quote_count = schema.get_field("quote_count").unwrap();
-
   let mut query = Vec::new();
 
   let mut sub_query: Vec<(Occur, Box<dyn Query>)> = Vec::new();
   if buckets.len() > 0 {
     for bucket in buckets {
       let term_query: Box<dyn Query> = Box::new(TermQuery::new(
-        Term::from_field_text(id, &bucket.key),
+        Term::from_field_text(tweet_schema.id, &bucket.key),
         IndexRecordOption::Basic,
       ));
       sub_query.push((Occur::Should, term_query));
@@ -1398,73 +1239,15 @@ fn get_tweets_stats(
     .iter()
     .filter_map(|doc_address| searcher.doc(*doc_address).ok())
     .map(|doc| TweetStats {
-      user_name: extract(&doc, &user_name, extract_string),
-      user_screen_name: extract(&doc, &user_screen_name, extract_string),
-      retweet_count: extract(&doc, &retweet_count, extract_u64),
-      reply_count: extract(&doc, &reply_count, extract_u64),
-      quote_count: extract(&doc, &quote_count, extract_u64),
+      user_name: extract(&doc, &tweet_schema.user_name, extract_string),
+      user_screen_name: extract(&doc, &tweet_schema.user_screen_name, extract_string),
+      retweet_count: extract(&doc, &tweet_schema.retweet_count, extract_u64),
+      reply_count: extract(&doc, &tweet_schema.reply_count, extract_u64),
+      quote_count: extract(&doc, &tweet_schema.quote_count, extract_u64),
     })
     .collect()
 }
 
-fn get_frequence_by_date(
-  schema: &Schema,
-  searcher: &LeasedItem<Searcher>,
-  result: ByDay,
-  tab: &String,
-) -> Vec<FrequenceByDate> {
-  let mut data = vec![];
-  for bucket in result.par_jour.buckets {
-    data.push(match tab.as_str() {
-      "retweets" => {
-        let mut retweets = 0;
-
-        let tweets_stats = get_tweets_stats(&schema, &searcher, bucket.tweets_id.buckets);
-
-        for stat in tweets_stats {
-          retweets += stat.retweet_count;
-        }
-        FrequenceByDate {
-          date: bucket.key.date().naive_utc(),
-          frequence: retweets,
-        }
-      }
-      "citations" => {
-        let mut quotes = 0;
-
-        let tweets_stats = get_tweets_stats(&schema, &searcher, bucket.tweets_id.buckets);
-
-        for stat in tweets_stats {
-          quotes += stat.quote_count;
-        }
-        FrequenceByDate {
-          date: bucket.key.date().naive_utc(),
-          frequence: quotes,
-        }
-      }
-      "repondus" => {
-        let mut replies = 0;
-
-        let tweets_stats = get_tweets_stats(&schema, &searcher, bucket.tweets_id.buckets);
-
-        for stat in tweets_stats {
-          replies += stat.reply_count;
-        }
-        FrequenceByDate {
-          date: bucket.key.date().naive_utc(),
-          frequence: replies,
-        }
-      }
-      _ => FrequenceByDate {
-        date: bucket.key.date().naive_utc(),
-        frequence: bucket.doc_count,
-      },
-    });
-  }
-
-  data
-}
-
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Tweet {
   pub id: String,
@@ -1479,6 +1262,38 @@ pub struct Tweet {
   pub quote_count: u64,
   pub hashtags: Vec<String>,
   pub urls: Vec<String>,
+  #[serde(flatten)]
+  pub relations: TweetRelations,
+  #[serde(flatten)]
+  pub metadata: TweetMetadata,
+}
+
+/// Comptes mentionnés par un tweet et tweet d'origine d'un retweet, d'une réponse ou d'une
+/// citation, avec son auteur
+#[derive(Debug, Default, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct TweetRelations {
+  /// identifiants des comptes mentionnés
+  #[serde(default)]
+  pub mentions: Vec<String>,
+  #[serde(default)]
+  pub is_retweet: bool,
+  pub retweeted_id: Option<String>,
+  pub retweeted_user_id: Option<String>,
+  pub in_reply_to_id: Option<String>,
+  pub in_reply_to_user_id: Option<String>,
+  pub quoted_id: Option<String>,
+  pub quoted_user_id: Option<String>,
+}
+
+/// Langue, position et caractère sensible d'un tweet
+#[derive(Debug, Default, Deserialize, Serialize, Clone, PartialEq)]
+pub struct TweetMetadata {
+  /// code de la langue, en minuscules
+  pub language: Option<String>,
+  pub longitude: Option<f64>,
+  pub latitude: Option<f64>,
+  #[serde(default)]
+  pub possibly_sensitive: bool,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -1499,34 +1314,16 @@ pub struct Author {
   pub user_screen_name: String,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-pub struct ByDay {
-  pub par_jour: ByDayBuckets,
-}
-
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Authors {
   pub authors: AuthorBuckets,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-pub struct ByDayBuckets {
-  pub buckets: Vec<ByDayBucket>,
-}
-
 #[derive(Debug, Deserialize, Serialize)]
 pub struct AuthorBuckets {
   pub buckets: Vec<AuthorBucket>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-pub struct ByDayBucket {
-  #[serde(deserialize_with = "f64_date_de")]
-  pub key: DateTime<Utc>,
-  pub doc_count: u64,
-  pub tweets_id: TweetsBuckets,
-}
-
 #[derive(Debug, Deserialize, Serialize)]
 pub struct AuthorBucket {
   pub key: String,
@@ -1553,14 +1350,6 @@ pub struct TweetStats {
   pub quote_count: u64,
 }
 
-fn f64_date_de<'de, D>(deserializer: D) -> Result<DateTime<Utc>, D::Error>
-where
-  D: Deserializer<'de>,
-{
-  let n: f64 = <f64 as serde::Deserialize>::deserialize(deserializer)?;
-  Ok(Utc.timestamp_millis(n as i64))
-}
-
 #[derive(Debug, Clone, Serialize, PartialEq, Eq, Deserialize)]
 pub struct Frequence {
   #[serde(rename = "label")]
@@ -1582,14 +1371,161 @@ pub struct HashtagCooccurence {
   pub hashtag2: String,
 }
 
+/// Intervalle d'un histogramme, repéré par son début en heure locale
 #[derive(Debug, Clone, Serialize, PartialEq, Eq, Deserialize)]
 pub struct FrequenceByDate {
-  #[serde(rename = "x", serialize_with = "date_fr")]
-  pub date: NaiveDate,
+  #[serde(rename = "x", serialize_with = "date_fr", deserialize_with = "date_fr_de")]
+  pub date: NaiveDateTime,
   #[serde(rename = "y")]
   pub frequence: u64,
 }
 
-fn date_fr<S: Serializer>(d: &NaiveDate, serializer: S) -> Result<S::Ok, S::Error> {
-  serializer.serialize_str(d.format("%Y-%m-%d").to_string().as_str())
+fn date_fr<S: Serializer>(d: &NaiveDateTime, serializer: S) -> Result<S::Ok, S::Error> {
+  let format = if d.time() == NaiveTime::from_hms(0, 0, 0) {
+    "%Y-%m-%d"
+  } else {
+    "%Y-%m-%dT%H:%M"
+  };
+  serializer.serialize_str(d.format(format).to_string().as_str())
+}
+
+/// Relit aussi les graphiques enregistrés avant les intervalles horaires, datés au jour
+fn date_fr_de<'de, D>(deserializer: D) -> Result<NaiveDateTime, D::Error>
+where
+  D: Deserializer<'de>,
+{
+  let s = String::deserialize(deserializer)?;
+  NaiveDateTime::parse_from_str(&s, "%Y-%m-%dT%H:%M")
+    .or_else(|_| NaiveDate::parse_from_str(&s, "%Y-%m-%d").map(|d| d.and_hms(0, 0, 0)))
+    .map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+  use chrono::TimeZone;
+
+  use super::*;
+  use crate::test_utils::{index_with, tweet};
+
+  #[test]
+  fn test_results_order() {
+    // le tweet 3 est un retweet
+    let index = index_with(&[
+      Tweet {
+        retweet_count: 5,
+        ..tweet(1)
+      },
+      Tweet {
+        retweet_count: 1,
+        reply_count: 9,
+        ..tweet(2)
+      },
+      Tweet {
+        retweet_count: 3,
+        reply_count: 1,
+        relations: TweetRelations {
+          is_retweet: true,
+          ..Default::default()
+        },
+        ..tweet(3)
+      },
+    ]);
+
+    let search = |order_by: OrderBy, order: &str, exclude_retweets: bool| {
+      let results = ResultsQuery {
+        exclude_retweets,
+        order_by,
+        order: SortOrder::from(order),
+        ..Default::default()
+      };
+      search_tweets_for_result(&index, &results, 1, &None, &[], &[])
+        .unwrap()
+        .tweets
+        .into_iter()
+        .map(|highlighted| highlighted.tweet.id)
+        .collect::<Vec<_>>()
+    };
+    let ids = |order_by: OrderBy, order: &str| search(order_by, order, false);
+
+    assert_eq!(
+      ids(OrderBy::RetweetCount, "decroissant"),
+      vec!["1", "3", "2"]
+    );
+    assert_eq!(ids(OrderBy::RetweetCount, "croissant"), vec!["2", "3", "1"]);
+    assert_eq!(
+      ids(OrderBy::EngagementCount, "decroissant"),
+      vec!["2", "1", "3"]
+    );
+    assert_eq!(
+      ids(OrderBy::EngagementCount, "croissant"),
+      vec!["3", "1", "2"]
+    );
+    assert_eq!(
+      search(OrderBy::RetweetCount, "decroissant", true),
+      vec!["1", "2"]
+    );
+
+    let ids = |tweets: Vec<Tweet>| tweets.into_iter().map(|tweet| tweet.id).collect::<Vec<_>>();
+    assert_eq!(
+      ids(search_tweets(&index, "loup", &Some(OrderBy::EngagementCount)).unwrap()),
+      vec!["2", "1", "3"]
+    );
+    let query_ids = |query: &str| {
+      let query = QueryExpr::parse(query).unwrap();
+      let order = SortOrder::Descending;
+      let tweets = search_tweets_with_limit(&index, &query, &OrderBy::ReplyCount, order, 2);
+      ids(tweets.unwrap())
+    };
+    assert_eq!(query_ids("loup @eleveurs_fr"), vec!["2", "3"]);
+    assert!(query_ids("loup date:[2022-06-13 TO 2022-06-30]").is_empty());
+  }
+
+  #[test]
+  fn test_results_dates() {
+    let day = |day: u32| Utc.ymd(2022, 6, day).and_hms(23, 59, 59);
+    let index = index_with(&[
+      tweet(1).at(day(4)),
+      tweet(2).at(day(5)),
+      tweet(3).at(day(6)),
+      tweet(4).at(day(7)),
+    ]);
+
+    let ids = |dates: Option<(NaiveDate, NaiveDate)>| {
+      let results = ResultsQuery {
+        dates,
+        order: SortOrder::Ascending,
+        ..Default::default()
+      };
+      search_tweets_for_result(&index, &results, 1, &None, &[], &[])
+        .unwrap()
+        .tweets
+        .into_iter()
+        .map(|highlighted| highlighted.tweet.id)
+        .collect::<Vec<_>>()
+    };
+
+    let june = |day: u32| NaiveDate::from_ymd(2022, 6, day);
+    assert_eq!(ids(None), vec!["1", "2", "3", "4"]);
+    assert_eq!(ids(Some((june(5), june(5)))), vec!["2"]);
+    assert_eq!(ids(Some((june(5), june(6)))), vec!["2", "3"]);
+  }
+
+  #[test]
+  fn test_hashtag_search() {
+    let index = index_with(&[
+      tweet(1).with_hashtags(&["vegan", "recette"]),
+      tweet(2).with_hashtags(&["vgean"]),
+      tweet(3).with_hashtags(&["vegans", "vegan"]),
+      tweet(4).with_hashtags(&["végétarien"]),
+    ]);
+
+    let matches = hashtag_search(&index, "vegan", 10).unwrap();
+    let matches: Vec<_> = matches
+      .iter()
+      .map(|hashtag| (hashtag.key.as_str(), hashtag.doc_count))
+      .collect();
+    assert_eq!(matches, vec![("vegan", 2), ("vegans", 1), ("vgean", 1)]);
+    assert_eq!(hashtag_search(&index, "vegan", 1).unwrap().len(), 1);
+    assert_eq!(edit_distance("vegan", "végétarien"), 6);
+  }
 }
@@ -0,0 +1,362 @@
+//! Liens partagés dans les tweets : URLs et domaines les plus partagés.
+//!
+//! Les URLs sont indexées normalisées par [`normalize_url`], pour qu'un même lien compte
+//! une seule fois quelle que soit sa forme : hôte en minuscules, sans fragment, sans
+//! paramètres de suivi ni barre oblique finale. Le domaine de chaque URL, sans `www.`, est
+//! indexé dans la facette `domains`.
+
+use std::collections::HashMap;
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use tantivy::{
+  aggregation::{
+    agg_req::{Aggregation, Aggregations, BucketAggregation, BucketAggregationType},
+    agg_result::{AggregationResult, BucketResult},
+    bucket::{CustomOrder, Order, OrderTarget, TermsAggregation},
+    AggregationCollector,
+  },
+  collector::FacetCollector,
+  query::{AllQuery, Query, TermQuery},
+  schema::{Facet, IndexRecordOption},
+  Index, Term,
+};
+use url::Url;
+
+use crate::{
+  frequence_by_date, Author, AuthorCount, FrequenceByDate, SearchError, TimeBuckets, TweetSchema,
+  TweetStream,
+};
+
+/// Paramètres de suivi retirés des URLs, en plus de ceux commençant par `utm_`
+const TRACKING_PARAMETERS: &[&str] = &["fbclid", "gclid", "igshid", "mc_cid", "mc_eid", "ref_src"];
+
+/// Options des classements de liens
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LinkOptions {
+  /// nombre d'URLs ou de domaines retournés
+  pub limit: usize,
+  /// nombre de comptes retournés pour chacun
+  pub authors: usize,
+}
+
+impl Default for LinkOptions {
+  fn default() -> Self {
+    LinkOptions {
+      limit: 20,
+      authors: 5,
+    }
+  }
+}
+
+/// URL ou domaine partagé dans les tweets d'un index
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SharedLink {
+  /// URL normalisée ou domaine
+  pub key: String,
+  /// nombre de tweets le partageant
+  pub doc_count: u64,
+  /// comptes l'ayant le plus partagé, par nombre de tweets décroissant
+  pub authors: Vec<AuthorCount>,
+  pub frequences: Vec<FrequenceByDate>,
+}
+
+/// Forme canonique de `url`, inchangée si elle ne se lit pas comme une URL
+pub fn normalize_url(url: &str) -> String {
+  let url = url.trim();
+  let mut parsed = match Url::parse(url) {
+    Ok(parsed) if parsed.has_host() => parsed,
+    _ => return url.to_string(),
+  };
+
+  parsed.set_fragment(None);
+  let pairs: Vec<(String, String)> = parsed.query_pairs().into_owned().collect();
+  let kept: Vec<&(String, String)> = pairs
+    .iter()
+    .filter(|(key, _)| !key.starts_with("utm_") && !TRACKING_PARAMETERS.contains(&key.as_str()))
+    .collect();
+  if kept.is_empty() {
+    parsed.set_query(None);
+  } else if kept.len() < pairs.len() {
+    parsed.query_pairs_mut().clear().extend_pairs(kept);
+  }
+
+  let normalized = parsed.to_string();
+  match parsed.query() {
+    None => normalized.trim_end_matches('/').to_string(),
+    Some(_) => normalized,
+  }
+}
+
+/// Domaine de `url` sans `www.`, `None` si elle n'a pas d'hôte
+pub fn url_domain(url: &str) -> Option<String> {
+  let parsed = Url::parse(url).ok()?;
+  let host = parsed.host_str()?.to_lowercase();
+
+  Some(host.strip_prefix("www.").unwrap_or(&host).to_string())
+}
+
+/// URLs les plus partagées dans les tweets de `index`, avec l'histogramme de `tab`
+pub fn top_shared_urls(
+  index: &Index,
+  start_date: &NaiveDate,
+  end_date: &NaiveDate,
+  tab: &str,
+  buckets: &TimeBuckets,
+  options: &LinkOptions,
+) -> Result<Vec<SharedLink>, SearchError> {
+  let searcher = index.reader()?.searcher();
+  let tweet_schema = TweetSchema::from_index(index)?;
+
+  let agg_req: Aggregations = vec![(
+    "urls".to_string(),
+    Aggregation::Bucket(BucketAggregation {
+      bucket_agg: BucketAggregationType::Terms(TermsAggregation {
+        field: "urls".to_string(),
+        order: Some(CustomOrder {
+          target: OrderTarget::Count,
+          order: Order::Desc,
+        }),
+        size: Some(options.limit as u32),
+        ..Default::default()
+      }),
+      sub_aggregation: Default::default(),
+    }),
+  )]
+  .into_iter()
+  .collect();
+  let agg_res = searcher.search(&AllQuery, &AggregationCollector::from_aggs(agg_req))?;
+  let buckets_entries = match agg_res.0.get("urls") {
+    Some(AggregationResult::BucketResult(BucketResult::Terms { buckets, .. })) => buckets.clone(),
+    _ => vec![],
+  };
+
+  buckets_entries
+    .into_iter()
+    .map(|bucket| {
+      let key = bucket.key.to_string();
+      let query = TermQuery::new(
+        Term::from_field_text(tweet_schema.urls, &key),
+        IndexRecordOption::Basic,
+      );
+      Ok(SharedLink {
+        key,
+        doc_count: bucket.doc_count,
+        authors: sharing_authors(index, &query, options.authors)?,
+        frequences: frequence_by_date(
+          &searcher,
+          &tweet_schema,
+          &query,
+          start_date,
+          end_date,
+          tab,
+          buckets,
+        )?,
+      })
+    })
+    .collect()
+}
+
+/// Domaines les plus partagés dans les tweets de `index`, avec l'histogramme de `tab`
+pub fn top_shared_domains(
+  index: &Index,
+  start_date: &NaiveDate,
+  end_date: &NaiveDate,
+  tab: &str,
+  buckets: &TimeBuckets,
+  options: &LinkOptions,
+) -> Result<Vec<SharedLink>, SearchError> {
+  let searcher = index.reader()?.searcher();
+  let tweet_schema = TweetSchema::from_index(index)?;
+
+  let mut collector = FacetCollector::for_field(tweet_schema.domains);
+  collector.add_facet(Facet::root());
+  let facet_counts = searcher.search(&AllQuery, &collector)?;
+  let mut domains: Vec<(Facet, u64)> = facet_counts
+    .get(Facet::root())
+    .map(|(facet, count)| (facet.clone(), count))
+    .collect();
+  domains.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+  domains.truncate(options.limit);
+
+  domains
+    .into_iter()
+    .map(|(facet, doc_count)| {
+      let query = TermQuery::new(
+        Term::from_facet(tweet_schema.domains, &facet),
+        IndexRecordOption::Basic,
+      );
+      Ok(SharedLink {
+        key: facet.to_path().concat(),
+        doc_count,
+        authors: sharing_authors(index, &query, options.authors)?,
+        frequences: frequence_by_date(
+          &searcher,
+          &tweet_schema,
+          &query,
+          start_date,
+          end_date,
+          tab,
+          buckets,
+        )?,
+      })
+    })
+    .collect()
+}
+
+/// Les `limit` comptes ayant publié le plus de tweets correspondant à `query`
+fn sharing_authors(
+  index: &Index,
+  query: &dyn Query,
+  limit: usize,
+) -> Result<Vec<AuthorCount>, SearchError> {
+  let mut counts: HashMap<String, AuthorCount> = HashMap::new();
+  for tweet in TweetStream::new(index, query)? {
+    let tweet = tweet?;
+    counts
+      .entry(tweet.user_id)
+      .or_insert_with(|| AuthorCount {
+        author: Author {
+          user_name: tweet.user_name,
+          user_screen_name: tweet.user_screen_name,
+        },
+        count: 0,
+      })
+      .count += 1;
+  }
+
+  let mut authors: Vec<AuthorCount> = counts.into_values().collect();
+  authors.sort_by(|a, b| {
+    b.count
+      .cmp(&a.count)
+      .then_with(|| a.author.user_screen_name.cmp(&b.author.user_screen_name))
+  });
+  authors.truncate(limit);
+
+  Ok(authors)
+}
+
+#[cfg(test)]
+mod tests {
+  use chrono::{TimeZone, Utc};
+
+  use super::*;
+  use crate::{
+    test_utils::{index_with, tweet},
+    Tweet,
+  };
+
+  #[test]
+  fn test_top_shared_links() {
+    assert_eq!(
+      normalize_url(" HTTPS://WWW.Example.org/loup/?utm_source=twitter&id=3#haut "),
+      "https://www.example.org/loup/?id=3"
+    );
+    assert_eq!(
+      normalize_url("https://example.org/loup/?fbclid=x"),
+      "https://example.org/loup"
+    );
+    assert_eq!(normalize_url("pas une url"), "pas une url");
+    assert_eq!(
+      url_domain("https://www.example.org/loup"),
+      Some("example.org".to_string())
+    );
+    assert_eq!(url_domain("pas une url"), None);
+
+    // un tweet par jour à partir du 12 juin 2022
+    let tweets: Vec<_> = [
+      (
+        1,
+        "eleveurs_fr",
+        vec![
+          "https://example.org/loup/",
+          "https://example.org/loup?utm_medium=x",
+        ],
+      ),
+      (
+        2,
+        "eleveurs_fr",
+        vec!["https://example.org/loup#commentaires"],
+      ),
+      (
+        3,
+        "bergers",
+        vec!["https://example.org/loup", "https://blog.fr/ours"],
+      ),
+      (4, "bergers", vec!["https://blog.fr/ours/"]),
+      (5, "bergers", vec!["https://www.example.org/ours"]),
+      (6, "bergers", vec![]),
+    ]
+    .into_iter()
+    .map(|(id, user, urls)| Tweet {
+      urls: urls.into_iter().map(str::to_string).collect(),
+      ..tweet(id)
+        .by(user)
+        .at(Utc.timestamp(1_655_000_000 + id * 86_400, 0))
+    })
+    .collect();
+    let index = index_with(&tweets);
+
+    let (start_date, end_date) = (
+      NaiveDate::from_ymd(2022, 6, 13),
+      NaiveDate::from_ymd(2022, 6, 17),
+    );
+    let options = LinkOptions {
+      limit: 2,
+      authors: 5,
+    };
+    let urls = top_shared_urls(
+      &index,
+      &start_date,
+      &end_date,
+      "total",
+      &TimeBuckets::default(),
+      &options,
+    )
+    .unwrap();
+    let keys = |links: &[SharedLink]| {
+      links
+        .iter()
+        .map(|link| (link.key.clone(), link.doc_count))
+        .collect::<Vec<_>>()
+    };
+    // les deux formes de la même URL du premier tweet ne comptent qu'une fois
+    assert_eq!(
+      keys(&urls),
+      vec![
+        ("https://example.org/loup".to_string(), 3),
+        ("https://blog.fr/ours".to_string(), 2)
+      ]
+    );
+    assert_eq!(
+      urls[0]
+        .frequences
+        .iter()
+        .map(|frequence| frequence.frequence)
+        .collect::<Vec<_>>(),
+      vec![1, 1, 1, 0, 0]
+    );
+
+    let domains = top_shared_domains(
+      &index,
+      &start_date,
+      &end_date,
+      "total",
+      &TimeBuckets::default(),
+      &options,
+    )
+    .unwrap();
+    assert_eq!(
+      keys(&domains),
+      vec![("example.org".to_string(), 4), ("blog.fr".to_string(), 2)]
+    );
+    assert_eq!(
+      domains[0]
+        .authors
+        .iter()
+        .map(|author| (author.author.user_screen_name.as_str(), author.count))
+        .collect::<Vec<_>>(),
+      vec![("bergers", 2), ("eleveurs_fr", 2)]
+    );
+  }
+}
@@ -0,0 +1,505 @@
+//! Maintenance d'un index : statistiques, fusion des segments, vérification, sauvegarde et
+//! restauration.
+//!
+//! Ces opérations sont destinées à la ligne de commande. La sauvegarde prend le verrou
+//! d'écriture de l'index pendant la copie ; la restauration remplace le répertoire de
+//! l'index et doit être faite serveur arrêté.
+
+use std::{
+  collections::HashSet,
+  fmt::Display,
+  fs,
+  path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, TimeZone, Utc};
+use serde::Serialize;
+use tantivy::{
+  fastfield::FastFieldReader, schema::Field, schema::IndexRecordOption, DocSet, Index, Searcher,
+  TERMINATED,
+};
+
+use crate::{
+  index_stamp, migrate::sibling, register_tokenizers, retrieve_index, SearchError, TweetSchema,
+  INDEX_STAMP_FILE, SCHEMA_VERSION,
+};
+
+/// Fichier de tantivy décrivant les segments de l'index
+const META_FILE: &str = "meta.json";
+
+/// Nombre maximal d'anomalies détaillées dans un rapport de vérification
+const MAX_REPORTED_PROBLEMS: usize = 100;
+
+/// État d'un index
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct IndexStats {
+  pub version: u32,
+  pub documents: u64,
+  /// documents supprimés, retirés des segments à leur prochaine fusion
+  pub deleted_documents: u64,
+  pub segments: usize,
+  /// taille du répertoire de l'index, en octets
+  pub size_bytes: u64,
+  pub first_published_time: Option<DateTime<Utc>>,
+  pub last_published_time: Option<DateTime<Utc>>,
+  pub distinct_authors: u64,
+  pub distinct_hashtags: u64,
+}
+
+/// Bilan d'une fusion des segments
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct MergeStats {
+  pub segments_before: usize,
+  pub segments_after: usize,
+  pub deleted_documents_purged: u64,
+  pub files_deleted: usize,
+}
+
+/// Rapport de vérification d'un index
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct VerifyReport {
+  pub version: u32,
+  pub documents: u64,
+  pub problem_count: u64,
+  /// les premières anomalies relevées, vide si l'index est sain
+  pub problems: Vec<String>,
+}
+
+impl VerifyReport {
+  pub fn is_ok(&self) -> bool {
+    self.problem_count == 0
+  }
+
+  fn problem(&mut self, problem: String) {
+    self.problem_count += 1;
+    if self.problems.len() < MAX_REPORTED_PROBLEMS {
+      self.problems.push(problem);
+    }
+  }
+}
+
+/// Bilan d'une sauvegarde ou d'une restauration
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct SnapshotStats {
+  pub files: u64,
+  pub size_bytes: u64,
+}
+
+/// Nombre de documents, de segments, période couverte et nombres d'auteurs et de hashtags
+/// distincts de l'index `directory_path`, quelle que soit sa version
+pub fn index_stats<P: AsRef<Path>>(directory_path: P) -> Result<IndexStats, SearchError> {
+  let directory_path = directory_path.as_ref();
+  let index = open_index(directory_path)?;
+  let schema = index.schema();
+  let searcher = index.reader()?.searcher();
+
+  let mut stats = IndexStats {
+    version: index_stamp(directory_path, &index)?.version,
+    documents: searcher.num_docs(),
+    segments: searcher.segment_readers().len(),
+    size_bytes: directory_size(directory_path)?,
+    distinct_authors: distinct_terms(&searcher, schema.get_field("user_id"))?,
+    distinct_hashtags: distinct_terms(&searcher, schema.get_field("hashtags"))?,
+    ..Default::default()
+  };
+  for segment_reader in searcher.segment_readers() {
+    stats.deleted_documents += u64::from(segment_reader.num_deleted_docs());
+  }
+
+  if let Some(published_time_ms) = schema.get_field("published_time_ms") {
+    let mut range: Option<(u64, u64)> = None;
+    for segment_reader in searcher.segment_readers() {
+      let reader = segment_reader.fast_fields().u64(published_time_ms)?;
+      for doc in segment_reader.doc_ids_alive() {
+        let value = reader.get(doc);
+        range = Some(match range {
+          Some((first, last)) => (first.min(value), last.max(value)),
+          None => (value, value),
+        });
+      }
+    }
+    if let Some((first, last)) = range {
+      stats.first_published_time = Some(Utc.timestamp_millis(first as i64));
+      stats.last_published_time = Some(Utc.timestamp_millis(last as i64));
+    }
+  }
+
+  Ok(stats)
+}
+
+/// Fusionne tous les segments de l'index `directory_path` en un seul, ce qui retire
+/// définitivement les documents supprimés, puis efface les fichiers devenus inutiles
+pub fn merge_index<P: AsRef<Path>>(directory_path: P) -> Result<MergeStats, SearchError> {
+  let index = open_index(&directory_path)?;
+  let searcher = index.reader()?.searcher();
+  let segment_ids = index.searchable_segment_ids()?;
+
+  let mut stats = MergeStats {
+    segments_before: segment_ids.len(),
+    segments_after: segment_ids.len(),
+    ..Default::default()
+  };
+  for segment_reader in searcher.segment_readers() {
+    stats.deleted_documents_purged += u64::from(segment_reader.num_deleted_docs());
+  }
+  drop(searcher);
+
+  let mut index_writer = index.writer(50_000_000)?;
+  if segment_ids.len() > 1 || stats.deleted_documents_purged > 0 {
+    index_writer.merge(&segment_ids).wait()?;
+    stats.segments_after = index.searchable_segment_ids()?.len();
+  }
+  stats.files_deleted = index_writer
+    .garbage_collect_files()
+    .wait()?
+    .deleted_files
+    .len();
+  index_writer.wait_merging_threads()?;
+
+  tracing::info!(
+    "index {} : {} segments fusionnés en {}, {} documents supprimés purgés",
+    directory_path.as_ref().display(),
+    stats.segments_before,
+    stats.segments_after,
+    stats.deleted_documents_purged
+  );
+
+  Ok(stats)
+}
+
+/// Vérifie la version et le schéma de l'index `directory_path`, puis chacun de ses
+/// documents : champs lisibles, identifiant unique, dates et engagement cohérents
+pub fn verify_index<P: AsRef<Path>>(directory_path: P) -> Result<VerifyReport, SearchError> {
+  let directory_path = directory_path.as_ref();
+  let index = open_index(directory_path)?;
+  let stamp = index_stamp(directory_path, &index)?;
+
+  let mut report = VerifyReport {
+    version: stamp.version,
+    ..Default::default()
+  };
+  if stamp.is_outdated() {
+    report.problem(format!(
+      "index en version {}, la version {SCHEMA_VERSION} est attendue",
+      stamp.version
+    ));
+  }
+  let tweet_schema = match TweetSchema::from_index(&index) {
+    Ok(tweet_schema) => tweet_schema.with_engagement(stamp.options.engagement),
+    Err(e) => {
+      report.problem(e.to_string());
+      return Ok(report);
+    }
+  };
+
+  let searcher = index.reader()?.searcher();
+  let mut ids = HashSet::new();
+  for (segment_ord, segment_reader) in searcher.segment_readers().iter().enumerate() {
+    let store_reader = segment_reader
+      .get_store_reader()
+      .map_err(maintenance_error)?;
+    for doc in segment_reader.doc_ids_alive() {
+      report.documents += 1;
+      let document = store_reader.get(doc)?;
+      let tweet = match tweet_schema.to_tweet(&document) {
+        Ok(tweet) => tweet,
+        Err(e) => {
+          report.problem(format!("document {segment_ord}.{doc} : {e}"));
+          continue;
+        }
+      };
+
+      if tweet.id.is_empty() {
+        report.problem(format!("document {segment_ord}.{doc} : identifiant vide"));
+      } else if !ids.insert(tweet.id.clone()) {
+        report.problem(format!("tweet {} indexé plusieurs fois", tweet.id));
+      }
+      if tweet.published_time.timestamp() != (tweet.published_time_ms / 1_000) as i64 {
+        report.problem(format!(
+          "tweet {} : published_time et published_time_ms diffèrent",
+          tweet.id
+        ));
+      }
+      let engagement = document
+        .get_first(tweet_schema.engagement_count)
+        .and_then(|value| value.as_u64());
+      if engagement != Some(tweet_schema.engagement.engagement(&tweet)) {
+        report.problem(format!(
+          "tweet {} : engagement_count ne correspond pas aux compteurs",
+          tweet.id
+        ));
+      }
+    }
+  }
+
+  Ok(report)
+}
+
+/// Copie l'index `directory_path` dans le répertoire `snapshot_path`, qui doit être vide ou
+/// absent. Seuls les fichiers des segments du dernier commit sont copiés.
+pub fn snapshot_index<P: AsRef<Path>, Q: AsRef<Path>>(
+  directory_path: P,
+  snapshot_path: Q,
+) -> Result<SnapshotStats, SearchError> {
+  let (directory_path, snapshot_path) = (directory_path.as_ref(), snapshot_path.as_ref());
+  if fs::read_dir(snapshot_path).is_ok_and(|mut entries| entries.next().is_some()) {
+    return Err(SearchError::Maintenance(format!(
+      "{} n'est pas vide",
+      snapshot_path.display()
+    )));
+  }
+
+  let index = open_index(directory_path)?;
+  // aucun commit ni nettoyage des fichiers tant que le verrou d'écriture est tenu
+  let index_writer = index.writer_with_num_threads(1, 15_000_000)?;
+  let metas = index.load_metas()?;
+
+  fs::create_dir_all(snapshot_path).map_err(maintenance_error)?;
+  let mut files = metas
+    .segments
+    .iter()
+    .flat_map(|segment| segment.list_files())
+    .filter(|file| directory_path.join(file).exists())
+    .collect::<Vec<_>>();
+  if directory_path.join(INDEX_STAMP_FILE).exists() {
+    files.push(PathBuf::from(INDEX_STAMP_FILE));
+  }
+  let mut stats = copy_files(directory_path, snapshot_path, &files)?;
+
+  let meta = serde_json::to_vec_pretty(&metas)?;
+  fs::write(snapshot_path.join(META_FILE), &meta).map_err(maintenance_error)?;
+  stats.files += 1;
+  stats.size_bytes += meta.len() as u64;
+  drop(index_writer);
+
+  tracing::info!(
+    "index {} sauvegardé dans {} : {} fichiers",
+    directory_path.display(),
+    snapshot_path.display(),
+    stats.files
+  );
+
+  Ok(stats)
+}
+
+/// Remplace l'index `directory_path` par la sauvegarde `snapshot_path`, après avoir vérifié
+/// que celle-ci s'ouvre dans la version courante du schéma
+pub fn restore_index<P: AsRef<Path>, Q: AsRef<Path>>(
+  snapshot_path: P,
+  directory_path: Q,
+) -> Result<SnapshotStats, SearchError> {
+  let (snapshot_path, directory_path) = (snapshot_path.as_ref(), directory_path.as_ref());
+  drop(retrieve_index(snapshot_path)?);
+
+  let restored = sibling(directory_path, "restauration");
+  if restored.exists() {
+    fs::remove_dir_all(&restored).map_err(maintenance_error)?;
+  }
+  fs::create_dir_all(&restored).map_err(maintenance_error)?;
+  let files = fs::read_dir(snapshot_path)
+    .map_err(maintenance_error)?
+    .filter_map(Result::ok)
+    .filter(|entry| entry.path().is_file())
+    .map(|entry| PathBuf::from(entry.file_name()))
+    .collect::<Vec<_>>();
+  let stats = copy_files(snapshot_path, &restored, &files)?;
+
+  if directory_path.exists() {
+    let previous = sibling(directory_path, "avant-restauration");
+    if previous.exists() {
+      fs::remove_dir_all(&previous).map_err(maintenance_error)?;
+    }
+    fs::rename(directory_path, &previous).map_err(maintenance_error)?;
+    fs::rename(&restored, directory_path).map_err(maintenance_error)?;
+    fs::remove_dir_all(&previous).map_err(maintenance_error)?;
+  } else {
+    fs::rename(&restored, directory_path).map_err(maintenance_error)?;
+  }
+
+  tracing::info!(
+    "index {} restauré depuis {} : {} fichiers",
+    directory_path.display(),
+    snapshot_path.display(),
+    stats.files
+  );
+
+  Ok(stats)
+}
+
+fn open_index<P: AsRef<Path>>(directory_path: P) -> Result<Index, SearchError> {
+  let index = Index::open_in_dir(&directory_path)?;
+  register_tokenizers(&index);
+
+  Ok(index)
+}
+
+/// Nombre de termes distincts de `field` portés par au moins un document non supprimé
+fn distinct_terms(searcher: &Searcher, field: Option<Field>) -> Result<u64, SearchError> {
+  let field = match field {
+    Some(field) => field,
+    None => return Ok(0),
+  };
+
+  let mut terms = HashSet::new();
+  for segment_reader in searcher.segment_readers() {
+    let inverted_index = segment_reader.inverted_index(field)?;
+    let mut stream = inverted_index.terms().stream().map_err(maintenance_error)?;
+    while stream.advance() {
+      let alive = match segment_reader.alive_bitset() {
+        None => true,
+        Some(alive_bitset) => {
+          let mut postings = inverted_index
+            .read_postings_from_terminfo(stream.value(), IndexRecordOption::Basic)
+            .map_err(maintenance_error)?;
+          let mut alive = false;
+          while !alive && postings.doc() != TERMINATED {
+            alive = alive_bitset.is_alive(postings.doc());
+            postings.advance();
+          }
+          alive
+        }
+      };
+      if alive && !terms.contains(stream.key()) {
+        terms.insert(stream.key().to_vec());
+      }
+    }
+  }
+
+  Ok(terms.len() as u64)
+}
+
+fn directory_size(directory_path: &Path) -> Result<u64, SearchError> {
+  let mut size = 0;
+  for entry in fs::read_dir(directory_path).map_err(maintenance_error)? {
+    let metadata = entry
+      .map_err(maintenance_error)?
+      .metadata()
+      .map_err(maintenance_error)?;
+    if metadata.is_file() {
+      size += metadata.len();
+    }
+  }
+
+  Ok(size)
+}
+
+/// Copie les fichiers `files`, relatifs à `from`, dans `to`
+fn copy_files(from: &Path, to: &Path, files: &[PathBuf]) -> Result<SnapshotStats, SearchError> {
+  let mut stats = SnapshotStats::default();
+  for file in files {
+    stats.size_bytes += fs::copy(from.join(file), to.join(file)).map_err(maintenance_error)?;
+    stats.files += 1;
+  }
+
+  Ok(stats)
+}
+
+fn maintenance_error<E: Display>(e: E) -> SearchError {
+  SearchError::Maintenance(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+  use std::io::Cursor;
+
+  use super::*;
+  use crate::{create_index_config, delete_tweets, doc_count, ingest};
+
+  fn line(id: &str, published_time: u64, user_id: &str, hashtag: &str) -> String {
+    format!(
+      r#"{{"id":"{id}","published_time":{published_time},"user_id":"{user_id}","user_name":"Éleveurs","user_screen_name":"eleveurs_fr","text":"Retour du loup","hashtags":["{hashtag}"],"urls":[],"retweet_count":0}}"#
+    )
+  }
+
+  #[test]
+  fn test_stats_merge_verify_snapshot() {
+    let directory =
+      std::env::temp_dir().join(format!("cocktail-maintenance-{}", std::process::id()));
+    let snapshot = sibling(&directory, "sauvegarde");
+    let _ = fs::remove_dir_all(&directory);
+    let _ = fs::remove_dir_all(&snapshot);
+    create_index_config(&directory).unwrap();
+
+    // deux segments, dont un tweet supprimé : seul auteur du hashtag `ours`
+    ingest(
+      &directory,
+      Cursor::new(
+        [
+          line("1", 1_655_000_000_000, "42", "loup"),
+          line("2", 1_656_000_000_000, "42", "loup"),
+        ]
+        .join("\n"),
+      ),
+    )
+    .unwrap();
+    ingest(
+      &directory,
+      Cursor::new(
+        [
+          line("3", 1_654_000_000_000, "43", "pastoralisme"),
+          line("4", 1_657_000_000_000, "44", "ours"),
+        ]
+        .join("\n"),
+      ),
+    )
+    .unwrap();
+    delete_tweets(&directory, &["4".to_string()]).unwrap();
+
+    let stats = index_stats(&directory).unwrap();
+    assert_eq!(
+      (
+        stats.version,
+        stats.documents,
+        stats.deleted_documents,
+        stats.segments
+      ),
+      (SCHEMA_VERSION, 3, 1, 2)
+    );
+    assert_eq!((stats.distinct_authors, stats.distinct_hashtags), (2, 2));
+    assert_eq!(
+      stats.first_published_time,
+      Some(Utc.timestamp(1_654_000_000, 0))
+    );
+    assert_eq!(
+      stats.last_published_time,
+      Some(Utc.timestamp(1_656_000_000, 0))
+    );
+
+    let merged = merge_index(&directory).unwrap();
+    assert_eq!(
+      (
+        merged.segments_before,
+        merged.segments_after,
+        merged.deleted_documents_purged
+      ),
+      (2, 1, 1)
+    );
+    let stats = index_stats(&directory).unwrap();
+    assert_eq!(
+      (stats.documents, stats.deleted_documents, stats.segments),
+      (3, 0, 1)
+    );
+
+    let report = verify_index(&directory).unwrap();
+    assert!(report.is_ok(), "{:?}", report.problems);
+    assert_eq!(report.documents, 3);
+
+    snapshot_index(&directory, &snapshot).unwrap();
+    assert!(snapshot_index(&directory, &snapshot).is_err());
+    delete_tweets(&directory, &["1".to_string(), "2".to_string()]).unwrap();
+    assert_eq!(
+      doc_count(&retrieve_index(&directory).unwrap(), None).unwrap(),
+      1
+    );
+
+    restore_index(&snapshot, &directory).unwrap();
+    assert_eq!(
+      doc_count(&retrieve_index(&directory).unwrap(), None).unwrap(),
+      3
+    );
+    assert!(verify_index(&directory).unwrap().is_ok());
+
+    fs::remove_dir_all(&directory).unwrap();
+    fs::remove_dir_all(&snapshot).unwrap();
+  }
+}
@@ -0,0 +1,387 @@
+//! Version du schéma des index et migration des index anciens.
+//!
+//! Chaque index créé par [`create_index_config_with_options`] porte un fichier
+//! [`INDEX_STAMP_FILE`] indiquant la version de son schéma et ses options d'analyse. Un index
+//! sans ce fichier, créé avant le versionnage, est daté d'après ses champs.
+//!
+//! Historique des versions :
+//! - 1 : schéma d'origine, `text` analysé par l'analyseur par défaut de tantivy ; les index
+//!   les plus anciens n'ont pas non plus `engagement_count` ni les champs `asc_*`
+//! - 2 : analyse du français, `text` racinisé et `text_exact`
+//! - 3 : engagement pondéré, champs `asc_*` retirés (tri croissant par le collecteur)
+//! - 4 : `urls` normalisées et non découpées, facette `domains`
+//! - 5 : mentions, retweets, réponses et citations (`mentions`, `is_retweet`, `*_id` et
+//!   `*_user_id` du tweet d'origine)
+//! - 6 : facette `language`, `longitude`, `latitude` et `possibly_sensitive` ; les index
+//!   migrés n'en ont pas, une nouvelle ingestion les complète
+
+use std::{
+  fmt::Display,
+  fs,
+  path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use tantivy::{schema::Schema, Document, Index};
+
+use crate::{
+  create_index_config_with_options, ingest::is_retweet_text, register_tokenizers,
+  EngagementWeights, IndexOptions, SearchError, TextAnalysisOptions, Tweet, TweetRelations,
+  TweetSchema, FRENCH_STEM_STOPWORDS_TOKENIZER,
+};
+
+/// Version du schéma créé par [`TweetSchema::build`]
+pub const SCHEMA_VERSION: u32 = 6;
+
+/// Fichier de l'index contenant son [`IndexStamp`]
+pub const INDEX_STAMP_FILE: &str = "cocktail-index.json";
+
+/// Nombre de documents entre deux commits du nouvel index
+const COMMIT_EVERY: u64 = 100_000;
+
+/// Version du schéma d'un index et options choisies à sa création
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexStamp {
+  pub version: u32,
+  #[serde(flatten)]
+  pub options: IndexOptions,
+}
+
+impl IndexStamp {
+  pub fn current(options: IndexOptions) -> Self {
+    IndexStamp {
+      version: SCHEMA_VERSION,
+      options,
+    }
+  }
+
+  pub fn is_outdated(&self) -> bool {
+    self.version < SCHEMA_VERSION
+  }
+}
+
+/// Bilan d'une migration
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct MigrationStats {
+  pub from_version: u32,
+  pub to_version: u32,
+  pub documents: u64,
+  /// documents stockés sans identifiant ou sans texte, non repris
+  pub skipped: u64,
+  /// ancien index conservé à la demande
+  pub backup: Option<PathBuf>,
+}
+
+pub fn write_index_stamp<P: AsRef<Path>>(
+  directory_path: P,
+  stamp: &IndexStamp,
+) -> std::io::Result<()> {
+  fs::write(
+    directory_path.as_ref().join(INDEX_STAMP_FILE),
+    serde_json::to_vec_pretty(stamp)?,
+  )
+}
+
+/// Version de l'index ouvert depuis `directory_path` : celle de son fichier de version s'il
+/// en a un, déduite de ses champs sinon
+pub fn index_stamp<P: AsRef<Path>>(
+  directory_path: P,
+  index: &Index,
+) -> Result<IndexStamp, SearchError> {
+  match fs::read(directory_path.as_ref().join(INDEX_STAMP_FILE)) {
+    Ok(content) => Ok(serde_json::from_slice(&content)?),
+    Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(inferred_stamp(&index.schema())),
+    Err(e) => Err(migration_error(e)),
+  }
+}
+
+fn inferred_stamp(schema: &Schema) -> IndexStamp {
+  let stopwords = schema
+    .get_field("text")
+    .and_then(|text| match schema.get_field_entry(text).field_type() {
+      tantivy::schema::FieldType::Str(options) => options
+        .get_indexing_options()
+        .map(|indexing| indexing.tokenizer() == FRENCH_STEM_STOPWORDS_TOKENIZER),
+      _ => None,
+    })
+    .unwrap_or_default();
+
+  let version = match TweetSchema::new(schema) {
+    Ok(tweet_schema) if tweet_schema.text_exact.is_some() => 2,
+    _ => 1,
+  };
+
+  IndexStamp {
+    version,
+    options: IndexOptions {
+      text_analysis: TextAnalysisOptions { stopwords },
+      engagement: EngagementWeights::default(),
+    },
+  }
+}
+
+/// Refuse un index dont le schéma est antérieur à [`SCHEMA_VERSION`]
+pub fn check_index_version<P: AsRef<Path>>(
+  directory_path: P,
+  index: &Index,
+) -> Result<(), SearchError> {
+  let stamp = index_stamp(&directory_path, index)?;
+  if stamp.is_outdated() {
+    return Err(SearchError::OutdatedIndex {
+      path: directory_path.as_ref().display().to_string(),
+      version: stamp.version,
+      expected: SCHEMA_VERSION,
+    });
+  }
+
+  Ok(())
+}
+
+/// Schéma des documents à écrire dans l'index `directory_path` : refusé si l'index est à
+/// migrer, l'engagement étant calculé selon les options de l'index
+pub fn writable_schema<P: AsRef<Path>>(
+  directory_path: P,
+  index: &Index,
+) -> Result<TweetSchema, SearchError> {
+  check_index_version(&directory_path, index)?;
+  let stamp = index_stamp(&directory_path, index)?;
+
+  Ok(TweetSchema::from_index(index)?.with_engagement(stamp.options.engagement))
+}
+
+/// Reconstruit l'index `directory_path` dans le schéma courant, en conservant ses options
+/// d'analyse. Les tweets stockés sont réindexés dans un nouvel index, créé à côté de
+/// l'ancien puis mis à sa place ; l'ancien est supprimé sauf si `keep_backup`.
+pub fn migrate_index<P: AsRef<Path>>(
+  directory_path: P,
+  keep_backup: bool,
+) -> Result<MigrationStats, SearchError> {
+  let directory_path = directory_path.as_ref();
+  let old_index = Index::open_in_dir(directory_path).map_err(migration_error)?;
+  register_tokenizers(&old_index);
+  let stamp = index_stamp(directory_path, &old_index)?;
+
+  let mut stats = MigrationStats {
+    from_version: stamp.version,
+    to_version: SCHEMA_VERSION,
+    ..Default::default()
+  };
+  if !stamp.is_outdated() {
+    tracing::info!(
+      "index {} déjà en version {}",
+      directory_path.display(),
+      stamp.version
+    );
+    return Ok(stats);
+  }
+
+  let new_directory = sibling(directory_path, "migration");
+  if new_directory.exists() {
+    fs::remove_dir_all(&new_directory).map_err(migration_error)?;
+  }
+  create_index_config_with_options(&new_directory, &stamp.options).map_err(migration_error)?;
+  let new_index = Index::open_in_dir(&new_directory).map_err(migration_error)?;
+  register_tokenizers(&new_index);
+  let tweet_schema = TweetSchema::from_index(&new_index)?.with_engagement(stamp.options.engagement);
+
+  {
+    let old_schema = old_index.schema();
+    let searcher = old_index.reader().map_err(migration_error)?.searcher();
+    let mut index_writer = new_index.writer(125_000_000).map_err(migration_error)?;
+    for segment_reader in searcher.segment_readers() {
+      let store_reader = segment_reader.get_store_reader().map_err(migration_error)?;
+      for document in store_reader.iter(segment_reader.alive_bitset()) {
+        let document = document.map_err(migration_error)?;
+        match stored_tweet(&old_schema, &document) {
+          Some(tweet) => {
+            index_writer
+              .add_document(tweet_schema.to_document(&tweet))
+              .map_err(migration_error)?;
+            stats.documents += 1;
+            if stats.documents.is_multiple_of(COMMIT_EVERY) {
+              index_writer.commit().map_err(migration_error)?;
+            }
+          }
+          None => stats.skipped += 1,
+        }
+      }
+    }
+    index_writer.commit().map_err(migration_error)?;
+    index_writer
+      .wait_merging_threads()
+      .map_err(migration_error)?;
+  }
+  drop(old_index);
+
+  let backup = sibling(directory_path, &format!("v{}", stamp.version));
+  fs::rename(directory_path, &backup).map_err(migration_error)?;
+  fs::rename(&new_directory, directory_path).map_err(migration_error)?;
+  if keep_backup {
+    stats.backup = Some(backup);
+  } else {
+    fs::remove_dir_all(&backup).map_err(migration_error)?;
+  }
+
+  tracing::info!(
+    "index {} migré de la version {} à la version {} : {} documents, {} ignorés",
+    directory_path.display(),
+    stats.from_version,
+    stats.to_version,
+    stats.documents,
+    stats.skipped
+  );
+
+  Ok(stats)
+}
+
+/// Répertoire voisin de `directory_path`, suffixé par `suffix`
+pub(crate) fn sibling(directory_path: &Path, suffix: &str) -> PathBuf {
+  let mut name = directory_path
+    .file_name()
+    .map(|name| name.to_os_string())
+    .unwrap_or_default();
+  name.push(format!(".{suffix}"));
+  directory_path.with_file_name(name)
+}
+
+/// Tweet stocké dans un index d'une version quelconque : les champs sont cherchés par leur
+/// nom, les compteurs absents valent 0
+fn stored_tweet(schema: &Schema, document: &Document) -> Option<Tweet> {
+  let text = |name: &str| {
+    schema
+      .get_field(name)
+      .and_then(|field| document.get_first(field))
+      .and_then(|value| value.as_text())
+      .map(str::to_string)
+  };
+  let texts = |name: &str| {
+    schema
+      .get_field(name)
+      .map(|field| {
+        document
+          .get_all(field)
+          .filter_map(|value| value.as_text())
+          .map(str::to_string)
+          .collect()
+      })
+      .unwrap_or_default()
+  };
+  let count = |name: &str| {
+    schema
+      .get_field(name)
+      .and_then(|field| document.get_first(field))
+      .and_then(|value| value.as_u64())
+  };
+
+  let published_time_ms = count("published_time_ms").or_else(|| {
+    schema
+      .get_field("published_time")
+      .and_then(|field| document.get_first(field))
+      .and_then(|value| value.as_date())
+      .map(|date| date.into_unix_timestamp() as u64 * 1_000)
+  })?;
+
+  Some(Tweet {
+    id: text("id")?,
+    user_id: text("user_id").unwrap_or_default(),
+    user_name: text("user_name").unwrap_or_default(),
+    user_screen_name: text("user_screen_name").unwrap_or_default(),
+    text: text("text")?,
+    published_time: chrono::TimeZone::timestamp_millis(&chrono::Utc, published_time_ms as i64),
+    published_time_ms,
+    retweet_count: count("retweet_count").unwrap_or_default(),
+    reply_count: count("reply_count").unwrap_or_default(),
+    quote_count: count("quote_count").unwrap_or_default(),
+    hashtags: texts("hashtags"),
+    urls: texts("urls"),
+    relations: TweetRelations {
+      mentions: texts("mentions"),
+      is_retweet: match count("is_retweet") {
+        Some(is_retweet) => is_retweet == 1,
+        None => is_retweet_text(&text("text")?),
+      },
+      retweeted_id: text("retweeted_id"),
+      retweeted_user_id: text("retweeted_user_id"),
+      in_reply_to_id: text("in_reply_to_id"),
+      in_reply_to_user_id: text("in_reply_to_user_id"),
+      quoted_id: text("quoted_id"),
+      quoted_user_id: text("quoted_user_id"),
+    },
+    metadata: Default::default(),
+  })
+}
+
+fn migration_error<E: Display>(e: E) -> SearchError {
+  SearchError::Migration(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+  use tantivy::{
+    doc,
+    schema::{FAST, INDEXED, STORED, STRING, TEXT},
+  };
+
+  use super::*;
+  use crate::{retrieve_index, search_tweets};
+
+  #[test]
+  fn test_migrate_legacy_index() {
+    let directory = std::env::temp_dir().join(format!("cocktail-migrate-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).unwrap();
+
+    // index de la version 1, sans engagement ni tris croissants
+    let mut schema_builder = Schema::builder();
+    let id = schema_builder.add_text_field("id", STRING | FAST | STORED);
+    let published_time_ms =
+      schema_builder.add_u64_field("published_time_ms", INDEXED | FAST | STORED);
+    let user_screen_name = schema_builder.add_text_field("user_screen_name", STRING | STORED);
+    let text = schema_builder.add_text_field("text", TEXT | STORED);
+    let hashtags = schema_builder.add_text_field("hashtags", STRING | FAST | STORED);
+    let retweet_count = schema_builder.add_u64_field("retweet_count", INDEXED | FAST | STORED);
+    let index = Index::create_in_dir(&directory, schema_builder.build()).unwrap();
+    let mut writer = index.writer(15_000_000).unwrap();
+    writer
+      .add_document(doc!(
+        id => "1",
+        published_time_ms => 1_655_000_000_000u64,
+        user_screen_name => "eleveurs_fr",
+        text => "Les loups reviennent",
+        hashtags => "pastoralisme",
+        retweet_count => 4u64,
+      ))
+      .unwrap();
+    writer.add_document(doc!(id => "sans texte")).unwrap();
+    writer.commit().unwrap();
+    drop(writer);
+    drop(index);
+
+    match retrieve_index(&directory) {
+      Err(SearchError::OutdatedIndex { version, .. }) => assert_eq!(version, 1),
+      other => panic!("index ancien accepté : {other:?}"),
+    }
+
+    let stats = migrate_index(&directory, false).unwrap();
+    assert_eq!(
+      (stats.from_version, stats.documents, stats.skipped),
+      (1, 1, 1)
+    );
+
+    let index = retrieve_index(&directory).unwrap();
+    assert_eq!(
+      index_stamp(&directory, &index).unwrap(),
+      IndexStamp::current(IndexOptions::default())
+    );
+    let tweets = search_tweets(&index, "loup", &None).unwrap();
+    assert_eq!(tweets.len(), 1);
+    assert_eq!(tweets[0].retweet_count, 4);
+    assert_eq!(tweets[0].hashtags, vec!["pastoralisme".to_string()]);
+
+    // déjà à jour : rien à faire
+    let stats = migrate_index(&directory, false).unwrap();
+    assert_eq!(stats.documents, 0);
+
+    fs::remove_dir_all(&directory).unwrap();
+  }
+}
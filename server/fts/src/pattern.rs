@@ -0,0 +1,431 @@
+//! Motifs de mots clés : préfixe, jokers, expression régulière, mot approché et expression
+//! dont les mots peuvent être séparés.
+//!
+//! Dans les blocs, un motif est un élément `~mode:valeur` (`~prefixe:écolo`,
+//! `~approx1:loup`, `~proximite2:pomme de pin`…) ; tout autre élément garde son sens de
+//! mot clé. Les motifs portent sur les mots indexés sans racinisation (champ
+//! `text_exact`), en minuscules et sans accents, sauf les expressions avec écart qui sont
+//! analysées comme les mots clés.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use tantivy::{
+  query::{BooleanQuery, FuzzyTermQuery, PhraseQuery, Query, RegexQuery, TermQuery, Weight},
+  schema::{Field, IndexRecordOption},
+  Index, Searcher, Term,
+};
+
+use crate::{analyzed_terms, exact_analyzer, QueryLanguageError, TweetSchema};
+
+/// Préfixe d'un élément de bloc décrivant un motif
+pub const PATTERN_PREFIX: char = '~';
+/// Distance d'édition maximale d'un mot approché
+pub const MAX_FUZZY_DISTANCE: u8 = 2;
+/// Nombre maximal de mots pouvant séparer ceux d'une expression
+pub const MAX_SLOP: u32 = 10;
+/// Nombre minimal de lettres d'un préfixe ou d'un motif à jokers, en dessous duquel presque
+/// tous les mots de l'index correspondraient
+const MIN_LITERAL_CHARS: usize = 2;
+
+/// Motif de correspondance d'un mot clé
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum KeywordPattern {
+  /// mots commençant par le préfixe
+  Prefix(String),
+  /// `*` pour un nombre quelconque de caractères, `?` pour un caractère
+  Wildcard(String),
+  /// expression régulière appliquée à chaque mot
+  Regex(String),
+  /// mots à au plus `distance` modifications du mot donné
+  Fuzzy { word: String, distance: u8 },
+  /// mots de l'expression dans l'ordre, séparés par au plus `slop` autres mots
+  Phrase { words: String, slop: u32 },
+}
+
+impl KeywordPattern {
+  /// Motif décrit par un élément de bloc, `None` pour un mot clé
+  pub fn from_item(item: &str) -> Option<KeywordPattern> {
+    let (mode, value) = item.strip_prefix(PATTERN_PREFIX)?.split_once(':')?;
+    let (name, parameter) = mode.split_at(
+      mode
+        .find(|c: char| c.is_ascii_digit())
+        .unwrap_or(mode.len()),
+    );
+    let parameter = match parameter {
+      "" => None,
+      parameter => Some(parameter.parse::<u32>().ok()?),
+    };
+    let value = value.to_string();
+
+    match (name, parameter) {
+      ("prefixe", None) => Some(KeywordPattern::Prefix(value)),
+      ("joker", None) => Some(KeywordPattern::Wildcard(value)),
+      ("regex", None) => Some(KeywordPattern::Regex(value)),
+      ("approx", Some(distance)) => Some(KeywordPattern::Fuzzy {
+        word: value,
+        distance: distance.try_into().ok()?,
+      }),
+      ("proximite", Some(slop)) => Some(KeywordPattern::Phrase { words: value, slop }),
+      _ => None,
+    }
+  }
+
+  /// Motif saisi dans le formulaire de requête, `parameter` étant la distance d'un mot
+  /// approché ou l'écart d'une expression (1 par défaut)
+  pub fn from_mode(
+    mode: &str,
+    value: &str,
+    parameter: Option<u32>,
+  ) -> Result<KeywordPattern, QueryLanguageError> {
+    let value = value.trim();
+    let parameter = parameter.unwrap_or(1);
+    let pattern = match mode {
+      "prefixe" => KeywordPattern::Prefix(value.trim_end_matches('*').to_string()),
+      "joker" => KeywordPattern::wildcard(value),
+      "regex" => KeywordPattern::Regex(value.to_string()),
+      "approx" => KeywordPattern::Fuzzy {
+        word: value.to_string(),
+        distance: parameter.try_into().unwrap_or(u8::MAX),
+      },
+      "proximite" => KeywordPattern::Phrase {
+        words: value.split_whitespace().collect::<Vec<_>>().join(" "),
+        slop: parameter,
+      },
+      _ => {
+        return Err(QueryLanguageError::InvalidPattern {
+          pattern: value.to_string(),
+          message: format!("mode de correspondance inconnu : {mode}"),
+        })
+      }
+    };
+
+    pattern.check()?;
+    Ok(pattern)
+  }
+
+  /// Motif à jokers, simple préfixe si son seul joker est un `*` final
+  pub fn wildcard(text: &str) -> KeywordPattern {
+    match text.strip_suffix('*') {
+      Some(prefix) if !prefix.contains(['*', '?']) => KeywordPattern::Prefix(prefix.to_string()),
+      _ => KeywordPattern::Wildcard(text.to_string()),
+    }
+  }
+
+  /// Élément de bloc décrivant le motif
+  pub fn to_item(&self) -> String {
+    match self {
+      KeywordPattern::Prefix(prefix) => format!("{PATTERN_PREFIX}prefixe:{prefix}"),
+      KeywordPattern::Wildcard(wildcard) => format!("{PATTERN_PREFIX}joker:{wildcard}"),
+      KeywordPattern::Regex(regex) => format!("{PATTERN_PREFIX}regex:{regex}"),
+      KeywordPattern::Fuzzy { word, distance } => {
+        format!("{PATTERN_PREFIX}approx{distance}:{word}")
+      }
+      KeywordPattern::Phrase { words, slop } => format!("{PATTERN_PREFIX}proximite{slop}:{words}"),
+    }
+  }
+
+  /// Vérifie que le motif peut être cherché sans parcourir tout l'index
+  pub fn check(&self) -> Result<(), QueryLanguageError> {
+    let invalid = |message: &str| {
+      Err(QueryLanguageError::InvalidPattern {
+        pattern: self.to_string(),
+        message: message.to_string(),
+      })
+    };
+
+    match self {
+      KeywordPattern::Prefix(text) | KeywordPattern::Wildcard(text) => {
+        let wildcards = matches!(self, KeywordPattern::Wildcard(_));
+        if !is_pattern_word(text, wildcards) {
+          return invalid("un seul mot, sans espace ni ponctuation, est attendu");
+        }
+        if wildcards && !text.contains(['*', '?']) {
+          return invalid("joker * ou ? attendu");
+        }
+        let literals: Vec<Vec<String>> = text.split(['*', '?']).map(folded_words).collect();
+        if literals.iter().any(|words| words.len() > 1) {
+          return invalid("un seul mot est attendu");
+        }
+        if literals.concat().concat().chars().count() < MIN_LITERAL_CHARS {
+          return invalid("au moins deux lettres sont nécessaires");
+        }
+      }
+      KeywordPattern::Regex(regex) => {
+        if regex.is_empty() {
+          return invalid("expression régulière vide");
+        }
+        if RegexQuery::from_pattern(regex, Field::from_field_id(0)).is_err() {
+          return invalid("expression régulière incorrecte");
+        }
+      }
+      KeywordPattern::Fuzzy { word, distance } => {
+        if !is_pattern_word(word, false) || folded_words(word).len() != 1 {
+          return invalid("un seul mot est attendu");
+        }
+        if !(1..=MAX_FUZZY_DISTANCE).contains(distance) {
+          return invalid("la distance doit être comprise entre 1 et 2");
+        }
+      }
+      KeywordPattern::Phrase { words, slop } => {
+        if words.contains('"') || words.split_whitespace().count() < 2 {
+          return invalid("une expression d'au moins deux mots est attendue");
+        }
+        if !(1..=MAX_SLOP).contains(slop) {
+          return invalid("l'écart doit être compris entre 1 et 10");
+        }
+      }
+    }
+
+    Ok(())
+  }
+
+  /// Requête sur le texte des tweets ; un motif invalide ne sélectionne aucun tweet
+  pub fn query(&self, index: &Index, tweet_schema: &TweetSchema) -> Box<dyn Query> {
+    if let Err(e) = self.check() {
+      tracing::warn!("{e}");
+      return Box::new(BooleanQuery::new(vec![]));
+    }
+
+    let text = tweet_schema.text;
+    let field = tweet_schema.text_exact.unwrap_or(text);
+
+    // les mots repliés sont alphanumériques : rien à échapper dans les expressions régulières
+    let query: Box<dyn Query> = match self {
+      KeywordPattern::Prefix(prefix) => {
+        regex_query(&format!("{}.*", folded_words(prefix).concat()), field)
+      }
+      KeywordPattern::Wildcard(wildcard) => regex_query(&wildcard_regex(wildcard), field),
+      KeywordPattern::Regex(regex) => regex_query(regex, field),
+      KeywordPattern::Fuzzy { word, distance } => Box::new(FuzzyTermQuery::new(
+        Term::from_field_text(field, &folded_words(word).concat()),
+        *distance,
+        true,
+      )),
+      KeywordPattern::Phrase { words, slop } => {
+        let mut terms = analyzed_terms(index, text, words);
+        match terms.len() {
+          0 => Box::new(BooleanQuery::new(vec![])),
+          1 => Box::new(TermQuery::new(
+            terms.pop().unwrap().1,
+            IndexRecordOption::Basic,
+          )),
+          _ => {
+            let mut phrase = PhraseQuery::new_with_offset(terms);
+            phrase.set_slop(*slop);
+            Box::new(phrase)
+          }
+        }
+      }
+    };
+
+    Box::new(PatternQuery {
+      pattern: self.clone(),
+      query,
+    })
+  }
+}
+
+/// Écriture dans le langage de requête : `écolo*`, `éc?lo*`, `/eco.*/`, `loup~1`,
+/// `"pomme de pin"~2`
+impl fmt::Display for KeywordPattern {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      KeywordPattern::Prefix(prefix) => write!(f, "{prefix}*"),
+      KeywordPattern::Wildcard(wildcard) => write!(f, "{wildcard}"),
+      KeywordPattern::Regex(regex) => write!(f, "/{}/", regex.replace('/', "\\/")),
+      KeywordPattern::Fuzzy { word, distance } => write!(f, "{word}~{distance}"),
+      KeywordPattern::Phrase { words, slop } => write!(f, "\"{words}\"~{slop}"),
+    }
+  }
+}
+
+/// Requête d'un motif : se comporte comme la requête construite, en gardant le motif pour
+/// l'explication de la requête
+pub struct PatternQuery {
+  pattern: KeywordPattern,
+  query: Box<dyn Query>,
+}
+
+impl PatternQuery {
+  pub fn pattern(&self) -> &KeywordPattern {
+    &self.pattern
+  }
+}
+
+impl Clone for PatternQuery {
+  fn clone(&self) -> Self {
+    PatternQuery {
+      pattern: self.pattern.clone(),
+      query: self.query.box_clone(),
+    }
+  }
+}
+
+/// Le `Debug` d'une expression régulière compilée contient tout son automate
+impl fmt::Debug for PatternQuery {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("PatternQuery")
+      .field("pattern", &self.pattern)
+      .finish()
+  }
+}
+
+impl Query for PatternQuery {
+  fn weight(&self, searcher: &Searcher, scoring_enabled: bool) -> tantivy::Result<Box<dyn Weight>> {
+    self.query.weight(searcher, scoring_enabled)
+  }
+
+  fn query_terms(&self, terms: &mut std::collections::BTreeMap<Term, bool>) {
+    self.query.query_terms(terms)
+  }
+}
+
+/// Un mot du motif, jokers compris si `wildcards` : lettres, chiffres, apostrophes, tirets
+fn is_pattern_word(word: &str, wildcards: bool) -> bool {
+  let is_wildcard = |c: char| wildcards && matches!(c, '*' | '?');
+  word
+    .chars()
+    .next()
+    .is_some_and(|c| c.is_alphanumeric() || is_wildcard(c))
+    && word
+      .chars()
+      .all(|c| c.is_alphanumeric() || matches!(c, '\'' | '’' | '-' | '_') || is_wildcard(c))
+}
+
+/// Mots de `text` tels qu'indexés dans `text_exact`
+fn folded_words(text: &str) -> Vec<String> {
+  let mut words = vec![];
+  exact_analyzer()
+    .token_stream(text)
+    .process(&mut |token| words.push(token.text.clone()));
+  words
+}
+
+fn wildcard_regex(wildcard: &str) -> String {
+  let mut regex = String::new();
+  let mut literal = String::new();
+  for c in wildcard.chars() {
+    match c {
+      '*' | '?' => {
+        regex.push_str(&folded_words(&literal).concat());
+        literal.clear();
+        regex.push_str(if c == '*' { ".*" } else { "." });
+      }
+      c => literal.push(c),
+    }
+  }
+  regex.push_str(&folded_words(&literal).concat());
+  regex
+}
+
+fn regex_query(pattern: &str, field: Field) -> Box<dyn Query> {
+  match RegexQuery::from_pattern(pattern, field) {
+    Ok(query) => Box::new(query),
+    Err(e) => {
+      tracing::warn!("expression régulière {pattern} ignorée : {e}");
+      Box::new(BooleanQuery::new(vec![]))
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use tantivy::{collector::Count, doc};
+
+  use super::*;
+  use crate::register_tokenizers;
+
+  #[test]
+  fn test_pattern_items() {
+    for item in [
+      "~prefixe:écolo",
+      "~joker:éc?lo*",
+      "~regex:eco.*",
+      "~approx1:loup",
+      "~proximite2:pomme de pin",
+    ] {
+      let pattern = KeywordPattern::from_item(item).unwrap();
+      assert_eq!(pattern.to_item(), item);
+      assert!(pattern.check().is_ok(), "{item}");
+    }
+
+    // les autres éléments restent des mots clés
+    for item in [
+      "écologie",
+      "~loup",
+      "~inconnu:loup",
+      "~approx:loup",
+      "=~prefixe:eco",
+    ] {
+      assert_eq!(KeywordPattern::from_item(item), None);
+    }
+
+    assert_eq!(
+      KeywordPattern::from_mode("joker", " écolo* ", None).unwrap(),
+      KeywordPattern::Prefix("écolo".to_string())
+    );
+    assert_eq!(
+      KeywordPattern::from_mode("proximite", "pomme  de pin", Some(3)).unwrap(),
+      KeywordPattern::Phrase {
+        words: "pomme de pin".to_string(),
+        slop: 3
+      }
+    );
+    for (mode, value, parameter) in [
+      ("prefixe", "é*", None),
+      ("prefixe", "pomme de", None),
+      ("joker", "écolo", None),
+      ("regex", "eco(", None),
+      ("approx", "loup", Some(3)),
+      ("proximite", "pomme", None),
+      ("proximite", "pomme de pin", Some(20)),
+      ("inconnu", "loup", None),
+    ] {
+      assert!(
+        KeywordPattern::from_mode(mode, value, parameter).is_err(),
+        "{mode} {value}"
+      );
+    }
+  }
+
+  #[test]
+  fn test_pattern_queries() {
+    let index = Index::create_in_ram(TweetSchema::build(&Default::default()));
+    register_tokenizers(&index);
+    let tweet_schema = TweetSchema::from_index(&index).unwrap();
+    let (text, text_exact) = (tweet_schema.text, tweet_schema.text_exact.unwrap());
+
+    let mut writer = index.writer(15_000_000).unwrap();
+    for content in [
+      "Les écologistes manifestent",
+      "ECOLOGIE et climat",
+      "Le loup est revenu",
+      "les loups et la pomme de pin",
+      "la pomme verte de pin",
+    ] {
+      writer
+        .add_document(doc!(text => content, text_exact => content))
+        .unwrap();
+    }
+    writer.commit().unwrap();
+    let searcher = index.reader().unwrap().searcher();
+    let count = |item: &str| {
+      let pattern = KeywordPattern::from_item(item).unwrap();
+      searcher
+        .search(pattern.query(&index, &tweet_schema).as_ref(), &Count)
+        .unwrap()
+    };
+
+    assert_eq!(count("~prefixe:Écolo"), 2);
+    assert_eq!(count("~joker:éc?log*s"), 1);
+    assert_eq!(count("~regex:lou(p|ps)"), 2);
+    assert_eq!(count("~approx1:loupe"), 2);
+    assert_eq!(count("~approx1:poire"), 0);
+    assert_eq!(count("~proximite1:pomme de pin"), 2);
+    assert_eq!(count("~proximite1:pomme pin"), 1);
+    assert_eq!(count("~proximite2:pomme pin"), 2);
+    // motif invalide : aucun tweet
+    assert_eq!(count("~regex:eco("), 0);
+  }
+}
@@ -0,0 +1,1027 @@
+//! Langage de requête textuel équivalent aux blocs du formulaire de requête.
+//!
+//! ```text
+//! (#vegan OR #vegetarien) @cocktail_fr "pomme de pin" -viande date:[2022-01-01 TO 2022-06-30] lang:fr
+//! ```
+//!
+//! Les termes juxtaposés sont combinés par `AND`, `OR` est moins prioritaire que `AND`,
+//! `-terme` ou `NOT terme` exclut un terme. `ET` / `OU` sont acceptés comme synonymes.
+//! `=mot` ou `="une expression"` cherche le texte sans racinisation. `écolo*` et `éc?lo*`
+//! cherchent les mots correspondant aux jokers, `/eco.*/` ceux correspondant à une
+//! expression régulière, `loup~1` les mots à une modification près et `"pomme pin"~2` les
+//! expressions dont les mots sont séparés par au plus deux autres.
+
+use std::{fmt, iter::Peekable, str::CharIndices};
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use crate::{Bloc, KeywordPattern, EXACT_PREFIX};
+
+/// Nombre maximal de blocs produits par la mise en forme normale disjonctive d'une requête
+const MAX_BLOCS: usize = 64;
+
+/// Terme élémentaire d'une requête
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum QueryAtom {
+  Keyword(String),
+  Phrase(String),
+  /// mot ou expression cherché sans racinisation
+  Exact(String),
+  /// préfixe, jokers, expression régulière, mot approché ou expression avec écart
+  Pattern(KeywordPattern),
+  Hashtag(String),
+  Account(String),
+  DateRange {
+    start: NaiveDate,
+    end: NaiveDate,
+  },
+  Lang(String),
+}
+
+/// Arbre d'une requête ; `And(vec![])` correspond à tous les tweets
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum QueryExpr {
+  Atom(QueryAtom),
+  Not(Box<QueryExpr>),
+  And(Vec<QueryExpr>),
+  Or(Vec<QueryExpr>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum QueryLanguageError {
+  /// `position` : index du caractère fautif, à partir de 0
+  #[error("caractère {} : {message}", .position + 1)]
+  Syntax { position: usize, message: String },
+  #[error("{0} ne peut pas être représenté par les blocs du formulaire")]
+  NotRepresentable(String),
+  #[error("motif « {pattern} » invalide : {message}")]
+  InvalidPattern { pattern: String, message: String },
+}
+
+/// Critères d'une étude équivalents à une requête
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StudyCriteria {
+  pub request_params: Vec<Vec<Bloc>>,
+  /// période de l'étude, donnée par `date:[début TO fin]`
+  pub dates: Option<(NaiveDate, NaiveDate)>,
+  /// langues des filtres de l'étude, données par `lang:` ou une alternative de `lang:`
+  pub languages: Vec<String>,
+}
+
+impl QueryLanguageError {
+  fn syntax(position: usize, message: impl Into<String>) -> Self {
+    QueryLanguageError::Syntax {
+      position,
+      message: message.into(),
+    }
+  }
+}
+
+impl QueryExpr {
+  /// Analyse une requête textuelle
+  pub fn parse(input: &str) -> Result<QueryExpr, QueryLanguageError> {
+    let tokens = tokenize(input)?;
+    let mut parser = Parser {
+      tokens,
+      index: 0,
+      end: input.chars().count(),
+    };
+
+    if parser.peek().is_none() {
+      return Ok(QueryExpr::And(vec![]));
+    }
+
+    let expr = parser.parse_or()?;
+    match parser.peek() {
+      None => Ok(expr),
+      Some((position, Token::RightParen)) => Err(QueryLanguageError::syntax(
+        *position,
+        "parenthèse fermante sans parenthèse ouvrante",
+      )),
+      Some((position, _)) => Err(QueryLanguageError::syntax(*position, "terme inattendu")),
+    }
+  }
+
+  /// Conjonction de `exprs`, aplatie
+  pub fn and(exprs: Vec<QueryExpr>) -> QueryExpr {
+    let mut flat = Vec::new();
+    for expr in exprs {
+      match expr {
+        QueryExpr::And(inner) => flat.extend(inner),
+        expr => flat.push(expr),
+      }
+    }
+    if flat.len() == 1 {
+      flat.remove(0)
+    } else {
+      QueryExpr::And(flat)
+    }
+  }
+
+  /// Disjonction de `exprs`, aplatie
+  pub fn or(exprs: Vec<QueryExpr>) -> QueryExpr {
+    let mut flat = Vec::new();
+    for expr in exprs {
+      match expr {
+        QueryExpr::Or(inner) => flat.extend(inner),
+        expr => flat.push(expr),
+      }
+    }
+    if flat.len() == 1 {
+      flat.remove(0)
+    } else {
+      QueryExpr::Or(flat)
+    }
+  }
+
+  /// Requête équivalente aux blocs du formulaire, telle que les interprète `get_query` :
+  /// chaque bloc applique son lien (ET / OU) aux blocs qui le précèdent depuis le dernier
+  /// bloc ET, les alternatives ainsi formées étant combinées par OU.
+  ///
+  /// Comme dans `get_query`, l'exclusion n'est appliquée que lorsque son lien est ET.
+  pub fn from_blocs(request_params: &[Vec<Bloc>]) -> QueryExpr {
+    let mut alternatives = Vec::new();
+    let mut pending = Vec::new();
+    for bloc in request_params.first().into_iter().flatten() {
+      if bloc.data.is_empty() {
+        continue;
+      }
+      let conjunction = QueryExpr::and(bloc.data.iter().map(|e| item_to_expr(e)).collect());
+      if bloc.link == "ET" {
+        let mut group = Vec::new();
+        if !pending.is_empty() {
+          group.push(QueryExpr::or(std::mem::take(&mut pending)));
+        }
+        group.push(conjunction);
+        alternatives.push(QueryExpr::and(group));
+      } else {
+        pending.push(conjunction);
+      }
+    }
+    if !pending.is_empty() {
+      alternatives.push(QueryExpr::or(pending));
+    }
+
+    let mut query = match alternatives.len() {
+      0 => vec![],
+      _ => vec![QueryExpr::or(alternatives)],
+    };
+
+    if let Some(exclusion) = request_params.get(1).and_then(|e| e.first()) {
+      if exclusion.link == "ET" {
+        query.extend(
+          exclusion
+            .data
+            .iter()
+            .map(|e| QueryExpr::Not(Box::new(item_to_expr(e)))),
+        );
+      }
+    }
+
+    QueryExpr::and(query)
+  }
+
+  /// Critères d'étude équivalents à la requête : `date:` et `lang:` au niveau principal
+  /// donnent la période et les langues de l'étude, le reste est traduit en blocs.
+  pub fn to_criteria(&self) -> Result<StudyCriteria, QueryLanguageError> {
+    let conjuncts = match self {
+      QueryExpr::And(exprs) => exprs.clone(),
+      expr => vec![expr.clone()],
+    };
+
+    let mut dates = None;
+    let mut languages: Option<Vec<String>> = None;
+    let mut rest = Vec::new();
+    for conjunct in conjuncts {
+      match conjunct {
+        QueryExpr::Atom(QueryAtom::DateRange { start, end }) => {
+          if dates.replace((start, end)).is_some() {
+            return Err(QueryLanguageError::NotRepresentable(
+              "plusieurs critères date:".to_string(),
+            ));
+          }
+        }
+        expr => match lang_alternative(&expr) {
+          Some(alternative) => {
+            if languages.replace(alternative).is_some() {
+              return Err(QueryLanguageError::NotRepresentable(
+                "plusieurs critères lang: combinés par ET".to_string(),
+              ));
+            }
+          }
+          None => rest.push(expr),
+        },
+      }
+    }
+
+    let mut languages = languages.unwrap_or_default();
+    languages.sort();
+    languages.dedup();
+
+    Ok(StudyCriteria {
+      request_params: QueryExpr::and(rest).to_blocs()?,
+      dates,
+      languages,
+    })
+  }
+
+  /// Blocs du formulaire équivalents à la requête : la sélection est mise sous forme
+  /// normale disjonctive (un bloc par alternative, liés par OU) et les termes exclus au
+  /// niveau principal forment le bloc d'exclusion.
+  pub fn to_blocs(&self) -> Result<Vec<Vec<Bloc>>, QueryLanguageError> {
+    let conjuncts = match self {
+      QueryExpr::And(exprs) => exprs.clone(),
+      expr => vec![expr.clone()],
+    };
+
+    let mut selection = Vec::new();
+    let mut exclusion = Vec::new();
+    for conjunct in conjuncts {
+      match conjunct {
+        QueryExpr::Not(inner) => match *inner {
+          QueryExpr::Atom(atom) => exclusion.push(atom_to_item(&atom)?),
+          QueryExpr::Or(exprs) if exprs.iter().all(|e| matches!(e, QueryExpr::Atom(_))) => {
+            for expr in exprs {
+              if let QueryExpr::Atom(atom) = expr {
+                exclusion.push(atom_to_item(&atom)?);
+              }
+            }
+          }
+          inner => {
+            return Err(QueryLanguageError::NotRepresentable(format!(
+              "la négation de « {} »",
+              inner
+            )))
+          }
+        },
+        expr => selection.push(expr),
+      }
+    }
+
+    let alternatives = disjunctive_normal_form(&QueryExpr::and(selection))?;
+    let mut blocs: Vec<Bloc> = alternatives
+      .into_iter()
+      .filter(|items| !items.is_empty())
+      .enumerate()
+      .map(|(i, mut data)| {
+        data.sort();
+        data.dedup();
+        Bloc {
+          data,
+          link: if i == 0 { "" } else { "OU" }.to_string(),
+        }
+      })
+      .collect();
+    if blocs.is_empty() {
+      blocs.push(Bloc {
+        data: vec![],
+        link: "".to_string(),
+      });
+    }
+
+    exclusion.sort();
+    exclusion.dedup();
+
+    Ok(vec![
+      blocs,
+      vec![Bloc {
+        data: exclusion,
+        link: "ET".to_string(),
+      }],
+    ])
+  }
+}
+
+/// Langues de `lang:fr` ou `(lang:fr OR lang:en)`
+fn lang_alternative(expr: &QueryExpr) -> Option<Vec<String>> {
+  match expr {
+    QueryExpr::Atom(QueryAtom::Lang(lang)) => Some(vec![lang.clone()]),
+    QueryExpr::Or(exprs) => exprs
+      .iter()
+      .map(|expr| match expr {
+        QueryExpr::Atom(QueryAtom::Lang(lang)) => Some(lang.clone()),
+        _ => None,
+      })
+      .collect(),
+    _ => None,
+  }
+}
+
+/// Alternatives (listes d'éléments de bloc) dont la disjonction équivaut à `expr`
+fn disjunctive_normal_form(expr: &QueryExpr) -> Result<Vec<Vec<String>>, QueryLanguageError> {
+  let alternatives = match expr {
+    QueryExpr::Atom(atom) => vec![vec![atom_to_item(atom)?]],
+    QueryExpr::Or(exprs) => {
+      let mut alternatives = Vec::new();
+      for expr in exprs {
+        alternatives.extend(disjunctive_normal_form(expr)?);
+      }
+      alternatives
+    }
+    QueryExpr::And(exprs) => {
+      let mut alternatives = vec![vec![]];
+      for expr in exprs {
+        let right = disjunctive_normal_form(expr)?;
+        alternatives = alternatives
+          .iter()
+          .flat_map(|left| {
+            right.iter().map(move |right| {
+              let mut items = left.clone();
+              items.extend(right.iter().cloned());
+              items
+            })
+          })
+          .collect();
+        if alternatives.len() > MAX_BLOCS {
+          return Err(QueryLanguageError::NotRepresentable(format!(
+            "une requête de plus de {MAX_BLOCS} blocs"
+          )));
+        }
+      }
+      alternatives
+    }
+    QueryExpr::Not(inner) => {
+      return Err(QueryLanguageError::NotRepresentable(format!(
+        "la négation imbriquée « -{} »",
+        Wrapped(inner)
+      )))
+    }
+  };
+
+  if alternatives.len() > MAX_BLOCS {
+    return Err(QueryLanguageError::NotRepresentable(format!(
+      "une requête de plus de {MAX_BLOCS} blocs"
+    )));
+  }
+  Ok(alternatives)
+}
+
+fn item_to_expr(item: &str) -> QueryExpr {
+  let atom = if let Some(pattern) = KeywordPattern::from_item(item) {
+    QueryAtom::Pattern(pattern)
+  } else if let Some(exact) = item.strip_prefix(EXACT_PREFIX) {
+    QueryAtom::Exact(exact.to_string())
+  } else if let Some(hashtag) = item.strip_prefix('#') {
+    QueryAtom::Hashtag(hashtag.to_string())
+  } else if let Some(account) = item.strip_prefix('@') {
+    QueryAtom::Account(account.to_string())
+  } else if item.contains(' ') {
+    QueryAtom::Phrase(item.to_string())
+  } else {
+    QueryAtom::Keyword(item.to_string())
+  };
+  QueryExpr::Atom(atom)
+}
+
+fn atom_to_item(atom: &QueryAtom) -> Result<String, QueryLanguageError> {
+  match atom {
+    QueryAtom::Keyword(keyword) => Ok(keyword.clone()),
+    QueryAtom::Phrase(phrase) => Ok(phrase.clone()),
+    QueryAtom::Exact(exact) => Ok(format!("{EXACT_PREFIX}{exact}")),
+    QueryAtom::Pattern(pattern) => Ok(pattern.to_item()),
+    QueryAtom::Hashtag(hashtag) => Ok(format!("#{hashtag}")),
+    QueryAtom::Account(account) => Ok(format!("@{account}")),
+    QueryAtom::DateRange { .. } | QueryAtom::Lang(_) => Err(QueryLanguageError::NotRepresentable(
+      format!("le critère « {} »", QueryExpr::Atom(atom.clone())),
+    )),
+  }
+}
+
+/// Forme canonique : une requête analysée puis affichée donne toujours le même texte
+impl fmt::Display for QueryExpr {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      QueryExpr::Atom(atom) => write!(f, "{atom}"),
+      QueryExpr::Not(inner) => write!(f, "-{}", Wrapped(inner)),
+      QueryExpr::And(exprs) => {
+        for (i, expr) in exprs.iter().enumerate() {
+          if i > 0 {
+            write!(f, " ")?;
+          }
+          match expr {
+            QueryExpr::Or(_) => write!(f, "({expr})")?,
+            expr => write!(f, "{expr}")?,
+          }
+        }
+        Ok(())
+      }
+      QueryExpr::Or(exprs) => {
+        for (i, expr) in exprs.iter().enumerate() {
+          if i > 0 {
+            write!(f, " OR ")?;
+          }
+          match expr {
+            QueryExpr::And(inner) if inner.is_empty() => write!(f, "()")?,
+            expr => write!(f, "{expr}")?,
+          }
+        }
+        Ok(())
+      }
+    }
+  }
+}
+
+/// Opérande d'une négation, entre parenthèses si ce n'est pas un terme
+struct Wrapped<'a>(&'a QueryExpr);
+
+impl fmt::Display for Wrapped<'_> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self.0 {
+      expr @ (QueryExpr::Atom(_) | QueryExpr::Not(_)) => write!(f, "{expr}"),
+      expr => write!(f, "({expr})"),
+    }
+  }
+}
+
+impl fmt::Display for QueryAtom {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      QueryAtom::Keyword(keyword) if is_bare_word(keyword) => write!(f, "{keyword}"),
+      QueryAtom::Keyword(keyword) => write!(f, "\"{keyword}\""),
+      QueryAtom::Phrase(phrase) => write!(f, "\"{phrase}\""),
+      QueryAtom::Exact(exact) if is_bare_word(exact) => write!(f, "{EXACT_PREFIX}{exact}"),
+      QueryAtom::Exact(exact) => write!(f, "{EXACT_PREFIX}\"{exact}\""),
+      QueryAtom::Pattern(pattern) => write!(f, "{pattern}"),
+      QueryAtom::Hashtag(hashtag) => write!(f, "#{hashtag}"),
+      QueryAtom::Account(account) => write!(f, "@{account}"),
+      QueryAtom::DateRange { start, end } => {
+        write!(f, "date:[{} TO {}]", start.format("%F"), end.format("%F"))
+      }
+      QueryAtom::Lang(lang) => write!(f, "lang:{lang}"),
+    }
+  }
+}
+
+/// Un mot clé peut être écrit sans guillemets s'il ne sera pas relu comme un opérateur
+/// ou un autre type de terme
+fn is_bare_word(word: &str) -> bool {
+  !word.is_empty()
+    && operator(word).is_none()
+    && !word.starts_with(['-', '#', '@', '/', EXACT_PREFIX])
+    && !word.contains([':', '*', '?', '~'])
+    && word.chars().all(is_word_char)
+}
+
+fn is_word_char(c: char) -> bool {
+  !c.is_whitespace() && !matches!(c, '(' | ')' | '"')
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+  LeftParen,
+  RightParen,
+  And,
+  Or,
+  Not,
+  Atom(QueryAtom),
+}
+
+fn operator(word: &str) -> Option<Token> {
+  match word {
+    "AND" | "ET" => Some(Token::And),
+    "OR" | "OU" => Some(Token::Or),
+    "NOT" => Some(Token::Not),
+    _ => None,
+  }
+}
+
+/// Découpe `input` en termes, chacun associé à la position de son premier caractère
+fn tokenize(input: &str) -> Result<Vec<(usize, Token)>, QueryLanguageError> {
+  let mut lexer = Lexer {
+    input,
+    chars: input.char_indices().peekable(),
+    position: 0,
+  };
+  let mut tokens = Vec::new();
+
+  while let Some(c) = lexer.peek() {
+    let start = lexer.position;
+    match c {
+      c if c.is_whitespace() => {
+        lexer.next();
+      }
+      '(' => {
+        lexer.next();
+        tokens.push((start, Token::LeftParen));
+      }
+      ')' => {
+        lexer.next();
+        tokens.push((start, Token::RightParen));
+      }
+      '-' => {
+        lexer.next();
+        match lexer.peek() {
+          Some(c) if !c.is_whitespace() && c != ')' => tokens.push((start, Token::Not)),
+          _ => return Err(QueryLanguageError::syntax(start, "négation sans terme")),
+        }
+      }
+      '"' => {
+        let words = lexer.quoted(start)?;
+        let atom = if lexer.peek() == Some('~') {
+          let position = lexer.position;
+          lexer.next();
+          let parameter = lexer.take_while(|c| c.is_ascii_digit());
+          let parameter: u32 = parameter
+            .parse()
+            .map_err(|_| QueryLanguageError::syntax(position, "écart attendu après ~"))?;
+          let pattern = match words.split_once(' ') {
+            None => KeywordPattern::Fuzzy {
+              word: words,
+              distance: parameter.try_into().unwrap_or(u8::MAX),
+            },
+            Some(_) => KeywordPattern::Phrase {
+              words,
+              slop: parameter,
+            },
+          };
+          QueryAtom::Pattern(checked(pattern, start)?)
+        } else {
+          match words.split_once(' ') {
+            None => QueryAtom::Keyword(words),
+            Some(_) => QueryAtom::Phrase(words),
+          }
+        };
+        tokens.push((start, Token::Atom(atom)));
+      }
+      '/' => {
+        let regex = lexer.regex(start)?;
+        let pattern = checked(KeywordPattern::Regex(regex), start)?;
+        tokens.push((start, Token::Atom(QueryAtom::Pattern(pattern))));
+      }
+      EXACT_PREFIX => {
+        lexer.next();
+        let exact = match lexer.peek() {
+          Some('"') => lexer.quoted(start + 1)?,
+          _ => lexer.take_while(is_word_char).to_string(),
+        };
+        if exact.is_empty() {
+          return Err(QueryLanguageError::syntax(start, "mot exact vide"));
+        }
+        tokens.push((start, Token::Atom(QueryAtom::Exact(exact))));
+      }
+      '#' | '@' => {
+        lexer.next();
+        let name = lexer.take_while(is_word_char);
+        if name.is_empty() {
+          let message = if c == '#' {
+            "hashtag vide"
+          } else {
+            "compte vide"
+          };
+          return Err(QueryLanguageError::syntax(start, message));
+        }
+        let atom = if c == '#' {
+          QueryAtom::Hashtag(name.to_string())
+        } else {
+          QueryAtom::Account(name.to_string())
+        };
+        tokens.push((start, Token::Atom(atom)));
+      }
+      _ if lexer.rest().starts_with("date:[") => {
+        "date:".chars().for_each(|_| {
+          lexer.next();
+        });
+        tokens.push((start, Token::Atom(lexer.date_range(start)?)));
+      }
+      _ => {
+        let word = lexer.take_while(is_word_char);
+        let token = if let Some(token) = operator(word) {
+          token
+        } else if let Some(lang) = word.strip_prefix("lang:") {
+          if lang.is_empty() || !lang.chars().all(|c| c.is_ascii_alphabetic()) {
+            return Err(QueryLanguageError::syntax(
+              start + 5,
+              "code de langue invalide",
+            ));
+          }
+          Token::Atom(QueryAtom::Lang(lang.to_ascii_lowercase()))
+        } else if word.starts_with("date:") {
+          return Err(QueryLanguageError::syntax(
+            start + 5,
+            "intervalle de dates attendu : date:[AAAA-MM-JJ TO AAAA-MM-JJ]",
+          ));
+        } else if let Some((word, distance)) = word.split_once('~') {
+          let distance = match distance {
+            "" => 1,
+            distance => distance.parse().map_err(|_| {
+              QueryLanguageError::syntax(
+                start + word.chars().count() + 1,
+                "distance attendue après ~",
+              )
+            })?,
+          };
+          let pattern = KeywordPattern::Fuzzy {
+            word: word.to_string(),
+            distance,
+          };
+          Token::Atom(QueryAtom::Pattern(checked(pattern, start)?))
+        } else if word.contains(['*', '?']) {
+          let pattern = KeywordPattern::wildcard(word);
+          Token::Atom(QueryAtom::Pattern(checked(pattern, start)?))
+        } else {
+          Token::Atom(QueryAtom::Keyword(word.to_string()))
+        };
+        tokens.push((start, token));
+      }
+    }
+  }
+
+  Ok(tokens)
+}
+
+/// Motif lu au caractère `start`, s'il est valide
+fn checked(pattern: KeywordPattern, start: usize) -> Result<KeywordPattern, QueryLanguageError> {
+  pattern
+    .check()
+    .map_err(|e| QueryLanguageError::syntax(start, e.to_string()))?;
+  Ok(pattern)
+}
+
+struct Lexer<'a> {
+  input: &'a str,
+  chars: Peekable<CharIndices<'a>>,
+  /// position en caractères du prochain caractère
+  position: usize,
+}
+
+impl<'a> Lexer<'a> {
+  fn peek(&mut self) -> Option<char> {
+    self.chars.peek().map(|(_, c)| *c)
+  }
+
+  fn next(&mut self) -> Option<char> {
+    let (_, c) = self.chars.next()?;
+    self.position += 1;
+    Some(c)
+  }
+
+  fn offset(&mut self) -> usize {
+    self
+      .chars
+      .peek()
+      .map(|(i, _)| *i)
+      .unwrap_or(self.input.len())
+  }
+
+  fn rest(&mut self) -> &'a str {
+    let offset = self.offset();
+    &self.input[offset..]
+  }
+
+  fn take_while(&mut self, predicate: impl Fn(char) -> bool) -> &'a str {
+    let start = self.offset();
+    while matches!(self.peek(), Some(c) if predicate(c)) {
+      self.next();
+    }
+    let end = self.offset();
+    &self.input[start..end]
+  }
+
+  /// Expression entre guillemets commençant au caractère `start`, espaces normalisés
+  fn quoted(&mut self, start: usize) -> Result<String, QueryLanguageError> {
+    self.next();
+    let text = self.take_while(|c| c != '"');
+    if self.next().is_none() {
+      return Err(QueryLanguageError::syntax(start, "guillemet non fermé"));
+    }
+    let words: Vec<&str> = text.split_whitespace().collect();
+    if words.is_empty() {
+      return Err(QueryLanguageError::syntax(start, "expression vide"));
+    }
+    Ok(words.join(" "))
+  }
+
+  /// Expression régulière entre barres obliques commençant au caractère `start`, `\/`
+  /// désignant une barre oblique
+  fn regex(&mut self, start: usize) -> Result<String, QueryLanguageError> {
+    self.next();
+    let mut regex = String::new();
+    loop {
+      match self.next() {
+        None => {
+          return Err(QueryLanguageError::syntax(
+            start,
+            "expression régulière non fermée",
+          ))
+        }
+        Some('/') => return Ok(regex),
+        Some('\\') if self.peek() == Some('/') => {
+          self.next();
+          regex.push('/');
+        }
+        Some(c) => regex.push(c),
+      }
+    }
+  }
+
+  fn skip_whitespace(&mut self) {
+    self.take_while(char::is_whitespace);
+  }
+
+  /// `[AAAA-MM-JJ TO AAAA-MM-JJ]`, `date:` ayant déjà été lu à partir de `start`
+  fn date_range(&mut self, start: usize) -> Result<QueryAtom, QueryLanguageError> {
+    self.next();
+    self.skip_whitespace();
+    let start_date = self.date()?;
+    self.skip_whitespace();
+    let to_position = self.position;
+    if self.take_while(|c| c.is_ascii_alphabetic()) != "TO" {
+      return Err(QueryLanguageError::syntax(to_position, "TO attendu"));
+    }
+    self.skip_whitespace();
+    let end_date = self.date()?;
+    self.skip_whitespace();
+    if self.peek() != Some(']') {
+      return Err(QueryLanguageError::syntax(self.position, "] attendu"));
+    }
+    self.next();
+
+    if start_date > end_date {
+      return Err(QueryLanguageError::syntax(
+        start,
+        "la date de début est postérieure à la date de fin",
+      ));
+    }
+
+    Ok(QueryAtom::DateRange {
+      start: start_date,
+      end: end_date,
+    })
+  }
+
+  fn date(&mut self) -> Result<NaiveDate, QueryLanguageError> {
+    let position = self.position;
+    let text = self.take_while(|c| c.is_ascii_digit() || c == '-');
+    NaiveDate::parse_from_str(text, "%Y-%m-%d").map_err(|_| {
+      QueryLanguageError::syntax(position, "date invalide, format attendu : AAAA-MM-JJ")
+    })
+  }
+}
+
+struct Parser {
+  tokens: Vec<(usize, Token)>,
+  index: usize,
+  /// position de fin du texte, pour les erreurs de fin de requête inattendue
+  end: usize,
+}
+
+impl Parser {
+  fn peek(&self) -> Option<&(usize, Token)> {
+    self.tokens.get(self.index)
+  }
+
+  fn next(&mut self) -> Option<(usize, Token)> {
+    let token = self.tokens.get(self.index).cloned();
+    self.index += 1;
+    token
+  }
+
+  fn parse_or(&mut self) -> Result<QueryExpr, QueryLanguageError> {
+    let mut exprs = vec![self.parse_and()?];
+    while let Some((_, Token::Or)) = self.peek() {
+      self.next();
+      exprs.push(self.parse_and()?);
+    }
+    Ok(QueryExpr::or(exprs))
+  }
+
+  fn parse_and(&mut self) -> Result<QueryExpr, QueryLanguageError> {
+    let mut exprs = vec![self.parse_unary()?];
+    loop {
+      match self.peek() {
+        Some((_, Token::And)) => {
+          self.next();
+          exprs.push(self.parse_unary()?);
+        }
+        Some((_, Token::Or | Token::RightParen)) | None => break,
+        Some(_) => exprs.push(self.parse_unary()?),
+      }
+    }
+    Ok(QueryExpr::and(exprs))
+  }
+
+  fn parse_unary(&mut self) -> Result<QueryExpr, QueryLanguageError> {
+    match self.next() {
+      Some((_, Token::Not)) => Ok(QueryExpr::Not(Box::new(self.parse_unary()?))),
+      Some((position, Token::LeftParen)) => {
+        if let Some((close, Token::RightParen)) = self.peek() {
+          return Err(QueryLanguageError::syntax(*close, "parenthèses vides"));
+        }
+        let expr = self.parse_or()?;
+        match self.next() {
+          Some((_, Token::RightParen)) => Ok(expr),
+          _ => Err(QueryLanguageError::syntax(
+            position,
+            "parenthèse non fermée",
+          )),
+        }
+      }
+      Some((_, Token::Atom(atom))) => Ok(QueryExpr::Atom(atom)),
+      Some((position, Token::And | Token::Or)) => Err(QueryLanguageError::syntax(
+        position,
+        "terme attendu avant l'opérateur",
+      )),
+      Some((position, Token::RightParen)) => {
+        Err(QueryLanguageError::syntax(position, "terme attendu"))
+      }
+      None => Err(QueryLanguageError::syntax(
+        self.end,
+        "fin de requête inattendue",
+      )),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn bloc(data: &[&str], link: &str) -> Bloc {
+    Bloc {
+      data: data.iter().map(|s| s.to_string()).collect(),
+      link: link.to_string(),
+    }
+  }
+
+  fn canonical(input: &str) -> String {
+    QueryExpr::parse(input).unwrap().to_string()
+  }
+
+  #[test]
+  fn test_canonical_form() {
+    assert_eq!(
+      canonical("#vegan  ET (@cocktail OU \"pomme   de pin\")  NOT viande"),
+      "#vegan (@cocktail OR \"pomme de pin\") -viande"
+    );
+    assert_eq!(canonical("a OR b c"), "a OR b c");
+    assert_eq!(canonical("(a OR b) c"), "(a OR b) c");
+    assert_eq!(canonical("-(a b) \"OR\""), "-(a b) \"OR\"");
+    assert_eq!(
+      canonical("date:[2022-01-01 TO 2022-06-30] lang:FR covid-19"),
+      "date:[2022-01-01 TO 2022-06-30] lang:fr covid-19"
+    );
+    assert_eq!(canonical("  "), "");
+    assert_eq!(
+      canonical("=Écologie =\"pomme  de pin\" =\"loup\""),
+      "=Écologie =\"pomme de pin\" =loup"
+    );
+
+    assert_eq!(
+      canonical("écolo* éc?lo* loup~ \"loup\"~2 \"pomme  de pin\"~3 /a\\/b[0-9]+/"),
+      "écolo* éc?lo* loup~1 loup~2 \"pomme de pin\"~3 /a\\/b[0-9]+/"
+    );
+
+    for query in [
+      "#vegan (@cocktail OR \"pomme de pin\") -viande",
+      "-(a OR b) c",
+      "-/eco.*/ (loup~2 OR \"pomme pin\"~1)",
+    ] {
+      assert_eq!(canonical(&canonical(query)), canonical(query));
+    }
+  }
+
+  #[test]
+  fn test_syntax_errors() {
+    let position = |input: &str| match QueryExpr::parse(input) {
+      Err(QueryLanguageError::Syntax { position, .. }) => position,
+      other => panic!("erreur attendue pour {input}, obtenu {other:?}"),
+    };
+
+    assert_eq!(position("a (b OR c"), 2);
+    assert_eq!(position("a \"pomme de"), 2);
+    assert_eq!(position("a OR"), 4);
+    assert_eq!(position("OR a"), 0);
+    assert_eq!(position("a b)"), 3);
+    assert_eq!(position("é # b"), 2);
+    assert_eq!(position("a - b"), 2);
+    assert_eq!(position("date:[2022-13-01 TO 2022-01-01]"), 6);
+    assert_eq!(position("date:[2022-01-01 AU 2022-02-01]"), 17);
+    assert_eq!(position("x date:[2022-02-01 TO 2022-01-01]"), 2);
+    assert_eq!(position("()"), 1);
+    assert_eq!(position("a = b"), 2);
+    assert_eq!(position("a é*"), 2);
+    assert_eq!(position("loup~x"), 5);
+    assert_eq!(position("loup~3"), 0);
+    assert_eq!(position("\"pomme pin\"~"), 11);
+    assert_eq!(position("a /eco("), 2);
+    assert_eq!(position("/eco(/"), 0);
+  }
+
+  #[test]
+  fn test_from_blocs() {
+    let request_params = vec![
+      vec![
+        bloc(&["#vegan", "pomme de pin"], ""),
+        bloc(&["@cocktail"], "OU"),
+        bloc(&["=viande"], "ET"),
+        bloc(&["lait"], "OU"),
+      ],
+      vec![bloc(&["#pac", "soja"], "ET")],
+    ];
+
+    assert_eq!(
+      QueryExpr::from_blocs(&request_params).to_string(),
+      "((#vegan \"pomme de pin\" OR @cocktail) =viande OR lait) -#pac -soja"
+    );
+
+    // les éléments qui ressemblent à des motifs restent des mots clés
+    let patterns = vec![
+      vec![bloc(&["~prefixe:écolo", "~approx1:loup", "eco*", "~inconnu:x"], "")],
+      vec![bloc(&["~proximite2:pomme de pin"], "ET")],
+    ];
+    let query = QueryExpr::from_blocs(&patterns);
+    assert_eq!(
+      query.to_string(),
+      "écolo* loup~1 \"eco*\" \"~inconnu:x\" -\"pomme de pin\"~2"
+    );
+    assert_eq!(
+      QueryExpr::parse(&query.to_string()).unwrap().to_blocs().unwrap(),
+      vec![
+        vec![bloc(&["eco*", "~approx1:loup", "~inconnu:x", "~prefixe:écolo"], "")],
+        patterns[1].clone(),
+      ]
+    );
+
+    let empty = vec![vec![bloc(&[], "")], vec![bloc(&[], "ET")]];
+    assert_eq!(QueryExpr::from_blocs(&empty), QueryExpr::And(vec![]));
+    assert_eq!(QueryExpr::And(vec![]).to_blocs().unwrap(), empty);
+  }
+
+  #[test]
+  fn test_to_blocs() {
+    let blocs = QueryExpr::parse("(#vegan OR #vegetarien) (soja OR \"lait d'avoine\") -viande")
+      .unwrap()
+      .to_blocs()
+      .unwrap();
+    assert_eq!(
+      blocs,
+      vec![
+        vec![
+          bloc(&["#vegan", "soja"], ""),
+          bloc(&["#vegan", "lait d'avoine"], "OU"),
+          bloc(&["#vegetarien", "soja"], "OU"),
+          bloc(&["#vegetarien", "lait d'avoine"], "OU"),
+        ],
+        vec![bloc(&["viande"], "ET")],
+      ]
+    );
+
+    // la requête relue depuis les blocs sélectionne les mêmes tweets
+    let query = QueryExpr::parse("a (b OR c) -(d OR e)").unwrap();
+    let blocs = query.to_blocs().unwrap();
+    assert_eq!(
+      QueryExpr::from_blocs(&blocs).to_string(),
+      "(a b OR a c) -d -e"
+    );
+    assert_eq!(QueryExpr::from_blocs(&blocs).to_blocs().unwrap(), blocs);
+
+    for query in [
+      "a OR -b",
+      "date:[2022-01-01 TO 2022-01-02]",
+      "lang:fr",
+      "-(a b)",
+    ] {
+      assert!(matches!(
+        QueryExpr::parse(query).unwrap().to_blocs(),
+        Err(QueryLanguageError::NotRepresentable(_))
+      ));
+    }
+  }
+
+  #[test]
+  fn test_to_criteria() {
+    let criteria = QueryExpr::parse("#vegan date:[2022-01-01 TO 2022-06-30] (lang:fr OR lang:en)")
+      .unwrap()
+      .to_criteria()
+      .unwrap();
+    assert_eq!(
+      criteria,
+      StudyCriteria {
+        request_params: vec![vec![bloc(&["#vegan"], "")], vec![bloc(&[], "ET")]],
+        dates: Some((
+          NaiveDate::from_ymd(2022, 1, 1),
+          NaiveDate::from_ymd(2022, 6, 30)
+        )),
+        languages: vec!["en".to_string(), "fr".to_string()],
+      }
+    );
+
+    // sans date: ni lang:, les critères de l'étude sont conservés
+    let criteria = QueryExpr::parse("a -b").unwrap().to_criteria().unwrap();
+    assert_eq!((criteria.dates, criteria.languages), (None, vec![]));
+
+    for query in [
+      "a OR lang:fr",
+      "-lang:fr",
+      "lang:fr lang:en",
+      "date:[2022-01-01 TO 2022-01-02] date:[2022-02-01 TO 2022-02-02]",
+      "(a date:[2022-01-01 TO 2022-01-02]) OR b",
+    ] {
+      assert!(matches!(
+        QueryExpr::parse(query).unwrap().to_criteria(),
+        Err(QueryLanguageError::NotRepresentable(_))
+      ));
+    }
+  }
+}
@@ -0,0 +1,364 @@
+//! Parcours des tweets d'un index sans les charger tous en mémoire.
+//!
+//! [`TweetStream`] lit les documents correspondant à une requête segment par segment, dans
+//! l'ordre de l'index et sans calcul de score. Les vues triées sont paginées par
+//! [`SearchAfter`] : la page suivante commence après le [`SearchCursor`] du dernier tweet
+//! affiché, sans relire les pages précédentes.
+
+use std::{collections::BinaryHeap, fmt, str::FromStr};
+
+use tantivy::{
+  collector::{Collector, SegmentCollector},
+  fastfield::{AliveBitSet, DynamicFastFieldReader, FastFieldReader},
+  query::{AllQuery, Query, Scorer, Weight},
+  schema::Field,
+  store::StoreReader,
+  DocAddress, DocId, DocSet, Document, Index, LeasedItem, Score, Searcher, SegmentOrdinal,
+  SegmentReader, TantivyError, TERMINATED,
+};
+
+use crate::{SearchError, Tweet, TweetSchema};
+
+/// Tweets correspondant à une requête, lus à la demande
+pub struct TweetStream {
+  searcher: LeasedItem<Searcher>,
+  tweet_schema: TweetSchema,
+  weight: Box<dyn Weight>,
+  next_segment: usize,
+  segment: Option<SegmentStream>,
+}
+
+/// Documents restant à lire dans le segment courant
+struct SegmentStream {
+  scorer: Box<dyn Scorer>,
+  store_reader: StoreReader,
+  alive_bitset: Option<AliveBitSet>,
+}
+
+impl TweetStream {
+  pub fn new(index: &Index, query: &dyn Query) -> Result<Self, SearchError> {
+    let searcher = index.reader()?.searcher();
+    let tweet_schema = TweetSchema::from_index(index)?;
+    let weight = query.weight(&searcher, false)?;
+
+    Ok(TweetStream {
+      searcher,
+      tweet_schema,
+      weight,
+      next_segment: 0,
+      segment: None,
+    })
+  }
+
+  fn next_document(&mut self) -> Result<Option<Document>, SearchError> {
+    loop {
+      if let Some(segment) = &mut self.segment {
+        while segment.scorer.doc() != TERMINATED {
+          let doc = segment.scorer.doc();
+          segment.scorer.advance();
+          let alive = segment
+            .alive_bitset
+            .as_ref()
+            .is_none_or(|alive_bitset| alive_bitset.is_alive(doc));
+          if alive {
+            return Ok(Some(segment.store_reader.get(doc)?));
+          }
+        }
+      }
+
+      let segment_reader = match self.searcher.segment_readers().get(self.next_segment) {
+        Some(segment_reader) => segment_reader,
+        None => return Ok(None),
+      };
+      self.segment = Some(SegmentStream {
+        scorer: self.weight.scorer(segment_reader, 1.0)?,
+        store_reader: segment_reader
+          .get_store_reader()
+          .map_err(TantivyError::from)?,
+        alive_bitset: segment_reader.alive_bitset().cloned(),
+      });
+      self.next_segment += 1;
+    }
+  }
+}
+
+impl Iterator for TweetStream {
+  type Item = Result<Tweet, SearchError>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    self
+      .next_document()
+      .transpose()
+      .map(|document| document.and_then(|document| self.tweet_schema.to_tweet(&document)))
+  }
+}
+
+/// Tous les tweets de l'index
+pub fn all_tweets(index: &Index) -> Result<TweetStream, SearchError> {
+  TweetStream::new(index, &AllQuery)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortOrder {
+  Ascending,
+  Descending,
+}
+
+impl SortOrder {
+  /// Clé croissante dans l'ordre de tri ; son propre inverse
+  fn key(&self, value: u64) -> u64 {
+    match self {
+      SortOrder::Ascending => value,
+      SortOrder::Descending => u64::MAX - value,
+    }
+  }
+}
+
+impl From<&str> for SortOrder {
+  fn from(key: &str) -> Self {
+    match key {
+      "croissant" => SortOrder::Ascending,
+      _ => SortOrder::Descending,
+    }
+  }
+}
+
+/// Position d'un tweet dans une vue triée : valeur du champ de tri, puis adresse du document
+/// pour départager les égalités. N'a de sens que pour l'index qui l'a produite, tant que ses
+/// segments ne changent pas.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchCursor {
+  pub value: u64,
+  pub doc: DocAddress,
+}
+
+impl SearchCursor {
+  fn key(&self, order: SortOrder) -> (u64, DocAddress) {
+    (order.key(self.value), self.doc)
+  }
+}
+
+impl fmt::Display for SearchCursor {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "{}.{}.{}",
+      self.value, self.doc.segment_ord, self.doc.doc_id
+    )
+  }
+}
+
+impl FromStr for SearchCursor {
+  type Err = SearchError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let mut parts = s.splitn(3, '.').map(str::parse::<u64>);
+    match (parts.next(), parts.next(), parts.next()) {
+      (Some(Ok(value)), Some(Ok(segment_ord)), Some(Ok(doc_id))) => Ok(SearchCursor {
+        value,
+        doc: DocAddress::new(
+          u32::try_from(segment_ord).map_err(|_| SearchError::ParseError)?,
+          u32::try_from(doc_id).map_err(|_| SearchError::ParseError)?,
+        ),
+      }),
+      _ => Err(SearchError::ParseError),
+    }
+  }
+}
+
+/// Page d'une vue triée et position à partir de laquelle lire la suivante
+#[derive(Debug, Clone)]
+pub struct TweetPage<T> {
+  pub tweets: Vec<T>,
+  pub next: Option<SearchCursor>,
+}
+
+/// Collecte les `limit` premiers documents selon le champ rapide `field`, après `after`
+pub struct SearchAfter {
+  field: Field,
+  order: SortOrder,
+  after: Option<SearchCursor>,
+  limit: usize,
+}
+
+impl SearchAfter {
+  pub fn new(field: Field, order: SortOrder, after: Option<SearchCursor>, limit: usize) -> Self {
+    SearchAfter {
+      field,
+      order,
+      after,
+      limit,
+    }
+  }
+}
+
+impl Collector for SearchAfter {
+  type Fruit = Vec<SearchCursor>;
+  type Child = SearchAfterSegmentCollector;
+
+  fn for_segment(
+    &self,
+    segment_ord: SegmentOrdinal,
+    segment_reader: &SegmentReader,
+  ) -> tantivy::Result<Self::Child> {
+    Ok(SearchAfterSegmentCollector {
+      reader: segment_reader.fast_fields().u64(self.field)?,
+      segment_ord,
+      order: self.order,
+      after: self.after.map(|cursor| cursor.key(self.order)),
+      limit: self.limit,
+      heap: BinaryHeap::with_capacity(self.limit),
+    })
+  }
+
+  fn requires_scoring(&self) -> bool {
+    false
+  }
+
+  fn merge_fruits(&self, segment_fruits: Vec<Vec<SearchCursor>>) -> tantivy::Result<Self::Fruit> {
+    let mut cursors: Vec<_> = segment_fruits.into_iter().flatten().collect();
+    cursors.sort_unstable_by_key(|cursor| cursor.key(self.order));
+    cursors.truncate(self.limit);
+
+    Ok(cursors)
+  }
+}
+
+pub struct SearchAfterSegmentCollector {
+  reader: DynamicFastFieldReader<u64>,
+  segment_ord: SegmentOrdinal,
+  order: SortOrder,
+  after: Option<(u64, DocAddress)>,
+  limit: usize,
+  /// les `limit` plus petites clés du segment, la plus grande au sommet
+  heap: BinaryHeap<(u64, DocAddress)>,
+}
+
+impl SegmentCollector for SearchAfterSegmentCollector {
+  type Fruit = Vec<SearchCursor>;
+
+  fn collect(&mut self, doc: DocId, _score: Score) {
+    let key = (
+      self.order.key(self.reader.get(doc)),
+      DocAddress::new(self.segment_ord, doc),
+    );
+    if self.after.is_some_and(|after| key <= after) {
+      return;
+    }
+    if self.heap.len() < self.limit {
+      self.heap.push(key);
+    } else if self.heap.peek().is_some_and(|largest| key < *largest) {
+      self.heap.pop();
+      self.heap.push(key);
+    }
+  }
+
+  fn harvest(self) -> Self::Fruit {
+    let order = self.order;
+    self
+      .heap
+      .into_iter()
+      .map(|(key, doc)| SearchCursor {
+        value: order.key(key),
+        doc,
+      })
+      .collect()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use tantivy::query::TermQuery;
+  use tantivy::{schema::IndexRecordOption, Term};
+
+  use super::*;
+  use crate::test_utils::{index_with, tweet};
+
+  #[test]
+  fn test_stream_and_search_after() {
+    // deux segments, et un document supprimé
+    let index = index_with(&[
+      Tweet {
+        retweet_count: 5,
+        ..tweet(1)
+      },
+      Tweet {
+        retweet_count: 1,
+        ..tweet(2)
+      },
+      Tweet {
+        retweet_count: 5,
+        ..tweet(3)
+      },
+    ]);
+    let tweet_schema = TweetSchema::from_index(&index).unwrap();
+    let mut writer = index.writer(15_000_000).unwrap();
+    for (id, retweet_count) in [(4, 3), (5, 9), (6, 7)] {
+      let document = tweet_schema.to_document(&Tweet {
+        user_id: "43".to_string(),
+        retweet_count,
+        ..tweet(id)
+      });
+      writer.add_document(document).unwrap();
+    }
+    writer.delete_term(Term::from_field_text(tweet_schema.id, "5"));
+    writer.commit().unwrap();
+
+    let mut ids = all_tweets(&index)
+      .unwrap()
+      .map(|tweet| tweet.unwrap().id)
+      .collect::<Vec<_>>();
+    ids.sort_unstable();
+    assert_eq!(ids, vec!["1", "2", "3", "4", "6"]);
+
+    let query = TermQuery::new(
+      Term::from_field_text(tweet_schema.user_id, "43"),
+      IndexRecordOption::Basic,
+    );
+    let ids = TweetStream::new(&index, &query)
+      .unwrap()
+      .map(|tweet| tweet.unwrap().id)
+      .collect::<Vec<_>>();
+    assert_eq!(ids, vec!["4", "6"]);
+
+    // pages de deux tweets, les égalités départagées par l'adresse du document
+    let searcher = index.reader().unwrap().searcher();
+    let pages = |order: SortOrder| {
+      let mut pages = vec![];
+      let mut after = None;
+      loop {
+        let collector = SearchAfter::new(tweet_schema.retweet_count, order, after, 2);
+        let cursors = searcher.search(&AllQuery, &collector).unwrap();
+        if cursors.is_empty() {
+          break;
+        }
+        after = cursors.last().copied();
+        pages.push(
+          cursors
+            .iter()
+            .map(|cursor| {
+              let document = searcher.doc(cursor.doc).unwrap();
+              tweet_schema.to_tweet(&document).unwrap().id
+            })
+            .collect::<Vec<_>>(),
+        );
+      }
+      pages
+    };
+    assert_eq!(
+      pages(SortOrder::Descending),
+      vec![vec!["6", "1"], vec!["3", "4"], vec!["2"]]
+    );
+    assert_eq!(
+      pages(SortOrder::Ascending),
+      vec![vec!["2", "4"], vec!["1", "3"], vec!["6"]]
+    );
+
+    let cursor = SearchCursor {
+      value: 5,
+      doc: DocAddress::new(1, 2),
+    };
+    assert_eq!(cursor.to_string().parse::<SearchCursor>().unwrap(), cursor);
+    assert!("5.1".parse::<SearchCursor>().is_err());
+  }
+}
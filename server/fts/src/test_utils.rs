@@ -0,0 +1,79 @@
+//! Tweets et index en mémoire des tests.
+//!
+//! ```ignore
+//! let index = index_with(&[
+//!   Tweet { retweet_count: 5, ..tweet(1) },
+//!   tweet(2).by("bergers").with_hashtags(&["loup"]),
+//! ]);
+//! ```
+
+use chrono::{DateTime, TimeZone, Utc};
+use tantivy::Index;
+
+use crate::{register_tokenizers, Tweet, TweetSchema};
+
+/// Tweet `id` de @eleveurs_fr, « Retour du loup » le 12 juin 2022 à 2 h 13 UTC, sans
+/// engagement, hashtag ni lien
+pub(crate) fn tweet(id: impl ToString) -> Tweet {
+  Tweet {
+    id: id.to_string(),
+    user_id: "42".to_string(),
+    user_name: "Éleveurs".to_string(),
+    user_screen_name: "eleveurs_fr".to_string(),
+    text: "Retour du loup".to_string(),
+    published_time: Utc.timestamp(1_655_000_000, 0),
+    published_time_ms: 1_655_000_000_000,
+    retweet_count: 0,
+    reply_count: 0,
+    quote_count: 0,
+    hashtags: vec![],
+    urls: vec![],
+    relations: Default::default(),
+    metadata: Default::default(),
+  }
+}
+
+impl Tweet {
+  /// Même tweet publié par `user`, identifiant, nom et pseudo confondus
+  pub(crate) fn by(self, user: &str) -> Self {
+    Tweet {
+      user_id: user.to_string(),
+      user_name: user.to_string(),
+      user_screen_name: user.to_string(),
+      ..self
+    }
+  }
+
+  /// Même tweet publié à `published_time`
+  pub(crate) fn at(self, published_time: DateTime<Utc>) -> Self {
+    Tweet {
+      published_time,
+      published_time_ms: published_time.timestamp_millis() as u64,
+      ..self
+    }
+  }
+
+  pub(crate) fn with_hashtags(self, hashtags: &[&str]) -> Self {
+    Tweet {
+      hashtags: hashtags.iter().map(|hashtag| hashtag.to_string()).collect(),
+      ..self
+    }
+  }
+}
+
+/// Index en mémoire au schéma par défaut contenant `tweets`, en un seul segment
+pub(crate) fn index_with(tweets: &[Tweet]) -> Index {
+  let index = Index::create_in_ram(TweetSchema::build(&Default::default()));
+  register_tokenizers(&index);
+  let tweet_schema = TweetSchema::from_index(&index).unwrap();
+
+  let mut writer = index.writer(15_000_000).unwrap();
+  for tweet in tweets {
+    writer
+      .add_document(tweet_schema.to_document(tweet))
+      .unwrap();
+  }
+  writer.commit().unwrap();
+
+  index
+}
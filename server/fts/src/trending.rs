@@ -0,0 +1,99 @@
+//! Nombre quotidien de tweets par hashtag sur les derniers jours du corpus.
+//!
+//! Ces comptes sont enregistrés dans la base topk par l'automatisation ; la base en déduit
+//! les hashtags en tendance pour une fenêtre récente et une période de référence quelconques.
+
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use tantivy::{
+  collector::TopDocs,
+  query::{AllQuery, RangeQuery},
+  Index,
+};
+
+use crate::{hashtags_terms, SearchError, TweetSchema};
+
+const DAY_MS: u64 = 24 * 60 * 60 * 1000;
+
+/// Nombre de tweets d'un jour (UTC) contenant un hashtag
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HashtagDayCount {
+  pub key: String,
+  pub day: NaiveDate,
+  pub doc_count: u64,
+}
+
+/// Comptes quotidiens des `size` hashtags les plus utilisés de chacun des `days` derniers
+/// jours du corpus, le dernier jour étant celui du tweet le plus récent de l'index
+pub fn hashtags_by_day(
+  index: &Index,
+  days: u32,
+  size: u32,
+) -> Result<Vec<HashtagDayCount>, SearchError> {
+  let tweet_schema = TweetSchema::from_index(index)?;
+  let searcher = index.reader()?.searcher();
+
+  let latest = searcher.search(
+    &AllQuery,
+    &TopDocs::with_limit(1).order_by_fast_field::<u64>(tweet_schema.published_time_ms),
+  )?;
+  let last_day = match latest.first() {
+    Some((published_time_ms, _)) => {
+      NaiveDateTime::from_timestamp((published_time_ms / 1000) as i64, 0).date()
+    }
+    None => return Ok(vec![]),
+  };
+
+  let mut counts = vec![];
+  for offset in (0..days as i64).rev() {
+    let day = last_day - Duration::days(offset);
+    let start_ms = day.and_hms(0, 0, 0).timestamp_millis().max(0) as u64;
+    let query = RangeQuery::new_u64(tweet_schema.published_time_ms, start_ms..start_ms + DAY_MS);
+
+    counts.extend(
+      hashtags_terms(&searcher, &query, size)?
+        .into_iter()
+        .map(|hashtag| HashtagDayCount {
+          key: hashtag.key,
+          day,
+          doc_count: hashtag.doc_count,
+        }),
+    );
+  }
+
+  Ok(counts)
+}
+
+#[cfg(test)]
+mod tests {
+  use chrono::{TimeZone, Utc};
+
+  use super::*;
+  use crate::test_utils::{index_with, tweet};
+
+  #[test]
+  fn test_hashtags_by_day() {
+    let tweets: Vec<_> = [
+      (Utc.ymd(2022, 6, 1).and_hms(8, 0, 0), vec!["pac"]),
+      (Utc.ymd(2022, 6, 3).and_hms(9, 0, 0), vec!["pac", "loup"]),
+      (Utc.ymd(2022, 6, 3).and_hms(23, 59, 0), vec!["loup"]),
+      (Utc.ymd(2022, 6, 4).and_hms(10, 0, 0), vec!["loup"]),
+    ]
+    .into_iter()
+    .enumerate()
+    .map(|(id, (published_time, hashtags))| tweet(id).at(published_time).with_hashtags(&hashtags))
+    .collect();
+    let index = index_with(&tweets);
+
+    let count = |key: &str, day: u32, doc_count: u64| HashtagDayCount {
+      key: key.to_string(),
+      day: NaiveDate::from_ymd(2022, 6, day),
+      doc_count,
+    };
+    // le 1er juin sort de la fenêtre de trois jours
+    assert_eq!(
+      hashtags_by_day(&index, 3, 10).unwrap(),
+      vec![count("loup", 3, 2), count("pac", 3, 1), count("loup", 4, 1)]
+    );
+  }
+}
@@ -0,0 +1,468 @@
+//! Schéma de l'index des tweets.
+//!
+//! La liste des champs n'est décrite qu'ici : [`TweetSchema::build`] sert à créer les
+//! index, [`TweetSchema::new`] vérifie qu'un index existant la respecte et résout une
+//! fois pour toutes les champs utilisés par les recherches, l'ingestion et la copie.
+
+use chrono::{TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use tantivy::{
+  schema::{Facet, Field, Schema, Value, FAST, INDEXED, STORED, STRING},
+  Document, Index,
+};
+
+use crate::{
+  exact_text_options, language_facet, normalize_url, stemmed_text_options, url_domain, OrderBy,
+  SearchError, TextAnalysisOptions, Tweet, TweetMetadata, TweetRelations,
+};
+
+/// Poids des retweets, réponses et citations dans le champ `engagement_count`, fixés à la
+/// création de l'index
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EngagementWeights {
+  pub retweets: u64,
+  pub replies: u64,
+  pub quotes: u64,
+}
+
+impl Default for EngagementWeights {
+  fn default() -> Self {
+    EngagementWeights {
+      retweets: 1,
+      replies: 1,
+      quotes: 1,
+    }
+  }
+}
+
+impl EngagementWeights {
+  pub fn engagement(&self, tweet: &Tweet) -> u64 {
+    (self.retweets.saturating_mul(tweet.retweet_count))
+      .saturating_add(self.replies.saturating_mul(tweet.reply_count))
+      .saturating_add(self.quotes.saturating_mul(tweet.quote_count))
+  }
+}
+
+/// Champs de l'index des tweets
+#[derive(Debug, Clone, Copy)]
+pub struct TweetSchema {
+  pub id: Field,
+  pub published_time: Field,
+  pub published_time_ms: Field,
+  pub user_id: Field,
+  pub user_name: Field,
+  pub user_screen_name: Field,
+  pub text: Field,
+  /// absent des index créés avant l'analyse du français
+  pub text_exact: Option<Field>,
+  /// URLs normalisées par [`normalize_url`]
+  pub urls: Field,
+  /// facette `/domaine` de chaque URL, dérivée de `urls`
+  pub domains: Field,
+  pub hashtags: Field,
+  /// identifiants des comptes mentionnés
+  pub mentions: Field,
+  /// 1 pour un retweet, 0 sinon
+  pub is_retweet: Field,
+  pub retweeted_id: Field,
+  pub retweeted_user_id: Field,
+  pub in_reply_to_id: Field,
+  pub in_reply_to_user_id: Field,
+  pub quoted_id: Field,
+  pub quoted_user_id: Field,
+  /// facette `/langue`
+  pub language: Field,
+  /// absents des tweets non géolocalisés
+  pub longitude: Field,
+  pub latitude: Field,
+  /// 1 pour un tweet signalé comme sensible, 0 sinon
+  pub possibly_sensitive: Field,
+  pub retweet_count: Field,
+  pub reply_count: Field,
+  pub quote_count: Field,
+  pub engagement_count: Field,
+  /// formule de `engagement_count` pour les documents écrits
+  pub engagement: EngagementWeights,
+}
+
+impl TweetSchema {
+  /// Schéma d'un nouvel index, le champ `text` étant analysé selon `options`
+  pub fn build(options: &TextAnalysisOptions) -> Schema {
+    let mut schema_builder = Schema::builder();
+
+    schema_builder.add_text_field("id", STRING | FAST | STORED);
+    schema_builder.add_date_field("published_time", INDEXED | FAST | STORED);
+    schema_builder.add_u64_field("published_time_ms", INDEXED | FAST | STORED);
+    schema_builder.add_text_field("user_id", STRING | FAST | STORED);
+    schema_builder.add_text_field("user_name", STRING | STORED);
+    schema_builder.add_text_field("user_screen_name", STRING | STORED);
+    schema_builder.add_text_field("text", stemmed_text_options(options));
+    schema_builder.add_text_field("text_exact", exact_text_options());
+    schema_builder.add_text_field("urls", STRING | FAST | STORED);
+    schema_builder.add_facet_field("domains", INDEXED);
+    schema_builder.add_text_field("hashtags", STRING | FAST | STORED);
+    schema_builder.add_text_field("mentions", STRING | FAST | STORED);
+    schema_builder.add_u64_field("is_retweet", INDEXED | FAST | STORED);
+    schema_builder.add_text_field("retweeted_id", STRING | STORED);
+    schema_builder.add_text_field("retweeted_user_id", STRING | FAST | STORED);
+    schema_builder.add_text_field("in_reply_to_id", STRING | STORED);
+    schema_builder.add_text_field("in_reply_to_user_id", STRING | FAST | STORED);
+    schema_builder.add_text_field("quoted_id", STRING | STORED);
+    schema_builder.add_text_field("quoted_user_id", STRING | FAST | STORED);
+    schema_builder.add_facet_field("language", INDEXED | STORED);
+    schema_builder.add_f64_field("longitude", INDEXED | FAST | STORED);
+    schema_builder.add_f64_field("latitude", INDEXED | FAST | STORED);
+    schema_builder.add_u64_field("possibly_sensitive", INDEXED | FAST | STORED);
+    schema_builder.add_u64_field("retweet_count", INDEXED | FAST | STORED);
+    schema_builder.add_u64_field("reply_count", INDEXED | FAST | STORED);
+    schema_builder.add_u64_field("quote_count", INDEXED | FAST | STORED);
+    schema_builder.add_u64_field("engagement_count", INDEXED | FAST | STORED);
+
+    schema_builder.build()
+  }
+
+  /// Vérifie que `schema` contient les champs de [`TweetSchema::build`], avec le même type
+  /// et au moins les mêmes options (indexé, stocké, rapide). L'analyseur du texte n'est pas
+  /// comparé : il dépend des options choisies à la création.
+  pub fn new(schema: &Schema) -> Result<Self, SearchError> {
+    let expected = Self::build(&TextAnalysisOptions::default());
+    for (_, expected_entry) in expected.fields() {
+      let name = expected_entry.name();
+      let field = match schema.get_field(name) {
+        Some(field) => field,
+        None if name == "text_exact" => continue,
+        None => return Err(SearchError::SchemaMismatch(format!("champ {name} absent"))),
+      };
+
+      let entry = schema.get_field_entry(field);
+      if entry.field_type().value_type() != expected_entry.field_type().value_type() {
+        return Err(SearchError::SchemaMismatch(format!(
+          "champ {name} de type {:?} au lieu de {:?}",
+          entry.field_type().value_type(),
+          expected_entry.field_type().value_type()
+        )));
+      }
+      for (option, expected_option, actual) in [
+        ("indexé", expected_entry.is_indexed(), entry.is_indexed()),
+        ("stocké", expected_entry.is_stored(), entry.is_stored()),
+        ("rapide", expected_entry.is_fast(), entry.is_fast()),
+      ] {
+        if expected_option && !actual {
+          return Err(SearchError::SchemaMismatch(format!(
+            "champ {name} non {option}"
+          )));
+        }
+      }
+    }
+
+    let field = |name: &str| schema.get_field(name).unwrap();
+    Ok(TweetSchema {
+      id: field("id"),
+      published_time: field("published_time"),
+      published_time_ms: field("published_time_ms"),
+      user_id: field("user_id"),
+      user_name: field("user_name"),
+      user_screen_name: field("user_screen_name"),
+      text: field("text"),
+      text_exact: schema.get_field("text_exact"),
+      urls: field("urls"),
+      domains: field("domains"),
+      hashtags: field("hashtags"),
+      mentions: field("mentions"),
+      is_retweet: field("is_retweet"),
+      retweeted_id: field("retweeted_id"),
+      retweeted_user_id: field("retweeted_user_id"),
+      in_reply_to_id: field("in_reply_to_id"),
+      in_reply_to_user_id: field("in_reply_to_user_id"),
+      quoted_id: field("quoted_id"),
+      quoted_user_id: field("quoted_user_id"),
+      language: field("language"),
+      longitude: field("longitude"),
+      latitude: field("latitude"),
+      possibly_sensitive: field("possibly_sensitive"),
+      retweet_count: field("retweet_count"),
+      reply_count: field("reply_count"),
+      quote_count: field("quote_count"),
+      engagement_count: field("engagement_count"),
+      engagement: EngagementWeights::default(),
+    })
+  }
+
+  pub fn from_index(index: &Index) -> Result<Self, SearchError> {
+    Self::new(&index.schema())
+  }
+
+  /// Même schéma, `engagement_count` étant calculé avec `engagement`
+  pub fn with_engagement(self, engagement: EngagementWeights) -> Self {
+    TweetSchema { engagement, ..self }
+  }
+
+  /// Champ rapide sur lequel trier les résultats
+  pub fn sort_field(&self, order_by: &OrderBy) -> Field {
+    match order_by {
+      OrderBy::PublishedTime => self.published_time_ms,
+      OrderBy::RetweetCount => self.retweet_count,
+      OrderBy::ReplyCount => self.reply_count,
+      OrderBy::QuoteCount => self.quote_count,
+      OrderBy::EngagementCount => self.engagement_count,
+    }
+  }
+
+  /// Document à indexer pour `tweet`, engagement compris
+  pub fn to_document(&self, tweet: &Tweet) -> Document {
+    let mut document = Document::default();
+    document.add_text(self.id, &tweet.id);
+    document.add_text(self.user_id, &tweet.user_id);
+    document.add_text(self.user_name, &tweet.user_name);
+    document.add_text(self.user_screen_name, &tweet.user_screen_name);
+    document.add_text(self.text, &tweet.text);
+    if let Some(text_exact) = self.text_exact {
+      document.add_text(text_exact, &tweet.text);
+    }
+    document.add_date(
+      self.published_time,
+      tantivy::DateTime::from_unix_timestamp((tweet.published_time_ms / 1_000) as i64),
+    );
+    document.add_u64(self.published_time_ms, tweet.published_time_ms);
+    document.add_u64(self.retweet_count, tweet.retweet_count);
+    document.add_u64(self.reply_count, tweet.reply_count);
+    document.add_u64(self.quote_count, tweet.quote_count);
+    document.add_u64(self.engagement_count, self.engagement.engagement(tweet));
+    tweet
+      .hashtags
+      .iter()
+      .for_each(|hashtag| document.add_text(self.hashtags, hashtag));
+    let mut urls: Vec<String> = vec![];
+    for url in tweet.urls.iter().map(|url| normalize_url(url)) {
+      if !urls.contains(&url) {
+        urls.push(url);
+      }
+    }
+    let mut domains: Vec<String> = urls.iter().filter_map(|url| url_domain(url)).collect();
+    domains.sort_unstable();
+    domains.dedup();
+    domains
+      .into_iter()
+      .for_each(|domain| document.add_facet(self.domains, Facet::from_path([domain])));
+    urls
+      .into_iter()
+      .for_each(|url| document.add_text(self.urls, url));
+
+    let relations = &tweet.relations;
+    relations
+      .mentions
+      .iter()
+      .for_each(|user_id| document.add_text(self.mentions, user_id));
+    document.add_u64(self.is_retweet, relations.is_retweet as u64);
+    for (field, value) in [
+      (self.retweeted_id, &relations.retweeted_id),
+      (self.retweeted_user_id, &relations.retweeted_user_id),
+      (self.in_reply_to_id, &relations.in_reply_to_id),
+      (self.in_reply_to_user_id, &relations.in_reply_to_user_id),
+      (self.quoted_id, &relations.quoted_id),
+      (self.quoted_user_id, &relations.quoted_user_id),
+    ] {
+      if let Some(value) = value {
+        document.add_text(field, value);
+      }
+    }
+
+    let metadata = &tweet.metadata;
+    if let Some(language) = &metadata.language {
+      document.add_facet(self.language, language_facet(language));
+    }
+    // une position n'est indexée que complète
+    if let (Some(longitude), Some(latitude)) = (metadata.longitude, metadata.latitude) {
+      document.add_f64(self.longitude, longitude);
+      document.add_f64(self.latitude, latitude);
+    }
+    document.add_u64(self.possibly_sensitive, metadata.possibly_sensitive as u64);
+
+    document
+  }
+
+  /// Tweet stocké dans `document`, erreur si un champ à valeur unique est absent
+  pub fn to_tweet(&self, document: &Document) -> Result<Tweet, SearchError> {
+    let published_time = self
+      .first(document, self.published_time, "published_time")?
+      .as_date()
+      .map(|date| Utc.timestamp(date.into_unix_timestamp(), 0))
+      .ok_or_else(|| invalid_value("published_time"))?;
+
+    Ok(Tweet {
+      id: self.text_value(document, self.id, "id")?,
+      user_id: self.text_value(document, self.user_id, "user_id")?,
+      user_name: self.text_value(document, self.user_name, "user_name")?,
+      user_screen_name: self.text_value(document, self.user_screen_name, "user_screen_name")?,
+      text: self.text_value(document, self.text, "text")?,
+      published_time,
+      published_time_ms: self.u64_value(document, self.published_time_ms, "published_time_ms")?,
+      retweet_count: self.u64_value(document, self.retweet_count, "retweet_count")?,
+      reply_count: self.u64_value(document, self.reply_count, "reply_count")?,
+      quote_count: self.u64_value(document, self.quote_count, "quote_count")?,
+      hashtags: text_values(document, self.hashtags),
+      urls: text_values(document, self.urls),
+      relations: TweetRelations {
+        mentions: text_values(document, self.mentions),
+        is_retweet: self.u64_value(document, self.is_retweet, "is_retweet")? == 1,
+        retweeted_id: optional_text_value(document, self.retweeted_id),
+        retweeted_user_id: optional_text_value(document, self.retweeted_user_id),
+        in_reply_to_id: optional_text_value(document, self.in_reply_to_id),
+        in_reply_to_user_id: optional_text_value(document, self.in_reply_to_user_id),
+        quoted_id: optional_text_value(document, self.quoted_id),
+        quoted_user_id: optional_text_value(document, self.quoted_user_id),
+      },
+      metadata: TweetMetadata {
+        language: document
+          .get_first(self.language)
+          .and_then(Value::as_facet)
+          .map(|facet| facet.to_path().concat()),
+        longitude: document.get_first(self.longitude).and_then(Value::as_f64),
+        latitude: document.get_first(self.latitude).and_then(Value::as_f64),
+        possibly_sensitive: self.u64_value(document, self.possibly_sensitive, "possibly_sensitive")?
+          == 1,
+      },
+    })
+  }
+
+  fn first<'a>(
+    &self,
+    document: &'a Document,
+    field: Field,
+    name: &str,
+  ) -> Result<&'a Value, SearchError> {
+    document
+      .get_first(field)
+      .ok_or_else(|| SearchError::InvalidDocument(format!("champ {name} absent")))
+  }
+
+  fn text_value(
+    &self,
+    document: &Document,
+    field: Field,
+    name: &str,
+  ) -> Result<String, SearchError> {
+    self
+      .first(document, field, name)?
+      .as_text()
+      .map(str::to_string)
+      .ok_or_else(|| invalid_value(name))
+  }
+
+  fn u64_value(&self, document: &Document, field: Field, name: &str) -> Result<u64, SearchError> {
+    self
+      .first(document, field, name)?
+      .as_u64()
+      .ok_or_else(|| invalid_value(name))
+  }
+}
+
+fn text_values(document: &Document, field: Field) -> Vec<String> {
+  document
+    .get_all(field)
+    .filter_map(Value::as_text)
+    .map(str::to_string)
+    .collect()
+}
+
+fn optional_text_value(document: &Document, field: Field) -> Option<String> {
+  document
+    .get_first(field)
+    .and_then(Value::as_text)
+    .map(str::to_string)
+}
+
+fn invalid_value(name: &str) -> SearchError {
+  SearchError::InvalidDocument(format!("valeur du champ {name} invalide"))
+}
+
+#[cfg(test)]
+mod tests {
+  use tantivy::schema::STORED;
+
+  use super::*;
+  use crate::test_utils::tweet;
+
+  #[test]
+  fn test_tweet_document_round_trip() {
+    let schema = TweetSchema::build(&Default::default());
+    let tweet_schema = TweetSchema::new(&schema).unwrap();
+
+    let tweet = Tweet {
+      text: "Retour du #loup dans les Alpes".to_string(),
+      retweet_count: 3,
+      reply_count: 2,
+      quote_count: 1,
+      hashtags: vec!["loup".to_string(), "alpes".to_string()],
+      urls: vec!["https://example.org".to_string()],
+      relations: TweetRelations {
+        mentions: vec!["43".to_string()],
+        is_retweet: true,
+        retweeted_id: Some("1530000000000000000".to_string()),
+        retweeted_user_id: Some("43".to_string()),
+        ..Default::default()
+      },
+      metadata: TweetMetadata {
+        language: Some("fr".to_string()),
+        longitude: Some(6.87),
+        latitude: Some(45.92),
+        possibly_sensitive: false,
+      },
+      ..tweet("1540000000000000000")
+    };
+
+    let document = tweet_schema.to_document(&tweet);
+    assert_eq!(
+      document
+        .get_first(tweet_schema.engagement_count)
+        .and_then(Value::as_u64),
+      Some(6)
+    );
+    let weighted = tweet_schema
+      .with_engagement(EngagementWeights {
+        retweets: 2,
+        replies: 1,
+        quotes: 0,
+      })
+      .to_document(&tweet);
+    assert_eq!(
+      weighted
+        .get_first(tweet_schema.engagement_count)
+        .and_then(Value::as_u64),
+      Some(8)
+    );
+
+    let decoded = tweet_schema.to_tweet(&document).unwrap();
+    assert_eq!(decoded.id, tweet.id);
+    assert_eq!(decoded.published_time, tweet.published_time);
+    assert_eq!(decoded.hashtags, tweet.hashtags);
+    assert_eq!(decoded.urls, tweet.urls);
+    assert_eq!(decoded.relations, tweet.relations);
+    assert_eq!(decoded.metadata, tweet.metadata);
+    assert_eq!(decoded.quote_count, tweet.quote_count);
+  }
+
+  #[test]
+  fn test_schema_mismatch() {
+    let mut schema_builder = Schema::builder();
+    schema_builder.add_text_field("id", STRING | STORED);
+    let error = TweetSchema::new(&schema_builder.build()).unwrap_err();
+    assert!(matches!(error, SearchError::SchemaMismatch(_)));
+
+    let mut schema = TweetSchema::build(&Default::default());
+    let tweet_schema = TweetSchema::new(&schema).unwrap();
+    assert!(tweet_schema.to_tweet(&Document::default()).is_err());
+
+    // un champ attendu rapide qui ne l'est pas
+    let mut schema_builder = Schema::builder();
+    for (_, entry) in schema.fields() {
+      if entry.name() == "retweet_count" {
+        schema_builder.add_u64_field("retweet_count", INDEXED | STORED);
+      } else {
+        schema_builder.add_field(entry.clone());
+      }
+    }
+    schema = schema_builder.build();
+    assert!(TweetSchema::new(&schema).is_err());
+  }
+}
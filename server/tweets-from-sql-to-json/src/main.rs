@@ -25,6 +25,68 @@ struct Tweet {
   retweet_count: i64,
   reply_count: i64,
   quote_count: i64,
+  #[serde(default)]
+  mentions: Option<Vec<String>>,
+  retweeted_id: Option<String>,
+  retweeted_user_id: Option<String>,
+  in_reply_to_id: Option<String>,
+  in_reply_to_user_id: Option<String>,
+  quoted_id: Option<String>,
+  quoted_user_id: Option<String>,
+}
+
+/// Vrai si la colonne `column` de `schema_name.table` existe
+async fn has_column(
+  pool: &PgPool,
+  schema_name: &str,
+  table: &str,
+  column: &str,
+) -> Result<bool, sqlx::Error> {
+  let row = sqlx::query(
+    "SELECT EXISTS (
+      SELECT FROM information_schema.columns
+      WHERE table_schema = $1 AND table_name = $2 AND column_name = $3
+    )",
+  )
+  .bind(schema_name)
+  .bind(table)
+  .bind(column)
+  .fetch_one(pool)
+  .await?;
+
+  Ok(row.get::<bool, _>(0))
+}
+
+/// Colonnes `{prefix}_id` et `{prefix}_user_id` du tweet d'origine lu dans `table`, et leur
+/// jointure ; NULL si `table` n'a pas de colonne `tweet_id` (schémas de l'import CSV)
+fn origin_join(
+  schema_name: &str,
+  table: &str,
+  column: &str,
+  prefix: &str,
+  available: bool,
+) -> (String, String) {
+  if !available {
+    return (
+      format!("NULL::text AS {prefix}_id, NULL::text AS {prefix}_user_id"),
+      String::new(),
+    );
+  }
+
+  (
+    format!("{prefix}.origin_id AS {prefix}_id, {prefix}.origin_user_id AS {prefix}_user_id"),
+    format!(
+      r#"LEFT JOIN (
+                SELECT DISTINCT ON (r.tweet_id)
+                    r.tweet_id AS id,
+                    r.{column} AS origin_id,
+                    o.user_id AS origin_user_id
+                FROM {schema_name}.{table} r
+                    LEFT JOIN {schema_name}.tweet o ON o.id = r.{column}
+                ORDER BY r.tweet_id
+            ) {prefix} USING (id)"#
+    ),
+  )
 }
 
 #[tokio::main]
@@ -67,6 +129,38 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
   debug!("Nombre de tweets trouvés: {}", count);
 
+  let mut origin_columns = vec![];
+  let mut origin_joins = vec![];
+  for (table, column, prefix) in [
+    ("retweet", "retweeted_tweet_id", "retweeted"),
+    ("reply", "in_reply_to_tweet_id", "in_reply_to"),
+    ("quote", "quoted_tweet_id", "quoted"),
+  ] {
+    let available = has_column(&pool, &schema_name, table, "tweet_id").await?;
+    let (columns, join) = origin_join(&schema_name, table, column, prefix, available);
+    origin_columns.push(columns);
+    origin_joins.push(join);
+  }
+  let (mentions_column, mentions_join) =
+    if has_column(&pool, &schema_name, "tweet_user_mention", "tweet_id").await? {
+      (
+        "m.mentions".to_string(),
+        format!(
+          r#"LEFT JOIN (
+                SELECT
+                    tweet_id as id,
+                    array_agg(DISTINCT user_id) as mentions
+                FROM
+                {schema_name}.tweet_user_mention
+                GROUP BY
+                    tweet_id
+            ) m USING (id)"#
+        ),
+      )
+    } else {
+      ("NULL::text[] AS mentions".to_string(), String::new())
+    };
+
   let query = format!(
     r#"
         WITH rt as (
@@ -101,7 +195,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             u.urls,
             COALESCE(rt.count, 0) AS retweet_count,
             COALESCE(reply.count, 0) AS reply_count,
-			COALESCE(quote_.count, 0) AS quote_count
+			COALESCE(quote_.count, 0) AS quote_count,
+            {mentions_column},
+            {origin_columns}
         FROM
           {schema_name}.tweet
             LEFT JOIN (
@@ -125,10 +221,16 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             LEFT JOIN rt USING(id)
 			LEFT JOIN reply USING(id)
 			LEFT JOIN quote_ USING(id)
+            {mentions_join}
+            {origin_joins}
         WHERE
             user_name is not null AND user_screen_name is not null
     "#,
-    schema_name = schema_name
+    schema_name = schema_name,
+    mentions_column = mentions_column,
+    mentions_join = mentions_join,
+    origin_columns = origin_columns.join(",\n            "),
+    origin_joins = origin_joins.join("\n            "),
   );
 
   debug!("Exécution de la requête SQL");